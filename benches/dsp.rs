@@ -0,0 +1,93 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use test_engine::engine::dsp::biquad::{BiquadBank, FilterType};
+use test_engine::engine::dsp::crossfeed::CrossfeedSettings;
+use test_engine::engine::dsp::de_esser::DeEsserSettings;
+use test_engine::engine::dsp::dsp_chain::{DspChain, DspLayout, DspSettings};
+use test_engine::engine::dsp::node::NodeId;
+use test_engine::engine::dsp::phaser::PhaserSettings;
+use test_engine::engine::dsp::precision::Precision;
+
+const FRAMES: usize = 4096;
+
+// A block of deterministic noise, `FRAMES` frames of `channels` channels
+fn noise(channels: usize) -> Vec<f32> {
+    let mut state = 0x2545_f491_u32;
+    (0..FRAMES * channels)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32 - 0.5
+        })
+        .collect()
+}
+
+// Compare runs with and without `--features simd`, e.g.
+// `cargo bench --bench dsp -- --save-baseline scalar` then
+// `cargo bench --bench dsp --features simd -- --baseline scalar`
+fn biquad_bank(c: &mut Criterion) {
+    let mut group = c.benchmark_group("biquad_bank");
+    for channels in [2, 6, 8] {
+        let input = noise(channels);
+        let mut bank = BiquadBank::new(channels, FilterType::Peaking, 48000.0, 1000.0, 0.7, 6.0);
+        let mut block = input.clone();
+        group.bench_with_input(BenchmarkId::from_parameter(channels), &channels, |b, _| {
+            b.iter(|| {
+                block.copy_from_slice(&input);
+                bank.process_interleaved(black_box(&mut block));
+            })
+        });
+    }
+    group.finish();
+}
+
+// The whole chain at its defaults, which runs the bass and the high-frequency EQ, the
+// stages `DspLayout::Planar` deinterleaves for
+fn dsp_layout(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dsp_layout");
+    for channels in [2, 6] {
+        let input = noise(channels);
+        for (name, layout) in [("interleaved", DspLayout::Interleaved), ("planar", DspLayout::Planar)] {
+            let mut chain = DspChain::new(48000.0, channels);
+            chain.set_layout(layout);
+            let mut block = input.clone();
+            group.bench_with_input(BenchmarkId::new(name, channels), &channels, |b, _| {
+                b.iter(|| {
+                    block.copy_from_slice(&input);
+                    chain.process(black_box(&mut block));
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+// Stereo at 48 kHz through the chain with the bass boost, de-esser, phaser and crossfeed
+// on, in either precision
+fn precision(c: &mut Criterion) {
+    let mut group = c.benchmark_group("precision");
+    let input = noise(2);
+    let settings = DspSettings {
+        de_esser: DeEsserSettings { enabled: true, ..Default::default() },
+        phaser: PhaserSettings { enabled: true, ..Default::default() },
+        crossfeed: CrossfeedSettings { enabled: true, ..Default::default() },
+        ..Default::default()
+    };
+    for (name, precision) in [("f32", Precision::F32), ("f64", Precision::F64)] {
+        let mut chain = DspChain::new(48000.0, 2);
+        chain.apply_settings(&settings);
+        chain.set_node_enabled(NodeId::Bass, true);
+        chain.set_precision(precision);
+        let mut block = input.clone();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                block.copy_from_slice(&input);
+                chain.process(black_box(&mut block));
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, biquad_bank, dsp_layout, precision);
+criterion_main!(benches);
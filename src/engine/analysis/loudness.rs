@@ -0,0 +1,297 @@
+use crate::engine::decoder::AudioDecoder;
+use crate::engine::dsp::limiter::TruePeak;
+use crate::engine::events::{EngineEvent, EventBus};
+use std::f64::consts::PI;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+// Loudness is measured over 400 ms blocks (short-term over 3 s ones), both stepped in
+// 100 ms sub-blocks
+const SUB_BLOCK_SECS: f64 = 0.1;
+const MOMENTARY_SUB_BLOCKS: usize = 4;
+const SHORT_TERM_SUB_BLOCKS: usize = 30;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+// Below the ungated mean, in LU: blocks quieter than this don't count
+const INTEGRATED_RELATIVE_GATE: f64 = -10.0;
+const RANGE_RELATIVE_GATE: f64 = -20.0;
+
+/// Loudness of a whole file per ITU-R BS.1770-4 and EBU Tech 3342.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessReport {
+    /// Gated programme loudness in LUFS. Negative infinity for silence or a file shorter
+    /// than one 400 ms block.
+    pub integrated_lufs: f64,
+    /// Loudness range in LU: the spread between the 10th and 95th percentile of the
+    /// gated 3 s short-term loudness.
+    pub loudness_range_lu: f64,
+    /// Highest level of the reconstructed waveform, 4x oversampled, in dBTP.
+    pub true_peak_dbtp: f64,
+    pub sample_peak_dbfs: f64,
+}
+
+/// A loudness analysis running on a background thread.
+pub struct LoudnessJob {
+    handle: Option<JoinHandle<Option<LoudnessReport>>>,
+    cancelled: Arc<AtomicBool>,
+    progress: Arc<AtomicU32>,
+}
+
+impl LoudnessJob {
+    pub fn spawn<D: AudioDecoder + Send + 'static>(mut decoder: D, events: EventBus) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let progress = Arc::new(AtomicU32::new(0.0f32.to_bits()));
+
+        let cancelled_worker = cancelled.clone();
+        let progress_worker = progress.clone();
+
+        let handle = thread::spawn(move || {
+            let channels = decoder.channels().max(1) as usize;
+            let total_frames = decoder
+                .duration()
+                .map(|d| d * decoder.sample_rate() as f64)
+                .filter(|f| *f > 0.0);
+            let mut meter = LoudnessMeter::new(decoder.sample_rate(), channels);
+            let mut frames_done = 0usize;
+            let mut last_reported = 0.0f32;
+
+            while let Some(samples) = decoder.decode_next() {
+                if cancelled_worker.load(Ordering::Relaxed) {
+                    return None;
+                }
+                meter.process(&samples);
+                frames_done += samples.len() / channels;
+
+                if let Some(total) = total_frames {
+                    let fraction = (frames_done as f64 / total).min(1.0) as f32;
+                    progress_worker.store(fraction.to_bits(), Ordering::Relaxed);
+                    if fraction - last_reported >= 0.01 {
+                        last_reported = fraction;
+                        events.emit(EngineEvent::LoudnessProgress(fraction));
+                    }
+                }
+            }
+
+            progress_worker.store(1.0f32.to_bits(), Ordering::Relaxed);
+            events.emit(EngineEvent::LoudnessProgress(1.0));
+            Some(meter.finish())
+        });
+
+        Self {
+            handle: Some(handle),
+            cancelled,
+            progress,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Fraction of the file scanned so far. Stays at 0 until the end for files whose
+    /// duration is unknown.
+    pub fn progress(&self) -> f32 {
+        f32::from_bits(self.progress.load(Ordering::Relaxed))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_none_or(|h| h.is_finished())
+    }
+
+    /// Blocks until the report is ready. Returns `None` if it was cancelled.
+    pub fn wait(mut self) -> Option<LoudnessReport> {
+        self.handle.take()?.join().ok().flatten()
+    }
+}
+
+impl Drop for LoudnessJob {
+    fn drop(&mut self) {
+        // Nobody can collect the result any more, so stop decoding early
+        if self.handle.is_some() {
+            self.cancel();
+        }
+    }
+}
+
+// One biquad section in f64, which the K-weighting's low corner needs at high rates
+struct Section {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Section {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+// The BS.1770 K-weighting filter, a high shelf modelling the head followed by the
+// RLB high-pass, with coefficients derived for any sample rate
+fn k_weighting(sample_rate: f64) -> [Section; 2] {
+    let shelf = {
+        let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / sample_rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        Section {
+            b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    };
+    let high_pass = {
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        Section {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    };
+    [shelf, high_pass]
+}
+
+// Weight of each channel's power. Assumes the usual 5.1 order, where the LFE doesn't
+// count and the surrounds weigh +1.5 dB
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels >= 6, channel) {
+        (true, 3) => 0.0,
+        (true, 4 | 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+fn to_lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+// Mean power of the blocks at or above `threshold` LUFS
+fn gated_mean(powers: &[f64], threshold: f64) -> Option<f64> {
+    let gated: Vec<f64> = powers.iter().copied().filter(|p| to_lufs(*p) >= threshold).collect();
+    (!gated.is_empty()).then(|| gated.iter().sum::<f64>() / gated.len() as f64)
+}
+
+struct LoudnessMeter {
+    channels: usize,
+    weights: Vec<f64>,
+    filters: Vec<[Section; 2]>,
+    true_peaks: Vec<TruePeak>,
+    sub_block_frames: usize,
+    // Weighted power summed over the current sub-block, and the frames in it so far
+    sub_block_sum: f64,
+    sub_block_len: usize,
+    // Mean power of every finished sub-block
+    sub_blocks: Vec<f64>,
+    sample_peak: f32,
+    true_peak: f32,
+}
+
+impl LoudnessMeter {
+    fn new(sample_rate: u32, channels: usize) -> Self {
+        let rate = sample_rate.max(1) as f64;
+        Self {
+            channels,
+            weights: (0..channels).map(|c| channel_weight(c, channels)).collect(),
+            filters: (0..channels).map(|_| k_weighting(rate)).collect(),
+            true_peaks: (0..channels).map(|_| TruePeak::new()).collect(),
+            sub_block_frames: ((rate * SUB_BLOCK_SECS).round() as usize).max(1),
+            sub_block_sum: 0.0,
+            sub_block_len: 0,
+            sub_blocks: Vec::new(),
+            sample_peak: 0.0,
+            true_peak: 0.0,
+        }
+    }
+
+    fn process(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            let mut power = 0.0;
+            for (c, sample) in frame.iter().enumerate() {
+                self.sample_peak = self.sample_peak.max(sample.abs());
+                self.true_peak = self.true_peak.max(self.true_peaks[c].push(*sample).1);
+                let [shelf, high_pass] = &mut self.filters[c];
+                let weighted = high_pass.process(shelf.process(*sample as f64));
+                power += self.weights[c] * weighted * weighted;
+            }
+            self.sub_block_sum += power;
+            self.sub_block_len += 1;
+            if self.sub_block_len == self.sub_block_frames {
+                self.sub_blocks.push(self.sub_block_sum / self.sub_block_len as f64);
+                self.sub_block_sum = 0.0;
+                self.sub_block_len = 0;
+            }
+        }
+    }
+
+    // Mean power of every full window of `len` sub-blocks, one per sub-block step
+    fn blocks(&self, len: usize) -> Vec<f64> {
+        self.sub_blocks
+            .windows(len)
+            .map(|window| window.iter().sum::<f64>() / len as f64)
+            .collect()
+    }
+
+    fn finish(mut self) -> LoudnessReport {
+        // Run the last samples through the interpolators so peaks near the end count
+        for true_peak in &mut self.true_peaks {
+            for _ in 0..TruePeak::DELAY {
+                self.true_peak = self.true_peak.max(true_peak.push(0.0).1);
+            }
+        }
+
+        let momentary = self.blocks(MOMENTARY_SUB_BLOCKS);
+        let integrated_lufs = gated_mean(&momentary, ABSOLUTE_GATE_LUFS)
+            .and_then(|mean| gated_mean(&momentary, to_lufs(mean) + INTEGRATED_RELATIVE_GATE))
+            .map_or(f64::NEG_INFINITY, to_lufs);
+
+        let short_term = self.blocks(SHORT_TERM_SUB_BLOCKS);
+        let loudness_range_lu = match gated_mean(&short_term, ABSOLUTE_GATE_LUFS) {
+            Some(mean) => {
+                let threshold = (to_lufs(mean) + RANGE_RELATIVE_GATE).max(ABSOLUTE_GATE_LUFS);
+                let mut gated: Vec<f64> = short_term
+                    .iter()
+                    .map(|p| to_lufs(*p))
+                    .filter(|l| *l >= threshold)
+                    .collect();
+                gated.sort_by(f64::total_cmp);
+                let percentile = |p: f64| gated[((gated.len() - 1) as f64 * p).round() as usize];
+                percentile(0.95) - percentile(0.10)
+            }
+            None => 0.0,
+        };
+
+        LoudnessReport {
+            integrated_lufs,
+            loudness_range_lu,
+            true_peak_dbtp: 20.0 * (self.true_peak as f64).log10(),
+            sample_peak_dbfs: 20.0 * (self.sample_peak as f64).log10(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::signal_generator::{Signal, SignalGenerator};
+
+    #[test]
+    fn reference_tone_measures_minus_23_lufs() {
+        // EBU Tech 3341's calibration signal: 1 kHz at -23 dBFS on both channels
+        let amplitude = 10.0f32.powf(-23.0 / 20.0);
+        let signal = Signal::Sine { frequency: 1000.0, amplitude };
+        for sample_rate in [44100, 48000] {
+            let generator = SignalGenerator::new(signal, sample_rate, 2, 20.0);
+            let report = LoudnessJob::spawn(generator, EventBus::new()).wait().unwrap();
+            assert!((report.integrated_lufs + 23.0).abs() < 0.1, "{report:?}");
+            assert!(report.loudness_range_lu.abs() < 0.1, "{report:?}");
+            assert!((report.sample_peak_dbfs + 23.0).abs() < 0.1, "{report:?}");
+            assert!((report.true_peak_dbtp + 23.0).abs() < 0.2, "{report:?}");
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::time::Instant;
+
+// Level reported for a channel with no signal at all
+const FLOOR_DB: f32 = -120.0;
+
+/// Attack/release smoothing for meter readings in dB. Each reading moves toward the new
+/// value with a one-pole response, using the attack time while rising and the release
+/// time while falling, so a meter polled at any rate rises and decays at the same speed.
+/// A time of zero follows that direction instantly, so an attack of zero gives a
+/// peak meter with a decaying fall.
+#[derive(Debug, Clone, Default)]
+pub struct Ballistics {
+    attack_secs: f32,
+    release_secs: f32,
+    values: Vec<f32>,
+    last: Option<Instant>,
+}
+
+impl Ballistics {
+    /// Keeps the current readings, so a change takes effect smoothly.
+    pub fn set_times(&mut self, attack_ms: f32, release_ms: f32) {
+        self.attack_secs = attack_ms.max(0.0) / 1000.0;
+        self.release_secs = release_ms.max(0.0) / 1000.0;
+    }
+
+    /// Replaces each value in `readings` with its smoothed counterpart. A change in the
+    /// number of readings starts over from the raw values.
+    pub fn apply(&mut self, readings: &mut [f32]) {
+        let now = Instant::now();
+        let elapsed = self.last.map_or(0.0, |last| now.duration_since(last).as_secs_f32());
+        self.last = Some(now);
+
+        if self.values.len() != readings.len() {
+            self.values = readings.to_vec();
+            return;
+        }
+
+        for (value, reading) in self.values.iter_mut().zip(readings.iter_mut()) {
+            let time = if *reading > *value { self.attack_secs } else { self.release_secs };
+            if time <= 0.0 {
+                *value = *reading;
+            } else {
+                let coeff = (-elapsed / time).exp();
+                *value = *reading + (*value - *reading) * coeff;
+            }
+            *reading = *value;
+        }
+    }
+}
+
+/// Whether the level meter reads each output channel or their mono sum. Only the
+/// measurement changes; what's played is left as it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MeterChannelMode {
+    /// One level, for the average of all channels, the same mono sum the spectrum is
+    /// taken from.
+    Summed,
+    /// A level per channel.
+    #[default]
+    PerChannel,
+}
+
+/// Peak level of each channel of interleaved `samples` in dB, floored at -120.
+pub fn peak_levels_db(samples: &[f32], channels: usize) -> Vec<f32> {
+    let channels = channels.max(1);
+    let mut peaks = vec![0.0f32; channels];
+    for frame in samples.chunks_exact(channels) {
+        for (peak, sample) in peaks.iter_mut().zip(frame) {
+            *peak = peak.max(sample.abs());
+        }
+    }
+    peaks.into_iter().map(peak_db).collect()
+}
+
+/// Peak level of the average of all channels of interleaved `samples` in dB, floored at
+/// -120. Channels in opposite polarity cancel in it.
+pub fn summed_peak_level_db(samples: &[f32], channels: usize) -> f32 {
+    let channels = channels.max(1);
+    let peak = samples
+        .chunks_exact(channels)
+        .map(|frame| (frame.iter().sum::<f32>() / channels as f32).abs())
+        .fold(0.0, f32::max);
+    peak_db(peak)
+}
+
+fn peak_db(peak: f32) -> f32 {
+    if peak > 0.0 { (20.0 * peak.log10()).max(FLOOR_DB) } else { FLOOR_DB }
+}
+
+/// How the front pair of interleaved `samples` holds up when summed to mono.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MonoCompatibility {
+    /// Correlation coefficient between left and right, `-1.0..=1.0`: `1.0` for identical
+    /// channels, around `0.0` for unrelated ones, negative when they partly cancel.
+    /// `0.0` while either channel is silent.
+    pub correlation: f32,
+    /// Level of the mono sum `(L + R) / 2` relative to the average level of the two
+    /// channels, in dB: `0.0` for identical channels, about -3 for unrelated ones, and
+    /// down to -120 as they cancel out.
+    pub mono_loss_db: f32,
+}
+
+/// Measures the first two channels. Anything with fewer is mono already and reports
+/// full compatibility.
+pub fn mono_compatibility(samples: &[f32], channels: usize) -> MonoCompatibility {
+    if channels < 2 {
+        return MonoCompatibility { correlation: 1.0, mono_loss_db: 0.0 };
+    }
+
+    let (mut lr, mut ll, mut rr, mut mm) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+    for frame in samples.chunks_exact(channels) {
+        let (l, r) = (frame[0] as f64, frame[1] as f64);
+        lr += l * r;
+        ll += l * l;
+        rr += r * r;
+        let m = (l + r) / 2.0;
+        mm += m * m;
+    }
+
+    let correlation = if ll > 0.0 && rr > 0.0 {
+        (lr / (ll * rr).sqrt()).clamp(-1.0, 1.0) as f32
+    } else {
+        0.0
+    };
+    let reference = (ll + rr) / 2.0;
+    let mono_loss_db = if reference > 0.0 && mm > 0.0 {
+        ((10.0 * (mm / reference).log10()) as f32).max(FLOOR_DB)
+    } else if reference > 0.0 {
+        FLOOR_DB
+    } else {
+        0.0
+    };
+    MonoCompatibility { correlation, mono_loss_db }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::decoder::AudioDecoder;
+    use crate::test_util::signal_generator::{Signal, SignalGenerator};
+    use std::time::Duration;
+
+    // A second of stereo noise, with the right channel rebuilt from each frame by `right`
+    fn stereo(right: impl Fn(&[f32]) -> f32) -> Vec<f32> {
+        let mut generator = SignalGenerator::new(Signal::WhiteNoise { amplitude: 0.5 }, 48000, 2, 1.0);
+        let mut samples = Vec::new();
+        while let Some(block) = generator.decode_next() {
+            samples.extend(block);
+        }
+        for frame in samples.chunks_exact_mut(2) {
+            frame[1] = right(frame);
+        }
+        samples
+    }
+
+    #[test]
+    fn correlation_of_in_phase_unrelated_and_inverted_channels() {
+        let same = mono_compatibility(&stereo(|frame| frame[0]), 2);
+        assert!((same.correlation - 1.0).abs() < 1e-4, "{same:?}");
+        assert!(same.mono_loss_db.abs() < 0.01, "{same:?}");
+
+        // The generator's channels are independent noise
+        let unrelated = mono_compatibility(&stereo(|frame| frame[1]), 2);
+        assert!(unrelated.correlation.abs() < 0.05, "{unrelated:?}");
+        assert!((unrelated.mono_loss_db + 3.0).abs() < 0.3, "{unrelated:?}");
+
+        let inverted = mono_compatibility(&stereo(|frame| -frame[0]), 2);
+        assert!((inverted.correlation + 1.0).abs() < 1e-4, "{inverted:?}");
+        assert_eq!(inverted.mono_loss_db, FLOOR_DB);
+    }
+
+    #[test]
+    fn sudden_silence_decays_over_the_release_time() {
+        let mut ballistics = Ballistics::default();
+        ballistics.set_times(0.0, 300.0);
+        ballistics.apply(&mut [0.0]);
+
+        let silence_from = Instant::now();
+        let mut readings = Vec::new();
+        while silence_from.elapsed() < Duration::from_secs(1) {
+            std::thread::sleep(Duration::from_millis(20));
+            let mut reading = [FLOOR_DB];
+            ballistics.apply(&mut reading);
+            readings.push((silence_from.elapsed().as_secs_f32(), reading[0]));
+        }
+
+        // A one-pole fall from 0 dB toward the floor, a little over 60% of the way
+        // after one release time
+        assert!(readings[0].1 > -20.0, "{readings:?}");
+        for &(secs, db) in &readings {
+            let expected = FLOOR_DB * (1.0 - (-secs / 0.3).exp());
+            assert!((db - expected).abs() < 10.0, "{secs} s: {db} dB, expected {expected}");
+        }
+
+        // With no attack time the rise is instant
+        let mut reading = [-6.0];
+        ballistics.apply(&mut reading);
+        assert_eq!(reading[0], -6.0);
+    }
+}
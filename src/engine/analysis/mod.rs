@@ -0,0 +1,4 @@
+pub mod loudness;
+pub mod meter;
+pub mod spectrum;
+pub mod waveform;
@@ -0,0 +1,145 @@
+use realfft::num_complex::Complex;
+use realfft::{RealFftPlanner, RealToComplex};
+use std::sync::Arc;
+
+pub const DEFAULT_FFT_SIZE: usize = 2048;
+// Level reported for bands with no energy at all
+const FLOOR_DB: f32 = -120.0;
+
+/// Magnitude spectrum of the most recent audio, mixed to mono and Hann-windowed.
+pub struct SpectrumAnalyzer {
+    fft: Arc<dyn RealToComplex<f32>>,
+    window: Vec<f32>,
+    input: Vec<f32>,
+    output: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    // Power per bin, scaled so a full-scale sine peaks at 1.0
+    power: Vec<f32>,
+    sample_rate: f32,
+    min_hz: f32,
+    max_hz: f32,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(fft_size: usize, sample_rate: f32) -> Self {
+        let fft_size = fft_size.max(2);
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(fft_size);
+        let window: Vec<f32> = (0..fft_size)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / fft_size as f32).cos())
+            .collect();
+
+        Self {
+            input: fft.make_input_vec(),
+            output: fft.make_output_vec(),
+            scratch: fft.make_scratch_vec(),
+            power: vec![0.0; fft_size / 2 + 1],
+            fft,
+            window,
+            sample_rate,
+            min_hz: 20.0,
+            max_hz: 20000.0,
+        }
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.window.len()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Frequency span `bands` spreads its bands over. The top is capped at Nyquist.
+    pub fn set_frequency_range(&mut self, min_hz: f32, max_hz: f32) {
+        self.min_hz = min_hz.max(1.0);
+        self.max_hz = max_hz.max(self.min_hz * 1.01);
+    }
+
+    /// Analyzes the last `fft_size` frames of interleaved `samples`, zero-padding in front
+    /// if there are fewer.
+    pub fn analyze(&mut self, samples: &[f32], channels: usize) {
+        let channels = channels.max(1);
+        let frames = samples.len() / channels;
+        let size = self.fft_size();
+        let used = frames.min(size);
+        let start = (frames - used) * channels;
+
+        self.input[..size - used].fill(0.0);
+        for (i, frame) in samples[start..].chunks_exact(channels).enumerate() {
+            let mono = frame.iter().sum::<f32>() / channels as f32;
+            let slot = size - used + i;
+            self.input[slot] = mono * self.window[slot];
+        }
+
+        if self.fft.process_with_scratch(&mut self.input, &mut self.output, &mut self.scratch).is_err() {
+            return;
+        }
+        // A Hann window halves the amplitude, and a real sine splits over two bins
+        let scale = 2.0 / self.window.iter().sum::<f32>();
+        for (power, bin) in self.power.iter_mut().zip(&self.output) {
+            *power = (bin.norm() * scale).powi(2);
+        }
+    }
+
+    /// Level of each FFT bin in dB, from DC up to Nyquist.
+    pub fn bins_db(&self) -> Vec<f32> {
+        self.power.iter().map(|&p| power_to_db(p)).collect()
+    }
+
+    /// Groups the bins into `band_count` logarithmically spaced bands between the
+    /// frequency range's ends and returns each band's average energy in dB. Bands
+    /// narrower than a bin take the bin nearest their center.
+    pub fn bands(&self, band_count: usize) -> Vec<f32> {
+        let bin_hz = self.sample_rate / self.fft_size() as f32;
+        let nyquist = self.sample_rate / 2.0;
+        let min_hz = self.min_hz.min(nyquist * 0.5);
+        let max_hz = self.max_hz.min(nyquist);
+        let ratio = max_hz / min_hz;
+        let last_bin = self.power.len() - 1;
+
+        (0..band_count)
+            .map(|band| {
+                let lo = min_hz * ratio.powf(band as f32 / band_count as f32);
+                let hi = min_hz * ratio.powf((band + 1) as f32 / band_count as f32);
+                let first = (lo / bin_hz).ceil() as usize;
+                let last = ((hi / bin_hz).ceil() as usize).min(last_bin + 1);
+                let power = if first < last {
+                    self.power[first..last].iter().sum::<f32>() / (last - first) as f32
+                } else {
+                    let center = (lo * hi).sqrt() / bin_hz;
+                    self.power[(center.round() as usize).min(last_bin)]
+                };
+                power_to_db(power)
+            })
+            .collect()
+    }
+}
+
+fn power_to_db(power: f32) -> f32 {
+    if power > 0.0 {
+        (10.0 * power.log10()).max(FLOOR_DB)
+    } else {
+        FLOOR_DB
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tone_lights_up_its_band() {
+        let mut analyzer = SpectrumAnalyzer::new(DEFAULT_FFT_SIZE, 48000.0);
+        let step = std::f32::consts::TAU * 1200.0 / 48000.0;
+        let tone: Vec<f32> = (0..4800).map(|n| 0.5 * (step * n as f32).sin()).collect();
+        analyzer.analyze(&tone, 1);
+
+        // 16 bands from 20 Hz to 20 kHz: 1.2 kHz sits in the middle of band 9, 973 Hz to
+        // 1.5 kHz. A half-scale sine is -6 dB, spread over the band's bins
+        let bands = analyzer.bands(16);
+        assert!(bands[9] > -20.0, "{bands:?}");
+        for (band, &level) in bands.iter().enumerate().filter(|&(band, _)| band != 9) {
+            assert!(level < -80.0, "band {band}: {bands:?}");
+        }
+    }
+}
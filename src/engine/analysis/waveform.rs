@@ -0,0 +1,163 @@
+use crate::engine::decoder::AudioDecoder;
+use crate::engine::events::{EngineEvent, EventBus};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+// Frames folded into each intermediate peak before the final reduction to the bucket count
+const FINE_BUCKET_FRAMES: usize = 256;
+
+/// `(min, max)` sample value per bucket, both in `-1.0..=1.0`.
+pub type Waveform = Vec<(f32, f32)>;
+
+/// A waveform overview being computed on a background thread.
+pub struct WaveformJob {
+    handle: Option<JoinHandle<Option<Waveform>>>,
+    cancelled: Arc<AtomicBool>,
+    progress: Arc<AtomicU32>,
+}
+
+impl WaveformJob {
+    pub fn spawn<D: AudioDecoder + Send + 'static>(
+        mut decoder: D,
+        buckets: usize,
+        events: EventBus,
+    ) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let progress = Arc::new(AtomicU32::new(0.0f32.to_bits()));
+
+        let cancelled_worker = cancelled.clone();
+        let progress_worker = progress.clone();
+
+        let handle = thread::spawn(move || {
+            let channels = decoder.channels().max(1) as usize;
+            let total_frames = decoder
+                .duration()
+                .map(|d| d * decoder.sample_rate() as f64)
+                .filter(|f| *f > 0.0);
+
+            let mut peaks: Waveform = Vec::new();
+            let mut current = (f32::MAX, f32::MIN);
+            let mut current_frames = 0;
+            let mut frames_done = 0usize;
+            let mut last_reported = 0.0f32;
+
+            while let Some(samples) = decoder.decode_next() {
+                if cancelled_worker.load(Ordering::Relaxed) {
+                    return None;
+                }
+
+                for frame in samples.chunks_exact(channels) {
+                    // Overview is mono: the mean of all channels
+                    let mono = frame.iter().sum::<f32>() / channels as f32;
+                    current.0 = current.0.min(mono);
+                    current.1 = current.1.max(mono);
+                    current_frames += 1;
+                    if current_frames == FINE_BUCKET_FRAMES {
+                        peaks.push(current);
+                        current = (f32::MAX, f32::MIN);
+                        current_frames = 0;
+                    }
+                }
+                frames_done += samples.len() / channels;
+
+                if let Some(total) = total_frames {
+                    let fraction = (frames_done as f64 / total).min(1.0) as f32;
+                    progress_worker.store(fraction.to_bits(), Ordering::Relaxed);
+                    if fraction - last_reported >= 0.01 {
+                        last_reported = fraction;
+                        events.emit(EngineEvent::WaveformProgress(fraction));
+                    }
+                }
+            }
+            if current_frames > 0 {
+                peaks.push(current);
+            }
+
+            progress_worker.store(1.0f32.to_bits(), Ordering::Relaxed);
+            events.emit(EngineEvent::WaveformProgress(1.0));
+            Some(reduce_peaks(&peaks, buckets))
+        });
+
+        Self {
+            handle: Some(handle),
+            cancelled,
+            progress,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Fraction of the file scanned so far. Stays at 0 until the end for files whose
+    /// duration is unknown.
+    pub fn progress(&self) -> f32 {
+        f32::from_bits(self.progress.load(Ordering::Relaxed))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_none_or(|h| h.is_finished())
+    }
+
+    /// Blocks until the overview is ready. Returns `None` if it was cancelled.
+    pub fn wait(mut self) -> Option<Waveform> {
+        self.handle.take()?.join().ok().flatten()
+    }
+}
+
+impl Drop for WaveformJob {
+    fn drop(&mut self) {
+        // Nobody can collect the result any more, so stop decoding early
+        if self.handle.is_some() {
+            self.cancel();
+        }
+    }
+}
+
+fn reduce_peaks(peaks: &[(f32, f32)], buckets: usize) -> Waveform {
+    if buckets == 0 {
+        return Vec::new();
+    }
+
+    (0..buckets)
+        .map(|b| {
+            let start = b * peaks.len() / buckets;
+            let end = ((b + 1) * peaks.len() / buckets).max(start + 1).min(peaks.len());
+            let (min, max) = peaks
+                .get(start..end)
+                .unwrap_or(&[])
+                .iter()
+                .fold((f32::MAX, f32::MIN), |acc, p| (acc.0.min(p.0), acc.1.max(p.1)));
+            if min > max {
+                // Fewer peaks than buckets, or an empty file
+                (0.0, 0.0)
+            } else {
+                (min.clamp(-1.0, 1.0), max.clamp(-1.0, 1.0))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::signal_generator::{Signal, SignalGenerator};
+
+    #[test]
+    fn has_the_requested_buckets_within_full_scale() {
+        // Noise well past full scale, so the clamp is exercised as well as the count
+        let signal = Signal::WhiteNoise { amplitude: 1.5 };
+        for buckets in [1, 100, 1000, 5000] {
+            let generator = SignalGenerator::new(signal, 44100, 2, 2.0);
+            let waveform = WaveformJob::spawn(generator, buckets, EventBus::new()).wait().unwrap();
+
+            assert_eq!(waveform.len(), buckets);
+            for &(min, max) in &waveform {
+                assert!((-1.0..=1.0).contains(&min), "min {min} out of range");
+                assert!((-1.0..=1.0).contains(&max), "max {max} out of range");
+                assert!(min <= max);
+            }
+        }
+    }
+}
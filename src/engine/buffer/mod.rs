@@ -1,58 +1,364 @@
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 use ringbuf::{traits::{Consumer, Producer, Split, Observer}, HeapRb, CachingProd, CachingCons};
 
+// Speed changes the consumer can fall behind by before the oldest are dropped
+const SPEED_MARKS: usize = 32;
+
 pub struct AudioBuffer {}
 
 pub struct AudioBufferProducer {
     inner: CachingProd<Arc<HeapRb<f32>>>,
+    space: Arc<SpaceSignal>,
+    frames: usize,
+    channels: Arc<AtomicUsize>,
+    marks: Arc<SpeedMarks>,
+    // Samples pushed since the buffer was created
+    pushed: u64,
 }
 
 pub struct AudioBufferConsumer {
     inner: CachingCons<Arc<HeapRb<f32>>>,
+    space: Arc<SpaceSignal>,
+    frames: usize,
+    channels: Arc<AtomicUsize>,
+    marks: Arc<SpeedMarks>,
+    // Samples popped or cleared since the buffer was created
+    popped: u64,
+    // Speed the last popped sample was rendered at, and the next mark to look at
+    speed: f32,
+    next_mark: u64,
+}
+
+// Playback speeds the producer rendered at, each from the sample count it starts at. A
+// ring the producer writes and the consumer reads without locking
+struct SpeedMarks {
+    written: AtomicU64,
+    starts: [AtomicU64; SPEED_MARKS],
+    speeds: [AtomicU32; SPEED_MARKS],
+}
+
+impl Default for SpeedMarks {
+    fn default() -> Self {
+        SpeedMarks {
+            written: AtomicU64::new(0),
+            starts: std::array::from_fn(|_| AtomicU64::new(0)),
+            speeds: std::array::from_fn(|_| AtomicU32::new(1.0f32.to_bits())),
+        }
+    }
+}
+
+// Lets a producer sleep until the consumer has drained enough room
+#[derive(Default)]
+struct SpaceSignal {
+    // Free samples the waiting producer needs, 0 while nobody is waiting
+    wanted: AtomicUsize,
+    lock: Mutex<()>,
+    cvar: Condvar,
+}
+
+impl SpaceSignal {
+    // Called from the audio callback, so it never blocks on the lock
+    fn notify(&self, vacant: usize) {
+        let wanted = self.wanted.load(Ordering::Acquire);
+        if wanted == 0 || vacant < wanted {
+            return;
+        }
+        // Holding the lock guarantees the producer is either before its check or already
+        // waiting. If it's mid-check the wake can be missed, which its timeout bounds.
+        let _guard = self.lock.try_lock();
+        self.cvar.notify_all();
+    }
 }
 
 impl AudioBufferProducer {
     pub fn push(&mut self, sample: f32) -> Result<(), f32> {
-        self.inner.try_push(sample)
+        if self.vacant_len() == 0 {
+            return Err(sample);
+        }
+        self.inner.try_push(sample)?;
+        self.pushed += 1;
+        Ok(())
     }
 
     pub fn push_slice(&mut self, samples: &[f32]) -> usize {
-        self.inner.push_slice(samples)
+        let count = samples.len().min(self.vacant_len());
+        let pushed = self.inner.push_slice(&samples[..count]);
+        self.pushed += pushed as u64;
+        pushed
+    }
+
+    /// Records that audio is rendered at `speed` from `after` samples past what's been
+    /// pushed so far, the ones still waiting to go in having been rendered at the old
+    /// speed. The consumer picks it up as it reaches that sample; see
+    /// `AudioBufferConsumer::speed`.
+    pub fn mark_speed(&mut self, speed: f32, after: usize) {
+        let marks = &self.marks;
+        let index = marks.written.load(Ordering::Relaxed);
+        let slot = index as usize % SPEED_MARKS;
+        marks.starts[slot].store(self.pushed + after as u64, Ordering::Relaxed);
+        marks.speeds[slot].store(speed.to_bits(), Ordering::Relaxed);
+        marks.written.store(index + 1, Ordering::Release);
     }
 
     pub fn vacant_len(&self) -> usize {
-        self.inner.vacant_len()
+        self.capacity().saturating_sub(self.inner.occupied_len())
+    }
+
+    pub fn occupied_len(&self) -> usize {
+        self.inner.occupied_len()
+    }
+
+    /// Usable size in samples: the frame count times the current channel count.
+    pub fn capacity(&self) -> usize {
+        capacity(self.frames, &self.channels, &self.inner)
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Whether the storage is sized for the current channel count. Once an output sets
+    /// another, the buffer holds more or fewer frames than it was made for until it's
+    /// rebuilt.
+    pub fn fits_channels(&self) -> bool {
+        self.inner.capacity().get() == self.frames * self.channels.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until at least `min` samples are free or `timeout` passes, and returns
+    /// whether the space is there. `min` is capped at the capacity.
+    pub fn wait_for_space(&self, min: usize, timeout: Duration) -> bool {
+        let min = min.clamp(1, self.capacity());
+        if self.vacant_len() >= min {
+            return true;
+        }
+
+        let deadline = Instant::now() + timeout;
+        let Ok(mut guard) = self.space.lock.lock() else {
+            return self.vacant_len() >= min;
+        };
+        self.space.wanted.store(min, Ordering::Release);
+        while self.vacant_len() < min {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            guard = match self.space.cvar.wait_timeout(guard, deadline - now) {
+                Ok((guard, _)) => guard,
+                Err(_) => break,
+            };
+        }
+        self.space.wanted.store(0, Ordering::Release);
+        self.vacant_len() >= min
     }
 
     pub fn clear(&mut self) {}
 }
 
 impl AudioBufferConsumer {
+    /// Doesn't wake a producer waiting in `wait_for_space`; call `notify_space` after a
+    /// run of pops.
     pub fn pop(&mut self) -> Option<f32> {
-        self.inner.try_pop()
+        let sample = self.inner.try_pop()?;
+        self.advance(1);
+        Some(sample)
     }
 
     pub fn pop_slice(&mut self, samples: &mut [f32]) -> usize {
-        self.inner.pop_slice(samples)
+        let count = self.inner.pop_slice(samples);
+        self.advance(count);
+        self.notify_space();
+        count
+    }
+
+    /// Playback speed the most recently popped sample was rendered at, as marked by the
+    /// producer with `mark_speed`. `1.0` until anything is marked.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    fn advance(&mut self, count: usize) {
+        self.popped += count as u64;
+        let marks = &self.marks;
+        let written = marks.written.load(Ordering::Acquire);
+        self.next_mark = self.next_mark.max(written.saturating_sub(SPEED_MARKS as u64));
+        while self.next_mark < written {
+            let slot = self.next_mark as usize % SPEED_MARKS;
+            if marks.starts[slot].load(Ordering::Relaxed) >= self.popped {
+                break;
+            }
+            self.speed = f32::from_bits(marks.speeds[slot].load(Ordering::Relaxed));
+            self.next_mark += 1;
+        }
+    }
+
+    /// Wakes a producer waiting in `wait_for_space` if enough room has drained. Never
+    /// blocks, so it's safe to call from the audio callback.
+    pub fn notify_space(&self) {
+        self.space.notify(self.capacity().saturating_sub(self.occupied_len()));
     }
 
     pub fn occupied_len(&self) -> usize {
         self.inner.occupied_len()
     }
 
+    /// Usable size in samples: the frame count times the current channel count.
+    pub fn capacity(&self) -> usize {
+        capacity(self.frames, &self.channels, &self.inner)
+    }
+
+    /// Size in frames, which stays the same whatever the channel count.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels.load(Ordering::Relaxed)
+    }
+
+    /// Sets how many interleaved channels the buffer carries. Outputs call this once they
+    /// know the device's channel count. The storage stays as it is, so with more channels
+    /// than it was made for it holds fewer frames; see `AudioBufferProducer::fits_channels`.
+    /// Samples already queued are kept, even past a smaller new capacity.
+    pub fn set_channels(&self, channels: usize) {
+        self.channels.store(channels.max(1), Ordering::Relaxed);
+        self.notify_space();
+    }
+
     pub fn clear(&mut self) {
-        while self.pop().is_some() {}
+        let mut cleared = 0;
+        while self.inner.try_pop().is_some() {
+            cleared += 1;
+        }
+        self.advance(cleared);
+        self.notify_space();
     }
 
     pub fn empty() -> Self {
         let rb = HeapRb::<f32>::new(1);
         let (_, cons) = rb.split();
-        AudioBufferConsumer { inner: cons }
+        AudioBufferConsumer {
+            inner: cons,
+            space: Arc::default(),
+            frames: 1,
+            channels: Arc::new(AtomicUsize::new(1)),
+            marks: Arc::default(),
+            popped: 0,
+            speed: 1.0,
+            next_mark: 0,
+        }
     }
 }
 
-pub fn create_audio_buffer(capacity: usize) -> (AudioBufferProducer, AudioBufferConsumer) {
-    let rb = HeapRb::<f32>::new(capacity);
+fn capacity<R: Observer>(frames: usize, channels: &AtomicUsize, inner: &R) -> usize {
+    (frames * channels.load(Ordering::Relaxed)).min(inner.capacity().get())
+}
+
+/// Creates a buffer holding `frames` frames of `channels` interleaved channels. The channel
+/// count can change later through `AudioBufferConsumer::set_channels`.
+pub fn create_audio_buffer(frames: usize, channels: usize) -> (AudioBufferProducer, AudioBufferConsumer) {
+    let frames = frames.max(1);
+    let channels = channels.max(1);
+    let rb = HeapRb::<f32>::new(frames * channels);
     let (prod, cons) = rb.split();
-    (AudioBufferProducer { inner: prod }, AudioBufferConsumer { inner: cons })
-}
\ No newline at end of file
+    let space = Arc::new(SpaceSignal::default());
+    let channels = Arc::new(AtomicUsize::new(channels));
+    let marks = Arc::new(SpeedMarks::default());
+    (
+        AudioBufferProducer {
+            inner: prod,
+            space: space.clone(),
+            frames,
+            channels: channels.clone(),
+            marks: marks.clone(),
+            pushed: 0,
+        },
+        AudioBufferConsumer {
+            inner: cons,
+            space,
+            frames,
+            channels,
+            marks,
+            popped: 0,
+            speed: 1.0,
+            next_mark: 0,
+        },
+    )
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Seconds of audio the buffer takes before it's full
+    fn held_secs(producer: &mut AudioBufferProducer, channels: usize, sample_rate: usize) -> f64 {
+        let pushed = producer.push_slice(&vec![0.0; 10 * sample_rate * channels]);
+        pushed as f64 / (channels * sample_rate) as f64
+    }
+
+    #[test]
+    fn producer_wakes_when_the_consumer_drains() {
+        let (mut producer, mut consumer) = create_audio_buffer(4800, 2);
+        producer.push_slice(&vec![0.0; producer.capacity()]);
+        let quarter = producer.capacity() / 4;
+
+        // Nothing drains, so it waits out the timeout
+        let started = Instant::now();
+        assert!(!producer.wait_for_space(quarter, Duration::from_millis(50)));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        // Woken as soon as the consumer makes room, long before the timeout
+        let drain = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            consumer.pop_slice(&mut vec![0.0; quarter * 2]);
+            consumer
+        });
+        let started = Instant::now();
+        assert!(producer.wait_for_space(quarter, Duration::from_secs(5)));
+        let waited = started.elapsed();
+        assert!(waited >= Duration::from_millis(90) && waited < Duration::from_secs(1), "{waited:?}");
+        drain.join().unwrap();
+    }
+
+    #[test]
+    fn holds_the_same_duration_for_any_channel_count() {
+        for channels in [1, 2, 6] {
+            let (mut producer, _consumer) = create_audio_buffer(48000, channels);
+            assert_eq!(held_secs(&mut producer, channels, 48000), 1.0, "{channels} channels");
+        }
+    }
+
+    #[test]
+    fn speed_changes_with_the_marked_sample() {
+        let (mut producer, mut consumer) = create_audio_buffer(100, 1);
+        producer.push_slice(&[0.0; 10]);
+        // Four more samples are still on their way at the old speed
+        producer.mark_speed(2.0, 4);
+        producer.push_slice(&[0.0; 20]);
+
+        let mut read = [0.0; 14];
+        consumer.pop_slice(&mut read);
+        assert_eq!(consumer.speed(), 1.0);
+        consumer.pop().unwrap();
+        assert_eq!(consumer.speed(), 2.0);
+
+        producer.mark_speed(0.5, 0);
+        consumer.clear();
+        assert_eq!(consumer.speed(), 2.0);
+        producer.push(0.0).unwrap();
+        consumer.pop().unwrap();
+        assert_eq!(consumer.speed(), 0.5);
+    }
+
+    #[test]
+    fn storage_is_sized_for_the_channels_it_was_made_for() {
+        for channels in [1, 2, 6] {
+            let (mut producer, consumer) = create_audio_buffer(48000, 2);
+            consumer.set_channels(channels);
+            assert_eq!(producer.fits_channels(), channels == 2, "{channels} channels");
+            // Fewer channels leave it holding the same duration, more a shorter one
+            let expected = 2.0f64.min(channels as f64) / channels as f64;
+            assert_eq!(held_secs(&mut producer, channels, 48000), expected, "{channels} channels");
+        }
+    }
+}
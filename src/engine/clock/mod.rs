@@ -1,4 +1,11 @@
-use std::sync::atomic::{AtomicU64, AtomicU8, AtomicBool, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, AtomicBool, Ordering};
+use std::time::Instant;
+use crate::engine::output::dropouts::DropoutLog;
+use crate::engine::output::tap::OutputTap;
+
+// Bits of `Clock::configured`
+const RATE_SET: u8 = 1;
+const CHANNELS_SET: u8 = 2;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -6,6 +13,9 @@ pub enum PlaybackState {
     Stopped = 0,
     Playing = 1,
     Paused = 2,
+    /// Reported by the engine while playing with a starved buffer. Never stored in the
+    /// clock, so the output keeps draining whatever arrives.
+    Buffering = 3,
 }
 
 impl From<u8> for PlaybackState {
@@ -13,18 +23,100 @@ impl From<u8> for PlaybackState {
         match value {
             1 => PlaybackState::Playing,
             2 => PlaybackState::Paused,
+            3 => PlaybackState::Buffering,
             _ => PlaybackState::Stopped,
         }
     }
 }
 
+/// What the output emits while paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum PauseBehavior {
+    /// Pause the device stream; anything it still asks for is silence.
+    #[default]
+    Silence = 0,
+    /// Keep the stream running and repeat the last frame, fading it to silence so a held
+    /// sample doesn't turn into a stuck tone. For integrations driving a virtual device.
+    HoldLast = 1,
+}
+
+impl From<u8> for PauseBehavior {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => PauseBehavior::HoldLast,
+            _ => PauseBehavior::Silence,
+        }
+    }
+}
+
+/// What happens to the audio already buffered when the engine seeks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum SeekBehavior {
+    /// Drop it and start the new position from silence.
+    #[default]
+    Flush = 0,
+    /// Keep playing a short tail of it until the new position's audio arrives, then
+    /// crossfade into that, so scrubbing doesn't gap or click.
+    Smooth = 1,
+}
+
+impl From<u8> for SeekBehavior {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => SeekBehavior::Smooth,
+            _ => SeekBehavior::Flush,
+        }
+    }
+}
+
 pub struct Clock {
     sample_pos: AtomicU64,
     sample_rate: AtomicU64,
-    channels: AtomicU8,
+    // Rate the device plays at, 0 while it's the same as `sample_rate`
+    device_sample_rate: AtomicU64,
+    channels: AtomicU32,
+    // Which of the device format fields an output has set, see `is_configured`
+    configured: AtomicU8,
     state: AtomicU8,
     clear_buffer: AtomicBool,
     eos: AtomicBool,
+    buffered_samples: AtomicU64,
+    buffer_capacity: AtomicU64,
+    buffering: AtomicBool,
+    volume: AtomicU32,
+    // f32 bits, in dB
+    output_gain_db: AtomicU32,
+    // f32 bits: source seconds played per second of output
+    playback_speed: AtomicU32,
+    speed_affects_pitch: AtomicBool,
+    underruns: AtomicU64,
+    dropouts: DropoutLog,
+    starved: AtomicBool,
+    decode_errors: AtomicU64,
+    fatal_errors: AtomicU64,
+    // Holding the output back until the buffer refills after a sustained underrun
+    auto_paused: AtomicBool,
+    clipped: AtomicBool,
+    limiter_reduction: AtomicU32,
+    // f32 bits: share of the time the decode thread spent working over its last window
+    decode_load: AtomicU32,
+    phase_inverted: AtomicBool,
+    pause_behavior: AtomicU8,
+    seek_behavior: AtomicU8,
+    // Frames to ask the device for per callback, 0 for its default
+    device_buffer_frames: AtomicU32,
+    latency_samples: AtomicU64,
+    // f64 bits: source time of the newest sample in the buffer (NaN while unknown), and
+    // the last measured drift
+    source_time: AtomicU64,
+    drift: AtomicU64,
+    output_tap: OutputTap,
+    epoch: Instant,
+    // Size of the last block the output played and when, in nanoseconds since `epoch`
+    last_block: AtomicU64,
+    last_block_at: AtomicU64,
 }
 
 impl Clock {
@@ -32,29 +124,129 @@ impl Clock {
         Self {
             sample_pos: AtomicU64::new(0),
             sample_rate: AtomicU64::new(sample_rate as u64),
-            channels: AtomicU8::new(2),
+            device_sample_rate: AtomicU64::new(0),
+            channels: AtomicU32::new(2),
+            configured: AtomicU8::new(0),
             state: AtomicU8::new(PlaybackState::Stopped as u8),
             clear_buffer: AtomicBool::new(false),
             eos: AtomicBool::new(false),
+            buffered_samples: AtomicU64::new(0),
+            buffer_capacity: AtomicU64::new(0),
+            buffering: AtomicBool::new(false),
+            volume: AtomicU32::new(1.0f32.to_bits()),
+            output_gain_db: AtomicU32::new(0.0f32.to_bits()),
+            playback_speed: AtomicU32::new(1.0f32.to_bits()),
+            speed_affects_pitch: AtomicBool::new(true),
+            underruns: AtomicU64::new(0),
+            dropouts: DropoutLog::default(),
+            starved: AtomicBool::new(true),
+            decode_errors: AtomicU64::new(0),
+            fatal_errors: AtomicU64::new(0),
+            auto_paused: AtomicBool::new(false),
+            clipped: AtomicBool::new(false),
+            limiter_reduction: AtomicU32::new(0.0f32.to_bits()),
+            decode_load: AtomicU32::new(0.0f32.to_bits()),
+            phase_inverted: AtomicBool::new(false),
+            pause_behavior: AtomicU8::new(PauseBehavior::Silence as u8),
+            seek_behavior: AtomicU8::new(SeekBehavior::Flush as u8),
+            device_buffer_frames: AtomicU32::new(0),
+            latency_samples: AtomicU64::new(0),
+            source_time: AtomicU64::new(f64::NAN.to_bits()),
+            drift: AtomicU64::new(0.0f64.to_bits()),
+            output_tap: OutputTap::default(),
+            epoch: Instant::now(),
+            last_block: AtomicU64::new(0),
+            last_block_at: AtomicU64::new(0),
         }
     }
 
+    /// Returns all playback state to how a new clock starts: position 0, `Stopped`, and
+    /// every flag, counter and meter cleared. Device format, buffer capacity, volume and
+    /// pause and seek behavior are configuration and are kept.
+    pub fn reset(&self) {
+        self.set_state(PlaybackState::Stopped);
+        self.set_sample_pos(0);
+        self.reset_clear_buffer();
+        self.set_eos(false);
+        self.set_buffering(false);
+        self.reset_underruns();
+        self.reset_decode_errors();
+        self.suppress_underrun();
+        self.set_auto_paused(false);
+        self.reset_clipped();
+        self.set_limiter_reduction_db(0.0);
+        self.set_decode_load(0.0);
+        self.set_phase_inverted(false);
+        self.set_latency_samples(0);
+        self.clear_source_time();
+        self.set_drift_secs(0.0);
+    }
+
     pub fn get_sample_pos(&self) -> u64 {
         self.sample_pos.load(Ordering::Relaxed)
     }
 
     pub fn set_sample_pos(&self, pos: u64) {
         self.sample_pos.store(pos, Ordering::SeqCst);
+        // A jump isn't a block being played, so there's nothing to interpolate across
+        self.last_block.store(0, Ordering::SeqCst);
     }
 
-    pub fn increment_samples(&self, amount: u64) {
-        if self.get_state() == PlaybackState::Playing {
-            self.sample_pos.fetch_add(amount, Ordering::Relaxed);
+    /// Moves the position to `secs`, rounded down to a whole frame so it never lands
+    /// between the channels of one, and returns the time it landed on. Like a seek, this
+    /// counts the processing latency as still to be played, so `get_time_secs` reads the
+    /// landed time back once that has played, or straight away when there's none. Lands
+    /// on zero while no output has set the format.
+    pub fn set_time_secs(&self, secs: f64) -> f64 {
+        let rate = self.get_sample_rate() as f64;
+        if !self.is_configured() || rate <= 0.0 {
+            self.set_sample_pos(0);
+            return 0.0;
         }
+        let frame = (secs.max(0.0) * rate) as u64;
+        self.set_sample_pos(frame * self.get_channels().max(1) as u64);
+        frame as f64 / rate
     }
 
+    // The output only calls this for samples it actually played. Re-checking the state
+    // here would drop samples popped just before a concurrent pause and skew the position.
+    pub fn increment_samples(&self, amount: u64) {
+        self.sample_pos.fetch_add(amount, Ordering::Relaxed);
+        let now = self.epoch.elapsed().as_nanos() as u64;
+        self.last_block_at.store(now, Ordering::Relaxed);
+        self.last_block.store(amount, Ordering::Relaxed);
+    }
+
+    // Position of what's audible: the samples played, minus the processing latency
+    // (resampler group delay) they still contain. Clamped at zero right after a start,
+    // and zero until an output has set the format.
     pub fn get_time_secs(&self) -> f64 {
-        let pos = self.get_sample_pos() as f64;
+        self.samples_to_secs(self.get_sample_pos() as f64)
+    }
+
+    /// `get_time_secs` for display. The last block the output played is spread over the
+    /// wall-clock time since it was handed over instead of counting all at once, so the
+    /// value moves continuously between callbacks. It trails the real position by at most
+    /// one block and never passes it.
+    pub fn get_time_secs_smoothed(&self) -> f64 {
+        let pos = self.get_sample_pos();
+        if self.get_state() != PlaybackState::Playing {
+            return self.samples_to_secs(pos as f64);
+        }
+
+        let block = self.last_block.load(Ordering::Relaxed).min(pos);
+        let now = self.epoch.elapsed().as_nanos() as u64;
+        let elapsed = now.saturating_sub(self.last_block_at.load(Ordering::Relaxed)) as f64 / 1e9;
+        let rate = self.get_sample_rate() as f64 * self.get_channels() as f64;
+        let played = (elapsed * rate * self.get_playback_speed() as f64).min(block as f64);
+        self.samples_to_secs((pos - block) as f64 + played)
+    }
+
+    fn samples_to_secs(&self, pos: f64) -> f64 {
+        if !self.is_configured() {
+            return 0.0;
+        }
+        let pos = (pos - self.get_latency_samples() as f64).max(0.0);
         let rate = self.sample_rate.load(Ordering::Relaxed) as f64;
         let channels = self.get_channels() as f64;
         if rate > 0.0 && channels > 0.0 {
@@ -72,20 +264,61 @@ impl Clock {
         self.state.store(state as u8, Ordering::SeqCst);
     }
 
+    /// Sets the rate of the audio in the buffer, which positions are counted in and the
+    /// decode thread produces. Ignores a rate of zero, keeping the previous one.
     pub fn set_sample_rate(&self, rate: u32) {
+        if rate == 0 {
+            return;
+        }
         self.sample_rate.store(rate as u64, Ordering::SeqCst);
+        self.configured.fetch_or(RATE_SET, Ordering::SeqCst);
     }
 
     pub fn get_sample_rate(&self) -> u32 {
         self.sample_rate.load(Ordering::Relaxed) as u32
     }
 
+    /// Sets the rate the device plays at. The first device to report one also sets the
+    /// buffer's rate; after that a device at another rate has the output convert to it
+    /// (see `cpal_backend::process_audio`) instead of the decode thread starting over.
+    /// Ignores a rate of zero.
+    pub fn set_device_sample_rate(&self, rate: u32) {
+        if rate == 0 {
+            return;
+        }
+        if self.configured.load(Ordering::SeqCst) & RATE_SET == 0 {
+            self.set_sample_rate(rate);
+        }
+        self.device_sample_rate.store(rate as u64, Ordering::SeqCst);
+    }
+
+    /// The device's rate, which is also the rate of everything the output tap holds.
+    /// Same as `get_sample_rate` unless a device at another rate set it.
+    pub fn get_device_sample_rate(&self) -> u32 {
+        match self.device_sample_rate.load(Ordering::Relaxed) {
+            0 => self.get_sample_rate(),
+            rate => rate as u32,
+        }
+    }
+
+    /// Ignores a count of zero, keeping the previous one.
     pub fn set_channels(&self, channels: u32) {
-        self.channels.store(channels as u8, Ordering::SeqCst);
+        if channels == 0 {
+            return;
+        }
+        self.channels.store(channels, Ordering::SeqCst);
+        self.configured.fetch_or(CHANNELS_SET, Ordering::SeqCst);
     }
 
     pub fn get_channels(&self) -> u32 {
-        self.channels.load(Ordering::Relaxed) as u32
+        self.channels.load(Ordering::Relaxed)
+    }
+
+    /// Whether an output has set both the sample rate and the channel count. Until then
+    /// they're placeholders, and positions computed from them would be wrong once the
+    /// device reports its real format.
+    pub fn is_configured(&self) -> bool {
+        self.configured.load(Ordering::Relaxed) == RATE_SET | CHANNELS_SET
     }
 
     pub fn signal_clear_buffer(&self) {
@@ -107,4 +340,376 @@ impl Clock {
     pub fn is_eos(&self) -> bool {
         self.eos.load(Ordering::Relaxed)
     }
-}
\ No newline at end of file
+
+    // Occupancy as last seen by the output callback, which owns the consumer
+    pub fn set_buffered_samples(&self, samples: u64) {
+        self.buffered_samples.store(samples, Ordering::Relaxed);
+    }
+
+    pub fn get_buffered_samples(&self) -> u64 {
+        self.buffered_samples.load(Ordering::Relaxed)
+    }
+
+    pub fn set_buffer_capacity(&self, capacity: u64) {
+        self.buffer_capacity.store(capacity, Ordering::SeqCst);
+    }
+
+    pub fn get_buffer_capacity(&self) -> u64 {
+        self.buffer_capacity.load(Ordering::Relaxed)
+    }
+
+    pub fn buffer_fill(&self) -> f32 {
+        let capacity = self.get_buffer_capacity();
+        if capacity > 0 {
+            (self.get_buffered_samples() as f32 / capacity as f32).min(1.0)
+        } else {
+            0.0
+        }
+    }
+
+    pub fn set_buffering(&self, buffering: bool) {
+        self.buffering.store(buffering, Ordering::SeqCst);
+    }
+
+    pub fn is_buffering(&self) -> bool {
+        self.buffering.load(Ordering::Relaxed)
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.volume.store(volume.to_bits(), Ordering::SeqCst);
+    }
+
+    pub fn get_volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed))
+    }
+
+    pub fn set_output_gain_db(&self, db: f32) {
+        self.output_gain_db.store(db.to_bits(), Ordering::SeqCst);
+    }
+
+    pub fn get_output_gain_db(&self) -> f32 {
+        f32::from_bits(self.output_gain_db.load(Ordering::Relaxed))
+    }
+
+    pub fn set_playback_speed(&self, speed: f32) {
+        self.playback_speed.store(speed.to_bits(), Ordering::SeqCst);
+    }
+
+    pub fn get_playback_speed(&self) -> f32 {
+        f32::from_bits(self.playback_speed.load(Ordering::Relaxed))
+    }
+
+    pub fn set_speed_affects_pitch(&self, affects: bool) {
+        self.speed_affects_pitch.store(affects, Ordering::SeqCst);
+    }
+
+    pub fn speed_affects_pitch(&self) -> bool {
+        self.speed_affects_pitch.load(Ordering::Relaxed)
+    }
+
+    // Called by the output once per callback. Only the transition into starvation counts,
+    // and the flag starts set so the initial fill after play or a seek isn't an underrun.
+    pub fn record_output(&self, starved: bool) {
+        if !starved {
+            self.starved.store(false, Ordering::Relaxed);
+        } else if !self.starved.swap(true, Ordering::Relaxed) {
+            self.record_underrun();
+        }
+    }
+
+    /// Counts an underrun the output learned about some other way, e.g. a JACK xrun.
+    pub fn record_underrun(&self) {
+        self.underruns.fetch_add(1, Ordering::Relaxed);
+        self.dropouts.record(self.get_time_secs());
+    }
+
+    pub fn suppress_underrun(&self) {
+        self.starved.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the last callback came up short, or nothing has played since `play` or a
+    /// seek.
+    pub fn is_starved(&self) -> bool {
+        self.starved.load(Ordering::Relaxed)
+    }
+
+    pub fn set_auto_paused(&self, paused: bool) {
+        self.auto_paused.store(paused, Ordering::SeqCst);
+    }
+
+    /// While set, a playing output sends silence without taking from the buffer or
+    /// moving the position, see `EngineConfig::underrun_auto_pause_secs`.
+    pub fn is_auto_paused(&self) -> bool {
+        self.auto_paused.load(Ordering::Relaxed)
+    }
+
+    pub fn get_underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    /// Where in the media the most recent underruns happened, see `DropoutLog`.
+    pub fn dropouts(&self) -> &DropoutLog {
+        &self.dropouts
+    }
+
+    /// Also forgets where they happened.
+    pub fn reset_underruns(&self) {
+        self.underruns.store(0, Ordering::SeqCst);
+        self.dropouts.clear();
+    }
+
+    pub fn record_decode_errors(&self, count: u64) {
+        self.decode_errors.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_fatal_error(&self) {
+        self.fatal_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get_decode_errors(&self) -> u64 {
+        self.decode_errors.load(Ordering::Relaxed)
+    }
+
+    pub fn get_fatal_errors(&self) -> u64 {
+        self.fatal_errors.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_decode_errors(&self) {
+        self.decode_errors.store(0, Ordering::SeqCst);
+        self.fatal_errors.store(0, Ordering::SeqCst);
+    }
+
+    pub fn set_clipped(&self) {
+        self.clipped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_clipped(&self) -> bool {
+        self.clipped.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_clipped(&self) {
+        self.clipped.store(false, Ordering::SeqCst);
+    }
+
+    pub fn set_limiter_reduction_db(&self, db: f32) {
+        self.limiter_reduction.store(db.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get_limiter_reduction_db(&self) -> f32 {
+        f32::from_bits(self.limiter_reduction.load(Ordering::Relaxed))
+    }
+
+    pub fn set_decode_load(&self, load: f32) {
+        self.decode_load.store(load.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get_decode_load(&self) -> f32 {
+        f32::from_bits(self.decode_load.load(Ordering::Relaxed))
+    }
+
+    /// What the output last wrote to the device, for visualizers.
+    pub fn output_tap(&self) -> &OutputTap {
+        &self.output_tap
+    }
+
+    pub fn set_phase_inverted(&self, inverted: bool) {
+        self.phase_inverted.store(inverted, Ordering::Relaxed);
+    }
+
+    pub fn is_phase_inverted(&self) -> bool {
+        self.phase_inverted.load(Ordering::Relaxed)
+    }
+
+    pub fn set_pause_behavior(&self, behavior: PauseBehavior) {
+        self.pause_behavior.store(behavior as u8, Ordering::SeqCst);
+    }
+
+    pub fn get_pause_behavior(&self) -> PauseBehavior {
+        PauseBehavior::from(self.pause_behavior.load(Ordering::Relaxed))
+    }
+
+    pub fn set_seek_behavior(&self, behavior: SeekBehavior) {
+        self.seek_behavior.store(behavior as u8, Ordering::SeqCst);
+    }
+
+    pub fn get_seek_behavior(&self) -> SeekBehavior {
+        SeekBehavior::from(self.seek_behavior.load(Ordering::Relaxed))
+    }
+
+    /// Buffer size an output requests when it opens the device, `None` for the default.
+    pub fn set_device_buffer_frames(&self, frames: Option<u32>) {
+        self.device_buffer_frames.store(frames.unwrap_or(0), Ordering::SeqCst);
+    }
+
+    pub fn get_device_buffer_frames(&self) -> Option<u32> {
+        let frames = self.device_buffer_frames.load(Ordering::Relaxed);
+        (frames > 0).then_some(frames)
+    }
+
+    pub fn set_latency_samples(&self, samples: u64) {
+        self.latency_samples.store(samples, Ordering::SeqCst);
+    }
+
+    pub fn get_latency_samples(&self) -> u64 {
+        self.latency_samples.load(Ordering::Relaxed)
+    }
+
+    /// Set by the decode thread after each push: the stream time, from the decoder's own
+    /// timestamps, of the newest sample now in the buffer.
+    pub fn set_source_time(&self, secs: f64) {
+        self.source_time.store(secs.to_bits(), Ordering::SeqCst);
+    }
+
+    pub fn clear_source_time(&self) {
+        self.set_source_time(f64::NAN);
+    }
+
+    /// How far `get_time_secs` is ahead of the decoder's position for what's playing,
+    /// going by the source time and the buffer fill. `None` until the decoder has
+    /// reported a position. Off by up to a packet while a push is in flight.
+    pub fn measure_drift(&self) -> Option<f64> {
+        let source = f64::from_bits(self.source_time.load(Ordering::SeqCst));
+        let rate = self.get_sample_rate() as f64 * self.get_channels() as f64;
+        if source.is_nan() || rate <= 0.0 || !self.is_configured() {
+            return None;
+        }
+        // The buffer holds output time; at another speed it covers more or less source
+        let buffered = self.get_buffered_samples() as f64 / rate * self.get_playback_speed() as f64;
+        Some(self.get_time_secs() - (source - buffered))
+    }
+
+    pub fn set_drift_secs(&self, drift: f64) {
+        self.drift.store(drift.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get_drift_secs(&self) -> f64 {
+        f64::from_bits(self.drift.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn reset_restores_every_initial_value() {
+        let clock = Clock::new(44100);
+        clock.set_device_sample_rate(44100);
+        clock.set_channels(2);
+
+        clock.set_state(PlaybackState::Playing);
+        clock.set_sample_pos(44100 * 2);
+        clock.signal_clear_buffer();
+        clock.set_eos(true);
+        clock.set_buffering(true);
+        clock.record_output(false);
+        clock.record_output(true);
+        clock.set_auto_paused(true);
+        clock.record_decode_errors(3);
+        clock.record_fatal_error();
+        clock.set_clipped();
+        clock.set_limiter_reduction_db(-6.0);
+        clock.set_decode_load(0.8);
+        clock.set_phase_inverted(true);
+        clock.set_latency_samples(256);
+        clock.set_source_time(1.0);
+        clock.set_drift_secs(0.05);
+
+        clock.reset();
+        assert_eq!(clock.get_state(), PlaybackState::Stopped);
+        assert_eq!(clock.get_sample_pos(), 0);
+        assert_eq!(clock.get_time_secs(), 0.0);
+        assert!(!clock.should_clear_buffer());
+        assert!(!clock.is_eos());
+        assert!(!clock.is_buffering());
+        assert!(clock.is_starved());
+        assert!(!clock.is_auto_paused());
+        assert_eq!(clock.get_underruns(), 0);
+        assert!(clock.dropouts().recent().is_empty());
+        assert_eq!(clock.get_decode_errors(), 0);
+        assert_eq!(clock.get_fatal_errors(), 0);
+        assert!(!clock.is_clipped());
+        assert_eq!(clock.get_limiter_reduction_db(), 0.0);
+        assert_eq!(clock.get_decode_load(), 0.0);
+        assert!(!clock.is_phase_inverted());
+        assert_eq!(clock.get_latency_samples(), 0);
+        assert_eq!(clock.measure_drift(), None);
+        assert_eq!(clock.get_drift_secs(), 0.0);
+    }
+
+    #[test]
+    fn time_reads_zero_until_the_device_sets_the_format() {
+        let clock = Clock::new(44100);
+        clock.set_state(PlaybackState::Playing);
+        clock.increment_samples(44100 * 2);
+        assert!(!clock.is_configured());
+        assert_eq!(clock.get_time_secs(), 0.0);
+        assert_eq!(clock.get_time_secs_smoothed(), 0.0);
+        assert_eq!(clock.set_time_secs(5.0), 0.0);
+        assert_eq!(clock.get_sample_pos(), 0);
+
+        // The rate alone isn't enough
+        clock.set_device_sample_rate(48000);
+        assert!(!clock.is_configured());
+        clock.set_channels(2);
+        assert!(clock.is_configured());
+        assert_eq!(clock.set_time_secs(5.0), 5.0);
+        assert_eq!(clock.get_time_secs(), 5.0);
+    }
+
+    #[test]
+    fn smoothed_position_advances_between_blocks() {
+        let mut clock = Clock::new(44100);
+        clock.set_device_sample_rate(44100);
+        clock.set_channels(2);
+        clock.set_state(PlaybackState::Playing);
+        clock.set_sample_pos(44100 * 2);
+
+        // One coarse 100 ms block, spread over the time it takes to play. Moving the
+        // epoch back stands in for time passing, so scheduling delays can't shift it.
+        clock.increment_samples(4410 * 2);
+        let mut previous = clock.get_time_secs_smoothed();
+        assert!((previous - 1.0).abs() < 0.01, "{previous}");
+        for _ in 0..4 {
+            clock.epoch -= Duration::from_millis(20);
+            let smoothed = clock.get_time_secs_smoothed();
+            assert!(smoothed > previous && smoothed <= clock.get_time_secs(), "{previous} then {smoothed}");
+            previous = smoothed;
+        }
+        clock.epoch -= Duration::from_millis(50);
+        assert_eq!(clock.get_time_secs_smoothed(), clock.get_time_secs());
+
+        // Paused, it's the real position
+        clock.increment_samples(4410 * 2);
+        clock.set_state(PlaybackState::Paused);
+        assert_eq!(clock.get_time_secs_smoothed(), clock.get_time_secs());
+    }
+
+    #[test]
+    fn set_time_reads_back_within_a_frame() {
+        for (rate, channels) in [(44100, 2), (48000, 1), (96000, 6)] {
+            let clock = Clock::new(rate);
+            clock.set_device_sample_rate(rate);
+            clock.set_channels(channels);
+            for secs in [0.0, 0.5, 1.234_567, 59.999_99, 3601.25] {
+                let landed = clock.set_time_secs(secs);
+                assert_eq!(clock.get_time_secs(), landed);
+                assert!((clock.get_time_secs() - secs).abs() < 1.0 / rate as f64, "{rate} Hz: {secs} s");
+            }
+        }
+    }
+
+    #[test]
+    fn stereo_seeks_land_on_frame_boundaries() {
+        let clock = Clock::new(44100);
+        clock.set_device_sample_rate(44100);
+        clock.set_channels(2);
+        for step in 0..10_000 {
+            let secs = step as f64 * 0.000_123_4;
+            let landed = clock.set_time_secs(secs);
+            assert_eq!(clock.get_sample_pos() % 2, 0, "{secs} s");
+            assert!(landed <= secs && secs - landed < 1.0 / 44100.0, "{secs} s landed on {landed}");
+        }
+    }
+}
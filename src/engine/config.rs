@@ -0,0 +1,159 @@
+use crate::engine::dsp::dsp_chain::DspLayout;
+use crate::engine::dsp::precision::Precision;
+use crate::engine::output::OutputBackend;
+
+/// Presets for the settings that trade latency against resilience to stalls, applied
+/// with `EngineConfig::with_latency_mode` or `AudioEngine::set_latency_mode`. Watermarks
+/// are fractions of the ring buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LatencyMode {
+    /// 100 ms ring buffer and a 256-frame device buffer. Reports buffering below 20%
+    /// and plays again at 50%; decodes whenever the buffer is under 70%, up to 90%.
+    /// Seeks and DSP changes are heard almost at once, but a busy system can drop out.
+    LowLatency,
+    /// The defaults: 1000 ms ring buffer and the device's own buffer size. Reports
+    /// buffering below 10% and plays again at 50%; decodes from 60% up to 90%.
+    #[default]
+    Balanced,
+    /// 3000 ms ring buffer and a 4096-frame device buffer. Reports buffering below 5%
+    /// and plays again at 60%; decodes from 50% up to 95%. Rides out long disk or CPU
+    /// stalls, at the cost of seeks and DSP changes taking up to seconds to be heard.
+    HighStability,
+}
+
+impl LatencyMode {
+    pub fn apply(self, config: &mut EngineConfig) {
+        let (buffer_ms, device_frames, low_water, recovered, decode_low, decode_high) = match self {
+            LatencyMode::LowLatency => (100, Some(256), 0.2, 0.5, 0.7, 0.9),
+            LatencyMode::Balanced => (1000, None, 0.1, 0.5, 0.6, 0.9),
+            LatencyMode::HighStability => (3000, Some(4096), 0.05, 0.6, 0.5, 0.95),
+        };
+        config.buffer_duration_ms = buffer_ms;
+        config.device_buffer_frames = device_frames;
+        config.buffering_low_water = low_water;
+        config.buffering_recovered = recovered;
+        config.decode_low_water = decode_low;
+        config.decode_high_water = decode_high;
+    }
+}
+
+/// Bounds of `EngineConfig::buffer_duration_ms`.
+pub const MIN_BUFFER_DURATION_MS: u32 = 50;
+pub const MAX_BUFFER_DURATION_MS: u32 = 5000;
+
+/// Bounds of `EngineConfig::resampler_chunk_frames`.
+pub const MIN_RESAMPLER_CHUNK_FRAMES: usize = 64;
+pub const MAX_RESAMPLER_CHUNK_FRAMES: usize = 8192;
+
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// Length of the output ring buffer in milliseconds, clamped to
+    /// `MIN_BUFFER_DURATION_MS..=MAX_BUFFER_DURATION_MS`. A shorter buffer makes seeks and
+    /// DSP changes audible sooner but leaves the decode thread less slack, so a stall in
+    /// it (disk, CPU load) underruns sooner. Sized at the device's sample rate.
+    pub buffer_duration_ms: u32,
+    /// Frames per callback to request from the output device, clamped to what it
+    /// supports. Smaller lowers output latency but wakes the callback more often, which
+    /// raises the risk of dropouts. `None` keeps the device's default. JACK ignores it,
+    /// since the server sets the period.
+    pub device_buffer_frames: Option<u32>,
+    /// Re-chunk decoded audio into blocks of this many frames before it reaches the DSP
+    /// chain, so adaptive processing and metering see the same block size regardless of
+    /// the codec's packet size. `None` processes the audio in the pieces it's converted
+    /// in, about `resampler_chunk_frames` frames at a time.
+    pub dsp_block_frames: Option<usize>,
+    /// Layout the DSP chain's per-channel filters run in, interleaved by default. See
+    /// `DspLayout` for what planar costs.
+    pub dsp_layout: DspLayout,
+    /// Arithmetic the DSP chain's filters, DC blocker and limiter run in. See `Precision`.
+    pub processing_precision: Precision,
+    /// Buffer fill fraction below which a playing engine reports `Buffering`.
+    pub buffering_low_water: f32,
+    /// Buffer fill fraction a buffering engine must climb back to before it reports
+    /// `Playing` again. Kept above the low-water mark so the state doesn't flap.
+    pub buffering_recovered: f32,
+    /// Buffer fill fraction at which the decode thread stops and idles.
+    pub decode_high_water: f32,
+    /// Buffer fill fraction an idle decode thread waits for before it refills up to
+    /// `decode_high_water` again. The gap between the two sets how often it wakes up.
+    pub decode_low_water: f32,
+    /// OS priority for the decode thread, `0..=99` from lowest to highest. `None` leaves
+    /// the platform default. If the platform refuses it the thread just keeps running at
+    /// its default priority. The audio callback thread's priority is managed by cpal.
+    pub decode_thread_priority: Option<u8>,
+    /// Fixed sample rate the DSP chain runs at and the buffer holds. Decoded audio is
+    /// resampled to it, and the output converts it to the device rate right before it's
+    /// played, so a mismatch costs a second resampling pass plus about 10 ms of latency.
+    /// `None` processes at the rate of the device a track starts on. Either way a device
+    /// at another rate showing up mid-track is handled by the output's conversion, without
+    /// the decode thread or the buffer starting over.
+    pub processing_sample_rate: Option<u32>,
+    /// Input frames the resamplers convert at a time. Larger chunks cost less CPU per
+    /// frame but add latency, since a chunk has to fill before any of it comes out.
+    /// Rounded up to a power of two and clamped to
+    /// `MIN_RESAMPLER_CHUNK_FRAMES..=MAX_RESAMPLER_CHUNK_FRAMES`, see
+    /// `effective_resampler_chunk_frames`. The output's conversion to a device at another
+    /// rate keeps its own small chunk.
+    pub resampler_chunk_frames: usize,
+    /// Skip corrupt packets and recover from codec resets instead of ending the track at
+    /// the first stream error. See `SymphoniaDecoder::set_tolerant`.
+    pub tolerant_decoding: bool,
+    /// Audio system to play through. JACK needs the `jack` feature, ASIO the `asio` one.
+    pub output_backend: OutputBackend,
+    /// Play into a `NullBackend` while the output can't be opened, so decoding, the clock
+    /// and analysis keep working without a device. Off by default, so `play` fails and
+    /// the caller finds out there's no output.
+    pub null_output_fallback: bool,
+    /// Drift between the clock and the decoder's own position, in seconds, past which the
+    /// playback thread moves the clock back in line. It has to persist for half a second
+    /// first, so a seek in flight doesn't trigger it. Moving the clock restarts the
+    /// output's rate conversion, so it can cost a few milliseconds of audio. `None`, the
+    /// default, only measures it.
+    pub drift_resync_threshold_secs: Option<f64>,
+    /// Meant for network streams: once the output has been starved for this many seconds
+    /// in a row, stop playing the silence and hold the position until the buffer is back
+    /// up to `buffering_recovered`, reporting `Buffering` and then `Ready`. The clock
+    /// doesn't move in between, so playback resumes exactly where it stalled instead of
+    /// mid-word. This also applies to the first fill after `play` or a seek. `None`, the
+    /// default, keeps playing through an underrun.
+    pub underrun_auto_pause_secs: Option<f64>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            buffer_duration_ms: 1000,
+            device_buffer_frames: None,
+            dsp_block_frames: None,
+            dsp_layout: DspLayout::Interleaved,
+            processing_precision: Precision::F32,
+            buffering_low_water: 0.1,
+            buffering_recovered: 0.5,
+            decode_high_water: 0.9,
+            decode_low_water: 0.6,
+            decode_thread_priority: None,
+            processing_sample_rate: None,
+            resampler_chunk_frames: 1024,
+            tolerant_decoding: false,
+            output_backend: OutputBackend::Cpal,
+            null_output_fallback: false,
+            drift_resync_threshold_secs: None,
+            underrun_auto_pause_secs: None,
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Sets every setting `mode` covers, leaving the rest as they are.
+    pub fn with_latency_mode(mut self, mode: LatencyMode) -> Self {
+        mode.apply(&mut self);
+        self
+    }
+
+    /// The chunk size the resamplers are actually built with.
+    pub fn effective_resampler_chunk_frames(&self) -> usize {
+        self.resampler_chunk_frames
+            .clamp(MIN_RESAMPLER_CHUNK_FRAMES, MAX_RESAMPLER_CHUNK_FRAMES)
+            .next_power_of_two()
+    }
+}
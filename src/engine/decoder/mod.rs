@@ -1,11 +1,65 @@
 pub mod symphonia_decoder;
 
-#[derive(Debug, Clone, Default)]
+// ReplayGain targets about -18 LUFS, R128 gains are relative to -23 LUFS
+const R128_TO_REPLAYGAIN_DB: f32 = 5.0;
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct AudioMetadata {
     pub duration_secs: Option<f64>,
     pub artist: Option<String>,
     pub title: Option<String>,
     pub album: Option<String>,
+    /// `REPLAYGAIN_TRACK_GAIN` in dB.
+    pub replaygain_track_gain_db: Option<f32>,
+    /// `REPLAYGAIN_ALBUM_GAIN` in dB.
+    pub replaygain_album_gain_db: Option<f32>,
+    /// Opus `R128_TRACK_GAIN` in dB, relative to -23 LUFS.
+    pub r128_track_gain_db: Option<f32>,
+    /// Opus `R128_ALBUM_GAIN` in dB, relative to -23 LUFS.
+    pub r128_album_gain_db: Option<f32>,
+}
+
+impl AudioMetadata {
+    /// Track gain to normalize with, in dB against the ReplayGain reference. For Opus the
+    /// R128 tag wins over a ReplayGain one, since Opus files aren't supposed to carry the
+    /// latter; other codecs never have the R128 fields set. Symphonia has no Opus codec of
+    /// its own, so they come from an Opus `AudioDecoder` passed to `load_decoder`.
+    pub fn normalization_gain_db(&self) -> Option<f32> {
+        self.r128_track_gain_db
+            .map(|db| db + R128_TO_REPLAYGAIN_DB)
+            .or(self.replaygain_track_gain_db)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackInfo {
+    pub id: u32,
+    pub codec: Option<String>,
+    pub language: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u32>,
+    pub duration_secs: Option<f64>,
+}
+
+/// What a source is encoded as. Fields a decoder can't tell are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CodecInfo {
+    /// Container format, e.g. `"FLAC"`, `"WAV"` or `"Ogg"`. Raw streams like MP3 name
+    /// their own format.
+    pub container: Option<String>,
+    /// Codec short name, e.g. `"flac"`, `"mp3"` or `"pcm_s16le"`.
+    pub codec: Option<String>,
+    pub bits_per_sample: Option<u32>,
+    /// Average over the whole source for compressed formats, in kbit/s.
+    pub bitrate_kbps: Option<u32>,
+    pub sample_rate: u32,
+    pub channels: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: Option<String>,
+    pub start_secs: f64,
 }
 
 pub trait AudioDecoder {
@@ -14,5 +68,58 @@ pub trait AudioDecoder {
     fn channels(&self) -> u32;
     fn seek(&mut self, time_secs: f64);
     fn duration(&self) -> Option<f64>;
+    /// Length in frames at `sample_rate`, exact where the container records it.
+    fn total_frames(&self) -> Option<u64> {
+        self.duration().map(|secs| (secs * self.sample_rate() as f64).round() as u64)
+    }
+    /// Seeks so the next decoded audio starts at `frame`, counted at `sample_rate`.
+    /// Going through seconds loses precision on long files, so decoders that work in
+    /// frames should override this.
+    fn seek_to_frame(&mut self, frame: u64) {
+        self.seek(frame as f64 / self.sample_rate() as f64);
+    }
     fn metadata(&self) -> Option<AudioMetadata>;
+    fn chapters(&self) -> Vec<Chapter> {
+        Vec::new()
+    }
+    /// Source position of the most recently decoded audio, from the stream's own
+    /// timestamps rather than the output clock. `None` if the decoder can't tell.
+    fn current_position_secs(&self) -> Option<f64> {
+        None
+    }
+    /// `false` for sources that can only be read front to back, like pipes and most
+    /// network streams. `seek` isn't called on those.
+    fn is_seekable(&self) -> bool {
+        true
+    }
+    /// Metadata that changed in-band since the last call, e.g. the title of an internet
+    /// radio stream moving to the next song. Only returns values that actually differ.
+    fn take_metadata_update(&mut self) -> Option<AudioMetadata> {
+        None
+    }
+    /// Errors decoding recovered from since the last call, e.g. corrupt packets skipped.
+    fn take_recovered_errors(&mut self) -> u64 {
+        0
+    }
+    /// Whether `decode_next` returned `None` because of an error rather than the end of
+    /// the stream.
+    fn has_failed(&self) -> bool {
+        false
+    }
+    /// Container and codec as one readable name, e.g. `"FLAC (flac)"`.
+    fn format_name(&self) -> String {
+        let info = self.codec_info();
+        match (info.container, info.codec) {
+            (Some(container), Some(codec)) => format!("{} ({})", container, codec),
+            (Some(name), None) | (None, Some(name)) => name,
+            (None, None) => "Unknown".to_string(),
+        }
+    }
+    fn codec_info(&self) -> CodecInfo {
+        CodecInfo {
+            sample_rate: self.sample_rate(),
+            channels: self.channels(),
+            ..CodecInfo::default()
+        }
+    }
 }
\ No newline at end of file
@@ -1,14 +1,17 @@
 use std::fs::File;
 use std::path::Path;
 use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::{Decoder, DecoderOptions};
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
 use symphonia::core::errors::Error;
 use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
+use symphonia::core::io::{MediaSource, MediaSourceStream, ReadBytes, SeekBuffered};
+use symphonia::core::meta::{MetadataOptions, Tag};
 use symphonia::core::probe::Hint;
-use symphonia::core::units::Time;
-use crate::engine::decoder::{AudioDecoder, AudioMetadata};
+use symphonia::core::units::{Time, TimeBase};
+use crate::engine::decoder::{AudioDecoder, AudioMetadata, Chapter, CodecInfo, TrackInfo};
+
+// A tolerant decoder gives up after this many errors in a row, as the stream is likely gone
+const MAX_CONSECUTIVE_ERRORS: usize = 64;
 
 pub struct SymphoniaDecoder {
     reader: Box<dyn FormatReader>,
@@ -17,17 +20,77 @@ pub struct SymphoniaDecoder {
     sample_rate: u32,
     channels: u32,
     duration: Option<f64>,
+    total_frames: Option<u64>,
     metadata: AudioMetadata,
+    tracks: Vec<TrackInfo>,
+    chapters: Vec<Chapter>,
+    codec_info: CodecInfo,
+    time_base: Option<TimeBase>,
+    // Timestamp of the last decoded packet, in `time_base` units
+    last_ts: Option<u64>,
+    // Timestamp a seek asked for. Audio before it is dropped, since the reader can only
+    // seek to the start of a packet
+    seek_target_ts: Option<u64>,
+    seekable: bool,
+    tolerant: bool,
+    // Set when a new metadata revision changed `metadata`, until it's taken
+    metadata_changed: bool,
+    // Errors skipped over since `take_recovered_errors` last ran
+    recovered_errors: u64,
+    failed: bool,
 }
 
 impl SymphoniaDecoder {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::open(path, None)
+    }
+
+    /// Opens the file and decodes the audio track with the given id instead of the first one.
+    pub fn new_with_track<P: AsRef<Path>>(
+        path: P,
+        track_id: u32,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::open(path, Some(track_id))
+    }
+
+    /// Decodes from any media source, e.g. a pipe or a network stream. `extension` helps
+    /// the format probe, like a file's extension would.
+    pub fn from_source(
+        source: Box<dyn MediaSource>,
+        extension: Option<&str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::open_source(source, extension, None)
+    }
+
+    fn open<P: AsRef<Path>>(
+        path: P,
+        track_id: Option<u32>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
         let path_ref = path.as_ref();
         let file = File::open(path_ref)?;
-        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+        let extension = path_ref.extension().and_then(|s| s.to_str());
+        Self::open_source(Box::new(file), extension, track_id)
+    }
+
+    fn open_source(
+        source: Box<dyn MediaSource>,
+        extension: Option<&str>,
+        track_id: Option<u32>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let seekable = source.is_seekable();
+        let byte_len = source.byte_len();
+        let mut mss = MediaSourceStream::new(source, Default::default());
+
+        // Peek at the start to tell the container, since the probe doesn't say which it found
+        let mut magic = [0u8; 12];
+        let peeked = mss.read_buf(&mut magic).unwrap_or(0);
+        mss.seek_buffered_rev(peeked);
+        let container = container_name(&magic[..peeked])
+            .map(str::to_string)
+            .or_else(|| extension.map(|ext| ext.to_uppercase()));
 
         let mut hint = Hint::new();
-        if let Some(ext) = path_ref.extension().and_then(|s| s.to_str()) {
+        if let Some(ext) = extension {
             hint.with_extension(ext);
         }
 
@@ -38,39 +101,96 @@ impl SymphoniaDecoder {
         let probed = symphonia::default::get_probe().format(&hint, mss, &fmt_opts, &meta_opts)?;
         let mut reader = probed.format;
 
-        // --- Extract Metadata ---
-        let mut metadata = AudioMetadata::default();
-
-        // Access visual tags
-        if let Some(ref current_metadata) = reader.metadata().current() {
-            for tag in current_metadata.tags() {
-                // Symphonia standardizes keys to uppercase, usually "ARTIST", "TITLE", etc.
-                match tag.key.as_str() {
-                    "ARTIST" => metadata.artist = Some(tag.value.to_string()),
-                    "TITLE" => metadata.title = Some(tag.value.to_string()),
-                    "ALBUM" => metadata.album = Some(tag.value.to_string()),
-                    _ => {}
+        let tracks: Vec<TrackInfo> = reader.tracks()
+            .iter()
+            .filter(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .map(|t| {
+                let params = &t.codec_params;
+                TrackInfo {
+                    id: t.id,
+                    codec: symphonia::default::get_codecs()
+                        .get_codec(params.codec)
+                        .map(|d| d.short_name.to_string()),
+                    language: t.language.clone(),
+                    sample_rate: params.sample_rate,
+                    channels: params.channels.map(|c| c.count() as u32),
+                    duration_secs: params.n_frames.zip(params.sample_rate)
+                        .map(|(frames, rate)| frames as f64 / rate as f64),
                 }
-            }
-        }
+            })
+            .collect();
 
-        let track = reader.tracks()
-            .iter()
-            .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
-            .ok_or("No supported audio tracks found")?;
+        let track = match track_id {
+            Some(id) => reader.tracks()
+                .iter()
+                .find(|t| t.id == id && t.codec_params.codec != CODEC_TYPE_NULL)
+                .ok_or_else(|| format!("No audio track with id {}", id))?,
+            None => reader.tracks()
+                .iter()
+                .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+                .ok_or("No supported audio tracks found")?,
+        };
 
         let track_id = track.id;
+        let time_base = track.codec_params.time_base;
         let sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
         let channels = track.codec_params.channels.map(|c| c.count() as u32).unwrap_or(2);
 
         let decoder = symphonia::default::get_codecs().make(&track.codec_params, &dec_opts)?;
 
-        let duration = track.codec_params.n_frames.map(|frames| {
+        let total_frames = track.codec_params.n_frames;
+        let duration = total_frames.map(|frames| {
             frames as f64 / sample_rate as f64
         });
 
-        // Ensure duration is in metadata struct
-        metadata.duration_secs = duration;
+        let codec = symphonia::default::get_codecs()
+            .get_codec(track.codec_params.codec)
+            .map(|d| d.short_name.to_string());
+        let bits_per_sample = track.codec_params.bits_per_sample;
+
+        // --- Extract Metadata ---
+        let mut metadata = AudioMetadata {
+            duration_secs: duration,
+            ..Default::default()
+        };
+        let opus = codec.as_deref() == Some("opus");
+        if let Some(ref current_metadata) = reader.metadata().skip_to_latest() {
+            apply_tags(&mut metadata, current_metadata.tags(), opus);
+        }
+
+        // PCM's rate follows from its format, anything else is averaged over the file
+        let is_pcm = codec.as_deref().is_some_and(|name| name.starts_with("pcm_"));
+        let bitrate_kbps = match bits_per_sample {
+            Some(bits) if is_pcm => {
+                Some(sample_rate as u64 * channels as u64 * bits as u64)
+            }
+            _ => byte_len
+                .zip(duration)
+                .filter(|(_, secs)| *secs > 0.0)
+                .map(|(bytes, secs)| (bytes as f64 * 8.0 / secs) as u64),
+        }
+        .map(|bps| ((bps + 500) / 1000) as u32);
+        let codec_info = CodecInfo {
+            container,
+            codec,
+            bits_per_sample,
+            bitrate_kbps,
+            sample_rate,
+            channels,
+        };
+
+        // Cue sheets and embedded chapters both surface as cues, timestamped like packets
+        let mut chapters: Vec<Chapter> = reader.cues()
+            .iter()
+            .map(|cue| Chapter {
+                title: cue.tags
+                    .iter()
+                    .find(|tag| tag.key.eq_ignore_ascii_case("TITLE"))
+                    .map(|tag| tag.value.to_string()),
+                start_secs: ts_to_secs(time_base, cue.start_ts, sample_rate),
+            })
+            .collect();
+        chapters.sort_by(|a, b| a.start_secs.total_cmp(&b.start_secs));
 
         Ok(Self {
             reader,
@@ -79,19 +199,125 @@ impl SymphoniaDecoder {
             sample_rate,
             channels,
             duration,
+            total_frames,
             metadata,
+            tracks,
+            chapters,
+            codec_info,
+            time_base,
+            last_ts: None,
+            seek_target_ts: None,
+            seekable,
+            tolerant: false,
+            metadata_changed: false,
+            recovered_errors: 0,
+            failed: false,
         })
     }
+
+    /// With `tolerant` on, corrupt packets and recoverable stream errors are skipped and a
+    /// `ResetRequired` rebuilds the codec, instead of ending playback at the first one.
+    /// Only I/O failures, unsupported features and long runs of errors still end it.
+    pub fn set_tolerant(&mut self, tolerant: bool) {
+        self.tolerant = tolerant;
+    }
+
+    // Rebuilds the codec from the track's parameters, which may have changed
+    fn reset_decoder(&mut self) -> bool {
+        let Some(track) = self.reader.tracks().iter().find(|t| t.id == self.track_id) else {
+            return false;
+        };
+        match symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default()) {
+            Ok(decoder) => {
+                self.decoder = decoder;
+                true
+            }
+            Err(err) => {
+                eprintln!("Failed to rebuild the decoder: {:?}", err);
+                false
+            }
+        }
+    }
+
+    // Picks up metadata revisions the reader found in-band since the last check
+    fn poll_metadata(&mut self) {
+        let mut log = self.reader.metadata();
+        if log.is_latest() {
+            return;
+        }
+        let Some(revision) = log.skip_to_latest() else {
+            return;
+        };
+        let mut metadata = self.metadata.clone();
+        let opus = self.codec_info.codec.as_deref() == Some("opus");
+        apply_tags(&mut metadata, revision.tags(), opus);
+        // Streams often repeat the same title, which isn't a change
+        if metadata != self.metadata {
+            self.metadata = metadata;
+            self.metadata_changed = true;
+        }
+    }
+
+    /// Every audio track in the container, in container order.
+    pub fn list_tracks(&self) -> Vec<TrackInfo> {
+        self.tracks.clone()
+    }
+
+    fn seek_to(&mut self, to: SeekTo) {
+        if let Ok(seeked) = self.reader.seek(SeekMode::Accurate, to) {
+            self.last_ts = Some(seeked.required_ts);
+            self.seek_target_ts = Some(seeked.required_ts);
+            self.decoder.reset();
+        }
+    }
+
+    // Timestamps count frames for nearly every audio format, but the time base has the
+    // final say
+    fn ts_to_frame(&self, ts: u64) -> u64 {
+        match self.time_base {
+            Some(time_base) => {
+                let time = time_base.calc_time(ts);
+                ((time.seconds as f64 + time.frac) * self.sample_rate as f64).round() as u64
+            }
+            None => ts,
+        }
+    }
+
+    fn frame_to_ts(&self, frame: u64) -> u64 {
+        match self.time_base {
+            Some(time_base) => time_base.calc_timestamp(Time::from(frame as f64 / self.sample_rate as f64)),
+            None => frame,
+        }
+    }
 }
 
 impl AudioDecoder for SymphoniaDecoder {
     fn decode_next(&mut self) -> Option<Vec<f32>> {
+        let mut errors = 0;
+        // A packet that asked for a new decoder, tried once more with the rebuilt one
+        let mut retry = None;
         loop {
-            let packet = match self.reader.next_packet() {
+            let packet = match retry.take().map_or_else(|| self.reader.next_packet(), Ok) {
                 Ok(packet) => packet,
                 Err(Error::IoError(ref err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => return None,
+                Err(Error::ResetRequired) if self.tolerant => {
+                    if !self.reset_decoder() {
+                        self.failed = true;
+                        return None;
+                    }
+                    self.recovered_errors += 1;
+                    continue;
+                }
+                // Corrupt container data, the reader resyncs on the next packet
+                Err(Error::DecodeError(err)) if self.tolerant && errors < MAX_CONSECUTIVE_ERRORS => {
+                    eprintln!("Skipping corrupt data: {:?}", err);
+                    errors += 1;
+                    self.recovered_errors += 1;
+                    continue;
+                }
                 Err(err) => {
                     eprintln!("Decoder error: {:?}", err);
+                    self.failed = true;
                     return None;
                 }
             };
@@ -99,20 +325,58 @@ impl AudioDecoder for SymphoniaDecoder {
             if packet.track_id() != self.track_id {
                 continue;
             }
+            self.poll_metadata();
 
             match self.decoder.decode(&packet) {
+                Ok(audio_buf) if audio_buf.frames() == 0 => continue,
                 Ok(audio_buf) => {
                     let spec = *audio_buf.spec();
+                    // Chained and adaptive streams can switch format between packets
+                    self.sample_rate = spec.rate;
+                    self.channels = spec.channels.count() as u32;
                     let mut sample_buf = SampleBuffer::<f32>::new(audio_buf.capacity() as u64, spec);
                     sample_buf.copy_interleaved_ref(audio_buf);
-                    return Some(sample_buf.samples().to_vec());
+                    let mut samples = sample_buf.samples().to_vec();
+
+                    self.last_ts = Some(packet.ts());
+                    if let Some(target) = self.seek_target_ts {
+                        let channels = spec.channels.count().max(1);
+                        let skip = self.ts_to_frame(target).saturating_sub(self.ts_to_frame(packet.ts()));
+                        if skip as usize >= samples.len() / channels {
+                            continue;
+                        }
+                        samples.drain(..skip as usize * channels);
+                        self.seek_target_ts = None;
+                        self.last_ts = Some(target);
+                    }
+                    return Some(samples);
                 }
                 Err(Error::DecodeError(err)) => {
                     eprintln!("Decode error: {:?}", err);
+                    self.recovered_errors += 1;
                     continue;
                 }
+                // The codec's parameters changed mid-stream, which a reset alone doesn't
+                // pick up
+                Err(Error::ResetRequired) if self.tolerant => {
+                    if !self.reset_decoder() {
+                        self.failed = true;
+                        return None;
+                    }
+                    self.recovered_errors += 1;
+                    if errors < MAX_CONSECUTIVE_ERRORS {
+                        errors += 1;
+                        retry = Some(packet);
+                    }
+                }
+                Err(err) if self.tolerant && errors < MAX_CONSECUTIVE_ERRORS && !is_fatal(&err) => {
+                    eprintln!("Skipping undecodable packet: {:?}", err);
+                    errors += 1;
+                    self.recovered_errors += 1;
+                }
                 Err(err) => {
                     eprintln!("Unexpected decoder error: {:?}", err);
+                    self.failed = true;
                     return None;
                 }
             }
@@ -128,20 +392,321 @@ impl AudioDecoder for SymphoniaDecoder {
     }
 
     fn seek(&mut self, time_secs: f64) {
-        let _ = self.reader.seek(
-            SeekMode::Accurate,
-            SeekTo::Time {
-                time: Time::from(time_secs),
-                track_id: Some(self.track_id),
-            },
-        );
+        self.seek_to(SeekTo::Time {
+            time: Time::from(time_secs),
+            track_id: Some(self.track_id),
+        });
+    }
+
+    fn seek_to_frame(&mut self, frame: u64) {
+        self.seek_to(SeekTo::TimeStamp {
+            ts: self.frame_to_ts(frame),
+            track_id: self.track_id,
+        });
     }
 
     fn duration(&self) -> Option<f64> {
         self.duration
     }
 
+    fn total_frames(&self) -> Option<u64> {
+        self.total_frames
+    }
+
     fn metadata(&self) -> Option<AudioMetadata> {
         Some(self.metadata.clone())
     }
-}
\ No newline at end of file
+
+    fn chapters(&self) -> Vec<Chapter> {
+        self.chapters.clone()
+    }
+
+    fn current_position_secs(&self) -> Option<f64> {
+        let ts = self.last_ts?;
+        Some(ts_to_secs(self.time_base, ts, self.sample_rate))
+    }
+
+    fn is_seekable(&self) -> bool {
+        self.seekable
+    }
+
+    fn take_metadata_update(&mut self) -> Option<AudioMetadata> {
+        if !std::mem::take(&mut self.metadata_changed) {
+            return None;
+        }
+        Some(self.metadata.clone())
+    }
+
+    fn take_recovered_errors(&mut self) -> u64 {
+        std::mem::take(&mut self.recovered_errors)
+    }
+
+    fn has_failed(&self) -> bool {
+        self.failed
+    }
+
+    fn codec_info(&self) -> CodecInfo {
+        self.codec_info.clone()
+    }
+}
+
+// Seconds at timestamp `ts`, counted in frames at `sample_rate` when there's no time base
+fn ts_to_secs(time_base: Option<TimeBase>, ts: u64, sample_rate: u32) -> f64 {
+    match time_base {
+        Some(time_base) => {
+            let time = time_base.calc_time(ts);
+            time.seconds as f64 + time.frac
+        }
+        None => ts as f64 / sample_rate.max(1) as f64,
+    }
+}
+
+// Container a stream's first bytes identify, if they're a known signature
+fn container_name(magic: &[u8]) -> Option<&'static str> {
+    match magic {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("WAV"),
+        [b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', _, ..] => Some("AIFF"),
+        [b'f', b'L', b'a', b'C', ..] => Some("FLAC"),
+        [b'O', b'g', b'g', b'S', ..] => Some("Ogg"),
+        [b'c', b'a', b'f', b'f', ..] => Some("CAF"),
+        [0x1a, 0x45, 0xdf, 0xa3, ..] => Some("Matroska"),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some("MP4"),
+        // An MPEG audio frame sync; layer bits of zero mark AAC in ADTS instead
+        [0xff, second, ..] if second & 0xe0 == 0xe0 => {
+            Some(if second & 0x06 == 0 { "ADTS" } else { "MP3" })
+        }
+        _ => None,
+    }
+}
+
+// R128 gain tags are only defined for Opus, so they're ignored on anything else
+fn apply_tags(metadata: &mut AudioMetadata, tags: &[Tag], opus: bool) {
+    for tag in tags {
+        // Symphonia standardizes keys to uppercase, usually "ARTIST", "TITLE", etc.
+        let value = tag.value.to_string();
+        match tag.key.as_str() {
+            "ARTIST" => metadata.artist = Some(value),
+            "TITLE" => metadata.title = Some(value),
+            "ALBUM" => metadata.album = Some(value),
+            "REPLAYGAIN_TRACK_GAIN" => metadata.replaygain_track_gain_db = parse_replaygain(&value),
+            "REPLAYGAIN_ALBUM_GAIN" => metadata.replaygain_album_gain_db = parse_replaygain(&value),
+            "R128_TRACK_GAIN" if opus => metadata.r128_track_gain_db = parse_r128(&value),
+            "R128_ALBUM_GAIN" if opus => metadata.r128_album_gain_db = parse_r128(&value),
+            _ => {}
+        }
+    }
+}
+
+// "-6.48 dB"
+fn parse_replaygain(value: &str) -> Option<f32> {
+    let number = value.trim().trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    number.trim().parse().ok()
+}
+
+// A Q7.8 fixed-point integer, i.e. 1/256 dB steps
+fn parse_r128(value: &str) -> Option<f32> {
+    value.trim().parse::<i16>().ok().map(|q| q as f32 / 256.0)
+}
+
+// Errors no amount of skipping gets past
+fn is_fatal(err: &Error) -> bool {
+    matches!(err, Error::IoError(_) | Error::Unsupported(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::ogg_flac::{flac_frame, flac_header, ogg_page};
+    use symphonia::core::audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef};
+    use symphonia::core::codecs::{CodecDescriptor, CodecParameters, FinalizeResult};
+    use symphonia::core::formats::Packet;
+    use symphonia::core::meta::Value;
+
+    fn tag(key: &str, value: &str) -> Tag {
+        Tag::new(None, key, Value::String(value.to_string()))
+    }
+
+    #[test]
+    fn tolerant_decoding_plays_on_past_a_corrupt_packet() {
+        let mut file = ogg_page(1, 0, 0x02, 0, &flac_header(44100, 2));
+        file.extend(ogg_page(1, 1, 0, 4096, &flac_frame(0, 8192, false)));
+        file.extend(ogg_page(1, 2, 0, 8192, &flac_frame(1, 8192, true)));
+        file.extend(ogg_page(1, 3, 0x04, 12288, &flac_frame(2, -8192, false)));
+        let path = std::env::temp_dir().join(format!("mewo-corrupt-{}.ogg", std::process::id()));
+        std::fs::write(&path, file).unwrap();
+        let mut decoder = SymphoniaDecoder::new(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        decoder.set_tolerant(true);
+
+        let mut blocks = Vec::new();
+        while let Some(samples) = decoder.decode_next() {
+            blocks.push(samples);
+        }
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks[0].iter().all(|&s| s == 0.25));
+        assert!(blocks[1].len() == 8192 && blocks[1].iter().all(|&s| s == -0.25));
+        assert_eq!(decoder.take_recovered_errors(), 1);
+        assert!(!decoder.has_failed());
+    }
+
+    // A 16-bit mono WAV whose every sample holds its own frame number
+    fn frame_numbered_wav(frames: u32) -> Vec<u8> {
+        let mut file = b"RIFF".to_vec();
+        file.extend((36 + frames * 2).to_le_bytes());
+        file.extend(b"WAVEfmt ");
+        file.extend(16u32.to_le_bytes());
+        file.extend([1, 0, 1, 0]);
+        file.extend(44100u32.to_le_bytes());
+        file.extend((44100u32 * 2).to_le_bytes());
+        file.extend([2, 0, 16, 0]);
+        file.extend(b"data");
+        file.extend((frames * 2).to_le_bytes());
+        file.extend((0..frames as i16).flat_map(i16::to_le_bytes));
+        file
+    }
+
+    #[test]
+    fn seeks_to_an_exact_frame() {
+        let frames = 30000u32;
+        let mut decoder = open("frames.wav", &frame_numbered_wav(frames));
+
+        assert_eq!(decoder.total_frames(), Some(frames as u64));
+        for frame in [12345, 1, 29999, 0, 4096] {
+            decoder.seek_to_frame(frame);
+            let first = decoder.decode_next().unwrap()[0];
+            assert_eq!((first * 32768.0) as u64, frame);
+        }
+    }
+
+    #[test]
+    fn timestamps_follow_the_time_base_over_the_sample_rate() {
+        // Milliseconds, as Matroska chapters are commonly timed
+        assert_eq!(ts_to_secs(Some(TimeBase::new(1, 1000)), 90_500, 44100), 90.5);
+        assert_eq!(ts_to_secs(None, 88200, 44100), 2.0);
+    }
+
+    // A codec whose parameters changed mid-stream, which can't decode anything more
+    // until it's rebuilt
+    struct ParamsChanged(CodecParameters, AudioBuffer<f32>);
+
+    impl Decoder for ParamsChanged {
+        fn try_new(params: &CodecParameters, _options: &DecoderOptions) -> symphonia::core::errors::Result<Self> {
+            Ok(Self(params.clone(), AudioBuffer::unused()))
+        }
+
+        fn supported_codecs() -> &'static [CodecDescriptor] {
+            &[]
+        }
+
+        fn reset(&mut self) {}
+
+        fn codec_params(&self) -> &CodecParameters {
+            &self.0
+        }
+
+        fn decode(&mut self, _packet: &Packet) -> symphonia::core::errors::Result<AudioBufferRef<'_>> {
+            Err(Error::ResetRequired)
+        }
+
+        fn finalize(&mut self) -> FinalizeResult {
+            FinalizeResult::default()
+        }
+
+        fn last_decoded(&self) -> AudioBufferRef<'_> {
+            self.1.as_audio_buffer_ref()
+        }
+    }
+
+    #[test]
+    fn rebuilds_the_codec_when_its_parameters_change_mid_stream() {
+        let mut decoder = open("reset.wav", &frame_numbered_wav(30000));
+        decoder.set_tolerant(true);
+        let first = decoder.decode_next().unwrap();
+
+        let params = decoder.decoder.codec_params().clone();
+        decoder.decoder = Box::new(ParamsChanged::try_new(&params, &DecoderOptions::default()).unwrap());
+        // The packet that asked for the rebuild is decoded again, so nothing goes missing
+        let next = decoder.decode_next().unwrap();
+        assert_eq!((next[0] * 32768.0) as usize, first.len());
+        assert_eq!(decoder.take_recovered_errors(), 1);
+        assert!(!decoder.has_failed());
+    }
+
+    // `file` opened under the name `name`
+    fn open(name: &str, file: &[u8]) -> SymphoniaDecoder {
+        let path = std::env::temp_dir().join(format!("mewo-{}-{}", std::process::id(), name));
+        std::fs::write(&path, file).unwrap();
+        let decoder = SymphoniaDecoder::new(&path);
+        let _ = std::fs::remove_file(&path);
+        decoder.unwrap()
+    }
+
+    #[test]
+    fn reports_the_codec_of_mp3_and_flac() {
+        // Silent MPEG-1 layer III frames, 128 kbit/s stereo at 44.1 kHz
+        let mut frame = vec![0xff, 0xfb, 0x90, 0x00];
+        frame.resize(417, 0);
+        let decoder = open("codec.mp3", &frame.repeat(40));
+        assert_eq!(decoder.format_name(), "MP3 (mp3)");
+        let info = decoder.codec_info();
+        assert_eq!(info.container.as_deref(), Some("MP3"));
+        assert_eq!(info.codec.as_deref(), Some("mp3"));
+        assert_eq!((info.sample_rate, info.channels), (44100, 2));
+        assert_eq!(info.bitrate_kbps, Some(128));
+
+        // A native FLAC stream: the STREAMINFO from the Ogg mapping header, then frames
+        let mut file = flac_header(44100, 2)[9..].to_vec();
+        for index in 0..4 {
+            file.extend(flac_frame(index, 8192, false));
+        }
+        let decoder = open("codec.flac", &file);
+        assert_eq!(decoder.format_name(), "FLAC (flac)");
+        let info = decoder.codec_info();
+        assert_eq!(info.container.as_deref(), Some("FLAC"));
+        assert_eq!(info.codec.as_deref(), Some("flac"));
+        assert_eq!(info.bits_per_sample, Some(16));
+        assert_eq!((info.sample_rate, info.channels), (44100, 2));
+    }
+
+    #[test]
+    fn lists_and_selects_the_tracks_of_a_multi_track_ogg() {
+        // Two logical FLAC streams, each a header and one empty frame
+        let mut file = Vec::new();
+        file.extend(ogg_page(1, 0, 0x02, 0, &flac_header(44100, 2)));
+        file.extend(ogg_page(2, 0, 0x02, 0, &flac_header(48000, 1)));
+        file.extend(ogg_page(1, 1, 0x04, 44100, &[0xff, 0xf8, 0, 0]));
+        file.extend(ogg_page(2, 1, 0x04, 48000, &[0xff, 0xf8, 0, 0]));
+        let path = std::env::temp_dir().join(format!("mewo-tracks-{}.ogg", std::process::id()));
+        std::fs::write(&path, file).unwrap();
+
+        let tracks = SymphoniaDecoder::new(&path).unwrap().list_tracks();
+        let second = SymphoniaDecoder::new_with_track(&path, 2);
+        let missing = SymphoniaDecoder::new_with_track(&path, 3);
+        let _ = std::fs::remove_file(&path);
+
+        let formats: Vec<_> = tracks.iter().map(|t| (t.id, t.sample_rate, t.channels)).collect();
+        assert_eq!(formats, [(1, Some(44100), Some(2)), (2, Some(48000), Some(1))]);
+        let second = second.unwrap();
+        assert_eq!((second.sample_rate(), second.channels()), (48000, 1));
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn reads_opus_r128_gain() {
+        // -3.5 dB and +1 dB in Q7.8
+        let tags = [tag("R128_TRACK_GAIN", "-896"), tag("R128_ALBUM_GAIN", "256")];
+        let mut metadata = AudioMetadata::default();
+        apply_tags(&mut metadata, &tags, true);
+        assert_eq!(metadata.r128_track_gain_db, Some(-3.5));
+        assert_eq!(metadata.r128_album_gain_db, Some(1.0));
+        // Moved from the -23 LUFS reference to ReplayGain's
+        assert_eq!(metadata.normalization_gain_db(), Some(1.5));
+        // and preferred to a ReplayGain tag the file shouldn't have
+        apply_tags(&mut metadata, &[tag("REPLAYGAIN_TRACK_GAIN", "-6.48 dB")], true);
+        assert_eq!(metadata.normalization_gain_db(), Some(1.5));
+
+        let mut metadata = AudioMetadata::default();
+        apply_tags(&mut metadata, &tags, false);
+        assert_eq!(metadata.r128_track_gain_db, None);
+    }
+}
@@ -1,8 +1,24 @@
-use crate::engine::dsp::biquad::{BiquadFilter, FilterType};
+use crate::engine::dsp::biquad::{BiquadBank, BiquadFilter, CascadedFilter, FilterType};
+use crate::engine::dsp::node::DspNode;
+use crate::engine::dsp::precision::Precision;
+
+// Corner of the rumble high-pass ahead of the shelf
+const RUMBLE_CUTOFF_HZ: f32 = 30.0;
+// Corner of the low-pass picking out the bass the adaptation weighs against the whole
+const ANALYSIS_CUTOFF_HZ: f32 = 150.0;
+// Average limiter gain reduction over an adaptation window that counts as the limiter
+// being engaged rather than catching the odd peak
+const LIMITER_ENGAGED_DB: f32 = 0.5;
+/// Default time `set_enabled` takes to fade the boost fully in or out.
+pub const DEFAULT_TOGGLE_RAMP_MS: f32 = 100.0;
+// Frames between shelf updates while the boost fades in or out
+const RAMP_STEP_FRAMES: usize = 32;
 
 pub struct BassProcessor {
-    high_pass: Vec<BiquadFilter>,
-    shelf: Vec<BiquadFilter>,
+    high_pass: CascadedFilter,
+    shelf: BiquadBank,
+    // One per channel, run over a copy of the input to measure its bass energy
+    analysis: Vec<BiquadFilter>,
     channels: usize,
     sample_rate: f32,
     low_energy: Vec<f32>,
@@ -12,21 +28,30 @@ pub struct BassProcessor {
     current_gain: f32,
     enabled: bool,
     intensity: f32,
+    auto_headroom: bool,
+    headroom: f32,
+    limiter_coupling: f32,
+    // Limiter gain reduction reported since the last adaptation, in positive dB
+    limiter_reduction: f32,
+    limiter_reports: usize,
+    // How much of `current_gain` the shelf applies, ramped between 0 and 1 on a toggle
+    mix: f32,
+    ramp_secs: f32,
 }
 
 impl BassProcessor {
     pub fn new(sample_rate: f32, channels: usize) -> Self {
-        let mut high_pass = Vec::new();
-        let mut shelf = Vec::new();
+        let high_pass = CascadedFilter::new(channels, FilterType::HighPass, sample_rate, RUMBLE_CUTOFF_HZ, 2);
+        let shelf = BiquadBank::new(channels, FilterType::LowShelf, sample_rate, 60.0, 0.6, 0.0);
 
-        for _ in 0..channels {
-            high_pass.push(BiquadFilter::new(FilterType::HighPass, sample_rate, 30.0, 0.707, 0.0));
-            shelf.push(BiquadFilter::new(FilterType::LowShelf, sample_rate, 60.0, 0.6, 0.0));
-        }
+        let analysis = (0..channels)
+            .map(|_| BiquadFilter::new(FilterType::LowPass, sample_rate, ANALYSIS_CUTOFF_HZ, 0.707, 0.0))
+            .collect();
 
         Self {
             high_pass,
             shelf,
+            analysis,
             channels,
             sample_rate,
             low_energy: vec![0.0; channels],
@@ -36,58 +61,201 @@ impl BassProcessor {
             current_gain: 0.0,
             enabled: false,
             intensity: 50.0,
+            auto_headroom: false,
+            headroom: 1.0,
+            limiter_coupling: 0.5,
+            limiter_reduction: 0.0,
+            limiter_reports: 0,
+            mix: 0.0,
+            ramp_secs: DEFAULT_TOGGLE_RAMP_MS / 1000.0,
         }
     }
 
+    /// Fades the boost in or out over the toggle ramp time, from wherever it is now, so
+    /// switching it at full boost doesn't jump.
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
     }
 
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Time a full fade in or out takes on `set_enabled`, `DEFAULT_TOGGLE_RAMP_MS` unless
+    /// changed. Zero switches at once.
+    pub fn set_toggle_ramp_ms(&mut self, ms: f32) {
+        self.ramp_secs = ms.max(0.0) / 1000.0;
+    }
+
     pub fn set_intensity(&mut self, intensity: f32) {
         self.intensity = intensity.clamp(0.0, 100.0);
     }
 
+    /// Order of the rumble high-pass, 2 (12 dB/oct, the default) up to 8 (48 dB/oct). A
+    /// steeper cut lets the shelf boost more without feeding subsonic content.
+    pub fn set_rumble_order(&mut self, order: usize) {
+        let order = order.clamp(2, 8).div_ceil(2) * 2;
+        if order != self.high_pass.order() {
+            self.high_pass.set_order(order);
+        }
+    }
+
+    /// How strongly the adaptive boost backs off while the limiter after it keeps
+    /// reducing gain, `0.0` (ignore the limiter) to `1.0`. At `0.5` (the default) each dB
+    /// of sustained reduction takes 0.1 dB off the target per adaptation step, and the
+    /// target stops climbing.
+    pub fn set_limiter_coupling(&mut self, coupling: f32) {
+        self.limiter_coupling = coupling.clamp(0.0, 1.0);
+    }
+
+    /// Reports the limiter's gain reduction after a block, in dB (negative while
+    /// limiting). Averaged over each adaptation window.
+    pub fn observe_limiter(&mut self, reduction_db: f32) {
+        self.limiter_reduction += -reduction_db.min(0.0);
+        self.limiter_reports += 1;
+    }
+
+    pub fn set_auto_headroom(&mut self, enabled: bool) {
+        self.auto_headroom = enabled;
+        self.update_headroom();
+    }
+
+    // The shelf adds at most the applied gain in dB, so taking that off up front keeps
+    // the boosted peaks at the input level
+    fn update_headroom(&mut self) {
+        self.headroom = if self.auto_headroom {
+            10.0f32.powf(-self.current_gain * self.mix / 20.0)
+        } else {
+            1.0
+        };
+    }
+
     fn update_gain(&mut self) {
         let diff = self.target_gain - self.current_gain;
         if diff.abs() > 0.0001 {
             self.current_gain += diff * 0.005;
-            for ch in 0..self.channels {
-                self.shelf[ch].update(
-                    FilterType::LowShelf,
-                    self.sample_rate,
-                    60.0,
-                    0.6,
-                    self.current_gain,
-                );
-            }
+            self.update_shelf();
         }
     }
 
+    fn update_shelf(&mut self) {
+        self.shelf.update(
+            FilterType::LowShelf,
+            self.sample_rate,
+            60.0,
+            0.6,
+            self.current_gain * self.mix,
+        );
+        self.update_headroom();
+    }
+
+    fn mix_target(&self) -> f32 {
+        if self.enabled { 1.0 } else { 0.0 }
+    }
+
+    // Moves the toggle fade on by `frames`, at a constant rate so a full fade takes the
+    // ramp time whatever the gain
+    fn step_mix(&mut self, frames: usize) {
+        let target = self.mix_target();
+        let step = if self.ramp_secs > 0.0 {
+            frames as f32 / (self.ramp_secs * self.sample_rate)
+        } else {
+            1.0
+        };
+        self.mix = if target > self.mix {
+            (self.mix + step).min(target)
+        } else {
+            (self.mix - step).max(target)
+        };
+        self.update_shelf();
+    }
+
     pub fn process(&mut self, samples: &mut [f32]) {
-        let frames = samples.len() / self.channels;
         self.update_gain();
 
-        for i in 0..frames {
-            for ch in 0..self.channels {
-                let idx = i * self.channels + ch;
-                let input = samples[idx];
-
-                self.total_energy[ch] += input * input;
-                self.low_energy[ch] += input * input;
+        if self.mix != self.mix_target() {
+            let step = RAMP_STEP_FRAMES * self.channels;
+            for chunk in samples.chunks_mut(step) {
+                self.step_mix(chunk.len() / self.channels);
+                self.process_frames(chunk);
+            }
+        } else {
+            self.process_frames(samples);
+        }
 
-                let mut x = self.high_pass[ch].process(input);
-                x = self.shelf[ch].process(x);
+        if self.count >= 2048 {
+            self.adapt();
+        }
+    }
 
-                samples[idx] = x;
+    fn process_frames(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(self.channels) {
+            for (ch, input) in frame.iter_mut().enumerate() {
+                let low = self.analysis[ch].process(*input);
+                self.total_energy[ch] += *input * *input;
+                self.low_energy[ch] += low * low;
+                *input *= self.headroom;
             }
             self.count += 1;
         }
+        self.high_pass.process_interleaved(samples);
+        self.shelf.process_interleaved(samples);
+    }
+
+    // Same processing as `process`, over one contiguous buffer per channel
+    pub fn process_planar(&mut self, planar: &mut [Vec<f32>]) {
+        self.update_gain();
+
+        let frames = planar.first().map_or(0, |channel| channel.len());
+        if self.mix != self.mix_target() {
+            let mut start = 0;
+            while start < frames {
+                let end = (start + RAMP_STEP_FRAMES).min(frames);
+                self.step_mix(end - start);
+                for (ch, channel) in planar.iter_mut().enumerate() {
+                    self.process_channel(ch, &mut channel[start..end]);
+                }
+                start = end;
+            }
+        } else {
+            for (ch, channel) in planar.iter_mut().enumerate() {
+                self.process_channel(ch, channel);
+            }
+        }
+        self.count += frames;
 
         if self.count >= 2048 {
             self.adapt();
         }
     }
 
+    fn process_channel(&mut self, ch: usize, channel: &mut [f32]) {
+        let energy: f32 = channel.iter().map(|x| x * x).sum();
+        let analysis = &mut self.analysis[ch];
+        let low_energy: f32 = channel.iter().map(|&x| analysis.process(x).powi(2)).sum();
+        if self.headroom != 1.0 {
+            channel.iter_mut().for_each(|x| *x *= self.headroom);
+        }
+        self.high_pass.process_channel(ch, channel);
+        self.shelf.process_channel(ch, channel);
+
+        self.total_energy[ch] += energy;
+        self.low_energy[ch] += low_energy;
+    }
+
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.high_pass.set_precision(precision);
+        self.shelf.set_precision(precision);
+    }
+
+    /// Clears the filter history. The adaptive gain is kept, so the boost doesn't
+    /// have to settle again.
+    pub fn reset(&mut self) {
+        self.high_pass.reset();
+        self.shelf.reset();
+        self.analysis.iter_mut().for_each(BiquadFilter::reset);
+    }
+
     fn adapt(&mut self) {
         if !self.enabled {
             self.target_gain = 0.0;
@@ -116,7 +284,19 @@ impl BassProcessor {
         bass_ratio /= self.channels as f32;
         total /= self.channels as f32;
 
-        if total > 0.0001 {
+        let limiting = if self.limiter_reports > 0 {
+            self.limiter_reduction / self.limiter_reports as f32
+        } else {
+            0.0
+        };
+        self.limiter_reduction = 0.0;
+        self.limiter_reports = 0;
+
+        if self.limiter_coupling > 0.0 && limiting > LIMITER_ENGAGED_DB {
+            // Boosting into a limiter that's already clamping only buys more limiting
+            let backoff = 0.2 * self.limiter_coupling * limiting;
+            self.target_gain = (self.target_gain - backoff).max(0.0);
+        } else if total > 0.0001 {
             if bass_ratio < 0.4 {
                 self.target_gain = (self.target_gain + 0.2).min(max_gain);
             } else if bass_ratio > 0.6 {
@@ -126,4 +306,146 @@ impl BassProcessor {
 
         self.count = 0;
     }
-}
\ No newline at end of file
+}
+
+impl DspNode for BassProcessor {
+    fn process(&mut self, samples: &mut [f32], _channels: usize, _sample_rate: f32) {
+        BassProcessor::process(self, samples);
+    }
+
+    fn reset(&mut self) {
+        BassProcessor::reset(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::decoder::AudioDecoder;
+    use crate::engine::dsp::limiter::ChannelLimiter;
+    use crate::test_util::signal_generator::{Signal, SignalGenerator};
+
+    // Peak of the last second of a two-second tone after the bass stage
+    fn peak_after_bass(frequency: f64) -> f32 {
+        let signal = Signal::Sine { frequency, amplitude: 0.5 };
+        let mut generator = SignalGenerator::new(signal, 44100, 2, 2.0);
+        let mut bass = BassProcessor::new(44100.0, 2);
+        let mut output = Vec::new();
+        while let Some(mut block) = generator.decode_next() {
+            bass.process(&mut block);
+            output.extend_from_slice(&block);
+        }
+        output[output.len() / 2..].iter().fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+
+    // Peak of half a second of a 0.5 amplitude tone at `frequency` through the boost,
+    // pinned at the full +8 dB whatever the adaptation would make of the signal. One
+    // block, so it doesn't get to adapt partway
+    fn boosted_peak(frequency: f64, auto_headroom: bool) -> f32 {
+        let mut bass = BassProcessor::new(44100.0, 2);
+        bass.set_enabled(true);
+        bass.set_intensity(100.0);
+        bass.target_gain = 8.0;
+        bass.current_gain = 8.0;
+        bass.mix = 1.0;
+        bass.update_shelf();
+        bass.set_auto_headroom(auto_headroom);
+
+        let signal = Signal::Sine { frequency, amplitude: 0.5 };
+        let mut generator = SignalGenerator::new(signal, 44100, 2, 0.5);
+        let mut block = Vec::new();
+        while let Some(samples) = generator.decode_next() {
+            block.extend(samples);
+        }
+        bass.process(&mut block);
+        block[block.len() / 2..].iter().fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+
+    #[test]
+    fn auto_headroom_keeps_full_boost_under_the_input_peak() {
+        // Deep in the shelf the boost alone drives a half-scale tone past full scale
+        assert!(boosted_peak(40.0, false) > 1.0);
+
+        // With the headroom it stays within 1 dB of where it went in
+        for frequency in [40.0, 60.0, 1000.0] {
+            let peak = boosted_peak(frequency, true);
+            assert!(peak < 0.5 * 1.122, "{frequency} Hz: {peak}");
+        }
+    }
+
+    // Runs `secs` of a 1 kHz tone, bass-light, through the boost and a limiter at -1 dBFS
+    // after it, reporting the limiter's gain reduction back after each block as the chain
+    // does. Returns the boost's target at the end
+    fn run_into_limiter(bass: &mut BassProcessor, amplitude: f32, secs: f64) -> f32 {
+        let mut limiter = ChannelLimiter::new(-1.0, 44100.0, 2);
+        let signal = Signal::Sine { frequency: 1000.0, amplitude };
+        let mut generator = SignalGenerator::new(signal, 44100, 2, secs);
+        while let Some(mut block) = generator.decode_next() {
+            bass.process(&mut block);
+            limiter.process(&mut block);
+            bass.observe_limiter(limiter.gain_reduction_db());
+        }
+        bass.target_gain
+    }
+
+    #[test]
+    fn target_stops_climbing_once_the_limiter_engages() {
+        let mut bass = BassProcessor::new(44100.0, 2);
+        bass.set_enabled(true);
+        bass.set_intensity(100.0);
+
+        // Under the limiter, bass-light audio gets boosted
+        let climbed = run_into_limiter(&mut bass, 0.5, 1.0);
+        assert!(climbed > 2.0, "{climbed}");
+
+        // Hot enough that the limiter keeps clamping, the target backs off instead
+        let backed_off = run_into_limiter(&mut bass, 2.0, 0.5);
+        assert!(backed_off < climbed, "{climbed} then {backed_off}");
+
+        // Uncoupled, the limiter is ignored and it keeps climbing
+        bass.set_limiter_coupling(0.0);
+        let uncoupled = run_into_limiter(&mut bass, 2.0, 0.5);
+        assert!(uncoupled > backed_off, "{backed_off} then {uncoupled}");
+    }
+
+    // Milliseconds a boost at the full +8 dB takes to reach 0 dB once disabled, processed
+    // in 1 ms blocks, and how much of the boost the fade still lets through halfway
+    fn disable_ramp(ramp_ms: f32) -> (usize, f32) {
+        let mut bass = BassProcessor::new(44100.0, 2);
+        bass.set_toggle_ramp_ms(ramp_ms);
+        bass.set_enabled(true);
+        bass.target_gain = 8.0;
+        bass.current_gain = 8.0;
+        bass.mix = 1.0;
+        bass.update_shelf();
+
+        bass.set_enabled(false);
+        let mut halfway = 1.0;
+        for ms in 1..1000 {
+            bass.process(&mut [0.1; 44 * 2]);
+            if ms as f32 == (ramp_ms / 2.0).round() {
+                halfway = bass.mix;
+            }
+            if bass.mix == 0.0 {
+                return (ms, halfway);
+            }
+        }
+        panic!("never reached 0 dB");
+    }
+
+    #[test]
+    fn disabling_from_full_boost_ramps_over_the_toggle_time() {
+        for ramp_ms in [100.0, 250.0] {
+            let (ms, halfway) = disable_ramp(ramp_ms);
+            assert!((ms as f32 - ramp_ms).abs() <= 1.0, "{ramp_ms} ms ramp took {ms} ms");
+            assert!((halfway - 0.5).abs() < 0.02, "{ramp_ms} ms ramp: {halfway} halfway");
+        }
+        assert_eq!(disable_ramp(0.0).0, 1);
+    }
+
+    #[test]
+    fn rumble_high_pass_attenuates_20_hz() {
+        assert!(peak_after_bass(20.0) < 0.25);
+        assert!(peak_after_bass(1000.0) > 0.45);
+    }
+}
@@ -1,10 +1,47 @@
 use std::f32::consts::PI;
 
+use crate::engine::dsp::precision::{Float, Precision};
+
+#[derive(Clone, Copy)]
 pub enum FilterType {
     HighPass,
     LowShelf,
     LowPass,
     HighShelf,
+    AllPass,
+    Peaking,
+}
+
+// Filter type, sample rate, frequency, Q and gain a biquad was last tuned with
+type Params = (FilterType, f32, f32, f32, f32);
+
+// Coefficients and state of a biquad running in f64
+#[derive(Clone, Copy)]
+struct Double {
+    coefficients: [f64; 5],
+    z1: f64,
+    z2: f64,
+}
+
+impl Double {
+    fn new(params: Params, z1: f32, z2: f32) -> Self {
+        let (filter_type, sample_rate, frequency, q, gain_db) = params;
+        Self {
+            coefficients: coefficients(filter_type, sample_rate, frequency, q, gain_db),
+            z1: z1 as f64,
+            z2: z2 as f64,
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, x: f32) -> f32 {
+        let [b0, b1, b2, a1, a2] = self.coefficients;
+        let x = x as f64;
+        let y = b0 * x + self.z1;
+        self.z1 = b1 * x - a1 * y + self.z2;
+        self.z2 = b2 * x - a2 * y;
+        y as f32
+    }
 }
 
 pub struct BiquadFilter {
@@ -15,6 +52,8 @@ pub struct BiquadFilter {
     a2: f32,
     z1: f32,
     z2: f32,
+    params: Params,
+    double: Option<Double>,
 }
 
 impl BiquadFilter {
@@ -33,6 +72,8 @@ impl BiquadFilter {
             a2: 0.0,
             z1: 0.0,
             z2: 0.0,
+            params: (filter_type, sample_rate, frequency, q, gain_db),
+            double: None,
         };
         f.update(filter_type, sample_rate, frequency, q, gain_db);
         f
@@ -46,82 +87,32 @@ impl BiquadFilter {
         q: f32,
         gain_db: f32,
     ) {
-        let w0 = 2.0 * PI * frequency / sample_rate;
-        let cos = w0.cos();
-        let sin = w0.sin();
-        let a = 10.0f32.powf(gain_db / 40.0);
-
-        match filter_type {
-            FilterType::HighPass => {
-                let alpha = sin / (2.0 * q);
-                let b0 = (1.0 + cos) / 2.0;
-                let b1 = -(1.0 + cos);
-                let b2 = (1.0 + cos) / 2.0;
-                let a0 = 1.0 + alpha;
-                let a1 = -2.0 * cos;
-                let a2 = 1.0 - alpha;
-
-                self.b0 = b0 / a0;
-                self.b1 = b1 / a0;
-                self.b2 = b2 / a0;
-                self.a1 = a1 / a0;
-                self.a2 = a2 / a0;
-            }
-
-            FilterType::LowPass => {
-                let alpha = sin / (2.0 * q);
-                let b0 = (1.0 - cos) / 2.0;
-                let b1 = 1.0 - cos;
-                let b2 = (1.0 - cos) / 2.0;
-                let a0 = 1.0 + alpha;
-                let a1 = -2.0 * cos;
-                let a2 = 1.0 - alpha;
-
-                self.b0 = b0 / a0;
-                self.b1 = b1 / a0;
-                self.b2 = b2 / a0;
-                self.a1 = a1 / a0;
-                self.a2 = a2 / a0;
-            }
-
-            FilterType::LowShelf => {
-                let alpha = sin / 2.0 * ((a + 1.0 / a) * (1.0 / q - 1.0) + 2.0).sqrt();
-
-                let b0 = a * ((a + 1.0) - (a - 1.0) * cos + alpha);
-                let b1 = 2.0 * a * ((a - 1.0) - (a + 1.0) * cos);
-                let b2 = a * ((a + 1.0) - (a - 1.0) * cos - alpha);
-                let a0 = (a + 1.0) + (a - 1.0) * cos + alpha;
-                let a1 = -2.0 * ((a - 1.0) + (a + 1.0) * cos);
-                let a2 = (a + 1.0) + (a - 1.0) * cos - alpha;
-
-                self.b0 = b0 / a0;
-                self.b1 = b1 / a0;
-                self.b2 = b2 / a0;
-                self.a1 = a1 / a0;
-                self.a2 = a2 / a0;
-            }
+        self.params = (filter_type, sample_rate, frequency, q, gain_db);
+        [self.b0, self.b1, self.b2, self.a1, self.a2] =
+            coefficients(filter_type, sample_rate, frequency, q, gain_db);
+        if let Some(double) = &mut self.double {
+            double.coefficients = coefficients(filter_type, sample_rate, frequency, q, gain_db);
+        }
+    }
 
-            FilterType::HighShelf => {
-                let alpha = sin / 2.0 * ((a + 1.0 / a) * (1.0 / q - 1.0) + 2.0).sqrt();
-
-                let b0 = a * ((a + 1.0) + (a - 1.0) * cos + alpha);
-                let b1 = -2.0 * a * ((a - 1.0) + (a + 1.0) * cos);
-                let b2 = a * ((a + 1.0) + (a - 1.0) * cos - alpha);
-                let a0 = (a + 1.0) - (a - 1.0) * cos + alpha;
-                let a1 = 2.0 * ((a - 1.0) - (a + 1.0) * cos);
-                let a2 = (a + 1.0) - (a - 1.0) * cos - alpha;
-
-                self.b0 = b0 / a0;
-                self.b1 = b1 / a0;
-                self.b2 = b2 / a0;
-                self.a1 = a1 / a0;
-                self.a2 = a2 / a0;
+    /// Switches the arithmetic the filter runs in, carrying its state over.
+    pub fn set_precision(&mut self, precision: Precision) {
+        match (precision, &self.double) {
+            (Precision::F64, None) => self.double = Some(Double::new(self.params, self.z1, self.z2)),
+            (Precision::F32, Some(double)) => {
+                self.z1 = double.z1 as f32;
+                self.z2 = double.z2 as f32;
+                self.double = None;
             }
+            _ => {}
         }
     }
 
     #[inline]
     pub fn process(&mut self, x: f32) -> f32 {
+        if let Some(double) = &mut self.double {
+            return double.process(x);
+        }
         let y = self.b0 * x + self.z1;
         self.z1 = self.b1 * x - self.a1 * y + self.z2;
         self.z2 = self.b2 * x - self.a2 * y;
@@ -131,5 +122,492 @@ impl BiquadFilter {
     pub fn reset(&mut self) {
         self.z1 = 0.0;
         self.z2 = 0.0;
+        if let Some(double) = &mut self.double {
+            double.z1 = 0.0;
+            double.z2 = 0.0;
+        }
+    }
+}
+
+#[cfg(feature = "simd")]
+const LANES: usize = 4;
+
+/// A bank of identical biquads, one per channel, with coefficients and state stored
+/// per field rather than per filter. Each channel has its own coefficients, so they can
+/// still be retuned independently. With the `simd` feature every frame runs through the
+/// bank four channels at a time as one vector filter, the storage padded out to whole
+/// groups, so stereo is a single group with two idle lanes.
+pub struct BiquadBank {
+    b0: Vec<f32>,
+    b1: Vec<f32>,
+    b2: Vec<f32>,
+    a1: Vec<f32>,
+    a2: Vec<f32>,
+    z1: Vec<f32>,
+    z2: Vec<f32>,
+    channels: usize,
+    params: Vec<Params>,
+    // One f64 filter per channel while running in `Precision::F64`
+    double: Option<Vec<Double>>,
+}
+
+impl BiquadBank {
+    pub fn new(
+        channels: usize,
+        filter_type: FilterType,
+        sample_rate: f32,
+        frequency: f32,
+        q: f32,
+        gain_db: f32,
+    ) -> Self {
+        #[cfg(feature = "simd")]
+        let padded = channels.next_multiple_of(LANES);
+        #[cfg(not(feature = "simd"))]
+        let padded = channels;
+        // Coefficients and state are stored for whole lane groups with the `simd` feature.
+        // The padding lanes only ever see silence, so they stay pass-through filters
+        let mut bank = Self {
+            b0: vec![1.0; padded],
+            b1: vec![0.0; padded],
+            b2: vec![0.0; padded],
+            a1: vec![0.0; padded],
+            a2: vec![0.0; padded],
+            z1: vec![0.0; padded],
+            z2: vec![0.0; padded],
+            channels,
+            params: vec![(filter_type, sample_rate, frequency, q, gain_db); channels],
+            double: None,
+        };
+        bank.update(filter_type, sample_rate, frequency, q, gain_db);
+        bank
+    }
+
+    /// Retunes every channel.
+    pub fn update(
+        &mut self,
+        filter_type: FilterType,
+        sample_rate: f32,
+        frequency: f32,
+        q: f32,
+        gain_db: f32,
+    ) {
+        let params = (filter_type, sample_rate, frequency, q, gain_db);
+        let single = coefficients(filter_type, sample_rate, frequency, q, gain_db);
+        let double = self.double.is_some().then(|| coefficients(filter_type, sample_rate, frequency, q, gain_db));
+        for ch in 0..self.channels {
+            self.set_coefficients(ch, params, single, double);
+        }
+    }
+
+    pub fn update_channel(
+        &mut self,
+        ch: usize,
+        filter_type: FilterType,
+        sample_rate: f32,
+        frequency: f32,
+        q: f32,
+        gain_db: f32,
+    ) {
+        let params = (filter_type, sample_rate, frequency, q, gain_db);
+        let single = coefficients(filter_type, sample_rate, frequency, q, gain_db);
+        let double = self.double.is_some().then(|| coefficients(filter_type, sample_rate, frequency, q, gain_db));
+        self.set_coefficients(ch, params, single, double);
+    }
+
+    fn set_coefficients(&mut self, ch: usize, params: Params, single: [f32; 5], double: Option<[f64; 5]>) {
+        self.params[ch] = params;
+        [self.b0[ch], self.b1[ch], self.b2[ch], self.a1[ch], self.a2[ch]] = single;
+        if let (Some(filters), Some(coefficients)) = (&mut self.double, double) {
+            filters[ch].coefficients = coefficients;
+        }
+    }
+
+    /// Switches the arithmetic every channel runs in, carrying their state over. F64 skips
+    /// the SIMD path.
+    pub fn set_precision(&mut self, precision: Precision) {
+        match (precision, &self.double) {
+            (Precision::F64, None) => {
+                let filters = (0..self.channels)
+                    .map(|ch| Double::new(self.params[ch], self.z1[ch], self.z2[ch]))
+                    .collect();
+                self.double = Some(filters);
+            }
+            (Precision::F32, Some(filters)) => {
+                for (ch, filter) in filters.iter().enumerate() {
+                    self.z1[ch] = filter.z1 as f32;
+                    self.z2[ch] = filter.z2 as f32;
+                }
+                self.double = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Filters interleaved `samples`, a whole number of frames holding a sample for
+    /// every channel.
+    pub fn process_interleaved(&mut self, samples: &mut [f32]) {
+        if let Some(filters) = &mut self.double {
+            for frame in samples.chunks_exact_mut(self.channels) {
+                for (filter, sample) in filters.iter_mut().zip(frame.iter_mut()) {
+                    *sample = filter.process(*sample);
+                }
+            }
+            return;
+        }
+        #[cfg(feature = "simd")]
+        self.process_lanes(samples);
+        #[cfg(not(feature = "simd"))]
+        self.process_scalar(samples);
+    }
+
+    // Runs every channel one sample at a time
+    #[cfg(not(feature = "simd"))]
+    #[inline]
+    fn process_scalar(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(self.channels) {
+            for (ch, sample) in frame.iter_mut().enumerate() {
+                let x = *sample;
+                let y = self.b0[ch] * x + self.z1[ch];
+                self.z1[ch] = self.b1[ch] * x - self.a1[ch] * y + self.z2[ch];
+                self.z2[ch] = self.b2[ch] * x - self.a2[ch] * y;
+                *sample = y;
+            }
+        }
+    }
+
+    // Runs each group of four channels as one vector filter, keeping its coefficients
+    // and state in registers across the block. A group past the last channel is filled
+    // out with silence on the way in and cut back on the way out.
+    #[cfg(feature = "simd")]
+    #[inline]
+    fn process_lanes(&mut self, samples: &mut [f32]) {
+        use wide::f32x4;
+
+        let load = |v: &[f32], at: usize| f32x4::new(v[at..at + LANES].try_into().unwrap());
+        for at in (0..self.b0.len()).step_by(LANES) {
+            let width = (self.channels - at).min(LANES);
+            let (b0, b1, b2) = (load(&self.b0, at), load(&self.b1, at), load(&self.b2, at));
+            let (a1, a2) = (load(&self.a1, at), load(&self.a2, at));
+            let (mut z1, mut z2) = (load(&self.z1, at), load(&self.z2, at));
+            let mut lanes = [0.0f32; LANES];
+            for frame in samples.chunks_exact_mut(self.channels) {
+                lanes[..width].copy_from_slice(&frame[at..at + width]);
+                let x = f32x4::new(lanes);
+                let y = b0 * x + z1;
+                z1 = b1 * x - a1 * y + z2;
+                z2 = b2 * x - a2 * y;
+                frame[at..at + width].copy_from_slice(&y.as_array_ref()[..width]);
+            }
+            self.z1[at..at + LANES].copy_from_slice(z1.as_array_ref());
+            self.z2[at..at + LANES].copy_from_slice(z2.as_array_ref());
+        }
+    }
+
+    /// Filters a contiguous run of samples from a single channel.
+    pub fn process_channel(&mut self, ch: usize, samples: &mut [f32]) {
+        if let Some(filters) = &mut self.double {
+            for sample in samples.iter_mut() {
+                *sample = filters[ch].process(*sample);
+            }
+            return;
+        }
+        let (b0, b1, b2, a1, a2) = (self.b0[ch], self.b1[ch], self.b2[ch], self.a1[ch], self.a2[ch]);
+        let (mut z1, mut z2) = (self.z1[ch], self.z2[ch]);
+        for sample in samples.iter_mut() {
+            let x = *sample;
+            let y = b0 * x + z1;
+            z1 = b1 * x - a1 * y + z2;
+            z2 = b2 * x - a2 * y;
+            *sample = y;
+        }
+        self.z1[ch] = z1;
+        self.z2[ch] = z2;
+    }
+
+    pub fn reset(&mut self) {
+        self.z1.fill(0.0);
+        self.z2.fill(0.0);
+        for filter in self.double.iter_mut().flatten() {
+            filter.z1 = 0.0;
+            filter.z2 = 0.0;
+        }
+    }
+}
+
+/// A Butterworth low- or high-pass of any even order, built from a cascade of biquad
+/// banks with the per-stage Q values that make the overall response maximally flat.
+/// Each second-order stage adds 12 dB/oct, so order 4 is 24 dB/oct, 8 is 48 dB/oct.
+pub struct CascadedFilter {
+    stages: Vec<BiquadBank>,
+    channels: usize,
+    filter_type: FilterType,
+    sample_rate: f32,
+    frequency: f32,
+    precision: Precision,
+}
+
+impl CascadedFilter {
+    /// `filter_type` should be `HighPass` or `LowPass`. `order` is rounded up to even.
+    pub fn new(
+        channels: usize,
+        filter_type: FilterType,
+        sample_rate: f32,
+        frequency: f32,
+        order: usize,
+    ) -> Self {
+        let mut filter = Self {
+            stages: Vec::new(),
+            channels,
+            filter_type,
+            sample_rate,
+            frequency,
+            precision: Precision::F32,
+        };
+        filter.set_order(order);
+        filter
+    }
+
+    pub fn order(&self) -> usize {
+        self.stages.len() * 2
+    }
+
+    /// Rebuilds the cascade, which clears its state.
+    pub fn set_order(&mut self, order: usize) {
+        let stages = order.div_ceil(2).max(1);
+        let order = stages * 2;
+        self.stages = (0..stages)
+            .map(|k| {
+                let q = butterworth_q(order, k);
+                let mut stage =
+                    BiquadBank::new(self.channels, self.filter_type, self.sample_rate, self.frequency, q, 0.0);
+                stage.set_precision(self.precision);
+                stage
+            })
+            .collect();
+    }
+
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.precision = precision;
+        for stage in &mut self.stages {
+            stage.set_precision(precision);
+        }
     }
-}
\ No newline at end of file
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+        let order = self.order();
+        for (k, stage) in self.stages.iter_mut().enumerate() {
+            stage.update(self.filter_type, self.sample_rate, frequency, butterworth_q(order, k), 0.0);
+        }
+    }
+
+    pub fn process_interleaved(&mut self, samples: &mut [f32]) {
+        for stage in &mut self.stages {
+            stage.process_interleaved(samples);
+        }
+    }
+
+    pub fn process_channel(&mut self, ch: usize, samples: &mut [f32]) {
+        for stage in &mut self.stages {
+            stage.process_channel(ch, samples);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {
+            stage.reset();
+        }
+    }
+}
+
+// Q of second-order section `k` of an `order`-pole Butterworth filter, from the angle of
+// its conjugate pole pair
+fn butterworth_q(order: usize, k: usize) -> f32 {
+    let angle = PI * (2 * k + 1) as f32 / (2 * order) as f32;
+    1.0 / (2.0 * angle.cos())
+}
+
+// Normalized `[b0, b1, b2, a1, a2]` of the RBJ cookbook filter, computed in `T`
+fn coefficients<T: Float>(filter_type: FilterType, sample_rate: f32, frequency: f32, q: f32, gain_db: f32) -> [T; 5] {
+    let c = T::from_f32;
+    let q = c(q);
+    let w0 = c(2.0) * T::PI * c(frequency) / c(sample_rate);
+    let cos = w0.cos();
+    let sin = w0.sin();
+    let a = c(10.0).powf(c(gain_db) / c(40.0));
+
+    match filter_type {
+        FilterType::HighPass => {
+            let alpha = sin / (c(2.0) * q);
+            let b0 = (c(1.0) + cos) / c(2.0);
+            let b1 = -(c(1.0) + cos);
+            let b2 = (c(1.0) + cos) / c(2.0);
+            let a0 = c(1.0) + alpha;
+            let a1 = c(-2.0) * cos;
+            let a2 = c(1.0) - alpha;
+
+            [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0]
+        }
+
+        FilterType::LowPass => {
+            let alpha = sin / (c(2.0) * q);
+            let b0 = (c(1.0) - cos) / c(2.0);
+            let b1 = c(1.0) - cos;
+            let b2 = (c(1.0) - cos) / c(2.0);
+            let a0 = c(1.0) + alpha;
+            let a1 = c(-2.0) * cos;
+            let a2 = c(1.0) - alpha;
+
+            [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0]
+        }
+
+        FilterType::LowShelf => {
+            let alpha = sin / c(2.0) * ((a + c(1.0) / a) * (c(1.0) / q - c(1.0)) + c(2.0)).sqrt();
+
+            let b0 = a * ((a + c(1.0)) - (a - c(1.0)) * cos + alpha);
+            let b1 = c(2.0) * a * ((a - c(1.0)) - (a + c(1.0)) * cos);
+            let b2 = a * ((a + c(1.0)) - (a - c(1.0)) * cos - alpha);
+            let a0 = (a + c(1.0)) + (a - c(1.0)) * cos + alpha;
+            let a1 = c(-2.0) * ((a - c(1.0)) + (a + c(1.0)) * cos);
+            let a2 = (a + c(1.0)) + (a - c(1.0)) * cos - alpha;
+
+            [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0]
+        }
+
+        FilterType::AllPass => {
+            let alpha = sin / (c(2.0) * q);
+            let b0 = c(1.0) - alpha;
+            let b1 = c(-2.0) * cos;
+            let b2 = c(1.0) + alpha;
+            let a0 = c(1.0) + alpha;
+            let a1 = c(-2.0) * cos;
+            let a2 = c(1.0) - alpha;
+
+            [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0]
+        }
+
+        FilterType::HighShelf => {
+            let alpha = sin / c(2.0) * ((a + c(1.0) / a) * (c(1.0) / q - c(1.0)) + c(2.0)).sqrt();
+
+            let b0 = a * ((a + c(1.0)) + (a - c(1.0)) * cos + alpha);
+            let b1 = c(-2.0) * a * ((a - c(1.0)) + (a + c(1.0)) * cos);
+            let b2 = a * ((a + c(1.0)) + (a - c(1.0)) * cos - alpha);
+            let a0 = (a + c(1.0)) - (a - c(1.0)) * cos + alpha;
+            let a1 = c(2.0) * ((a - c(1.0)) - (a + c(1.0)) * cos);
+            let a2 = (a + c(1.0)) - (a - c(1.0)) * cos - alpha;
+
+            [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0]
+        }
+
+        FilterType::Peaking => {
+            let alpha = sin / (c(2.0) * q);
+            let b0 = c(1.0) + alpha * a;
+            let b1 = c(-2.0) * cos;
+            let b2 = c(1.0) - alpha * a;
+            let a0 = c(1.0) + alpha / a;
+            let a1 = c(-2.0) * cos;
+            let a2 = c(1.0) - alpha / a;
+
+            [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::decoder::AudioDecoder;
+    use crate::test_util::signal_generator::{Signal, SignalGenerator};
+
+    // Gain and phase shift of `filter` for a sine at `frequency`, measured after it settles
+    fn sine_response(filter: &mut BiquadFilter, frequency: f32, sample_rate: f32) -> (f32, f32) {
+        let step = 2.0 * std::f32::consts::PI * frequency / sample_rate;
+        let settle = sample_rate as usize / 2;
+        let (mut in_phase, mut quadrature, mut input_power) = (0.0f64, 0.0f64, 0.0f64);
+        for n in 0..settle * 2 {
+            let x = (step * n as f32).sin();
+            let y = filter.process(x);
+            if n >= settle {
+                in_phase += (y * x) as f64;
+                quadrature += (y * (step * n as f32).cos()) as f64;
+                input_power += (x * x) as f64;
+            }
+        }
+        let gain = (in_phase * in_phase + quadrature * quadrature).sqrt() / input_power;
+        (gain as f32, quadrature.atan2(in_phase) as f32)
+    }
+
+    #[test]
+    fn all_pass_is_flat_but_shifts_the_phase() {
+        for frequency in [100.0, 500.0, 1000.0, 2000.0, 8000.0] {
+            let mut filter = BiquadFilter::new(FilterType::AllPass, 48000.0, 1000.0, 0.7, 0.0);
+            let (gain, phase) = sine_response(&mut filter, frequency, 48000.0);
+            assert!((gain - 1.0).abs() < 0.01, "{frequency} Hz: gain {gain}");
+            assert!(phase.abs() > 0.1, "{frequency} Hz: phase {phase}");
+        }
+
+        // Half a turn at the center frequency
+        let mut filter = BiquadFilter::new(FilterType::AllPass, 48000.0, 1000.0, 0.7, 0.0);
+        let (_, phase) = sine_response(&mut filter, 1000.0, 48000.0);
+        assert!(phase.abs() > 3.0, "phase {phase}");
+    }
+
+    #[test]
+    fn cascade_slope_an_octave_below_the_cutoff() {
+        let step = 2.0 * std::f32::consts::PI * 500.0 / 48000.0;
+        for order in [2, 4, 6, 8] {
+            let mut filter = CascadedFilter::new(1, FilterType::HighPass, 48000.0, 1000.0, order);
+            let (mut output_power, mut input_power) = (0.0f64, 0.0f64);
+            for n in 0..48000 {
+                let x = (step * n as f32).sin();
+                let mut frame = [x];
+                filter.process_interleaved(&mut frame);
+                if n >= 24000 {
+                    output_power += (frame[0] * frame[0]) as f64;
+                    input_power += (x * x) as f64;
+                }
+            }
+            let measured_db = 10.0 * (output_power / input_power).log10();
+            // Butterworth: 1 / sqrt(1 + (fc / f)^2n), about 6 dB per order an octave down
+            let expected_db = -10.0 * (1.0 + 4f64.powi(order as i32)).log10();
+            assert!(
+                (measured_db - expected_db).abs() < 0.5,
+                "order {order}: {measured_db} dB, expected {expected_db} dB"
+            );
+        }
+    }
+
+    #[test]
+    fn bank_matches_separate_filters() {
+        // With the `simd` feature stereo is one group with two idle lanes, and six channels
+        // a full group plus a padded one
+        for channels in [2, 6] {
+            bank_matches_separate_filters_with(channels);
+        }
+    }
+
+    fn bank_matches_separate_filters_with(channels: usize) {
+        let mut bank = BiquadBank::new(channels, FilterType::Peaking, 48000.0, 1000.0, 0.7, 6.0);
+        let mut filters: Vec<_> = (0..channels)
+            .map(|_| BiquadFilter::new(FilterType::Peaking, 48000.0, 1000.0, 0.7, 6.0))
+            .collect();
+        for (ch, filter) in filters.iter_mut().enumerate() {
+            let frequency = 100.0 * (ch + 1) as f32;
+            bank.update_channel(ch, FilterType::LowShelf, 48000.0, frequency, 0.7, -3.0);
+            filter.update(FilterType::LowShelf, 48000.0, frequency, 0.7, -3.0);
+        }
+
+        let signal = Signal::WhiteNoise { amplitude: 0.5 };
+        let mut generator = SignalGenerator::new(signal, 48000, channels as u32, 0.5);
+        while let Some(mut block) = generator.decode_next() {
+            let expected: Vec<f32> = block
+                .chunks_exact(channels)
+                .flat_map(|frame| frame.iter().zip(&mut filters).map(|(x, f)| f.process(*x)).collect::<Vec<_>>())
+                .collect();
+            bank.process_interleaved(&mut block);
+            for (got, want) in block.iter().zip(&expected) {
+                assert!((got - want).abs() < 1e-5, "{channels} channels: {got} vs {want}");
+            }
+        }
+    }
+}
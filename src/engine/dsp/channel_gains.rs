@@ -0,0 +1,131 @@
+use crate::engine::dsp::node::DspNode;
+
+// Time constant of the gain smoothing, long enough that a gain change doesn't click
+const SMOOTHING_SECS: f32 = 0.01;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ChannelGainsSettings {
+    /// Gain of each output channel in dB, indexed by channel. Channels past the end are
+    /// left at 0 dB; `f32::NEG_INFINITY` mutes a channel.
+    pub gains_db: Vec<f32>,
+    /// Level balance of the front pair, `-1.0..=1.0`. Negative values attenuate the right
+    /// channel, positive ones the left, linearly down to silence at the ends. The other
+    /// side stays at unity, unlike a constant-power pan.
+    pub balance: f32,
+}
+
+/// Independent, smoothed gain per output channel, for trimming an imbalance or
+/// surround levels.
+pub struct ChannelGains {
+    channels: usize,
+    target: Vec<f32>,
+    current: Vec<f32>,
+    coeff: f32,
+}
+
+impl ChannelGains {
+    pub fn new(sample_rate: f32, channels: usize) -> Self {
+        Self {
+            channels,
+            target: vec![1.0; channels],
+            current: vec![1.0; channels],
+            coeff: (-1.0 / (SMOOTHING_SECS * sample_rate)).exp(),
+        }
+    }
+
+    pub fn set_channel_gain(&mut self, ch: usize, db: f32) {
+        if let Some(target) = self.target.get_mut(ch) {
+            *target = 10.0f32.powf(db / 20.0);
+        }
+    }
+
+    pub fn apply_settings(&mut self, settings: &ChannelGainsSettings) {
+        for ch in 0..self.channels {
+            let db = settings.gains_db.get(ch).copied().unwrap_or(0.0);
+            self.set_channel_gain(ch, db);
+        }
+        if self.channels >= 2 {
+            let balance = settings.balance.clamp(-1.0, 1.0);
+            self.target[0] *= 1.0 - balance.max(0.0);
+            self.target[1] *= 1.0 + balance.min(0.0);
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if self.target == self.current && self.current.iter().all(|&g| g == 1.0) {
+            return;
+        }
+
+        for frame in samples.chunks_exact_mut(self.channels) {
+            for (ch, sample) in frame.iter_mut().enumerate() {
+                let gain = &mut self.current[ch];
+                *gain = self.target[ch] + (*gain - self.target[ch]) * self.coeff;
+                *sample *= *gain;
+            }
+        }
+        // Land exactly on the target once close enough, so the bypass check can kick in
+        for (current, target) in self.current.iter_mut().zip(&self.target) {
+            if (*current - target).abs() < 1e-4 {
+                *current = *target;
+            }
+        }
+    }
+}
+
+impl DspNode for ChannelGains {
+    fn process(&mut self, samples: &mut [f32], _channels: usize, _sample_rate: f32) {
+        ChannelGains::process(self, samples);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::decoder::AudioDecoder;
+    use crate::test_util::signal_generator::{Signal, SignalGenerator};
+
+    #[test]
+    fn muting_channel_0_leaves_channel_1() {
+        let mut gains = ChannelGains::new(48000.0, 2);
+        gains.set_channel_gain(0, f32::NEG_INFINITY);
+        let signal = Signal::Sine { frequency: 440.0, amplitude: 0.5 };
+        let mut generator = SignalGenerator::new(signal, 48000, 2, 1.0);
+        let mut output = Vec::new();
+        let mut input = Vec::new();
+        while let Some(mut block) = generator.decode_next() {
+            input.extend_from_slice(&block);
+            gains.process(&mut block);
+            output.extend_from_slice(&block);
+        }
+        // Half a second in the smoothing is long over: the left channel is silent and the
+        // right untouched
+        for (out, original) in output.chunks_exact(2).zip(input.chunks_exact(2)).skip(24000) {
+            assert_eq!(out[0], 0.0);
+            assert_eq!(out[1], original[1]);
+        }
+    }
+
+    #[test]
+    fn full_right_balance_mutes_the_left_only() {
+        let mut gains = ChannelGains::new(48000.0, 2);
+        gains.apply_settings(&ChannelGainsSettings { balance: 1.0, ..Default::default() });
+        let mut output = vec![0.5; 48000 * 2];
+        for block in output.chunks_mut(1024 * 2) {
+            gains.process(block);
+        }
+        // Settled after half a second
+        for frame in output.chunks_exact(2).skip(24000) {
+            assert_eq!(frame, [0.0, 0.5]);
+        }
+
+        // Halfway only takes the left down, by half
+        gains.apply_settings(&ChannelGainsSettings { balance: 0.5, ..Default::default() });
+        let mut output = vec![0.5; 48000 * 2];
+        for block in output.chunks_mut(1024 * 2) {
+            gains.process(block);
+        }
+        assert_eq!(&output[output.len() - 2..], [0.25, 0.5]);
+    }
+}
@@ -0,0 +1,239 @@
+/// How many channels the engine renders, independent of what the device asks for.
+///
+/// Whatever is chosen, the result is mapped in software to the device's channel count
+/// before it reaches the output, so a device that can't open the requested layout still
+/// plays it (e.g. `ForceMono` on a stereo device duplicates the mono signal to L/R).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
+pub enum ChannelMode {
+    #[default]
+    Auto = 0,
+    ForceMono = 1,
+    ForceStereo = 2,
+}
+
+impl ChannelMode {
+    pub fn channels(&self) -> Option<usize> {
+        match self {
+            ChannelMode::Auto => None,
+            ChannelMode::ForceMono => Some(1),
+            ChannelMode::ForceStereo => Some(2),
+        }
+    }
+}
+
+/// Linear gains a surround source's channels are mixed into the front pair with when it
+/// plays on one or two channels. Fronts always go in at unity and the left and right
+/// surrounds to their own side, so a full-scale mix can exceed 0 dBFS; the limiter at the
+/// end of the DSP chain catches that.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DownmixMatrix {
+    pub center: f32,
+    pub lfe: f32,
+    pub surround: f32,
+    /// Matrix-encode the surrounds (Lt/Rt) instead of keeping each on its own side: both
+    /// go into both outputs, in opposite polarity, weighted towards their own side. A
+    /// Pro Logic II decoder can steer them back out.
+    pub matrix_encoded: bool,
+}
+
+/// How a source with more than two channels is folded down for one or two. Sources are
+/// taken to be in WAVE order: front left, front right, center, LFE, then the surrounds,
+/// left before right (5.1 is `L R C LFE Ls Rs`, 6.1 `L R C LFE Cb Ls Rs`, 7.1
+/// `L R C LFE Lb Rb Ls Rs`). Three and five channels have no LFE, four are quad.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Downmix {
+    /// Folds channels onto the outputs in turn and averages what lands together. Ignores
+    /// what the channels are, so 5.1 puts C and Ls on the left and LFE and Rs on the right.
+    Fold,
+    /// ITU-R BS.775: center and surrounds at -3 dB, LFE dropped. The default.
+    #[default]
+    Itu,
+    /// Dolby Pro Logic II Lt/Rt: center at -3 dB, surrounds matrix-encoded at -3 dB, LFE
+    /// dropped.
+    Dolby,
+    Custom(DownmixMatrix),
+}
+
+impl Downmix {
+    /// `None` for `Fold`, which has no matrix.
+    pub fn matrix(self) -> Option<DownmixMatrix> {
+        let minus_3db = std::f32::consts::FRAC_1_SQRT_2;
+        match self {
+            Downmix::Fold => None,
+            Downmix::Itu => Some(DownmixMatrix {
+                center: minus_3db,
+                lfe: 0.0,
+                surround: minus_3db,
+                matrix_encoded: false,
+            }),
+            Downmix::Dolby => Some(DownmixMatrix {
+                center: minus_3db,
+                lfe: 0.0,
+                surround: minus_3db,
+                matrix_encoded: true,
+            }),
+            Downmix::Custom(matrix) => Some(matrix),
+        }
+    }
+}
+
+pub struct ChannelMapper {
+    source_channels: usize,
+    content_channels: usize,
+    output_channels: usize,
+    // Per source channel, its gain into the left and right output, for surround sources
+    // folding to one or two channels with a matrix
+    matrix: Option<Vec<[f32; 2]>>,
+}
+
+impl ChannelMapper {
+    pub fn new(source_channels: usize, output_channels: usize, mode: ChannelMode, downmix: Downmix) -> Self {
+        let source_channels = source_channels.max(1);
+        let content_channels = mode.channels().unwrap_or(output_channels);
+        let matrix = match downmix.matrix() {
+            Some(matrix) if source_channels > 2 && content_channels <= 2 => {
+                Some(stereo_gains(source_channels, &matrix))
+            }
+            _ => None,
+        };
+        Self {
+            source_channels,
+            content_channels,
+            output_channels: output_channels.max(1),
+            matrix,
+        }
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.source_channels == self.content_channels
+            && self.content_channels == self.output_channels
+    }
+
+    pub fn process(&self, input: &[f32]) -> Vec<f32> {
+        let content = match &self.matrix {
+            Some(gains) => {
+                let stereo = downmix_stereo(input, gains);
+                remap(&stereo, 2, self.content_channels)
+            }
+            None => remap(input, self.source_channels, self.content_channels),
+        };
+        remap(&content, self.content_channels, self.output_channels)
+    }
+}
+
+// Left and right gain of each channel of a `channels`-channel source
+fn stereo_gains(channels: usize, matrix: &DownmixMatrix) -> Vec<[f32; 2]> {
+    let has_center = channels != 4;
+    let has_lfe = channels >= 6;
+    let first_surround = 2 + has_center as usize + has_lfe as usize;
+    // Pro Logic II weights: a surround goes into its own side at sqrt(3)/2 and the other at
+    // 1/2, which keeps its power
+    let (own, other) = (3.0f32.sqrt() / 2.0, 0.5);
+    let split = std::f32::consts::FRAC_1_SQRT_2;
+
+    let mut gains = vec![[0.0; 2]; channels];
+    gains[0] = [1.0, 0.0];
+    gains[1] = [0.0, 1.0];
+    if has_center {
+        gains[2] = [matrix.center, matrix.center];
+    }
+    if has_lfe {
+        gains[3] = [matrix.lfe, matrix.lfe];
+    }
+    // A lone back center, as in 6.1, comes first and is split evenly between the sides
+    let back_center = (channels - first_surround) % 2;
+    for (i, gain) in gains[first_surround..].iter_mut().enumerate() {
+        let side = i.checked_sub(back_center).map(|i| i % 2);
+        let s = matrix.surround;
+        *gain = match (side, matrix.matrix_encoded) {
+            (Some(0), false) => [s, 0.0],
+            (Some(_), false) => [0.0, s],
+            (Some(0), true) => [-s * own, s * other],
+            (Some(_), true) => [-s * other, s * own],
+            (None, false) => [s * split, s * split],
+            (None, true) => [-s * split, s * split],
+        };
+    }
+    gains
+}
+
+fn downmix_stereo(input: &[f32], gains: &[[f32; 2]]) -> Vec<f32> {
+    let mut output = Vec::with_capacity(input.len() / gains.len() * 2);
+    for frame in input.chunks_exact(gains.len()) {
+        let (mut left, mut right) = (0.0, 0.0);
+        for (sample, [l, r]) in frame.iter().zip(gains) {
+            left += sample * l;
+            right += sample * r;
+        }
+        output.push(left);
+        output.push(right);
+    }
+    output
+}
+
+fn remap(input: &[f32], from: usize, to: usize) -> Vec<f32> {
+    if from == to {
+        return input.to_vec();
+    }
+
+    let frames = input.len() / from;
+    let mut output = vec![0.0; frames * to];
+    // How many source channels fold onto each output channel
+    let folds: Vec<f32> = (0..to)
+        .map(|ch| ((from + to - 1 - ch) / to).max(1) as f32)
+        .collect();
+
+    for i in 0..frames {
+        let frame = &input[i * from..(i + 1) * from];
+        let out = &mut output[i * to..(i + 1) * to];
+
+        if from == 1 {
+            // Mono feeds the front pair, leaving any other channels silent
+            for sample in out.iter_mut().take(2) {
+                *sample = frame[0];
+            }
+        } else if to == 1 {
+            out[0] = frame.iter().sum::<f32>() / from as f32;
+        } else {
+            // Fold each source channel onto an output channel and average what lands together
+            for (ch, sample) in frame.iter().enumerate() {
+                out[ch % to] += sample;
+            }
+            for (sample, fold) in out.iter_mut().zip(&folds) {
+                *sample /= fold;
+            }
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One 5.1 frame with only `channel` sounding, folded to stereo by `downmix`
+    fn downmixed(downmix: Downmix, channel: usize) -> Vec<f32> {
+        let mut frame = [0.0; 6];
+        frame[channel] = 1.0;
+        ChannelMapper::new(6, 2, ChannelMode::Auto, downmix).process(&frame)
+    }
+
+    #[test]
+    fn center_of_5_1_lands_equally_in_both_sides() {
+        let minus_3db = std::f32::consts::FRAC_1_SQRT_2;
+        for downmix in [Downmix::Itu, Downmix::Dolby] {
+            assert_eq!(downmixed(downmix, 2), [minus_3db, minus_3db], "{downmix:?}");
+            // LFE dropped
+            assert_eq!(downmixed(downmix, 3), [0.0, 0.0]);
+        }
+        // Without matrix encoding each surround stays on its own side
+        assert_eq!(downmixed(Downmix::Itu, 4), [minus_3db, 0.0]);
+        assert_eq!(downmixed(Downmix::Itu, 5), [0.0, minus_3db]);
+
+        let custom = DownmixMatrix { center: 0.5, lfe: 0.25, surround: 0.0, matrix_encoded: false };
+        assert_eq!(downmixed(Downmix::Custom(custom), 2), [0.5, 0.5]);
+        assert_eq!(downmixed(Downmix::Custom(custom), 3), [0.25, 0.25]);
+    }
+}
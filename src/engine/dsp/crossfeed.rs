@@ -0,0 +1,156 @@
+use crate::engine::dsp::biquad::{BiquadFilter, FilterType};
+use crate::engine::dsp::node::DspNode;
+use crate::engine::dsp::precision::Precision;
+
+// Roughly the interaural delay of a listener sitting in front of a speaker pair
+const DELAY_SECS: f32 = 0.0003;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct CrossfeedSettings {
+    pub enabled: bool,
+    /// Linear gain of the opposite channel's feed, `0.0..=1.0`.
+    pub amount: f32,
+    /// Low-pass cutoff of the crossfed signal in Hz.
+    pub cutoff: f32,
+}
+
+/// `cutoff` limited to what the crossfeed's low-pass takes at `sample_rate`, 100 Hz up
+/// to 0.45 of the rate.
+pub fn clamp_cutoff(cutoff: f32, sample_rate: f32) -> f32 {
+    cutoff.clamp(100.0, sample_rate * 0.45)
+}
+
+impl Default for CrossfeedSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            amount: 0.3,
+            cutoff: 700.0,
+        }
+    }
+}
+
+/// Bauer/Meier style headphone crossfeed: each ear also hears a delayed, low-passed
+/// copy of the other channel. Only stereo audio is processed.
+pub struct Crossfeed {
+    settings: CrossfeedSettings,
+    sample_rate: f32,
+    low_pass: [BiquadFilter; 2],
+    delay: Vec<[f32; 2]>,
+    delay_pos: usize,
+}
+
+impl Crossfeed {
+    pub fn new(sample_rate: f32) -> Self {
+        let settings = CrossfeedSettings::default();
+        let delay_len = ((sample_rate * DELAY_SECS) as usize).max(1);
+
+        Self {
+            settings,
+            sample_rate,
+            low_pass: [
+                BiquadFilter::new(FilterType::LowPass, sample_rate, settings.cutoff, 0.5, 0.0),
+                BiquadFilter::new(FilterType::LowPass, sample_rate, settings.cutoff, 0.5, 0.0),
+            ],
+            delay: vec![[0.0; 2]; delay_len],
+            delay_pos: 0,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.settings.enabled = enabled;
+    }
+
+    pub fn set_amount(&mut self, amount: f32) {
+        self.settings.amount = amount.clamp(0.0, 1.0);
+    }
+
+    pub fn set_cutoff(&mut self, cutoff: f32) {
+        self.settings.cutoff = clamp_cutoff(cutoff, self.sample_rate);
+        for filter in &mut self.low_pass {
+            filter.update(FilterType::LowPass, self.sample_rate, self.settings.cutoff, 0.5, 0.0);
+        }
+    }
+
+    pub fn apply_settings(&mut self, settings: &CrossfeedSettings) {
+        self.set_enabled(settings.enabled);
+        self.set_amount(settings.amount);
+        let cutoff = clamp_cutoff(settings.cutoff, self.sample_rate);
+        if cutoff != self.settings.cutoff {
+            self.set_cutoff(cutoff);
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32], channels: usize) {
+        if !self.settings.enabled || channels != 2 {
+            return;
+        }
+
+        let amount = self.settings.amount;
+        let norm = 1.0 / (1.0 + amount);
+
+        for frame in samples.chunks_exact_mut(2) {
+            let left = frame[0];
+            let right = frame[1];
+
+            let delayed = self.delay[self.delay_pos];
+            self.delay[self.delay_pos] = [
+                self.low_pass[0].process(left),
+                self.low_pass[1].process(right),
+            ];
+            self.delay_pos = (self.delay_pos + 1) % self.delay.len();
+
+            frame[0] = (left + amount * delayed[1]) * norm;
+            frame[1] = (right + amount * delayed[0]) * norm;
+        }
+    }
+
+    pub fn set_precision(&mut self, precision: Precision) {
+        for filter in &mut self.low_pass {
+            filter.set_precision(precision);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for filter in &mut self.low_pass {
+            filter.reset();
+        }
+        for slot in &mut self.delay {
+            *slot = [0.0; 2];
+        }
+        self.delay_pos = 0;
+    }
+}
+
+impl DspNode for Crossfeed {
+    fn process(&mut self, samples: &mut [f32], channels: usize, _sample_rate: f32) {
+        Crossfeed::process(self, samples, channels);
+    }
+
+    fn reset(&mut self) {
+        Crossfeed::reset(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hard_left_feeds_a_delayed_quieter_copy_right() {
+        let mut crossfeed = Crossfeed::new(48000.0);
+        crossfeed.set_enabled(true);
+        let mut samples = vec![0.0; 4800 * 2];
+        samples[0] = 1.0;
+        crossfeed.process(&mut samples, 2);
+
+        let right: Vec<f32> = samples.iter().skip(1).step_by(2).copied().collect();
+        let delay = (48000.0 * DELAY_SECS) as usize;
+        assert!(right[..delay].iter().all(|&s| s == 0.0));
+        assert!(right[delay] > 0.0);
+        let peak = right.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+        assert!(peak < samples[0], "{peak} against {}", samples[0]);
+    }
+}
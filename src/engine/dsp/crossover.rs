@@ -0,0 +1,199 @@
+use crate::engine::dsp::biquad::{BiquadFilter, FilterType};
+use crate::engine::dsp::node::DspNode;
+use crate::engine::dsp::precision::Precision;
+
+// Two cascaded Butterworth sections make a 4th-order Linkwitz-Riley filter
+const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct CrossoverSettings {
+    pub enabled: bool,
+    /// Crossover frequency in Hz.
+    pub frequency: f32,
+    /// Output channels (left, right) that receive the low band.
+    pub low_channels: [usize; 2],
+    /// Output channels (left, right) that receive the high band.
+    pub high_channels: [usize; 2],
+}
+
+impl Default for CrossoverSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            frequency: 2500.0,
+            low_channels: [0, 1],
+            high_channels: [2, 3],
+        }
+    }
+}
+
+// One LR4 low-pass/high-pass pair for a single input channel
+pub(crate) struct Lr4 {
+    low: [BiquadFilter; 2],
+    high: [BiquadFilter; 2],
+}
+
+impl Lr4 {
+    pub(crate) fn new(sample_rate: f32, frequency: f32) -> Self {
+        let section = |filter_type| BiquadFilter::new(filter_type, sample_rate, frequency, BUTTERWORTH_Q, 0.0);
+        Self {
+            low: [section(FilterType::LowPass), section(FilterType::LowPass)],
+            high: [section(FilterType::HighPass), section(FilterType::HighPass)],
+        }
+    }
+
+    pub(crate) fn update(&mut self, sample_rate: f32, frequency: f32) {
+        for filter in &mut self.low {
+            filter.update(FilterType::LowPass, sample_rate, frequency, BUTTERWORTH_Q, 0.0);
+        }
+        for filter in &mut self.high {
+            filter.update(FilterType::HighPass, sample_rate, frequency, BUTTERWORTH_Q, 0.0);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn process(&mut self, x: f32) -> (f32, f32) {
+        let low = self.low[0].process(x);
+        let high = self.high[0].process(x);
+        (self.low[1].process(low), self.high[1].process(high))
+    }
+
+    pub(crate) fn set_precision(&mut self, precision: Precision) {
+        for filter in self.low.iter_mut().chain(self.high.iter_mut()) {
+            filter.set_precision(precision);
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        for filter in self.low.iter_mut().chain(self.high.iter_mut()) {
+            filter.reset();
+        }
+    }
+}
+
+/// 2-way Linkwitz-Riley (LR4) crossover for bi-amping. Splits the front stereo pair into
+/// a low and a high band and writes each to its own pair of output channels; every other
+/// channel is silenced. The two bands sum back to a flat magnitude response.
+pub struct Crossover {
+    settings: CrossoverSettings,
+    sample_rate: f32,
+    bands: [Lr4; 2],
+}
+
+impl Crossover {
+    pub fn new(sample_rate: f32) -> Self {
+        let settings = CrossoverSettings::default();
+        Self {
+            settings,
+            sample_rate,
+            bands: [
+                Lr4::new(sample_rate, settings.frequency),
+                Lr4::new(sample_rate, settings.frequency),
+            ],
+        }
+    }
+
+    pub fn apply_settings(&mut self, settings: &CrossoverSettings) {
+        let frequency = settings.frequency.clamp(20.0, self.sample_rate * 0.45);
+        if frequency != self.settings.frequency {
+            for band in &mut self.bands {
+                band.update(self.sample_rate, frequency);
+            }
+        }
+        if settings.enabled && !self.settings.enabled {
+            self.reset();
+        }
+        self.settings = CrossoverSettings {
+            frequency,
+            ..*settings
+        };
+    }
+
+    pub fn process(&mut self, samples: &mut [f32], channels: usize) {
+        let routes = self.settings.low_channels.iter().chain(&self.settings.high_channels);
+        // Needs a stereo source and somewhere to put every band
+        if !self.settings.enabled || channels < 2 || routes.clone().any(|&ch| ch >= channels) {
+            return;
+        }
+
+        let [low_l, low_r] = self.settings.low_channels;
+        let [high_l, high_r] = self.settings.high_channels;
+
+        for frame in samples.chunks_exact_mut(channels) {
+            let (low_left, high_left) = self.bands[0].process(frame[0]);
+            let (low_right, high_right) = self.bands[1].process(frame[1]);
+
+            frame.fill(0.0);
+            frame[low_l] += low_left;
+            frame[low_r] += low_right;
+            frame[high_l] += high_left;
+            frame[high_r] += high_right;
+        }
+    }
+
+    pub fn set_precision(&mut self, precision: Precision) {
+        for band in &mut self.bands {
+            band.set_precision(precision);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for band in &mut self.bands {
+            band.reset();
+        }
+    }
+}
+
+impl DspNode for Crossover {
+    fn process(&mut self, samples: &mut [f32], channels: usize, _sample_rate: f32) {
+        Crossover::process(self, samples, channels);
+    }
+
+    fn reset(&mut self) {
+        Crossover::reset(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::decoder::AudioDecoder;
+    use crate::test_util::signal_generator::{Signal, SignalGenerator};
+
+    // Peaks of the high band and of both bands summed back together, over the last half
+    // second
+    fn peaks(frequency: f64) -> (f32, f32) {
+        let mut crossover = Crossover::new(48000.0);
+        crossover.apply_settings(&CrossoverSettings { enabled: true, ..CrossoverSettings::default() });
+        let signal = Signal::Sine { frequency, amplitude: 0.5 };
+        let mut generator = SignalGenerator::new(signal, 48000, 4, 1.0);
+        let mut output = Vec::new();
+        while let Some(mut block) = generator.decode_next() {
+            crossover.process(&mut block, 4);
+            output.extend(block.chunks_exact(4).map(|frame| (frame[2], frame[0] + frame[2])));
+        }
+        output[output.len() / 2..]
+            .iter()
+            .fold((0.0, 0.0), |(high, summed), (h, s)| (f32::max(high, h.abs()), f32::max(summed, s.abs())))
+    }
+
+    #[test]
+    fn bands_sum_flat() {
+        for frequency in [250.0, 1250.0, 2500.0, 5000.0, 10000.0] {
+            let (_, summed) = peaks(frequency);
+            let deviation_db = 20.0 * (summed / 0.5).log10();
+            assert!(deviation_db.abs() < 0.1, "{frequency} Hz off by {deviation_db} dB");
+        }
+    }
+
+    #[test]
+    fn bands_split_at_the_crossover() {
+        // 6 dB down each at the crossover, and two octaves below the high band is gone
+        let (high, _) = peaks(2500.0);
+        assert!((high - 0.25).abs() < 0.01, "{high}");
+        let (high, _) = peaks(625.0);
+        assert!(high < 0.01, "{high}");
+    }
+}
@@ -0,0 +1,145 @@
+use crate::engine::dsp::node::DspNode;
+use crate::engine::dsp::precision::Precision;
+use std::f32::consts::PI;
+
+// Well below anything audible, so only the offset itself is removed
+const CUTOFF_HZ: f32 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DcBlockerSettings {
+    pub enabled: bool,
+}
+
+impl Default for DcBlockerSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// One-pole DC-blocking high-pass, `y[n] = x[n] - x[n-1] + R * y[n-1]`, per channel.
+pub struct DcBlocker {
+    settings: DcBlockerSettings,
+    channels: usize,
+    r: f32,
+    prev_input: Vec<f32>,
+    prev_output: Vec<f32>,
+    sample_rate: f32,
+    // `r` and each channel's previous input and output, while running in `Precision::F64`
+    double: Option<(f64, Vec<[f64; 2]>)>,
+}
+
+impl DcBlocker {
+    pub fn new(sample_rate: f32, channels: usize) -> Self {
+        Self {
+            settings: DcBlockerSettings::default(),
+            channels,
+            r: (-2.0 * PI * CUTOFF_HZ / sample_rate).exp(),
+            prev_input: vec![0.0; channels],
+            prev_output: vec![0.0; channels],
+            sample_rate,
+            double: None,
+        }
+    }
+
+    /// Switches the arithmetic the filter runs in, carrying its state over.
+    pub fn set_precision(&mut self, precision: Precision) {
+        match (precision, &self.double) {
+            (Precision::F64, None) => {
+                let r = (-2.0 * std::f64::consts::PI * CUTOFF_HZ as f64 / self.sample_rate as f64).exp();
+                let state = (0..self.channels)
+                    .map(|ch| [self.prev_input[ch] as f64, self.prev_output[ch] as f64])
+                    .collect();
+                self.double = Some((r, state));
+            }
+            (Precision::F32, Some((_, state))) => {
+                for (ch, [input, output]) in state.iter().enumerate() {
+                    self.prev_input[ch] = *input as f32;
+                    self.prev_output[ch] = *output as f32;
+                }
+                self.double = None;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn apply_settings(&mut self, settings: &DcBlockerSettings) {
+        if settings.enabled && !self.settings.enabled {
+            // Don't resume from state left over from before it was switched off
+            self.reset();
+        }
+        self.settings = *settings;
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if !self.settings.enabled {
+            return;
+        }
+
+        if let Some((r, state)) = &mut self.double {
+            for frame in samples.chunks_exact_mut(self.channels) {
+                for (sample, [prev_input, prev_output]) in frame.iter_mut().zip(state.iter_mut()) {
+                    let x = *sample as f64;
+                    let y = x - *prev_input + *r * *prev_output;
+                    *prev_input = x;
+                    *prev_output = y;
+                    *sample = y as f32;
+                }
+            }
+            return;
+        }
+
+        for frame in samples.chunks_exact_mut(self.channels) {
+            for (ch, sample) in frame.iter_mut().enumerate() {
+                let x = *sample;
+                let y = x - self.prev_input[ch] + self.r * self.prev_output[ch];
+                self.prev_input[ch] = x;
+                self.prev_output[ch] = y;
+                *sample = y;
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.prev_input.fill(0.0);
+        self.prev_output.fill(0.0);
+        if let Some((_, state)) = &mut self.double {
+            state.fill([0.0; 2]);
+        }
+    }
+}
+
+impl DspNode for DcBlocker {
+    fn process(&mut self, samples: &mut [f32], _channels: usize, _sample_rate: f32) {
+        DcBlocker::process(self, samples);
+    }
+
+    fn reset(&mut self) {
+        DcBlocker::reset(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::decoder::AudioDecoder;
+    use crate::test_util::signal_generator::{Signal, SignalGenerator};
+
+    #[test]
+    fn offset_converges_to_zero_mean() {
+        let signal = Signal::Sine { frequency: 440.0, amplitude: 0.5 };
+        let mut generator = SignalGenerator::new(signal, 48000, 2, 2.0);
+        let mut blocker = DcBlocker::new(48000.0, 2);
+        let mut output = Vec::new();
+        while let Some(mut block) = generator.decode_next() {
+            block.iter_mut().for_each(|s| *s += 0.3);
+            blocker.process(&mut block);
+            output.extend_from_slice(&block);
+        }
+        // Whole cycles of the tone, so only the offset is left in the mean
+        let tail = &output[output.len() - 48000..];
+        let mean = tail.iter().sum::<f32>() / tail.len() as f32;
+        assert!(mean.abs() < 1e-3, "mean {mean}");
+    }
+}
@@ -0,0 +1,173 @@
+use crate::engine::dsp::crossover::Lr4;
+use crate::engine::dsp::node::DspNode;
+use crate::engine::dsp::precision::Precision;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DeEsserSettings {
+    pub enabled: bool,
+    /// Lower edge of the sibilant band in Hz.
+    pub frequency: f32,
+    pub threshold_db: f32,
+    pub ratio: f32,
+}
+
+impl Default for DeEsserSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            frequency: 6000.0,
+            threshold_db: -30.0,
+            ratio: 4.0,
+        }
+    }
+}
+
+/// Compresses only the sibilant band. The band is split off with a Linkwitz-Riley
+/// crossover, whose two halves stay in phase, so turning the high half down removes it
+/// cleanly. With no gain reduction they sum back to a flat magnitude response.
+pub struct DeEsser {
+    settings: DeEsserSettings,
+    sample_rate: f32,
+    channels: usize,
+    bands: Vec<Lr4>,
+    envelope: Vec<f32>,
+    attack_coeff: f32,
+    release_coeff: f32,
+}
+
+impl DeEsser {
+    pub fn new(sample_rate: f32, channels: usize) -> Self {
+        let settings = DeEsserSettings::default();
+        let attack_time = 0.001;
+        let release_time = 0.05;
+
+        Self {
+            settings,
+            sample_rate,
+            channels,
+            bands: (0..channels).map(|_| Lr4::new(sample_rate, settings.frequency)).collect(),
+            envelope: vec![0.0; channels],
+            attack_coeff: (-1.0 / (sample_rate * attack_time)).exp(),
+            release_coeff: (-1.0 / (sample_rate * release_time)).exp(),
+        }
+    }
+
+    pub fn apply_settings(&mut self, settings: &DeEsserSettings) {
+        let frequency = settings.frequency.clamp(2000.0, self.sample_rate * 0.45);
+        if frequency != self.settings.frequency {
+            for bands in &mut self.bands {
+                bands.update(self.sample_rate, frequency);
+            }
+        }
+        self.settings = DeEsserSettings {
+            enabled: settings.enabled,
+            frequency,
+            threshold_db: settings.threshold_db.clamp(-60.0, 0.0),
+            ratio: settings.ratio.max(1.0),
+        };
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if !self.settings.enabled {
+            return;
+        }
+
+        let threshold_db = self.settings.threshold_db;
+        let slope = 1.0 - 1.0 / self.settings.ratio;
+
+        for frame in samples.chunks_exact_mut(self.channels) {
+            for (ch, sample) in frame.iter_mut().enumerate() {
+                let (low, high) = self.bands[ch].process(*sample);
+
+                let level = high.abs();
+                let coeff = if level > self.envelope[ch] {
+                    self.attack_coeff
+                } else {
+                    self.release_coeff
+                };
+                self.envelope[ch] = coeff * (self.envelope[ch] - level) + level;
+
+                let level_db = 20.0 * (self.envelope[ch] + 1e-10).log10();
+                let gain = if level_db > threshold_db {
+                    let reduction_db = (level_db - threshold_db) * slope;
+                    10.0f32.powf(-reduction_db / 20.0)
+                } else {
+                    1.0
+                };
+                *sample = low + high * gain;
+            }
+        }
+    }
+
+    pub fn set_precision(&mut self, precision: Precision) {
+        for bands in &mut self.bands {
+            bands.set_precision(precision);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for bands in &mut self.bands {
+            bands.reset();
+        }
+        self.envelope.iter_mut().for_each(|e| *e = 0.0);
+    }
+}
+
+impl DspNode for DeEsser {
+    fn process(&mut self, samples: &mut [f32], _channels: usize, _sample_rate: f32) {
+        DeEsser::process(self, samples);
+    }
+
+    fn reset(&mut self) {
+        DeEsser::reset(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const LOW_HZ: f32 = 200.0;
+    const HIGH_HZ: f32 = 8000.0;
+
+    // Amplitudes of the low and high tone in the de-essed mix of both at `amplitude`,
+    // measured over the second half once the detector has settled
+    fn tone_levels(amplitude: f32) -> (f32, f32) {
+        let mut de_esser = DeEsser::new(SAMPLE_RATE, 1);
+        de_esser.apply_settings(&DeEsserSettings { enabled: true, ..DeEsserSettings::default() });
+
+        let tone = |frequency: f32, n: usize| (2.0 * PI * frequency * n as f32 / SAMPLE_RATE).sin();
+        let mut samples: Vec<f32> =
+            (0..SAMPLE_RATE as usize).map(|n| amplitude * (tone(LOW_HZ, n) + tone(HIGH_HZ, n))).collect();
+        de_esser.process(&mut samples);
+
+        let level = |frequency: f32| {
+            let half = samples.len() / 2;
+            let (mut sin, mut cos) = (0.0f32, 0.0f32);
+            for (n, sample) in samples.iter().enumerate().skip(half) {
+                let phase = 2.0 * PI * frequency * n as f32 / SAMPLE_RATE;
+                sin += sample * phase.sin();
+                cos += sample * phase.cos();
+            }
+            2.0 * sin.hypot(cos) / half as f32
+        };
+        (level(LOW_HZ), level(HIGH_HZ))
+    }
+
+    #[test]
+    fn attenuates_only_loud_sibilance() {
+        // Sibilance well over the -30 dB threshold is pulled down, the low tone isn't
+        let (low, high) = tone_levels(0.3);
+        assert!((low - 0.3).abs() < 0.3 * 0.05, "low {low}");
+        assert!(high < 0.3 * 0.5, "high {high}");
+
+        // Below the threshold neither is touched
+        let (low, high) = tone_levels(0.01);
+        assert!((low - 0.01).abs() < 0.01 * 0.05, "low {low}");
+        assert!((high - 0.01).abs() < 0.01 * 0.05, "high {high}");
+    }
+}
@@ -1,39 +1,557 @@
 use crate::engine::dsp::bass::BassProcessor;
+use crate::engine::dsp::channel_gains::{ChannelGains, ChannelGainsSettings};
+use crate::engine::dsp::crossfeed::{Crossfeed, CrossfeedSettings};
+use crate::engine::dsp::crossover::{Crossover, CrossoverSettings};
+use crate::engine::dsp::dc_blocker::{DcBlocker, DcBlockerSettings};
+use crate::engine::dsp::de_esser::{DeEsser, DeEsserSettings};
 use crate::engine::dsp::eq::HighFreqEQ;
-use crate::engine::dsp::limiter::Limiter;
+use crate::engine::dsp::lfo_mod::{LfoMod, LfoModSettings};
+use crate::engine::dsp::limiter::{ChannelLimiter, LimiterSettings};
+use crate::engine::dsp::node::{default_order, DspNode, NodeFactory, NodeId};
+use crate::engine::dsp::parametric_eq::{EqSettings, ParametricEq};
+use crate::engine::dsp::phase_correction::{PhaseCorrection, PhaseCorrectionSettings};
+use crate::engine::dsp::phaser::{Phaser, PhaserSettings};
+use crate::engine::dsp::precision::Precision;
+use crate::engine::dsp::routing::{ChannelRouting, Router};
+
+/// Parameters of the optional DSP nodes. The engine keeps the authoritative copy and
+/// re-applies it whenever the chain is rebuilt for a new output format.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DspSettings {
+    pub dc_blocker: DcBlockerSettings,
+    pub phase_correction: PhaseCorrectionSettings,
+    pub eq: EqSettings,
+    pub de_esser: DeEsserSettings,
+    pub crossfeed: CrossfeedSettings,
+    pub phaser: PhaserSettings,
+    pub lfo_mod: LfoModSettings,
+    pub crossover: CrossoverSettings,
+    pub channel_gains: ChannelGainsSettings,
+    pub routing: ChannelRouting,
+    pub limiter: LimiterSettings,
+    /// Order the stages run in, see `DspChain::reorder`. `None` is `NodeId::DEFAULT_ORDER`.
+    pub order: Option<Vec<NodeId>>,
+    /// Stages switched off with `DspChain::set_node_enabled`. The bass has its own switch
+    /// and isn't listed.
+    pub disabled: Vec<NodeId>,
+}
+
+/// Memory layout the per-channel filter stages run in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DspLayout {
+    /// Process the interleaved block directly, one frame at a time.
+    #[default]
+    Interleaved,
+    /// Deinterleave into one contiguous buffer per channel for the bass and EQ filters,
+    /// so each filter runs over a contiguous run. Costs two extra copies per block.
+    /// `cargo bench --bench dsp -- dsp_layout` compares the two.
+    Planar,
+}
 
 pub struct DspChain {
+    dc_blocker: DcBlocker,
+    phase_correction: PhaseCorrection,
     pub(crate) bass: BassProcessor,
     hf_eq: HighFreqEQ,
-    limiter: Vec<Limiter>,
+    eq: ParametricEq,
+    de_esser: DeEsser,
+    phaser: Phaser,
+    lfo_mod: LfoMod,
+    crossfeed: Crossfeed,
+    crossover: Crossover,
+    channel_gains: ChannelGains,
+    router: Router,
+    limiter: ChannelLimiter,
     channels: usize,
+    sample_rate: f32,
+    layout: DspLayout,
+    planar: Vec<Vec<f32>>,
+    order: Vec<NodeId>,
+    // Stages in `order` that pass audio through untouched
+    disabled: Vec<NodeId>,
+    // Custom nodes, whether or not they're currently in `order`
+    custom: Vec<(u32, Box<dyn DspNode + Send>)>,
+    // Linear gain ahead of every stage, and the one the next block ramps from. `None`
+    // until the first block, so a fresh chain starts at its gain
+    input_gain: f32,
+    input_gain_from: Option<f32>,
 }
 
 impl DspChain {
     pub fn new(sample_rate: f32, channels: usize) -> Self {
-        let mut limiter = Vec::new();
-        for _ in 0..channels {
-            limiter.push(Limiter::new(-0.1, sample_rate));
-        }
-
         Self {
+            dc_blocker: DcBlocker::new(sample_rate, channels),
+            phase_correction: PhaseCorrection::new(sample_rate, channels),
             bass: BassProcessor::new(sample_rate, channels),
             hf_eq: HighFreqEQ::new(sample_rate, channels),
-            limiter,
+            eq: ParametricEq::new(sample_rate, channels),
+            de_esser: DeEsser::new(sample_rate, channels),
+            phaser: Phaser::new(sample_rate, channels),
+            lfo_mod: LfoMod::new(sample_rate, channels),
+            crossfeed: Crossfeed::new(sample_rate),
+            crossover: Crossover::new(sample_rate),
+            channel_gains: ChannelGains::new(sample_rate, channels),
+            router: Router::new(channels),
+            limiter: ChannelLimiter::new(-0.1, sample_rate, channels),
             channels,
+            sample_rate,
+            layout: DspLayout::Interleaved,
+            planar: vec![Vec::new(); channels],
+            order: NodeId::DEFAULT_ORDER.to_vec(),
+            disabled: Vec::new(),
+            custom: Vec::new(),
+            input_gain: 1.0,
+            input_gain_from: None,
+        }
+    }
+
+    /// Gain applied ahead of every stage, e.g. track normalization, so the limiter still
+    /// catches what it pushes over full scale. Ramped over the next block.
+    pub fn set_input_gain_db(&mut self, db: f32) {
+        self.input_gain = 10.0f32.powf(db / 20.0);
+    }
+
+    pub fn set_layout(&mut self, layout: DspLayout) {
+        self.layout = layout;
+    }
+
+    /// Arithmetic the filter stages, the DC blocker and the limiter run in. Each keeps its
+    /// state across the switch. Custom nodes are left as they are.
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.dc_blocker.set_precision(precision);
+        self.bass.set_precision(precision);
+        self.hf_eq.set_precision(precision);
+        self.eq.set_precision(precision);
+        self.de_esser.set_precision(precision);
+        self.phaser.set_precision(precision);
+        self.crossfeed.set_precision(precision);
+        self.crossover.set_precision(precision);
+        self.limiter.set_precision(precision);
+    }
+
+    pub fn apply_settings(&mut self, settings: &DspSettings) {
+        self.dc_blocker.apply_settings(&settings.dc_blocker);
+        self.phase_correction.apply_settings(&settings.phase_correction);
+        self.eq.apply_settings(&settings.eq);
+        self.de_esser.apply_settings(&settings.de_esser);
+        self.phaser.apply_settings(&settings.phaser);
+        self.lfo_mod.apply_settings(&settings.lfo_mod);
+        self.crossfeed.apply_settings(&settings.crossfeed);
+        self.crossover.apply_settings(&settings.crossover);
+        self.channel_gains.apply_settings(&settings.channel_gains);
+        self.router.apply_settings(&settings.routing);
+        self.limiter.apply_settings(&settings.limiter);
+        match &settings.order {
+            Some(order) => {
+                // Custom nodes only live in this chain, so an order naming ones it doesn't
+                // have still applies to the rest
+                let known: Vec<NodeId> = order.iter().copied().filter(|id| self.has_node(*id)).collect();
+                let _ = self.reorder(&known);
+            }
+            None => self.restore_default_order(),
+        }
+        self.disabled.clear();
+        for id in settings.disabled.iter().filter(|id| **id != NodeId::Bass) {
+            self.set_node_enabled(*id, false);
+        }
+    }
+
+    /// Puts the built-in stages back in their default order. Custom nodes keep the index
+    /// they run at now (`insert` may have put them anywhere); ones not running yet go
+    /// before the limiter.
+    fn restore_default_order(&mut self) {
+        let placed: Vec<(usize, NodeId)> = self
+            .order
+            .iter()
+            .enumerate()
+            .filter(|(_, id)| matches!(id, NodeId::Custom(_)) && self.has_node(**id))
+            .map(|(i, id)| (i, *id))
+            .collect();
+        let unplaced = self
+            .custom
+            .iter()
+            .map(|(id, _)| *id)
+            .filter(|id| !placed.iter().any(|(_, node)| *node == NodeId::Custom(*id)));
+
+        let mut order = default_order(unplaced);
+        for (index, id) in placed {
+            order.insert(index.min(order.len()), id);
+        }
+        self.order = order;
+    }
+
+    /// Takes the custom nodes out, so a rebuilt chain can carry on with them through
+    /// `sync_custom_nodes`.
+    pub(crate) fn take_custom_nodes(&mut self) -> Vec<(u32, Box<dyn DspNode + Send>)> {
+        std::mem::take(&mut self.custom)
+    }
+
+    /// Makes the custom nodes match the engine's list. Ones this chain (or `carried`)
+    /// already has keep their instance and state, new ones are built and removed ones
+    /// dropped. Call `apply_settings` afterwards to put them in order.
+    pub(crate) fn sync_custom_nodes(
+        &mut self,
+        nodes: &[(u32, NodeFactory)],
+        carried: Vec<(u32, Box<dyn DspNode + Send>)>,
+    ) {
+        let mut existing = std::mem::take(&mut self.custom);
+        existing.extend(carried);
+        self.custom = nodes
+            .iter()
+            .map(|(id, make)| match existing.iter().position(|(node, _)| node == id) {
+                Some(i) => existing.swap_remove(i),
+                None => (*id, make()),
+            })
+            .collect();
+    }
+
+    /// Stages in the order they currently run.
+    pub fn order(&self) -> &[NodeId] {
+        &self.order
+    }
+
+    /// Adds `node` at `index` in the order (clamped to its length) and returns its id.
+    pub fn insert(&mut self, index: usize, node: Box<dyn DspNode + Send>) -> NodeId {
+        let id = self.custom.iter().map(|(id, _)| id + 1).max().unwrap_or(0);
+        self.custom.push((id, node));
+        let index = index.min(self.order.len());
+        self.order.insert(index, NodeId::Custom(id));
+        NodeId::Custom(id)
+    }
+
+    /// Takes a stage out of the chain. A custom node is dropped; a built-in one keeps its
+    /// state and can be put back with `reorder`. Returns whether it was running.
+    pub fn remove(&mut self, id: NodeId) -> bool {
+        if let NodeId::Custom(n) = id {
+            self.custom.retain(|(custom, _)| *custom != n);
+        }
+        let before = self.order.len();
+        self.order.retain(|node| *node != id);
+        self.order.len() != before
+    }
+
+    /// Runs the stages in `order`. Stages left out are bypassed but keep their state, so
+    /// a later call can put them back. Fails without changing anything if a stage is
+    /// listed twice or a custom id was never inserted.
+    pub fn reorder(&mut self, order: &[NodeId]) -> Result<(), Box<dyn std::error::Error>> {
+        for (i, id) in order.iter().enumerate() {
+            if !self.has_node(*id) {
+                return Err(format!("No DSP node {:?} in the chain", id).into());
+            }
+            if order[..i].contains(id) {
+                return Err(format!("DSP node {:?} is listed twice", id).into());
+            }
+        }
+        self.order = order.to_vec();
+        Ok(())
+    }
+
+    /// Switches a stage off or back on without taking it out of the order. Off passes
+    /// audio through untouched, but the stage keeps its state, so it carries on smoothly
+    /// when switched back on. The bass fades in and out instead, see
+    /// `BassProcessor::set_enabled`.
+    pub fn set_node_enabled(&mut self, id: NodeId, enabled: bool) {
+        if id == NodeId::Bass {
+            self.bass.set_enabled(enabled);
+            return;
+        }
+        self.disabled.retain(|node| *node != id);
+        if !enabled {
+            self.disabled.push(id);
+        }
+    }
+
+    pub fn is_node_enabled(&self, id: NodeId) -> bool {
+        match id {
+            NodeId::Bass => self.bass.is_enabled(),
+            _ => !self.disabled.contains(&id),
+        }
+    }
+
+    fn has_node(&self, id: NodeId) -> bool {
+        match id {
+            NodeId::Custom(n) => self.custom.iter().any(|(custom, _)| *custom == n),
+            _ => true,
+        }
+    }
+
+    /// Restarts the LFO-driven effects so they line up the same way after a seek.
+    pub fn reset_modulation(&mut self) {
+        self.phaser.reset();
+        self.lfo_mod.reset();
+    }
+
+    /// Lets the custom nodes forget the audio before a seek.
+    pub fn reset_custom_nodes(&mut self) {
+        for (_, node) in &mut self.custom {
+            node.reset();
         }
     }
 
     pub fn process(&mut self, samples: &mut [f32]) {
-        self.bass.process(samples);
-        self.hf_eq.process(samples);
+        // Adaptive stages step their smoothing once per call, so an empty block would
+        // still move them
+        if samples.len() < self.channels {
+            return;
+        }
+        self.apply_input_gain(samples);
+        // Neighbouring planar stages share one deinterleave/reinterleave pass
+        let mut planar = false;
+        for i in 0..self.order.len() {
+            let id = self.order[i];
+            if self.disabled.contains(&id) {
+                continue;
+            }
+            let wants_planar =
+                self.layout == DspLayout::Planar && matches!(id, NodeId::Bass | NodeId::HfEq);
+            if wants_planar && !planar {
+                self.deinterleave(samples);
+            } else if planar && !wants_planar {
+                self.reinterleave(samples);
+            }
+            planar = wants_planar;
+            self.process_node(id, samples, planar);
+        }
+        if planar {
+            self.reinterleave(samples);
+        }
+
+        let reduction = self.limiter_reduction_db();
+        self.bass.observe_limiter(reduction);
+    }
 
+    fn apply_input_gain(&mut self, samples: &mut [f32]) {
+        let from = self.input_gain_from.replace(self.input_gain).unwrap_or(self.input_gain);
+        if from == 1.0 && self.input_gain == 1.0 {
+            return;
+        }
+        let frames = (samples.len() / self.channels.max(1)) as f32;
+        for (i, frame) in samples.chunks_exact_mut(self.channels.max(1)).enumerate() {
+            let gain = from + (self.input_gain - from) * (i + 1) as f32 / frames;
+            frame.iter_mut().for_each(|s| *s *= gain);
+        }
+    }
+
+    fn process_node(&mut self, id: NodeId, samples: &mut [f32], planar: bool) {
+        match id {
+            NodeId::Bass if planar => self.bass.process_planar(&mut self.planar),
+            NodeId::HfEq if planar => self.hf_eq.process_planar(&mut self.planar),
+            _ => {
+                let (channels, sample_rate) = (self.channels, self.sample_rate);
+                if let Some(node) = self.node_mut(id) {
+                    node.process(samples, channels, sample_rate);
+                }
+            }
+        }
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Option<&mut dyn DspNode> {
+        Some(match id {
+            NodeId::DcBlocker => &mut self.dc_blocker,
+            NodeId::PhaseCorrection => &mut self.phase_correction,
+            NodeId::Bass => &mut self.bass,
+            NodeId::HfEq => &mut self.hf_eq,
+            NodeId::Eq => &mut self.eq,
+            NodeId::DeEsser => &mut self.de_esser,
+            NodeId::Phaser => &mut self.phaser,
+            NodeId::LfoMod => &mut self.lfo_mod,
+            NodeId::Crossfeed => &mut self.crossfeed,
+            NodeId::Crossover => &mut self.crossover,
+            NodeId::Router => &mut self.router,
+            NodeId::ChannelGains => &mut self.channel_gains,
+            NodeId::Limiter => &mut self.limiter,
+            NodeId::Custom(n) => {
+                let (_, node) = self.custom.iter_mut().find(|(custom, _)| *custom == n)?;
+                node.as_mut()
+            }
+        })
+    }
+
+    /// The strongest gain reduction any channel's limiter applied at the end of the last
+    /// block, in dB (negative while limiting). `0.0` while the limiter is switched off.
+    pub fn limiter_reduction_db(&self) -> f32 {
+        if self.disabled.contains(&NodeId::Limiter) {
+            return 0.0;
+        }
+        self.limiter.gain_reduction_db()
+    }
+
+    pub fn phase_inversion_detected(&self) -> bool {
+        self.phase_correction.inversion_detected()
+    }
+
+    fn deinterleave(&mut self, samples: &[f32]) {
         let frames = samples.len() / self.channels;
-        for i in 0..frames {
-            for ch in 0..self.channels {
-                let idx = i * self.channels + ch;
-                samples[idx] = self.limiter[ch].process(samples[idx]);
+        for (ch, channel) in self.planar.iter_mut().enumerate() {
+            channel.clear();
+            channel.extend((0..frames).map(|i| samples[i * self.channels + ch]));
+        }
+    }
+
+    fn reinterleave(&self, samples: &mut [f32]) {
+        for (ch, channel) in self.planar.iter().enumerate() {
+            for (i, sample) in channel.iter().enumerate() {
+                samples[i * self.channels + ch] = *sample;
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::decoder::AudioDecoder;
+    use crate::test_util::signal_generator::{Signal, SignalGenerator};
+    use std::sync::Arc;
+
+    struct Passthrough;
+
+    impl DspNode for Passthrough {
+        fn process(&mut self, _samples: &mut [f32], _channels: usize, _sample_rate: f32) {}
+    }
+
+    #[test]
+    fn default_order_keeps_inserted_positions() {
+        let mut chain = DspChain::new(44100.0, 2);
+        let first = chain.insert(0, Box::new(Passthrough));
+        let middle = chain.insert(3, Box::new(Passthrough));
+
+        chain.apply_settings(&DspSettings::default());
+
+        assert_eq!(chain.order()[0], first);
+        assert_eq!(chain.order()[3], middle);
+        assert_eq!(chain.order().last(), Some(&NodeId::Limiter));
+    }
+
+    // Runs a sweep through the bass, de-esser, phaser and crossfeed in `precision`
+    fn sweep_through_chain(precision: Precision) -> Vec<f32> {
+        let mut chain = DspChain::new(48000.0, 2);
+        chain.set_precision(precision);
+        chain.bass.set_enabled(true);
+        let mut settings = DspSettings::default();
+        settings.de_esser.enabled = true;
+        settings.phaser.enabled = true;
+        settings.crossfeed.enabled = true;
+        chain.apply_settings(&settings);
+
+        let signal = Signal::LogSweep { start_hz: 20.0, end_hz: 20000.0, amplitude: 0.5 };
+        let mut generator = SignalGenerator::new(signal, 48000, 2, 2.0);
+        let mut output = Vec::new();
+        while let Some(mut block) = generator.decode_next() {
+            chain.process(&mut block);
+            output.extend_from_slice(&block);
+        }
+        output
+    }
+
+    #[test]
+    fn f64_and_f32_agree() {
+        let single = sweep_through_chain(Precision::F32);
+        let double = sweep_through_chain(Precision::F64);
+        let error = single.iter().zip(&double).fold(0.0f32, |max, (a, b)| max.max((a - b).abs()));
+        // Apart by no more than about -50 dBFS, the rounding `F64` is there to remove
+        assert!(error < 0.003, "differ by {error}");
+        assert!(error > 0.0);
+    }
+
+    // Doubles the signal the first time it runs, then triples it, and so on
+    #[derive(Default)]
+    struct Escalating {
+        calls: u32,
+    }
+
+    impl DspNode for Escalating {
+        fn process(&mut self, samples: &mut [f32], _channels: usize, _sample_rate: f32) {
+            self.calls += 1;
+            samples.iter_mut().for_each(|s| *s *= (self.calls + 1) as f32);
+        }
+    }
+
+    #[test]
+    fn each_chain_gets_its_own_custom_node() {
+        let make: NodeFactory = Arc::new(|| Box::new(Escalating::default()) as Box<dyn DspNode + Send>);
+        let nodes = [(0, make)];
+        let run = |chain: &mut DspChain| {
+            let mut block = vec![0.25; 256];
+            chain.process(&mut block);
+            block[0]
+        };
+
+        let mut playback = DspChain::new(44100.0, 2);
+        playback.sync_custom_nodes(&nodes, Vec::new());
+        playback.reorder(&[NodeId::Custom(0)]).unwrap();
+        assert_eq!(run(&mut playback), 0.5);
+        assert_eq!(run(&mut playback), 0.75);
+
+        // A second chain starts from a fresh instance
+        let mut offline = DspChain::new(44100.0, 2);
+        offline.sync_custom_nodes(&nodes, Vec::new());
+        offline.reorder(&[NodeId::Custom(0)]).unwrap();
+        assert_eq!(run(&mut offline), 0.5);
+
+        // A rebuilt chain carries on with the instance it was handed
+        let carried = playback.take_custom_nodes();
+        let mut rebuilt = DspChain::new(48000.0, 2);
+        rebuilt.sync_custom_nodes(&nodes, carried);
+        rebuilt.reorder(&[NodeId::Custom(0)]).unwrap();
+        assert_eq!(run(&mut rebuilt), 1.0);
+    }
+
+    // Two seconds of noise over full scale through the bass boost and limiter only, in
+    // `order`. Without the limiter at work both stages are linear, and either order
+    // would sound the same
+    fn bass_and_limiter(order: &[NodeId]) -> Vec<f32> {
+        let mut chain = DspChain::new(44100.0, 2);
+        chain.bass.set_enabled(true);
+        chain.bass.set_intensity(100.0);
+        chain.reorder(order).unwrap();
+        let signal = Signal::WhiteNoise { amplitude: 1.5 };
+        let mut generator = SignalGenerator::new(signal, 44100, 2, 2.0);
+        let mut output = Vec::new();
+        while let Some(mut block) = generator.decode_next() {
+            chain.process(&mut block);
+            output.extend_from_slice(&block);
+        }
+        output
+    }
+
+    #[test]
+    fn reordering_two_nodes_changes_the_output() {
+        let limited_last = bass_and_limiter(&[NodeId::Bass, NodeId::Limiter]);
+        let limited_first = bass_and_limiter(&[NodeId::Limiter, NodeId::Bass]);
+        assert_eq!(limited_last.len(), limited_first.len());
+        let difference = limited_last.iter().zip(&limited_first).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+        assert!(difference > 1e-3, "differ by {difference}");
+    }
+
+    struct Doubler;
+
+    impl DspNode for Doubler {
+        fn process(&mut self, samples: &mut [f32], _channels: usize, _sample_rate: f32) {
+            samples.iter_mut().for_each(|s| *s *= 2.0);
+        }
+    }
+
+    #[test]
+    fn disabled_hf_eq_passes_through_while_the_rest_still_runs() {
+        let make: NodeFactory = Arc::new(|| Box::new(Doubler) as Box<dyn DspNode + Send>);
+        let mut chain = DspChain::new(44100.0, 2);
+        chain.sync_custom_nodes(&[(0, make)], Vec::new());
+        chain.reorder(&[NodeId::HfEq, NodeId::Custom(0)]).unwrap();
+        let signal = Signal::Sine { frequency: 12000.0, amplitude: 0.25 };
+        let mut generator = SignalGenerator::new(signal, 44100, 2, 0.1);
+        let input = generator.decode_next().unwrap();
+
+        let mut filtered = input.clone();
+        chain.process(&mut filtered);
+        assert!(filtered.iter().zip(&input).any(|(out, inp)| (out - inp * 2.0).abs() > 1e-3));
+
+        chain.set_node_enabled(NodeId::HfEq, false);
+        assert!(!chain.is_node_enabled(NodeId::HfEq));
+        let mut bypassed = input.clone();
+        chain.process(&mut bypassed);
+        assert!(bypassed.iter().zip(&input).all(|(out, inp)| *out == inp * 2.0));
+    }
+}
@@ -1,34 +1,50 @@
-use crate::engine::dsp::biquad::{BiquadFilter, FilterType};
+use crate::engine::dsp::biquad::{BiquadBank, FilterType};
+use crate::engine::dsp::node::DspNode;
+use crate::engine::dsp::precision::Precision;
 
 pub struct HighFreqEQ {
-    filters: Vec<BiquadFilter>,
-    channels: usize,
+    filters: BiquadBank,
 }
 
 impl HighFreqEQ {
     pub fn new(sample_rate: f32, channels: usize) -> Self {
-        let mut filters = Vec::with_capacity(channels);
-        for _ in 0..channels {
-            filters.push(BiquadFilter::new(
-                FilterType::HighShelf,
-                sample_rate,
-                12000.0,
-                0.7,
-                -1.5,
-            ));
-        }
+        let filters = BiquadBank::new(
+            channels,
+            FilterType::HighShelf,
+            sample_rate,
+            12000.0,
+            0.7,
+            -1.5,
+        );
 
-        Self { filters, channels }
+        Self { filters }
     }
 
     pub fn process(&mut self, samples: &mut [f32]) {
-        let frames = samples.len() / self.channels;
+        self.filters.process_interleaved(samples);
+    }
 
-        for i in 0..frames {
-            for ch in 0..self.channels {
-                let idx = i * self.channels + ch;
-                samples[idx] = self.filters[ch].process(samples[idx]);
-            }
+    pub fn process_planar(&mut self, planar: &mut [Vec<f32>]) {
+        for (ch, channel) in planar.iter_mut().enumerate() {
+            self.filters.process_channel(ch, channel);
         }
     }
-}
\ No newline at end of file
+
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.filters.set_precision(precision);
+    }
+
+    pub fn reset(&mut self) {
+        self.filters.reset();
+    }
+}
+
+impl DspNode for HighFreqEQ {
+    fn process(&mut self, samples: &mut [f32], _channels: usize, _sample_rate: f32) {
+        HighFreqEQ::process(self, samples);
+    }
+
+    fn reset(&mut self) {
+        HighFreqEQ::reset(self);
+    }
+}
@@ -1,32 +1,55 @@
-pub struct Limiter {
-    threshold: f32,
-    attack_coeff: f32,
-    release_coeff: f32,
-    envelope: f32,
-    gain: f32,
-    smoothing_coeff: f32,
+use crate::engine::dsp::node::DspNode;
+use crate::engine::dsp::precision::{Float, Precision};
+use std::f32::consts::PI;
+
+// Oversampling factor and taps per phase of the true-peak interpolator
+const TRUE_PEAK_PHASES: usize = 4;
+const TRUE_PEAK_TAPS: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LimiterSettings {
+    /// Detect peaks on a 4x oversampled copy of the signal, so the ones that only form
+    /// between samples when the DAC reconstructs the waveform are held under the ceiling
+    /// too. Costs a 48-tap filter per channel and delays the audio by 6 frames, so
+    /// switching it during playback can click.
+    pub true_peak: bool,
 }
 
-impl Limiter {
-    pub fn new(threshold_db: f32, sample_rate: f32) -> Self {
-        let threshold = 10.0f32.powf(threshold_db / 20.0);
-        let attack_time = 0.01;
-        let release_time = 0.25;
-        let smoothing_time = 0.01;
+// Envelope follower and gain smoothing of a limiter, computed in `T`
+struct Envelope<T> {
+    threshold: T,
+    attack_coeff: T,
+    release_coeff: T,
+    envelope: T,
+    gain: T,
+    smoothing_coeff: T,
+}
+
+impl<T: Float> Envelope<T> {
+    fn new(threshold_db: f32, sample_rate: f32) -> Self {
+        let c = T::from_f32;
+        let sample_rate = c(sample_rate);
+        let threshold = c(10.0).powf(c(threshold_db) / c(20.0));
+        let attack_time = c(0.01);
+        let release_time = c(0.25);
+        let smoothing_time = c(0.01);
 
         Self {
             threshold,
-            attack_coeff: (-1.0 / (sample_rate * attack_time)).exp(),
-            release_coeff: (-1.0 / (sample_rate * release_time)).exp(),
-            smoothing_coeff: (-1.0 / (sample_rate * smoothing_time)).exp(),
-            envelope: 0.0,
-            gain: 1.0,
+            attack_coeff: (c(-1.0) / (sample_rate * attack_time)).exp(),
+            release_coeff: (c(-1.0) / (sample_rate * release_time)).exp(),
+            smoothing_coeff: (c(-1.0) / (sample_rate * smoothing_time)).exp(),
+            envelope: c(0.0),
+            gain: c(1.0),
         }
     }
 
+    // Follows `level`, the detected peak, and returns the gain to apply
     #[inline]
-    pub fn process(&mut self, input: f32) -> f32 {
-        let x = input.abs() + 1e-10;
+    fn follow(&mut self, level: T) -> T {
+        let x = level + T::from_f32(1e-10);
 
         if x > self.envelope {
             self.envelope = self.attack_coeff * (self.envelope - x) + x;
@@ -37,16 +60,281 @@ impl Limiter {
         let target_gain = if self.envelope > self.threshold {
             self.threshold / self.envelope
         } else {
-            1.0
+            T::from_f32(1.0)
         };
 
         self.gain = self.smoothing_coeff * (self.gain - target_gain) + target_gain;
+        self.gain
+    }
 
-        input * self.gain
+    fn reset(&mut self) {
+        self.envelope = T::from_f32(0.0);
+        self.gain = T::from_f32(1.0);
+    }
+}
+
+// Estimates the peaks between samples by interpolating each one to four points with a
+// windowed-sinc polyphase filter, as in ITU-R BS.1770
+pub(crate) struct TruePeak {
+    phases: [[f32; TRUE_PEAK_TAPS]; TRUE_PEAK_PHASES],
+    // Last `TRUE_PEAK_TAPS` input samples, newest at `pos`
+    history: [f32; TRUE_PEAK_TAPS],
+    // Peaks of the last `DELAY` frames, held so the envelope's release doesn't sag
+    // between the peaks of high frequencies, which only land every few frames
+    peaks: [f32; TruePeak::DELAY],
+    pos: usize,
+}
+
+impl TruePeak {
+    // The filter is centered half its length back, so that's how late the audio comes out
+    pub(crate) const DELAY: usize = TRUE_PEAK_TAPS / 2;
+
+    pub(crate) fn new() -> Self {
+        let half = Self::DELAY as f32;
+        let mut phases = [[0.0; TRUE_PEAK_TAPS]; TRUE_PEAK_PHASES];
+        for (p, taps) in phases.iter_mut().enumerate() {
+            // Tap `k` weighs the sample `k` back, for the point `p / 4` past the delayed one
+            for (k, tap) in taps.iter_mut().enumerate() {
+                let t = k as f32 - half + p as f32 / TRUE_PEAK_PHASES as f32;
+                let sinc = if t == 0.0 { 1.0 } else { (PI * t).sin() / (PI * t) };
+                let window = 0.42 + 0.5 * (PI * t / half).cos() + 0.08 * (2.0 * PI * t / half).cos();
+                *tap = sinc * window;
+            }
+            let sum: f32 = taps.iter().sum();
+            taps.iter_mut().for_each(|tap| *tap /= sum);
+        }
+
+        Self {
+            phases,
+            history: [0.0; TRUE_PEAK_TAPS],
+            peaks: [0.0; Self::DELAY],
+            pos: 0,
+        }
+    }
+
+    // Takes a sample and returns the one from `DELAY` frames ago along with the highest
+    // absolute level the waveform reached over the last `DELAY` frames
+    #[inline]
+    pub(crate) fn push(&mut self, input: f32) -> (f32, f32) {
+        self.pos = (self.pos + 1) % TRUE_PEAK_TAPS;
+        self.history[self.pos] = input;
+
+        let mut peak: f32 = 0.0;
+        for taps in &self.phases {
+            let mut sum = 0.0;
+            for (k, tap) in taps.iter().enumerate() {
+                sum += tap * self.history[(self.pos + TRUE_PEAK_TAPS - k) % TRUE_PEAK_TAPS];
+            }
+            peak = peak.max(sum.abs());
+        }
+        self.peaks[self.pos % Self::DELAY] = peak;
+        let peak = self.peaks.iter().fold(0.0, |max: f32, p| max.max(*p));
+        let delayed = self.history[(self.pos + TRUE_PEAK_TAPS - Self::DELAY) % TRUE_PEAK_TAPS];
+        (delayed, peak)
+    }
+
+    fn reset(&mut self) {
+        self.history = [0.0; TRUE_PEAK_TAPS];
+        self.peaks = [0.0; Self::DELAY];
+    }
+}
+
+pub struct Limiter {
+    single: Envelope<f32>,
+    // Takes over from `single` while running in `Precision::F64`
+    double: Option<Envelope<f64>>,
+    true_peak: Option<TruePeak>,
+    threshold_db: f32,
+    sample_rate: f32,
+}
+
+impl Limiter {
+    pub fn new(threshold_db: f32, sample_rate: f32) -> Self {
+        Self {
+            single: Envelope::new(threshold_db, sample_rate),
+            double: None,
+            true_peak: None,
+            threshold_db,
+            sample_rate,
+        }
+    }
+
+    /// Switches the arithmetic the envelope and gain are computed in, carrying them over.
+    pub fn set_precision(&mut self, precision: Precision) {
+        match (precision, &self.double) {
+            (Precision::F64, None) => {
+                let mut double = Envelope::new(self.threshold_db, self.sample_rate);
+                double.envelope = self.single.envelope as f64;
+                double.gain = self.single.gain as f64;
+                self.double = Some(double);
+            }
+            (Precision::F32, Some(double)) => {
+                self.single.envelope = double.envelope as f32;
+                self.single.gain = double.gain as f32;
+                self.double = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Detects peaks between samples as well as on them. See `LimiterSettings::true_peak`.
+    pub fn set_true_peak(&mut self, enabled: bool) {
+        if enabled != self.true_peak.is_some() {
+            self.true_peak = enabled.then(TruePeak::new);
+        }
+    }
+
+    #[inline]
+    pub fn process(&mut self, input: f32) -> f32 {
+        let (input, level) = match &mut self.true_peak {
+            Some(true_peak) => true_peak.push(input),
+            None => (input, input.abs()),
+        };
+        match &mut self.double {
+            Some(double) => (input as f64 * double.follow(level as f64)) as f32,
+            None => input * self.single.follow(level),
+        }
+    }
+
+    /// Gain applied to the last processed sample in dB, `0.0` when not limiting.
+    pub fn gain_reduction_db(&self) -> f32 {
+        match &self.double {
+            Some(double) => (20.0 * double.gain.log10()) as f32,
+            None => 20.0 * self.single.gain.log10(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.single.reset();
+        if let Some(double) = &mut self.double {
+            double.reset();
+        }
+        if let Some(true_peak) = &mut self.true_peak {
+            true_peak.reset();
+        }
+    }
+}
+
+/// A `Limiter` per channel of interleaved audio, as the DSP chain's last stage.
+pub struct ChannelLimiter {
+    limiters: Vec<Limiter>,
+}
+
+impl ChannelLimiter {
+    pub fn new(threshold_db: f32, sample_rate: f32, channels: usize) -> Self {
+        Self {
+            limiters: (0..channels).map(|_| Limiter::new(threshold_db, sample_rate)).collect(),
+        }
+    }
+
+    pub fn apply_settings(&mut self, settings: &LimiterSettings) {
+        for limiter in &mut self.limiters {
+            limiter.set_true_peak(settings.true_peak);
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        let channels = self.limiters.len().max(1);
+        for frame in samples.chunks_exact_mut(channels) {
+            for (sample, limiter) in frame.iter_mut().zip(&mut self.limiters) {
+                *sample = limiter.process(*sample);
+            }
+        }
+    }
+
+    /// The strongest gain reduction across channels at the last processed frame, in dB.
+    pub fn gain_reduction_db(&self) -> f32 {
+        self.limiters
+            .iter()
+            .map(|l| l.gain_reduction_db())
+            .fold(0.0, f32::min)
+    }
+
+    pub fn set_precision(&mut self, precision: Precision) {
+        for limiter in &mut self.limiters {
+            limiter.set_precision(precision);
+        }
     }
 
     pub fn reset(&mut self) {
-        self.envelope = 0.0;
-        self.gain = 1.0;
+        self.limiters.iter_mut().for_each(Limiter::reset);
+    }
+}
+
+impl DspNode for ChannelLimiter {
+    fn process(&mut self, samples: &mut [f32], _channels: usize, _sample_rate: f32) {
+        ChannelLimiter::process(self, samples);
+    }
+
+    fn reset(&mut self) {
+        ChannelLimiter::reset(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A quarter of the sample rate at a 45 degree phase: every sample lands at 0.707 of
+    // the waveform's peak, which only shows up between them
+    fn inter_sample_tone(amplitude: f32) -> impl Iterator<Item = f32> {
+        (0..48000).map(move |n| amplitude * (PI / 2.0 * (n % 4) as f32 + PI / 4.0).sin())
+    }
+
+    // Highest level the waveform of `samples` reaches, between samples included
+    fn true_peak(samples: &[f32]) -> f32 {
+        let mut detector = TruePeak::new();
+        samples.iter().fold(0.0, |max, s| max.max(detector.push(*s).1))
     }
-}
\ No newline at end of file
+
+    // Limits the tone to -1 dBFS and returns the gain reduction and the output's true peak
+    // over the last half
+    fn limit(true_peak_mode: bool) -> (f32, f32) {
+        let mut limiter = Limiter::new(-1.0, 48000.0);
+        limiter.set_true_peak(true_peak_mode);
+        let output: Vec<f32> = inter_sample_tone(1.0).map(|s| limiter.process(s)).collect();
+        (limiter.gain_reduction_db(), true_peak(&output[output.len() / 2..]))
+    }
+
+    // Gain reduction after half a second of a 1 kHz stereo tone whose channels peak at
+    // `left` and `right`
+    fn reduction_for(left: f32, right: f32) -> f32 {
+        let mut limiter = ChannelLimiter::new(-1.0, 48000.0, 2);
+        let mut samples: Vec<f32> = (0..24000)
+            .flat_map(|n| {
+                let s = (2.0 * PI * 1000.0 * n as f32 / 48000.0).sin();
+                [left * s, right * s]
+            })
+            .collect();
+        limiter.process(&mut samples);
+        limiter.gain_reduction_db()
+    }
+
+    #[test]
+    fn meters_the_strongest_reduction_across_channels() {
+        // A loud right channel shows even though the left one is quiet
+        let loud = reduction_for(0.1, 2.0);
+        assert!(loud < -3.0, "{loud} dB");
+
+        let quiet = reduction_for(0.1, 0.1);
+        assert!(quiet.abs() < 0.01, "{quiet} dB");
+    }
+
+    #[test]
+    fn true_peak_mode_catches_inter_sample_overs() {
+        let input: Vec<f32> = inter_sample_tone(1.0).collect();
+        let sample_peak = input.iter().fold(0.0, |max: f32, s| max.max(s.abs()));
+        assert!(sample_peak < 0.71);
+        assert!(true_peak(&input) > 0.99);
+
+        let ceiling = 10.0f32.powf(-1.0 / 20.0);
+        let (reduction, peak) = limit(false);
+        assert_eq!(reduction, 0.0);
+        assert!(peak > 0.99, "{peak}");
+
+        // Held to within 0.2 dB of the ceiling, where sample mode lets it through at 0 dBFS
+        let (reduction, peak) = limit(true);
+        assert!(reduction < -0.9, "{reduction} dB");
+        assert!(peak < ceiling * 1.023, "{peak}");
+    }
+}
@@ -2,5 +2,20 @@ pub mod resampler;
 pub mod biquad;
 pub mod limiter;
 pub mod bass;
+pub mod channel_gains;
+pub mod channel_mapper;
+pub mod dc_blocker;
+pub mod crossfeed;
+pub mod crossover;
+pub mod de_esser;
+pub mod lfo_mod;
+pub mod node;
+pub mod parametric_eq;
+pub mod phase_correction;
+pub mod phaser;
+pub mod precision;
+pub mod preset;
+pub mod routing;
+pub mod time_stretch;
 mod eq;
-pub(crate) mod dsp_chain;
\ No newline at end of file
+pub mod dsp_chain;
\ No newline at end of file
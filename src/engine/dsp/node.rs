@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+/// A processing stage that can be placed anywhere in the DSP chain, built in or added
+/// with `AudioEngine::add_dsp_node`.
+///
+/// `process` runs on the decode thread for every block, ahead of the output buffer, so
+/// it has to keep up with real time: don't allocate, lock, block on I/O or anything
+/// else that can take an unbounded time in it. Set up buffers when the node is built
+/// and size them for the largest block you expect. Blocks vary in length, and the
+/// channel count and sample rate can change between calls when the output device does.
+pub trait DspNode {
+    /// Processes one block of interleaved audio in place.
+    fn process(&mut self, samples: &mut [f32], channels: usize, sample_rate: f32);
+
+    /// Forgets filter history, e.g. after a seek, so it doesn't leak into unrelated audio.
+    fn reset(&mut self) {}
+}
+
+/// Builds a custom node. Every chain the engine runs (playback, `process_samples`, the
+/// monitor) gets its own instance from it, so they never share state or need a lock.
+pub(crate) type NodeFactory = Arc<dyn Fn() -> Box<dyn DspNode + Send> + Send + Sync>;
+
+/// Identifies a stage of the DSP chain, for reordering and removing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NodeId {
+    DcBlocker,
+    PhaseCorrection,
+    Bass,
+    HfEq,
+    Eq,
+    DeEsser,
+    Phaser,
+    LfoMod,
+    Crossfeed,
+    Crossover,
+    Router,
+    ChannelGains,
+    Limiter,
+    /// A node added with `AudioEngine::add_dsp_node` or `DspChain::insert`.
+    Custom(u32),
+}
+
+impl NodeId {
+    /// Order the built-in stages run in unless changed.
+    pub const DEFAULT_ORDER: [NodeId; 13] = [
+        NodeId::DcBlocker,
+        NodeId::PhaseCorrection,
+        NodeId::Bass,
+        NodeId::HfEq,
+        NodeId::Eq,
+        NodeId::DeEsser,
+        NodeId::Phaser,
+        NodeId::LfoMod,
+        NodeId::Crossfeed,
+        NodeId::Crossover,
+        NodeId::Router,
+        NodeId::ChannelGains,
+        NodeId::Limiter,
+    ];
+}
+
+/// `NodeId::DEFAULT_ORDER` with the given custom nodes just ahead of the limiter, so the
+/// limiter still catches anything they push over full scale.
+pub fn default_order(custom: impl IntoIterator<Item = u32>) -> Vec<NodeId> {
+    let mut order = NodeId::DEFAULT_ORDER.to_vec();
+    let limiter = order.len() - 1;
+    order.splice(limiter..limiter, custom.into_iter().map(NodeId::Custom));
+    order
+}
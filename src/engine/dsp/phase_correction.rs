@@ -0,0 +1,182 @@
+use crate::engine::dsp::node::DspNode;
+
+// Correlation is measured over blocks of this length
+const WINDOW_SECS: f32 = 0.5;
+// Below this the front pair counts as polarity-inverted, above the release it's fine again
+const DETECT_CORRELATION: f32 = -0.8;
+const RELEASE_CORRELATION: f32 = -0.5;
+// Windows quieter than about -60 dBFS RMS say nothing either way
+const MIN_ENERGY: f32 = 1e-6;
+// Time constant of the polarity flip, so it fades through zero instead of clicking
+const FLIP_SECS: f32 = 0.005;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PhaseCorrectionSettings {
+    /// Flip the right channel while the front pair measures as polarity-inverted.
+    pub auto: bool,
+    /// Channels whose polarity is always inverted, indexed by channel.
+    pub inverted: Vec<bool>,
+}
+
+/// Watches the correlation between the first two channels for a channel that's an
+/// inverted copy of the other (which cancels out when summed to mono), and applies
+/// per-channel polarity inversion, manual or automatic.
+pub struct PhaseCorrection {
+    settings: PhaseCorrectionSettings,
+    channels: usize,
+    window_frames: usize,
+    frames: usize,
+    sum_lr: f32,
+    sum_ll: f32,
+    sum_rr: f32,
+    detected: bool,
+    polarity: Vec<f32>,
+    coeff: f32,
+}
+
+impl PhaseCorrection {
+    pub fn new(sample_rate: f32, channels: usize) -> Self {
+        Self {
+            settings: PhaseCorrectionSettings::default(),
+            channels,
+            window_frames: ((sample_rate * WINDOW_SECS) as usize).max(1),
+            frames: 0,
+            sum_lr: 0.0,
+            sum_ll: 0.0,
+            sum_rr: 0.0,
+            detected: false,
+            polarity: vec![1.0; channels],
+            coeff: (-1.0 / (FLIP_SECS * sample_rate)).exp(),
+        }
+    }
+
+    pub fn apply_settings(&mut self, settings: &PhaseCorrectionSettings) {
+        self.settings = settings.clone();
+    }
+
+    /// Whether the front pair currently measures as polarity-inverted, whether or not
+    /// it's being corrected.
+    pub fn inversion_detected(&self) -> bool {
+        self.detected
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if self.channels < 2 {
+            return;
+        }
+
+        let targets: Vec<f32> = (0..self.channels)
+            .map(|ch| {
+                let manual = self.settings.inverted.get(ch).copied().unwrap_or(false);
+                let auto = ch == 1 && self.settings.auto && self.detected;
+                if manual != auto { -1.0 } else { 1.0 }
+            })
+            .collect();
+        if self.polarity == targets && targets.iter().all(|&p| p == 1.0) {
+            // Nothing to apply, just keep measuring
+            for frame in samples.chunks_exact(self.channels) {
+                self.measure(frame[0], frame[1]);
+            }
+            return;
+        }
+
+        for frame in samples.chunks_exact_mut(self.channels) {
+            self.measure(frame[0], frame[1]);
+            for (ch, sample) in frame.iter_mut().enumerate() {
+                let polarity = &mut self.polarity[ch];
+                *polarity = targets[ch] + (*polarity - targets[ch]) * self.coeff;
+                *sample *= *polarity;
+            }
+        }
+        for (polarity, target) in self.polarity.iter_mut().zip(&targets) {
+            if (*polarity - target).abs() < 1e-6 {
+                *polarity = *target;
+            }
+        }
+    }
+
+    fn measure(&mut self, left: f32, right: f32) {
+        self.sum_lr += left * right;
+        self.sum_ll += left * left;
+        self.sum_rr += right * right;
+        self.frames += 1;
+        if self.frames < self.window_frames {
+            return;
+        }
+
+        let n = self.frames as f32;
+        if self.sum_ll / n > MIN_ENERGY && self.sum_rr / n > MIN_ENERGY {
+            let correlation = self.sum_lr / (self.sum_ll * self.sum_rr).sqrt();
+            if correlation < DETECT_CORRELATION {
+                self.detected = true;
+            } else if correlation > RELEASE_CORRELATION {
+                self.detected = false;
+            }
+        }
+        self.frames = 0;
+        self.sum_lr = 0.0;
+        self.sum_ll = 0.0;
+        self.sum_rr = 0.0;
+    }
+
+    pub fn reset(&mut self) {
+        self.frames = 0;
+        self.sum_lr = 0.0;
+        self.sum_ll = 0.0;
+        self.sum_rr = 0.0;
+        self.detected = false;
+    }
+}
+
+impl DspNode for PhaseCorrection {
+    fn process(&mut self, samples: &mut [f32], _channels: usize, _sample_rate: f32) {
+        PhaseCorrection::process(self, samples);
+    }
+
+    fn reset(&mut self) {
+        PhaseCorrection::reset(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::decoder::AudioDecoder;
+    use crate::test_util::signal_generator::{Signal, SignalGenerator};
+
+    // Runs two seconds of a tone through automatic correction, with the right channel
+    // inverted if `invert_right`, and returns the output
+    fn correct(invert_right: bool) -> (PhaseCorrection, Vec<f32>) {
+        let mut corrector = PhaseCorrection::new(48000.0, 2);
+        corrector.apply_settings(&PhaseCorrectionSettings { auto: true, ..Default::default() });
+        let signal = Signal::Sine { frequency: 440.0, amplitude: 0.5 };
+        let mut generator = SignalGenerator::new(signal, 48000, 2, 2.0);
+        let mut output = Vec::new();
+        while let Some(mut block) = generator.decode_next() {
+            if invert_right {
+                block.chunks_exact_mut(2).for_each(|frame| frame[1] = -frame[1]);
+            }
+            corrector.process(&mut block);
+            output.extend_from_slice(&block);
+        }
+        (corrector, output)
+    }
+
+    #[test]
+    fn inverted_right_channel_is_flagged_and_fixed() {
+        let (corrector, output) = correct(true);
+        assert!(corrector.inversion_detected());
+        for frame in output[output.len() / 2..].chunks_exact(2) {
+            assert!((frame[0] - frame[1]).abs() < 1e-5, "{frame:?}");
+        }
+    }
+
+    #[test]
+    fn matching_channels_are_left_alone() {
+        let (corrector, output) = correct(false);
+        assert!(!corrector.inversion_detected());
+        assert!(output.chunks_exact(2).all(|frame| frame[0] == frame[1]));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::engine::dsp::biquad::{BiquadFilter, FilterType};
+use crate::engine::dsp::node::DspNode;
+use crate::engine::dsp::precision::Precision;
+use std::f32::consts::PI;
+
+const MIN_FREQ: f32 = 200.0;
+const MAX_FREQ: f32 = 4000.0;
+const STAGE_Q: f32 = 0.7;
+// Filter coefficients follow the LFO at this many frames per update
+const CONTROL_INTERVAL: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PhaserSettings {
+    pub enabled: bool,
+    pub rate_hz: f32,
+    /// How much of the sweep range the LFO covers, `0.0..=1.0`.
+    pub depth: f32,
+    /// Portion of the wet output fed back into the input, `-0.95..=0.95`.
+    pub feedback: f32,
+    /// Number of all-pass stages, rounded down to an even count between 2 and 12.
+    pub stages: usize,
+    /// Wet/dry mix, `0.0..=1.0`. Notches are deepest at 0.5.
+    pub mix: f32,
+}
+
+impl Default for PhaserSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rate_hz: 0.5,
+            depth: 0.8,
+            feedback: 0.3,
+            stages: 4,
+            mix: 0.5,
+        }
+    }
+}
+
+/// Cascaded all-pass stages swept by a sine LFO, mixed back with the dry signal so
+/// the phase shift turns into moving notches.
+pub struct Phaser {
+    settings: PhaserSettings,
+    sample_rate: f32,
+    channels: usize,
+    stages: Vec<Vec<BiquadFilter>>,
+    precision: Precision,
+    last_wet: Vec<f32>,
+    lfo_phase: f32,
+    counter: usize,
+}
+
+impl Phaser {
+    pub fn new(sample_rate: f32, channels: usize) -> Self {
+        let settings = PhaserSettings::default();
+        let mut phaser = Self {
+            settings,
+            sample_rate,
+            channels,
+            stages: Vec::new(),
+            precision: Precision::F32,
+            last_wet: vec![0.0; channels],
+            lfo_phase: 0.0,
+            counter: 0,
+        };
+        phaser.build_stages(settings.stages);
+        phaser
+    }
+
+    fn build_stages(&mut self, stages: usize) {
+        self.stages = (0..self.channels)
+            .map(|_| {
+                (0..stages)
+                    .map(|_| {
+                        let mut stage =
+                            BiquadFilter::new(FilterType::AllPass, self.sample_rate, MIN_FREQ, STAGE_Q, 0.0);
+                        stage.set_precision(self.precision);
+                        stage
+                    })
+                    .collect()
+            })
+            .collect();
+        self.counter = 0;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.settings.enabled = enabled;
+    }
+
+    pub fn apply_settings(&mut self, settings: &PhaserSettings) {
+        let stages = (settings.stages.clamp(2, 12) / 2) * 2;
+        if stages != self.settings.stages {
+            self.build_stages(stages);
+        }
+        self.settings = PhaserSettings {
+            enabled: settings.enabled,
+            rate_hz: settings.rate_hz.clamp(0.01, 20.0),
+            depth: settings.depth.clamp(0.0, 1.0),
+            feedback: settings.feedback.clamp(-0.95, 0.95),
+            stages,
+            mix: settings.mix.clamp(0.0, 1.0),
+        };
+    }
+
+    fn update_stages(&mut self) {
+        let lfo = 0.5 + 0.5 * self.lfo_phase.sin();
+        let freq = MIN_FREQ * (MAX_FREQ / MIN_FREQ).powf(lfo * self.settings.depth);
+        for channel in &mut self.stages {
+            for stage in channel.iter_mut() {
+                stage.update(FilterType::AllPass, self.sample_rate, freq, STAGE_Q, 0.0);
+            }
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if !self.settings.enabled {
+            return;
+        }
+
+        let phase_step = 2.0 * PI * self.settings.rate_hz / self.sample_rate;
+        let mix = self.settings.mix;
+        let feedback = self.settings.feedback;
+
+        for frame in samples.chunks_exact_mut(self.channels) {
+            if self.counter.is_multiple_of(CONTROL_INTERVAL) {
+                self.update_stages();
+            }
+            self.counter = self.counter.wrapping_add(1);
+            self.lfo_phase = (self.lfo_phase + phase_step) % (2.0 * PI);
+
+            for (ch, sample) in frame.iter_mut().enumerate() {
+                let dry = *sample;
+                let mut wet = dry + feedback * self.last_wet[ch];
+                for stage in &mut self.stages[ch] {
+                    wet = stage.process(wet);
+                }
+                self.last_wet[ch] = wet;
+                *sample = dry * (1.0 - mix) + wet * mix;
+            }
+        }
+    }
+
+    pub fn set_precision(&mut self, precision: Precision) {
+        self.precision = precision;
+        for stage in self.stages.iter_mut().flatten() {
+            stage.set_precision(precision);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for channel in &mut self.stages {
+            for stage in channel.iter_mut() {
+                stage.reset();
+            }
+        }
+        self.last_wet.iter_mut().for_each(|s| *s = 0.0);
+        self.lfo_phase = 0.0;
+        self.counter = 0;
+    }
+}
+
+impl DspNode for Phaser {
+    fn process(&mut self, samples: &mut [f32], _channels: usize, _sample_rate: f32) {
+        Phaser::process(self, samples);
+    }
+
+    fn reset(&mut self) {
+        Phaser::reset(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Level of a sine at `frequency` after the phaser, in 10 ms windows over two seconds
+    fn envelope(frequency: f32) -> Vec<f32> {
+        let sample_rate = 48000.0;
+        let mut phaser = Phaser::new(sample_rate, 1);
+        let settings = PhaserSettings { enabled: true, rate_hz: 0.5, feedback: 0.0, ..PhaserSettings::default() };
+        phaser.apply_settings(&settings);
+
+        let step = 2.0 * PI * frequency / sample_rate;
+        let mut samples: Vec<f32> = (0..2 * sample_rate as usize).map(|n| (step * n as f32).sin()).collect();
+        phaser.process(&mut samples);
+        samples
+            .chunks_exact(480)
+            .map(|window| (window.iter().map(|s| s * s).sum::<f32>() / window.len() as f32).sqrt())
+            .collect()
+    }
+
+    #[test]
+    fn notch_sweeps_through_the_spectrum() {
+        let low = envelope(300.0);
+        let high = envelope(1500.0);
+
+        // Each probe tone falls into a notch while the other still passes, so the notch
+        // moves through the spectrum rather than sitting in one place
+        let peak = |levels: &[f32]| levels.iter().cloned().fold(0.0, f32::max);
+        let notched_alone = |a: &[f32], b: &[f32]| {
+            a.iter().zip(b).any(|(&a_level, &b_level)| a_level < 0.2 * peak(a) && b_level > 0.5 * peak(b))
+        };
+        assert!(notched_alone(&low, &high));
+        assert!(notched_alone(&high, &low));
+    }
+}
@@ -0,0 +1,91 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// Arithmetic the DSP chain's recursive stages (the biquad filters, the DC blocker and
+/// the limiter) run in. Audio still passes between stages as `f32`, whose 24-bit
+/// mantissa is far below audibility; what `F64` removes is the rounding that builds up
+/// inside filter state and coefficients, most of all in low-frequency filters at high
+/// sample rates and in long cascades.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Precision {
+    #[default]
+    F32,
+    /// Bypasses the `simd` filter bank path. `cargo bench --bench dsp -- precision`
+    /// compares its cost with `F32`.
+    F64,
+}
+
+// Just enough of a float type to run filter and limiter math in either precision
+pub(crate) trait Float:
+    Copy
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    const PI: Self;
+    fn from_f32(value: f32) -> Self;
+    fn exp(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn powf(self, n: Self) -> Self;
+}
+
+impl Float for f32 {
+    const PI: Self = std::f32::consts::PI;
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+
+    fn exp(self) -> Self {
+        f32::exp(self)
+    }
+
+    fn sin(self) -> Self {
+        f32::sin(self)
+    }
+
+    fn cos(self) -> Self {
+        f32::cos(self)
+    }
+
+    fn sqrt(self) -> Self {
+        f32::sqrt(self)
+    }
+
+    fn powf(self, n: Self) -> Self {
+        f32::powf(self, n)
+    }
+}
+
+impl Float for f64 {
+    const PI: Self = std::f64::consts::PI;
+
+    fn from_f32(value: f32) -> Self {
+        value as f64
+    }
+
+    fn exp(self) -> Self {
+        f64::exp(self)
+    }
+
+    fn sin(self) -> Self {
+        f64::sin(self)
+    }
+
+    fn cos(self) -> Self {
+        f64::cos(self)
+    }
+
+    fn sqrt(self) -> Self {
+        f64::sqrt(self)
+    }
+
+    fn powf(self, n: Self) -> Self {
+        f64::powf(self, n)
+    }
+}
@@ -0,0 +1,35 @@
+use crate::engine::dsp::dsp_chain::DspSettings;
+
+/// Everything needed to recall a DSP setup: the bass boost plus every optional node.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DspPreset {
+    pub bass_boost: bool,
+    /// Bass boost intensity, `0.0..=100.0`.
+    pub bass_intensity: f32,
+    pub settings: DspSettings,
+}
+
+impl Default for DspPreset {
+    fn default() -> Self {
+        Self {
+            bass_boost: false,
+            bass_intensity: 50.0,
+            settings: DspSettings::default(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl DspPreset {
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parses a preset saved by `to_json`. Missing fields fall back to their defaults so
+    /// presets saved by older versions still load.
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
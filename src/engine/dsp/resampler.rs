@@ -1,11 +1,47 @@
-use rubato::{Resampler as RubatoResampler, Fft, FixedSync};
+use rubato::{
+    calculate_cutoff, Async, Fft, FixedAsync, FixedSync, Resampler as RubatoResampler,
+    SincInterpolationParameters, SincInterpolationType, WindowFunction,
+};
 use audioadapter_buffers::direct::SequentialSliceOfVecs;
+use crate::engine::dsp::biquad::{BiquadFilter, FilterType};
+
+// How far `set_ratio` may move away from the nominal conversion ratio, in either direction
+const MAX_RELATIVE_RATIO: f64 = 4.0;
+// Cutoff of the extra anti-aliasing low-pass, relative to the new Nyquist
+const ANTI_ALIAS_CUTOFF: f32 = 0.9;
+// Largest relative ratio change ramped within one chunk. rubato sizes a ramped chunk's
+// output from the average ratio, which overruns its input on bigger jumps
+const MAX_RATIO_STEP: f64 = 1.03;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResamplerKind {
+    /// Synchronous FFT resampler. Cheapest and cleanest for a fixed ratio, but the
+    /// ratio can't change once it's built.
+    #[default]
+    Fft,
+    /// Asynchronous sinc resampler. Costs more CPU but supports continuous ratio
+    /// changes through `Resampler::set_ratio`.
+    Sinc,
+}
 
 pub struct Resampler {
-    resampler: Fft<f32>,
+    resampler: Box<dyn RubatoResampler<f32> + Send>,
+    kind: ResamplerKind,
     channels: usize,
     chunk_size: usize,
     buffer: Vec<f32>,
+    // One chunk of input and the most output a chunk can turn into, one `Vec` per
+    // channel, allocated up front so converting a chunk doesn't allocate
+    planar_in: Vec<Vec<f32>>,
+    planar_out: Vec<Vec<f32>>,
+    source_sample_rate: u32,
+    nominal_ratio: f64,
+    // Absolute ratio `set_ratio` asked for, approached a step per chunk
+    target_ratio: f64,
+    bandlimited: bool,
+    // Two Butterworth sections per channel, only present while speeding up past what
+    // the sinc filter built for the nominal ratio can band-limit on its own
+    anti_alias: Option<Vec<[BiquadFilter; 2]>>,
 }
 
 impl Resampler {
@@ -15,59 +51,179 @@ impl Resampler {
         channels: usize,
         chunk_size: usize,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let resampler = Fft::<f32>::new(
-            source_sample_rate as usize,
-            target_sample_rate as usize,
-            chunk_size,
-            2,
+        Self::with_kind(
+            source_sample_rate,
+            target_sample_rate,
             channels,
-            FixedSync::Input,
-        )?;
+            chunk_size,
+            ResamplerKind::Fft,
+        )
+    }
 
+    pub fn with_kind(
+        source_sample_rate: u32,
+        target_sample_rate: u32,
+        channels: usize,
+        chunk_size: usize,
+        kind: ResamplerKind,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let resampler: Box<dyn RubatoResampler<f32> + Send> = match kind {
+            ResamplerKind::Fft => Box::new(Fft::<f32>::new(
+                source_sample_rate as usize,
+                target_sample_rate as usize,
+                chunk_size,
+                2,
+                channels,
+                FixedSync::Input,
+            )?),
+            ResamplerKind::Sinc => {
+                let sinc_len = 128;
+                let window = WindowFunction::BlackmanHarris2;
+                let params = SincInterpolationParameters {
+                    sinc_len,
+                    f_cutoff: calculate_cutoff(sinc_len, window),
+                    interpolation: SincInterpolationType::Quadratic,
+                    oversampling_factor: 256,
+                    window,
+                };
+                Box::new(Async::<f32>::new_sinc(
+                    target_sample_rate as f64 / source_sample_rate as f64,
+                    MAX_RELATIVE_RATIO,
+                    &params,
+                    chunk_size,
+                    channels,
+                    FixedAsync::Input,
+                )?)
+            }
+        };
+
+        let max_output = resampler.output_frames_max();
         Ok(Self {
             resampler,
+            kind,
             channels,
             chunk_size,
             buffer: Vec::with_capacity(chunk_size * channels),
+            planar_in: vec![vec![0.0; chunk_size]; channels],
+            planar_out: vec![vec![0.0; max_output]; channels],
+            source_sample_rate,
+            nominal_ratio: target_sample_rate as f64 / source_sample_rate as f64,
+            target_ratio: target_sample_rate as f64 / source_sample_rate as f64,
+            bandlimited: true,
+            anti_alias: None,
         })
     }
 
+    pub fn kind(&self) -> ResamplerKind {
+        self.kind
+    }
+
+    /// Input frames converted at a time.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Changes the conversion ratio relative to the nominal one it was built with, e.g.
+    /// `2.0` produces twice as many output frames (half speed, an octave down).
+    ///
+    /// The change is ramped over the next chunks so it doesn't click. Only the `Sinc`
+    /// resampler supports this; the `Fft` one returns an error and keeps its ratio.
+    pub fn set_ratio(&mut self, ratio: f32) -> Result<(), Box<dyn std::error::Error>> {
+        if self.kind != ResamplerKind::Sinc {
+            return Err("The FFT resampler can't change its ratio, use ResamplerKind::Sinc".into());
+        }
+        let ratio = (ratio as f64).clamp(1.0 / MAX_RELATIVE_RATIO, MAX_RELATIVE_RATIO);
+        self.target_ratio = self.nominal_ratio * ratio;
+        self.update_anti_alias(ratio);
+        Ok(())
+    }
+
+    /// With `bandlimited` on (the default), speeding up with `set_ratio` below the
+    /// nominal ratio low-passes the input at the new Nyquist first. The sinc filter is
+    /// designed once for the nominal ratio, so without this, content between the new and
+    /// the old Nyquist folds back down as aliasing.
+    pub fn set_bandlimited(&mut self, bandlimited: bool) {
+        self.bandlimited = bandlimited;
+        let ratio = self.target_ratio / self.nominal_ratio;
+        self.update_anti_alias(ratio);
+    }
+
+    fn update_anti_alias(&mut self, relative_ratio: f64) {
+        let effective = self.nominal_ratio * relative_ratio;
+        // The sinc filter already cuts at the lower of the two Nyquists of the nominal ratio
+        if !self.bandlimited || effective >= self.nominal_ratio.min(1.0) {
+            self.anti_alias = None;
+            return;
+        }
+
+        let source_rate = self.source_sample_rate as f32;
+        let cutoff = ANTI_ALIAS_CUTOFF * effective as f32 * source_rate / 2.0;
+        let q = std::f32::consts::FRAC_1_SQRT_2;
+        let filters = self.anti_alias.get_or_insert_with(|| {
+            let section = || BiquadFilter::new(FilterType::LowPass, source_rate, cutoff, q, 0.0);
+            (0..self.channels).map(|_| [section(), section()]).collect()
+        });
+        for filter in filters.iter_mut().flatten() {
+            filter.update(FilterType::LowPass, source_rate, cutoff, q, 0.0);
+        }
+    }
+
     pub fn process(&mut self, input: &[f32]) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
-        self.buffer.extend_from_slice(input);
+        let mut output = Vec::new();
+        self.process_into(input, &mut output)?;
+        Ok(output)
+    }
+
+    /// Like `process`, but appends the converted audio to `output`. Feeding whole chunks
+    /// into an `output` with room for `output_frames_max` frames per chunk doesn't
+    /// allocate, so it can run on the audio thread.
+    pub fn process_into(&mut self, input: &[f32], output: &mut Vec<f32>) -> Result<(), Box<dyn std::error::Error>> {
+        match &mut self.anti_alias {
+            Some(filters) => {
+                for frame in input.chunks_exact(self.channels) {
+                    for (sample, sections) in frame.iter().zip(filters.iter_mut()) {
+                        let x = sections[0].process(*sample);
+                        self.buffer.push(sections[1].process(x));
+                    }
+                }
+            }
+            None => self.buffer.extend_from_slice(input),
+        }
 
-        let mut all_output = Vec::new();
+        let chunk_len = self.chunk_size * self.channels;
+        while self.buffer.len() >= chunk_len {
+            let current = self.resampler.resample_ratio();
+            if current != self.target_ratio {
+                let step = (self.target_ratio / current).clamp(1.0 / MAX_RATIO_STEP, MAX_RATIO_STEP);
+                self.resampler.set_resample_ratio(current * step, true)?;
+            }
 
-        while self.buffer.len() >= self.chunk_size * self.channels {
-            let chunk: Vec<f32> = self.buffer.drain(0..self.chunk_size * self.channels).collect();
             let num_frames = self.chunk_size;
-
-            let mut input_buffer = vec![vec![0.0; num_frames]; self.channels];
-            for i in 0..num_frames {
-                for ch in 0..self.channels {
-                    input_buffer[ch][i] = chunk[i * self.channels + ch];
+            for (i, frame) in self.buffer[..chunk_len].chunks_exact(self.channels).enumerate() {
+                for (channel, sample) in self.planar_in.iter_mut().zip(frame) {
+                    channel[i] = *sample;
                 }
             }
+            self.buffer.drain(..chunk_len);
 
             let out_len = self.resampler.output_frames_next();
-            let mut output_buffer = vec![vec![0.0; out_len]; self.channels];
+            let input_adapter = SequentialSliceOfVecs::new(&self.planar_in, self.channels, num_frames)?;
+            let mut output_adapter = SequentialSliceOfVecs::new_mut(&mut self.planar_out, self.channels, out_len)?;
 
-            let input_adapter = SequentialSliceOfVecs::new(&input_buffer, self.channels, num_frames)?;
-            let mut output_adapter = SequentialSliceOfVecs::new_mut(&mut output_buffer, self.channels, out_len)?;
-
-            self.resampler.process_into_buffer(
+            let (_, written) = self.resampler.process_into_buffer(
                 &input_adapter,
                 &mut output_adapter,
                 None,
             )?;
 
-            for i in 0..out_len {
-                for ch in 0..self.channels {
-                    all_output.push(output_buffer[ch][i]);
+            for i in 0..written {
+                for channel in &self.planar_out {
+                    output.push(channel[i]);
                 }
             }
         }
 
-        Ok(all_output)
+        Ok(())
     }
 
     pub fn flush(&mut self) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
@@ -82,7 +238,133 @@ impl Resampler {
         self.process(&[])
     }
 
+    /// Like `flush`, but returns only the output the held input accounts for, including
+    /// what the filter's delay still holds, and none of the padding's. For handing over to
+    /// another resampler mid-stream, where the padding would play as a gap.
+    pub fn flush_partial(&mut self) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let remaining_frames = self.buffer.len() / self.channels;
+        let ratio = self.resampler.resample_ratio();
+        let frames = (remaining_frames as f64 * ratio).ceil() as usize + self.output_delay();
+        self.buffer.resize(self.chunk_size * self.channels, 0.0);
+        let mut output = self.process(&[])?;
+        output.truncate(frames * self.channels);
+        Ok(output)
+    }
+
+    /// Drops buffered input and the filter history, so output after a seek depends only on
+    /// input fed after it. A ratio changed with `set_ratio` is kept.
+    pub fn reset(&mut self) {
+        self.resampler.reset();
+        if self.kind == ResamplerKind::Sinc {
+            let _ = self.resampler.set_resample_ratio(self.target_ratio, false);
+        }
+        self.buffer.clear();
+        for filter in self.anti_alias.iter_mut().flatten().flatten() {
+            filter.reset();
+        }
+    }
+
+    /// Frames any input is delayed by before it appears in the output, at the output rate.
+    pub fn output_delay(&self) -> usize {
+        self.resampler.output_delay()
+    }
+
+    /// Most output frames one chunk of input can turn into.
+    pub fn output_frames_max(&self) -> usize {
+        self.resampler.output_frames_max()
+    }
+
     pub fn input_frames_next(&self) -> usize {
         self.resampler.input_frames_next()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RMS of what a resampler running at twice the speed makes of a tone at `frequency`,
+    // once the ratio ramp has settled
+    fn sped_up_level(frequency: f32, bandlimited: bool) -> f32 {
+        let mut resampler = Resampler::with_kind(44100, 44100, 1, 256, ResamplerKind::Sinc).unwrap();
+        resampler.set_bandlimited(bandlimited);
+        resampler.set_ratio(0.5).unwrap();
+        let step = 2.0 * std::f32::consts::PI * frequency / 44100.0;
+        let input: Vec<f32> = (0..88200).map(|n| 0.5 * (step * n as f32).sin()).collect();
+        let output = resampler.process(&input).unwrap();
+        let settled = &output[output.len() / 2..];
+        (settled.iter().map(|s| s * s).sum::<f32>() / settled.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn speeding_up_near_nyquist_doesnt_alias() {
+        // At twice the speed 18 kHz would come out at 36 kHz, past the output's Nyquist,
+        // so anything left of it is an alias folded down to 8.1 kHz
+        let unfiltered = sped_up_level(18000.0, false);
+        let filtered = sped_up_level(18000.0, true);
+        assert!(unfiltered > 0.3, "{unfiltered}");
+        assert!(filtered < 0.005, "{filtered}");
+
+        // A tone that still fits below the new Nyquist goes through
+        let kept = sped_up_level(5000.0, true);
+        assert!(kept > 0.3, "{kept}");
+    }
+
+    #[test]
+    fn reset_output_depends_only_on_new_input() {
+        let step = 2.0 * std::f32::consts::PI * 440.0 / 44100.0;
+        let after: Vec<f32> = (0..4096).flat_map(|n| [0.5 * (step * n as f32).sin(); 2]).collect();
+        for kind in [ResamplerKind::Fft, ResamplerKind::Sinc] {
+            let mut fresh = Resampler::with_kind(44100, 48000, 2, 256, kind).unwrap();
+            let expected = fresh.process(&after).unwrap();
+
+            // Loud input before the reset, ending partway through a chunk
+            let mut seeked = Resampler::with_kind(44100, 48000, 2, 256, kind).unwrap();
+            let before: Vec<f32> = (0..300 * 2).map(|n| if n % 3 == 0 { 0.9 } else { -0.9 }).collect();
+            seeked.process(&before).unwrap();
+            seeked.reset();
+            let output = seeked.process(&after).unwrap();
+
+            assert_eq!(output.len(), expected.len());
+            let worst = output.iter().zip(&expected).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+            assert!(worst < 1e-6, "{kind:?}: {worst}");
+        }
+    }
+
+    #[test]
+    fn ratio_sweep_changes_output_length_smoothly() {
+        let mut resampler = Resampler::with_kind(44100, 44100, 2, 256, ResamplerKind::Sinc).unwrap();
+        let chunk = vec![0.1; 256 * 2];
+        let mut lengths = Vec::new();
+        for step in 0..150 {
+            let ratio = 1.0 + (step as f32 / 100.0).min(1.0);
+            resampler.set_ratio(ratio).unwrap();
+            let frames = resampler.process(&chunk).unwrap().len() / 2;
+            lengths.push(frames);
+            // Each chunk follows the ratio it was fed at, give or take rounding
+            assert!(frames.abs_diff((256.0 * ratio) as usize) <= 4, "{lengths:?}");
+        }
+        assert!(lengths[140..].iter().all(|&len| len == 512), "{lengths:?}");
+    }
+
+    #[test]
+    fn large_ratio_jump_glides_without_panicking() {
+        let mut resampler = Resampler::with_kind(44100, 44100, 2, 256, ResamplerKind::Sinc).unwrap();
+        let chunk = vec![0.1; 256 * 2];
+        resampler.set_ratio(0.5).unwrap();
+        for _ in 0..50 {
+            resampler.process(&chunk).unwrap();
+        }
+
+        // A quadrupling in one call, as a speed change from 2x to half speed asks for
+        resampler.set_ratio(2.0).unwrap();
+        let mut lengths = Vec::new();
+        for _ in 0..100 {
+            let frames = resampler.process(&chunk).unwrap().len() / 2;
+            lengths.push(frames);
+            assert!((124..=516).contains(&frames), "{lengths:?}");
+        }
+        assert!(lengths.windows(2).all(|w| w[1] + 4 >= w[0]), "{lengths:?}");
+        assert_eq!(lengths[lengths.len() - 1], 512, "{lengths:?}");
+    }
+}
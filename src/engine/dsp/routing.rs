@@ -0,0 +1,100 @@
+use crate::engine::dsp::node::DspNode;
+
+/// Which source channels feed each output channel, and how loud. `matrix[out]` lists the
+/// `(source channel, gain)` pairs mixed into device channel `out`. Empty means identity.
+///
+/// Once any route is set, channels without one are silent. Routes to or from channels
+/// the device doesn't have are ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ChannelRouting {
+    pub matrix: Vec<Vec<(usize, f32)>>,
+}
+
+impl ChannelRouting {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `source` to `output` at `gain` (linear).
+    pub fn route(mut self, source: usize, output: usize, gain: f32) -> Self {
+        if self.matrix.len() <= output {
+            self.matrix.resize(output + 1, Vec::new());
+        }
+        self.matrix[output].push((source, gain));
+        self
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.matrix.is_empty()
+    }
+}
+
+/// Applies a `ChannelRouting` to interleaved audio at the device's channel count.
+pub struct Router {
+    routing: ChannelRouting,
+    channels: usize,
+    frame: Vec<f32>,
+}
+
+impl Router {
+    pub fn new(channels: usize) -> Self {
+        Self {
+            routing: ChannelRouting::default(),
+            channels,
+            frame: vec![0.0; channels],
+        }
+    }
+
+    pub fn apply_settings(&mut self, routing: &ChannelRouting) {
+        self.routing = routing.clone();
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if self.routing.is_identity() {
+            return;
+        }
+
+        for frame in samples.chunks_exact_mut(self.channels) {
+            self.frame.copy_from_slice(frame);
+            for (out, sample) in frame.iter_mut().enumerate() {
+                let routes = self.routing.matrix.get(out).map_or(&[][..], |r| r.as_slice());
+                *sample = routes
+                    .iter()
+                    .filter_map(|&(source, gain)| self.frame.get(source).map(|x| x * gain))
+                    .sum();
+            }
+        }
+    }
+}
+
+impl DspNode for Router {
+    fn process(&mut self, samples: &mut [f32], _channels: usize, _sample_rate: f32) {
+        Router::process(self, samples);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swaps_left_and_right() {
+        let mut router = Router::new(2);
+        router.apply_settings(&ChannelRouting::new().route(1, 0, 1.0).route(0, 1, 1.0));
+        let mut samples = [0.1, 0.2, 0.3, 0.4];
+        router.process(&mut samples);
+        assert_eq!(samples, [0.2, 0.1, 0.4, 0.3]);
+    }
+
+    #[test]
+    fn sends_mono_to_the_third_channel_only() {
+        // A route past the device's four channels is ignored
+        let mut router = Router::new(4);
+        router.apply_settings(&ChannelRouting::new().route(0, 2, 0.5).route(0, 6, 1.0));
+        let mut samples = [0.8, 0.8, 0.0, 0.0, -0.4, -0.4, 0.0, 0.0];
+        router.process(&mut samples);
+        assert_eq!(samples, [0.0, 0.0, 0.4, 0.0, 0.0, 0.0, -0.2, 0.0]);
+    }
+}
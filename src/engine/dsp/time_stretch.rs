@@ -0,0 +1,167 @@
+use std::f32::consts::PI;
+
+// Length of each grain. Long enough to hold a few periods of low notes, short enough
+// that transients don't smear audibly
+const GRAIN_SECS: f32 = 0.04;
+// How far a grain may move from its nominal position to line up with the previous one
+const SEARCH_SECS: f32 = 0.01;
+
+/// Changes playback speed without changing pitch, by waveform-similarity overlap-add
+/// (WSOLA). Grains are taken from the input at `speed` times the rate they're laid down
+/// in the output, each moved within a small window to where it best continues the
+/// previous one, and crossfaded with Hann windows. Works on interleaved audio and holds
+/// back about a grain of it.
+pub struct TimeStretch {
+    channels: usize,
+    speed: f64,
+    grain: usize,
+    search: usize,
+    window: Vec<f32>,
+    // Interleaved input not consumed yet
+    input: Vec<f32>,
+    // Frame in `input` the next grain is nominally taken from
+    nominal: f64,
+    // Where the previous grain would have continued in `input`, past its first half
+    continuation: Option<usize>,
+    // Windowed second half of the previous grain, added to the next one's first half
+    overlap: Vec<f32>,
+    // Mono mixdown of the stretch of input a search looks at
+    mono: Vec<f32>,
+}
+
+impl TimeStretch {
+    pub fn new(sample_rate: u32, channels: usize, speed: f32) -> Self {
+        let channels = channels.max(1);
+        let hop = ((GRAIN_SECS * sample_rate as f32 / 2.0) as usize).max(1);
+        let grain = hop * 2;
+        // Periodic Hann, so windows half a grain apart sum to exactly one
+        let window = (0..grain)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / grain as f32).cos())
+            .collect();
+
+        Self {
+            channels,
+            speed: speed as f64,
+            grain,
+            search: (SEARCH_SECS * sample_rate as f32) as usize,
+            window,
+            input: Vec::new(),
+            nominal: 0.0,
+            continuation: None,
+            overlap: vec![0.0; hop * channels],
+            mono: Vec::new(),
+        }
+    }
+
+    /// Input frames consumed per output frame. Takes effect from the next grain.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(f32::EPSILON) as f64;
+    }
+
+    /// Stretches interleaved `samples` and returns whatever output they complete, which
+    /// averages `1 / speed` times as many frames.
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.input.extend_from_slice(samples);
+        let channels = self.channels;
+        let hop = self.grain / 2;
+        let mut output = Vec::new();
+
+        loop {
+            let frames = self.input.len() / channels;
+            let center = self.nominal.round() as usize;
+            if center + self.search + self.grain > frames {
+                break;
+            }
+
+            let start = match self.continuation {
+                Some(target) => self.best_match(target, center),
+                None => center,
+            };
+            // With nothing before it to crossfade from, the first grain starts at full level
+            let first = self.continuation.is_none();
+            let grain = &self.input[start * channels..(start + self.grain) * channels];
+            for (i, frame) in grain[..hop * channels].chunks_exact(channels).enumerate() {
+                let gain = if first { 1.0 } else { self.window[i] };
+                for (c, sample) in frame.iter().enumerate() {
+                    output.push(self.overlap[i * channels + c] + sample * gain);
+                }
+            }
+            for (i, frame) in grain[hop * channels..].chunks_exact(channels).enumerate() {
+                for (c, sample) in frame.iter().enumerate() {
+                    self.overlap[i * channels + c] = sample * self.window[hop + i];
+                }
+            }
+            self.nominal += hop as f64 * self.speed;
+
+            // Drop input that neither the next search nor the next comparison reaches
+            let keep_from = (self.nominal as usize).saturating_sub(self.search).min(start + hop);
+            self.input.drain(..keep_from * channels);
+            self.nominal -= keep_from as f64;
+            self.continuation = Some(start + hop - keep_from);
+        }
+        output
+    }
+
+    /// Stretches what's left of the input as if silence followed it, without the silence.
+    pub fn flush(&mut self) -> Vec<f32> {
+        let frames = self.input.len() / self.channels;
+        let remaining = ((frames as f64 - self.nominal).max(0.0) / self.speed).round() as usize;
+        let padding = vec![0.0; (self.grain + 2 * self.search) * self.channels];
+        let mut output = self.process(&padding);
+        output.extend_from_slice(&self.overlap);
+        output.truncate(remaining * self.channels);
+        self.reset();
+        output
+    }
+
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.nominal = 0.0;
+        self.continuation = None;
+        self.overlap.fill(0.0);
+    }
+
+    // Start within the search range of `center` whose first half best matches the `hop`
+    // frames at `target`, the audio that would naturally have followed the previous
+    // grain's overlap
+    fn best_match(&mut self, target: usize, center: usize) -> usize {
+        let channels = self.channels;
+        let hop = self.grain / 2;
+        let lo = center.saturating_sub(self.search);
+        let hi = center + self.search;
+        let first = lo.min(target);
+        let last = (hi + hop).max(target + hop);
+        self.mono.clear();
+        self.mono.extend(
+            self.input[first * channels..last * channels]
+                .chunks_exact(channels)
+                .map(|frame| frame.iter().sum::<f32>()),
+        );
+
+        let reference = &self.mono[target - first..target - first + hop];
+        // Silence matches anything equally well, so don't move off the nominal position
+        if reference.iter().all(|s| *s == 0.0) {
+            return center;
+        }
+        let candidates = &self.mono[lo - first..hi - first + hop];
+        let mut energy: f32 = candidates[..hop].iter().map(|s| s * s).sum();
+        let mut best = (f32::MIN, lo);
+        for offset in 0..=hi - lo {
+            if offset > 0 {
+                let (out, into) = (candidates[offset - 1], candidates[offset + hop - 1]);
+                energy = (energy - out * out + into * into).max(0.0);
+            }
+            let dot: f32 = reference
+                .iter()
+                .zip(&candidates[offset..offset + hop])
+                .map(|(a, b)| a * b)
+                .sum();
+            // Normalized by the candidate's level only, since the reference is the same for all
+            let score = dot / energy.sqrt().max(1e-9);
+            if score > best.0 {
+                best = (score, lo + offset);
+            }
+        }
+        best.1
+    }
+}
@@ -1,23 +1,234 @@
-use crate::engine::buffer::{create_audio_buffer, AudioBufferProducer};
-use crate::engine::clock::{Clock, PlaybackState};
-use crate::engine::decoder::{symphonia_decoder::SymphoniaDecoder, AudioDecoder, AudioMetadata};
-use crate::engine::output::{output_manager::OutputManager, AudioOutput};
-use std::path::Path;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{self, Sender, Receiver};
+use crate::engine::analysis::loudness::LoudnessJob;
+use crate::engine::analysis::meter::{
+    mono_compatibility, peak_levels_db, summed_peak_level_db, Ballistics, MeterChannelMode, MonoCompatibility,
+};
+use crate::engine::analysis::spectrum::{SpectrumAnalyzer, DEFAULT_FFT_SIZE};
+use crate::engine::analysis::waveform::WaveformJob;
+use crate::engine::buffer::{create_audio_buffer, AudioBufferConsumer, AudioBufferProducer};
+use crate::engine::clock::{Clock, PauseBehavior, PlaybackState, SeekBehavior};
+use crate::engine::config::{EngineConfig, LatencyMode, MAX_BUFFER_DURATION_MS, MIN_BUFFER_DURATION_MS};
+use crate::engine::decoder::{symphonia_decoder::SymphoniaDecoder, AudioDecoder, AudioMetadata, Chapter, CodecInfo};
+use crate::engine::events::{EngineEvent, EventBus};
+use crate::engine::input::InputCapture;
+use crate::engine::session::{Session, SessionError};
+use crate::engine::streaming::StreamingInput;
+use crate::engine::output::{output_manager::OutputManager, AudioOutput, OutputFormat};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::{Condvar, Mutex};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use thread_priority::{set_current_thread_priority, ThreadPriority, ThreadPriorityValue};
 
-use crate::engine::dsp::dsp_chain::DspChain;
-use crate::engine::dsp::resampler::Resampler;
+use crate::engine::dsp::bass::DEFAULT_TOGGLE_RAMP_MS;
+use crate::engine::dsp::channel_mapper::{ChannelMapper, ChannelMode, Downmix};
+use crate::engine::dsp::crossfeed;
+use crate::engine::dsp::dsp_chain::{DspChain, DspSettings};
+use crate::engine::dsp::lfo_mod::{LfoTarget, LfoWaveform};
+use crate::engine::dsp::node::{default_order, DspNode, NodeFactory, NodeId};
+use crate::engine::dsp::parametric_eq::{EqBand, EqPreset};
+use crate::engine::dsp::preset::DspPreset;
+use crate::engine::dsp::resampler::{Resampler, ResamplerKind};
+use crate::engine::dsp::time_stretch::TimeStretch;
+use crate::engine::dsp::routing::ChannelRouting;
+
+// Within this many seconds of a chapter start, "previous" goes to the chapter before it
+const CHAPTER_RESTART_WINDOW_SECS: f64 = 3.0;
+// Silence queued ahead of monitored input, so small timing jitter between the input and
+// output callbacks doesn't starve the output
+const MONITOR_CUSHION_SECS: f32 = 0.02;
+// Gain at the bottom of `VolumeTaper::Logarithmic`, just above silence
+const LOG_TAPER_FLOOR_DB: f32 = -60.0;
+// Range `set_output_gain_db` clamps to
+const MIN_OUTPUT_GAIN_DB: f32 = -60.0;
+const MAX_OUTPUT_GAIN_DB: f32 = 12.0;
+// Output channels the per-channel setters go up to, well past any common interface
+const MAX_OUTPUT_CHANNELS: usize = 64;
+// Range `set_playback_speed` clamps to, within what the varispeed resampler can reach
+const MIN_PLAYBACK_SPEED: f32 = 0.25;
+const MAX_PLAYBACK_SPEED: f32 = 4.0;
+// A seek waits this long for another to replace it before the decoder carries it out, but
+// no longer than the cap after the first one, so a continuous drag still gets heard
+const SEEK_SETTLE: Duration = Duration::from_millis(10);
+const SEEK_SETTLE_MAX: Duration = Duration::from_millis(50);
+// Consecutive playback thread ticks a drift must last before the clock is corrected
+const DRIFT_CONFIRMATIONS: u32 = 5;
+// Span of recent output `get_output_levels_db` takes its peak over
+const LEVEL_WINDOW_SECS: f32 = 0.02;
+// Span of recent output the mono compatibility check measures, capped by the output tap
+const CORRELATION_WINDOW_SECS: f32 = 0.1;
+// Span the decode thread's load is averaged over before it's published
+const DECODE_LOAD_WINDOW: Duration = Duration::from_millis(500);
+// Shortest an idle decode thread sleeps, so a stalled output doesn't turn the wait for
+// the low mark into a spin
+const MIN_DECODE_IDLE: Duration = Duration::from_millis(5);
 
 enum DecoderCommand {
-    Seek(f64),
+    Seek(SeekTarget),
     Stop,
+    // Stop decoding, but get everything already decoded into the buffer first
+    Finish,
     SetBassBoost(bool),
+    SetBassAutoHeadroom(bool),
+    SetBassRumbleOrder(usize),
     SetBassIntensity(f32),
+    SetBassLimiterCoupling(f32),
+    SetBassToggleRamp(f32),
+    SetNormalizationGain(f32),
+    SetChannelMode(ChannelMode),
+    SetDownmix(Downmix),
+    UpdateDsp(Box<DspSettings>),
+    SetDspNodes(Vec<(u32, NodeFactory)>),
+}
+
+enum SeekTarget {
+    Secs(f64),
+    // Source frame, see `AudioEngine::seek_to_sample`
+    Frame(u64),
+}
+
+/// Everything a UI typically polls, gathered in one call. Each field is read atomically,
+/// but the snapshot as a whole isn't one transaction: the position may be a callback
+/// ahead of the state it was read alongside.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EngineStatus {
+    pub position_secs: f64,
+    pub duration_secs: Option<f64>,
+    pub state: PlaybackState,
+    pub volume: f32,
+    pub buffer_fill: f32,
+    /// Times the output ran dry mid-playback since the current track was loaded.
+    pub underruns: u64,
+    /// Set once any output sample exceeds full scale, until `reset_clip_indicator`.
+    pub clipped: bool,
+}
+
+/// How `set_volume`'s `0.0..=1.0` control value maps to a gain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VolumeTaper {
+    /// The value is the gain, so the top half of the range sounds nearly the same.
+    #[default]
+    Linear,
+    /// The value is spread evenly in dB from -60 dB at the bottom to 0 dB at the top, which
+    /// tracks perceived loudness far better: 0.5 is -30 dB, 0.9 is -6 dB. 0.0 is silent.
+    Logarithmic,
+}
+
+impl VolumeTaper {
+    pub fn gain(&self, volume: f32) -> f32 {
+        let volume = volume.clamp(0.0, 1.0);
+        match self {
+            VolumeTaper::Linear => volume,
+            VolumeTaper::Logarithmic if volume == 0.0 => 0.0,
+            VolumeTaper::Logarithmic => {
+                10.0f32.powf((volume - 1.0) * -LOG_TAPER_FLOOR_DB / 20.0)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekError {
+    /// The loaded source can only be played front to back.
+    NotSeekable,
+    /// No output has reported its format yet, so there's no rate to place the position at.
+    NoOutputFormat,
+}
+
+impl std::fmt::Display for SeekError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SeekError::NotSeekable => write!(f, "The loaded source isn't seekable"),
+            SeekError::NoOutputFormat => write!(f, "The output device hasn't reported its format yet"),
+        }
+    }
+}
+
+impl std::error::Error for SeekError {}
+
+/// Why `AudioEngine::wait_until_finished` returned without the track playing to its end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitError {
+    /// Nothing was playing when it was called, so nothing would ever finish.
+    NotPlaying,
+    /// The timeout passed first. Playback carries on.
+    TimedOut,
+    /// The decoder hit an error it couldn't recover from, which cut the track short.
+    DecodeFailed,
+    /// Playback stopped before the end of the stream, e.g. because the output failed.
+    Stopped,
+}
+
+impl std::fmt::Display for WaitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WaitError::NotPlaying => write!(f, "Nothing is playing"),
+            WaitError::TimedOut => write!(f, "Timed out waiting for playback to finish"),
+            WaitError::DecodeFailed => write!(f, "Decoding failed before the end of the track"),
+            WaitError::Stopped => write!(f, "Playback stopped before the end of the track"),
+        }
+    }
+}
+
+impl std::error::Error for WaitError {}
+
+// Whether the playback thread is still running, so `wait_until_finished` can sleep until
+// it exits instead of polling
+#[derive(Default)]
+struct FinishSignal {
+    running: Mutex<bool>,
+    cvar: Condvar,
+}
+
+impl FinishSignal {
+    fn set_running(&self, running: bool) {
+        if let Ok(mut guard) = self.running.lock() {
+            *guard = running;
+        }
+        self.cvar.notify_all();
+    }
+}
+
+// An input device being played through the DSP chain
+struct Monitor {
+    _capture: Option<InputCapture>,
+    running: Arc<AtomicBool>,
+    worker: JoinHandle<AudioBufferProducer>,
+    dsp_tx: Sender<DspSettings>,
+}
+
+/// A file being opened in the background by `AudioEngine::load_async`. Hand it to
+/// `AudioEngine::complete_load` once `EngineEvent::Loaded` arrives (or `is_ready` says so).
+pub struct PendingLoad {
+    generation: u64,
+    path: PathBuf,
+    result: Arc<Mutex<Option<Result<SymphoniaDecoder, String>>>>,
+    worker: JoinHandle<()>,
+}
+
+impl PendingLoad {
+    pub fn is_ready(&self) -> bool {
+        self.worker.is_finished()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// What the engine is playing from, see `AudioEngine::source`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// A file opened by `load`, `load_track` or `load_async`.
+    File(PathBuf),
+    /// A decoder handed to `load_decoder`.
+    Decoder,
+    /// Samples pushed through `push_samples` on a `new_streaming` engine.
+    Streaming,
 }
 
 pub struct AudioEngine {
@@ -28,154 +239,882 @@ pub struct AudioEngine {
     producer_return_rx: Option<Receiver<AudioBufferProducer>>,
     decode_thread: Option<JoinHandle<()>>,
     playback_thread: Option<JoinHandle<()>>,
+    playback_finished: Arc<FinishSignal>,
     is_decoding: Arc<AtomicBool>,
     command_tx: Option<Sender<DecoderCommand>>,
-    bass_boost_enabled: Arc<AtomicBool>,
-    bass_boost_intensity: Arc<std::sync::Mutex<f32>>,
-    current_metadata: Option<AudioMetadata>,
+    dsp_params: DspParams,
+    // Shared with the decode thread, which replaces it when the stream carries new metadata
+    current_metadata: Arc<Mutex<Option<AudioMetadata>>>,
+    format_info: Option<CodecInfo>,
+    chapters: Vec<Chapter>,
+    source: Option<Source>,
+    // Track picked with `load_track`, `None` for the file's default one
+    file_track: Option<u32>,
+    events: EventBus,
+    config: EngineConfig,
+    channel_mode: ChannelMode,
+    downmix: Downmix,
+    dsp_settings: DspSettings,
+    // Bumped by every load so a background load can tell it has been superseded
+    load_generation: Arc<AtomicU64>,
+    loading: Option<u64>,
+    streaming: Option<StreamingInput>,
+    seekable: bool,
+    // Position the next decoder started seeks to once an output has set the format, for
+    // a session restored before then
+    start_secs: Option<f64>,
+    // Rate and length of the loaded source, for frame-based seeking
+    source_sample_rate: u32,
+    total_frames: Option<u64>,
+    // Atomic so `set_volume` works through a shared reference
+    volume: AtomicF32,
+    volume_taper: VolumeTaper,
+    normalization: bool,
+    // Files waiting for `play_next`, with their gain offsets in dB
+    queue: VecDeque<(PathBuf, f32)>,
+    // Offset of the queued track playing now, 0 dB for anything loaded directly
+    track_gain_db: f32,
+    // Chain behind `process_samples`, with the format it was built for
+    offline_dsp: Option<((u32, u32), DspChain)>,
+    monitor: Option<Monitor>,
+    dsp_nodes: Vec<(u32, NodeFactory)>,
+    next_dsp_node: u32,
+    spectrum: SpectrumAnalyzer,
+    spectrum_input: Vec<f32>,
+    spectrum_ballistics: Ballistics,
+    level_ballistics: Ballistics,
+    meter_channel_mode: MeterChannelMode,
 }
 
 impl AudioEngine {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_config(EngineConfig::default())
+    }
+
+    /// Runs the DSP chain at `sample_rate` whatever the device's rate. See
+    /// `EngineConfig::processing_sample_rate`.
+    pub fn with_sample_rate(sample_rate: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_config(EngineConfig {
+            processing_sample_rate: Some(sample_rate),
+            ..EngineConfig::default()
+        })
+    }
+
+    /// An engine fed with raw interleaved PCM through `push_samples` instead of a decoder,
+    /// for audio the application generates itself. Loading a file leaves streaming mode.
+    pub fn new_streaming(sample_rate: u32, channels: u32) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut engine = Self::new()?;
+        engine.streaming = Some(engine.build_streaming_input(sample_rate, channels as usize)?);
+        engine.seekable = false;
+        engine.source = Some(Source::Streaming);
+        Ok(engine)
+    }
+
+    /// Runs `samples` through the DSP chain into the output buffer. Returns how many were
+    /// accepted, which is short (possibly zero) when the buffer is full; push the rest
+    /// later. Only whole frames are accepted.
+    pub fn push_samples(&mut self, samples: &[f32]) -> usize {
+        let output_rate = self.clock.get_sample_rate();
+        let output_channels = self.clock.get_channels() as usize;
+        // Follow the device if it reconnected with a different format
+        if let Some(stream) = &self.streaming {
+            if !stream.matches_output(output_rate, output_channels) {
+                let (rate, channels) = (stream.sample_rate(), stream.channels());
+                self.streaming = self.build_streaming_input(rate, channels).ok();
+            }
+        }
+
+        let Some(mut stream) = self.streaming.take() else {
+            return 0;
+        };
+        self.configure_stream_dsp(stream.dsp_mut());
+        let accepted = match &mut self.producer {
+            Some(producer) => stream.push(producer, samples),
+            None => 0,
+        };
+        self.streaming = Some(stream);
+        accepted
+    }
+
+    /// Runs interleaved `samples` through a DSP chain configured exactly like playback's,
+    /// in place, with no device or decoder involved, e.g. to preview an export. They must
+    /// be at the rate and channel count playback processes at (see `processing_format`).
+    ///
+    /// The chain is a separate instance from the one playing, so its filter and limiter
+    /// state carries over between calls here but never mixes with playback.
+    pub fn process_samples(&mut self, samples: &mut [f32]) {
+        let (rate, channels) = self.processing_format();
+        let mut dsp = match self.offline_dsp.take() {
+            Some((format, dsp)) if format == (rate, channels) => dsp,
+            _ => {
+                let mut dsp = DspChain::new(rate as f32, channels as usize);
+                dsp.set_layout(self.config.dsp_layout);
+                dsp.set_precision(self.config.processing_precision);
+                dsp
+            }
+        };
+        configure_dsp(&mut dsp, &self.dsp_params, true, &self.dsp_settings, &self.dsp_nodes, Vec::new());
+        dsp.process(samples);
+        self.offline_dsp = Some(((rate, channels), dsp));
+    }
+
+    /// Sample rate and channel count the DSP chain runs at: the fixed processing rate if
+    /// one is configured, otherwise the rate of the device the track started on.
+    pub fn processing_format(&self) -> (u32, u32) {
+        (self.clock.get_sample_rate(), self.clock.get_channels())
+    }
+
+    /// Input frames per chunk the resamplers are built with: `resampler_chunk_frames`
+    /// from the config after rounding, see `EngineConfig::effective_resampler_chunk_frames`.
+    pub fn resampler_chunk_frames(&self) -> usize {
+        self.config.effective_resampler_chunk_frames()
+    }
+
+    // Brings a live or pushed stream's chain in line with playback's settings. It isn't a
+    // track, so no track's normalization applies
+    fn configure_stream_dsp(&self, dsp: &mut DspChain) {
+        configure_dsp(dsp, &self.dsp_params, false, &self.dsp_settings, &self.dsp_nodes, Vec::new());
+    }
+
+    fn build_streaming_input(
+        &self,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Result<StreamingInput, Box<dyn std::error::Error>> {
+        StreamingInput::new(
+            sample_rate,
+            channels,
+            self.clock.get_sample_rate(),
+            self.clock.get_channels() as usize,
+            self.channel_mode,
+            self.downmix,
+            &self.config,
+        )
+    }
+
+    pub fn with_config(config: EngineConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let backend = config.output_backend;
+        let fallback = config.null_output_fallback;
+        Self::with_config_and_output(config, move |consumer, clock| {
+            Box::new(OutputManager::with_backend(consumer, clock, backend, fallback))
+        })
+    }
+
+    /// Plays through the output `make_output` builds from the engine's buffer consumer and
+    /// clock instead of the system's default device, e.g. a `MockOutput`, a JACK client or
+    /// a network sink. See `AudioOutput` for what the backend has to do with them.
+    pub fn with_output<F>(make_output: F) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        F: FnOnce(AudioBufferConsumer, Arc<Clock>) -> Box<dyn AudioOutput + Send>,
+    {
+        Self::with_config_and_output(EngineConfig::default(), make_output)
+    }
+
+    pub fn with_config_and_output<F>(
+        config: EngineConfig,
+        make_output: F,
+    ) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        F: FnOnce(AudioBufferConsumer, Arc<Clock>) -> Box<dyn AudioOutput + Send>,
+    {
         let clock = Arc::new(Clock::new(44100));
-        let (producer, consumer) = create_audio_buffer(44100 * 2);
-        let output = OutputManager::new(consumer, clock.clone());
-        Ok(Self {
+        // With a fixed processing rate the buffer carries it, and the output converts
+        if let Some(rate) = config.processing_sample_rate {
+            clock.set_sample_rate(rate);
+        }
+        clock.set_device_buffer_frames(config.device_buffer_frames);
+        let frames = buffer_frames(config.buffer_duration_ms, clock.get_sample_rate());
+        let (producer, consumer) = create_audio_buffer(frames, 2);
+        clock.set_buffer_capacity(consumer.capacity() as u64);
+        let output = make_output(consumer, clock.clone());
+        let mut engine = Self {
             clock,
-            output: Arc::new(Mutex::new(Box::new(output))),
+            output: Arc::new(Mutex::new(output)),
             producer: Some(producer),
             producer_return_rx: None,
             decode_thread: None,
             playback_thread: None,
+            playback_finished: Arc::new(FinishSignal::default()),
             is_decoding: Arc::new(AtomicBool::new(false)),
             command_tx: None,
-            bass_boost_enabled: Arc::new(AtomicBool::new(false)),
-            bass_boost_intensity: Arc::new(std::sync::Mutex::new(50.0)),
-            current_metadata: None,
-        })
+            dsp_params: DspParams {
+                bass_boost_enabled: Arc::new(AtomicBool::new(false)),
+                bass_auto_headroom: Arc::new(AtomicBool::new(false)),
+                bass_rumble_order: Arc::new(AtomicUsize::new(2)),
+                bass_boost_intensity: Arc::new(AtomicF32::new(50.0)),
+                bass_limiter_coupling: Arc::new(AtomicF32::new(0.5)),
+                bass_toggle_ramp_ms: Arc::new(AtomicF32::new(DEFAULT_TOGGLE_RAMP_MS)),
+                normalization_gain_db: Arc::new(AtomicF32::new(0.0)),
+            },
+            current_metadata: Arc::new(Mutex::new(None)),
+            format_info: None,
+            chapters: Vec::new(),
+            source: None,
+            file_track: None,
+            events: EventBus::new(),
+            config,
+            channel_mode: ChannelMode::Auto,
+            downmix: Downmix::default(),
+            dsp_settings: DspSettings::default(),
+            load_generation: Arc::new(AtomicU64::new(0)),
+            loading: None,
+            streaming: None,
+            seekable: true,
+            start_secs: None,
+            source_sample_rate: 0,
+            total_frames: None,
+            volume: AtomicF32::new(1.0),
+            volume_taper: VolumeTaper::Linear,
+            normalization: false,
+            queue: VecDeque::new(),
+            track_gain_db: 0.0,
+            offline_dsp: None,
+            monitor: None,
+            dsp_nodes: Vec::new(),
+            next_dsp_node: 0,
+            spectrum: SpectrumAnalyzer::new(DEFAULT_FFT_SIZE, 44100.0),
+            spectrum_input: Vec::new(),
+            spectrum_ballistics: Ballistics::default(),
+            level_ballistics: Ballistics::default(),
+            meter_channel_mode: MeterChannelMode::default(),
+        };
+        // The output may have set a channel count the buffer wasn't made for
+        engine.resize_buffer();
+        Ok(engine)
     }
 
     pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn std::error::Error>> {
         // 1. Stop existing playback (this handles joining threads and returning the producer)
         self.stop();
+        self.cancel_pending_load();
+        self.source = None;
+
+        let mut decoder = SymphoniaDecoder::new(&path)?;
+        decoder.set_tolerant(self.config.tolerant_decoding);
+        self.source = Some(Source::File(path.as_ref().to_path_buf()));
+        self.file_track = None;
+        self.start_decoder(decoder)
+    }
+
+    /// Loads one audio track of a multi-track container. `track_index` indexes the list
+    /// returned by `SymphoniaDecoder::list_tracks`.
+    pub fn load_track<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        track_index: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.stop();
+        self.cancel_pending_load();
+        self.source = None;
+
+        let tracks = SymphoniaDecoder::new(&path)?.list_tracks();
+        let track = tracks.get(track_index).ok_or_else(|| {
+            format!(
+                "Track index {} is out of range, the file has {} audio track(s)",
+                track_index,
+                tracks.len()
+            )
+        })?;
+        let mut decoder = SymphoniaDecoder::new_with_track(&path, track.id)?;
+        decoder.set_tolerant(self.config.tolerant_decoding);
+        self.source = Some(Source::File(path.as_ref().to_path_buf()));
+        self.file_track = Some(track.id);
+        self.start_decoder(decoder)
+    }
+
+    /// Plays from any `AudioDecoder`, e.g. a `SignalGenerator` or a custom source, instead
+    /// of a file. Waveform generation isn't available for decoders loaded this way.
+    pub fn load_decoder<D: AudioDecoder + Send + 'static>(
+        &mut self,
+        decoder: D,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.stop();
+        self.cancel_pending_load();
+        self.source = Some(Source::Decoder);
+        self.start_decoder(decoder)
+    }
+
+    /// Opens and probes `path` on a worker thread and returns immediately. The engine
+    /// counts as loading until the result is passed to `complete_load`, and refuses to
+    /// play meanwhile. `EngineEvent::Loaded` or `LoadFailed` fires when the worker is done.
+    /// Any later load cancels this one.
+    pub fn load_async<P: AsRef<Path>>(&mut self, path: P) -> PendingLoad {
+        self.stop();
+        let generation = self.cancel_pending_load();
+        self.loading = Some(generation);
+        self.source = None;
+
+        let path = path.as_ref().to_path_buf();
+        let result = Arc::new(Mutex::new(None));
+        let worker_path = path.clone();
+        let worker_result = result.clone();
+        let current_generation = self.load_generation.clone();
+        let events = self.events.clone();
+        let tolerant = self.config.tolerant_decoding;
+
+        let worker = thread::spawn(move || {
+            let decoder = SymphoniaDecoder::new(&worker_path)
+                .map(|mut decoder| {
+                    decoder.set_tolerant(tolerant);
+                    decoder
+                })
+                .map_err(|e| e.to_string());
+            // A superseded load finishes quietly, nobody is waiting for it anymore
+            if current_generation.load(Ordering::SeqCst) == generation {
+                events.emit(match &decoder {
+                    Ok(_) => EngineEvent::Loaded,
+                    Err(e) => EngineEvent::LoadFailed(e.clone()),
+                });
+            }
+            if let Ok(mut slot) = worker_result.lock() {
+                *slot = Some(decoder);
+            }
+        });
+
+        PendingLoad {
+            generation,
+            path,
+            result,
+            worker,
+        }
+    }
+
+    /// Starts decoding the file opened by `load_async`. Blocks only if the worker hasn't
+    /// finished yet. Fails if the load failed or a newer load superseded it.
+    pub fn complete_load(&mut self, pending: PendingLoad) -> Result<(), Box<dyn std::error::Error>> {
+        if self.loading != Some(pending.generation) {
+            return Err("This load was superseded by a newer one".into());
+        }
+        self.loading = None;
+
+        let _ = pending.worker.join();
+        let decoder = pending
+            .result
+            .lock()
+            .map_err(|_| "Load worker panicked")?
+            .take()
+            .ok_or("Load worker panicked")??;
+        self.source = Some(Source::File(pending.path));
+        self.file_track = None;
+        self.start_decoder(decoder)
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    // Invalidates any in-flight `load_async` and returns the new generation
+    fn cancel_pending_load(&mut self) -> u64 {
+        self.loading = None;
+        self.load_generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
 
-        let mut decoder = SymphoniaDecoder::new(path)?;
+    fn start_decoder<D: AudioDecoder + Send + 'static>(
+        &mut self,
+        mut decoder: D,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.streaming = None;
 
         // --- CAPTURE METADATA ---
-        self.current_metadata = decoder.metadata();
+        self.set_current_metadata(decoder.metadata());
+        self.format_info = Some(decoder.codec_info());
+        self.track_gain_db = 0.0;
+        self.apply_volume();
+        self.apply_normalization();
+        self.chapters = decoder.chapters();
+        self.seekable = decoder.is_seekable();
+        self.source_sample_rate = decoder.sample_rate();
+        self.total_frames = decoder.total_frames();
+        self.clock.reset_underruns();
+        self.clock.reset_decode_errors();
+        self.clock.reset_clipped();
 
-        // 2. Setup the return channel for the producer
-        let (producer_tx, producer_rx) = mpsc::channel();
-        self.producer_return_rx = Some(producer_rx);
+        // Nothing is buffered between tracks, so a new one can go back to the device's own
+        // rate and spare the output converting, if the device changed during the last one
+        if self.config.processing_sample_rate.is_none() {
+            self.clock.set_sample_rate(self.clock.get_device_sample_rate());
+        }
 
-        let mut producer = self.producer.take().ok_or("Producer missing")?;
+        // Every thread that could hold the producer has been joined by now, so if it's
+        // missing it went down with one that panicked. Play into a fresh buffer instead
+        if self.producer.is_none() {
+            self.rebuild_buffer();
+        }
+        self.resize_buffer();
 
         let is_decoding = self.is_decoding.clone();
         let clock = self.clock.clone();
-        let bass_boost_enabled = self.bass_boost_enabled.clone();
-        let bass_boost_intensity = self.bass_boost_intensity.clone();
+        let output = self.output.clone();
+        let events = self.events.clone();
+        let current_metadata = self.current_metadata.clone();
+        let dsp_params = self.dsp_params.clone();
+        let dsp_block_frames = self.config.dsp_block_frames;
+        let dsp_layout = self.config.dsp_layout;
+        let processing_precision = self.config.processing_precision;
+        let resampler_chunk = self.config.effective_resampler_chunk_frames();
+        let thread_priority = self.config.decode_thread_priority;
+        let high_water_fraction = self.config.decode_high_water.clamp(0.0, 1.0);
+        let low_water_fraction = self.config.decode_low_water.clamp(0.0, 1.0);
+        let mut refilling = true;
+        let mut channel_mode = self.channel_mode;
+        let mut downmix = self.downmix;
+        let mut dsp_settings = self.dsp_settings.clone();
+        let mut dsp_nodes = self.dsp_nodes.clone();
+        let mut start_secs = self.start_secs.take().filter(|_| self.seekable);
 
-        let mut output_rate = clock.get_sample_rate();
+        // The buffer's rate, which a device at another rate doesn't change; the output
+        // converts instead
+        let mut processing_rate = clock.get_sample_rate();
         let mut output_channels = clock.get_channels();
-        let decoder_rate = decoder.sample_rate();
-        let decoder_channels = decoder.channels() as usize;
-
-        let mut resampler =
-            if decoder_rate != output_rate || decoder_channels != output_channels as usize {
-                Some(Resampler::new(
-                    decoder_rate,
-                    output_rate,
-                    decoder_channels,
-                    1024,
-                )?)
-            } else {
-                None
-            };
+        let mut decoder_rate = decoder.sample_rate();
+        // A decoder reporting zero channels is treated as mono rather than dividing by zero
+        let mut decoder_channels = (decoder.channels() as usize).max(1);
 
-        let mut dsp = DspChain::new(output_rate as f32, output_channels as usize);
-        dsp.bass
-            .set_enabled(bass_boost_enabled.load(Ordering::SeqCst));
-        if let Ok(v) = bass_boost_intensity.lock() {
-            dsp.bass.set_intensity(*v);
-        }
+        let mut resampler = if decoder_rate != processing_rate {
+            Some(Resampler::new(
+                decoder_rate,
+                processing_rate,
+                decoder_channels,
+                resampler_chunk,
+            )?)
+        } else {
+            None
+        };
+        let mut mapper = ChannelMapper::new(decoder_channels, output_channels as usize, channel_mode, downmix);
+        clock.set_latency_samples(resampler_latency(&resampler, output_channels));
+
+        let mut dsp = DspChain::new(processing_rate as f32, output_channels as usize);
+        dsp.set_layout(dsp_layout);
+        dsp.set_precision(processing_precision);
+        configure_dsp(&mut dsp, &dsp_params, true, &dsp_settings, &dsp_nodes, Vec::new());
+        // The last decoded packet, converted from `decoded_pos` on. It goes through the
+        // resampler and DSP chain about a DSP block at a time, between command checks, so a
+        // large packet that upsamples to more than the buffer holds can't hold up a seek or
+        // a stop
+        let mut decoded: Vec<f32> = Vec::new();
+        let mut decoded_pos = 0;
+        // Stream time the decoded packet starts at, if the decoder can tell
+        let mut decoded_start: Option<f64> = None;
+        let convert_frames = dsp_block_frames.unwrap_or(resampler_chunk).max(1);
+        // Converted audio waiting to fill a complete DSP block
+        let mut pending: Vec<f32> = Vec::new();
+        // Processed audio the buffer had no room for yet, from `outgoing_pos` on. It goes in
+        // as space frees up, between command checks
+        let mut outgoing: Vec<f32> = Vec::new();
+        let mut outgoing_pos = 0;
+        // Stream time converted up to, and whether the decoder has reached the end
+        let mut decoded_until: Option<f64> = None;
+        let mut finished = false;
+        // Set by `Finish`: the decoder isn't read again, as if the file had ended here
+        let mut draining = false;
+        // Normal speed until the loop picks up the clock's, building whichever stage it needs
+        let mut speed = 1.0f32;
+        let mut speed_affects_pitch = true;
+        let mut stretch: Option<TimeStretch> = None;
+
+        // 2. Setup the return channel for the producer. It's only taken once nothing above
+        // can fail, so a failed load leaves it in place for the next one
+        let (producer_tx, producer_rx) = mpsc::channel();
+        self.producer_return_rx = Some(producer_rx);
+
+        let mut producer = self.producer.take().ok_or("Producer missing")?;
+        // Whatever the last stream left in the buffer keeps the speed it was marked with
+        producer.mark_speed(speed, 0);
 
         let (tx, rx) = mpsc::channel();
         self.command_tx = Some(tx);
         is_decoding.store(true, Ordering::SeqCst);
         clock.set_eos(false);
         clock.set_sample_pos(0);
+        // A seek made with nothing loaded must not wipe the new track's first buffer
+        clock.reset_clear_buffer();
 
         let handle = thread::spawn(move || {
-            // Main decoding loop
-            while is_decoding.load(Ordering::Relaxed) {
-                while let Ok(cmd) = rx.try_recv() {
+            if let Some(priority) = thread_priority {
+                raise_thread_priority(priority);
+            }
+            let mut load = LoadMeter::new();
+            // A command that ended an idle wait, handled before any still queued
+            let mut woken_by: Option<DecoderCommand> = None;
+
+            // Main decoding loop. Every exit breaks out of it rather than returning, so the
+            // producer always makes it back to the engine
+            'decode: while is_decoding.load(Ordering::Relaxed) {
+                load.publish(&clock);
+                // Only the last of the seeks queued up is carried out, so dragging a seekbar
+                // doesn't clear the buffer and restart decoding for every step of it. More
+                // of them pile up while one waits for the stale audio to be dropped, or
+                // while the decoder waits `SEEK_SETTLE` for the next
+                let mut seek = None;
+                let mut settle: Option<(Instant, Instant)> = None;
+                loop {
+                    let cmd = match (woken_by.take(), settle) {
+                        (Some(cmd), _) => Some(cmd),
+                        (None, Some((_, until))) => {
+                            rx.recv_timeout(until.saturating_duration_since(Instant::now())).ok()
+                        }
+                        (None, None) => rx.try_recv().ok(),
+                    };
+                    let Some(cmd) = cmd else {
+                        break;
+                    };
                     match cmd {
-                        DecoderCommand::Seek(t) => {
-                            decoder.seek(t);
-                            producer.clear();
-                            clock.set_eos(false);
+                        DecoderCommand::Seek(target) => {
+                            let now = Instant::now();
+                            let first = settle.map_or(now, |(first, _)| first);
+                            settle = Some((first, (now + SEEK_SETTLE).min(first + SEEK_SETTLE_MAX)));
+                            seek = Some(target);
                         }
                         DecoderCommand::Stop => {
+                            clock.set_decode_load(0.0);
                             is_decoding.store(false, Ordering::SeqCst);
-                            return; // Return early, execute defer logic below
+                            break 'decode;
                         }
+                        DecoderCommand::Finish => draining = true,
                         DecoderCommand::SetBassBoost(v) => dsp.bass.set_enabled(v),
+                        DecoderCommand::SetBassAutoHeadroom(v) => dsp.bass.set_auto_headroom(v),
+                        DecoderCommand::SetBassRumbleOrder(v) => dsp.bass.set_rumble_order(v),
                         DecoderCommand::SetBassIntensity(v) => dsp.bass.set_intensity(v),
+                        DecoderCommand::SetBassLimiterCoupling(v) => dsp.bass.set_limiter_coupling(v),
+                        DecoderCommand::SetBassToggleRamp(v) => dsp.bass.set_toggle_ramp_ms(v),
+                        DecoderCommand::SetNormalizationGain(db) => dsp.set_input_gain_db(db),
+                        DecoderCommand::SetChannelMode(mode) => {
+                            channel_mode = mode;
+                            mapper = ChannelMapper::new(
+                                decoder_channels,
+                                output_channels as usize,
+                                channel_mode,
+                                downmix,
+                            );
+                        }
+                        DecoderCommand::SetDownmix(matrix) => {
+                            downmix = matrix;
+                            mapper = ChannelMapper::new(
+                                decoder_channels,
+                                output_channels as usize,
+                                channel_mode,
+                                downmix,
+                            );
+                        }
+                        DecoderCommand::SetDspNodes(nodes) => {
+                            dsp.sync_custom_nodes(&nodes, Vec::new());
+                            dsp.apply_settings(&dsp_settings);
+                            dsp_nodes = nodes;
+                        }
+                        DecoderCommand::UpdateDsp(settings) => {
+                            dsp.apply_settings(&settings);
+                            dsp_settings = *settings;
+                        }
+                    }
+                }
+                if let Some((first, _)) = settle {
+                    load.add_idle(first.elapsed());
+                }
+                // The clock can't hold a time until an output has set the format
+                if clock.is_configured() {
+                    if let Some(secs) = start_secs.take() {
+                        clock.set_time_secs(secs);
+                        clock.clear_source_time();
+                        clock.signal_clear_buffer();
+                        seek = seek.or(Some(SeekTarget::Secs(secs)));
+                    }
+                }
+                if let Some(target) = seek {
+                    match target {
+                        SeekTarget::Secs(t) => decoder.seek(t),
+                        SeekTarget::Frame(frame) => decoder.seek_to_frame(frame),
                     }
+                    if let Some(r) = &mut resampler {
+                        r.reset();
+                    }
+                    if let Some(s) = &mut stretch {
+                        s.reset();
+                    }
+                    dsp.reset_modulation();
+                    dsp.reset_custom_nodes();
+                    decoded.clear();
+                    decoded_pos = 0;
+                    pending.clear();
+                    outgoing.clear();
+                    outgoing_pos = 0;
+                    decoded_until = None;
+                    finished = false;
+                    producer.clear();
+                    clock.clear_source_time();
+                    let waiting_from = Instant::now();
+                    drop_stale_audio(&clock, &output);
+                    // Audio pushed between the output's clear and this seek being picked up
+                    // is just as stale. Nothing has been pushed since, so it goes in one more
+                    if producer.occupied_len() > 0 {
+                        clock.signal_clear_buffer();
+                        drop_stale_audio(&clock, &output);
+                    }
+                    load.add_idle(waiting_from.elapsed());
+                    clock.set_eos(false);
                 }
 
+                // Only an output that sets the buffer's rate itself, or a new channel count,
+                // makes the chain start over
                 let rate = clock.get_sample_rate();
                 let ch = clock.get_channels();
-                if rate != output_rate || ch != output_channels {
-                    output_rate = rate;
+                if rate != processing_rate || ch != output_channels {
+                    processing_rate = rate;
                     output_channels = ch;
-                    resampler = if decoder_rate != output_rate
-                        || decoder_channels != output_channels as usize
-                    {
-                        Some(
-                            Resampler::new(decoder_rate, output_rate, decoder_channels, 1024)
-                                .unwrap(),
-                        )
-                    } else {
-                        None
+                    resampler = match build_input_resampler(
+                        decoder_rate,
+                        processing_rate,
+                        decoder_channels,
+                        speed,
+                        speed_affects_pitch,
+                        resampler_chunk,
+                    ) {
+                        Ok(resampler) => resampler,
+                        Err(_) => {
+                            end_failed_stream(&clock, &is_decoding);
+                            break 'decode;
+                        }
                     };
-                    dsp = DspChain::new(output_rate as f32, output_channels as usize);
-                    dsp.bass
-                        .set_enabled(bass_boost_enabled.load(Ordering::SeqCst));
-                    if let Ok(v) = bass_boost_intensity.lock() {
-                        dsp.bass.set_intensity(*v);
-                    }
+                    stretch =
+                        build_time_stretch(processing_rate, decoder_channels, speed, speed_affects_pitch);
+                    clock.set_latency_samples(resampler_latency(&resampler, output_channels));
+                    mapper = ChannelMapper::new(
+                        decoder_channels,
+                        output_channels as usize,
+                        channel_mode,
+                        downmix,
+                    );
+                    let carried = dsp.take_custom_nodes();
+                    dsp = DspChain::new(processing_rate as f32, output_channels as usize);
+                    dsp.set_layout(dsp_layout);
+                    dsp.set_precision(processing_precision);
+                    configure_dsp(&mut dsp, &dsp_params, true, &dsp_settings, &dsp_nodes, carried);
+                    pending.clear();
+                    outgoing.clear();
+                    outgoing_pos = 0;
                     producer.clear();
                 }
 
-                if producer.vacant_len() < 1024 {
-                    thread::sleep(Duration::from_millis(5));
-                    continue;
-                }
-
-                if let Some(mut samples) = decoder.decode_next() {
-                    if let Some(r) = &mut resampler {
-                        samples = r.process(&samples).unwrap_or(samples);
+                let new_speed = clock.get_playback_speed();
+                let new_affects_pitch = clock.speed_affects_pitch();
+                if new_speed != speed || new_affects_pitch != speed_affects_pitch {
+                    let mode_changed = new_affects_pitch != speed_affects_pitch;
+                    // The output advances the clock by the speed audio was rendered at, so
+                    // the new one starts after what's already waiting to go in
+                    if new_speed != speed {
+                        producer.mark_speed(new_speed, pending.len() + outgoing.len() - outgoing_pos);
                     }
-                    dsp.process(&mut samples);
-
-                    let mut pushed = 0;
-                    while pushed < samples.len() {
-                        let n = producer.push_slice(&samples[pushed..]);
-                        pushed += n;
-                        if n == 0 {
-                            thread::sleep(Duration::from_millis(2));
+                    speed = new_speed;
+                    speed_affects_pitch = new_affects_pitch;
+                    // Within varispeed a sinc resampler ramps to the new ratio without a
+                    // break; anything else swaps the stage doing the work
+                    if speed_affects_pitch {
+                        match &mut resampler {
+                            Some(r) if !mode_changed && r.kind() == ResamplerKind::Sinc => {
+                                let _ = r.set_ratio(1.0 / speed);
+                            }
+                            _ => {
+                                match build_input_resampler(
+                                    decoder_rate,
+                                    processing_rate,
+                                    decoder_channels,
+                                    speed,
+                                    speed_affects_pitch,
+                                    resampler_chunk,
+                                ) {
+                                    Ok(built) => resampler = built,
+                                    Err(_) => {
+                                        end_failed_stream(&clock, &is_decoding);
+                                        break 'decode;
+                                    }
+                                }
+                            }
+                        }
+                    } else if mode_changed {
+                        match build_resampler(decoder_rate, processing_rate, decoder_channels, resampler_chunk) {
+                            Ok(built) => resampler = built,
+                            Err(_) => {
+                                end_failed_stream(&clock, &is_decoding);
+                                break 'decode;
+                            }
                         }
                     }
-                } else {
-                    if let Some(r) = &mut resampler {
-                        if let Ok(flush) = r.flush() {
-                            producer.push_slice(&flush);
+                    match &mut stretch {
+                        Some(s) if !speed_affects_pitch && speed != 1.0 => s.set_speed(speed),
+                        _ => {
+                            stretch = build_time_stretch(
+                                processing_rate,
+                                decoder_channels,
+                                speed,
+                                speed_affects_pitch,
+                            )
                         }
                     }
+                    clock.set_latency_samples(resampler_latency(&resampler, output_channels));
+                }
+
+                // Nothing new is decoded until the last block is all in. Waiting here rather
+                // than in a push loop keeps the commands above polled
+                let capacity = producer.capacity();
+                if outgoing_pos < outgoing.len() {
+                    outgoing_pos += producer.push_slice(&outgoing[outgoing_pos..]);
+                    let unpushed = pending.len() + outgoing.len() - outgoing_pos;
+                    publish_source_time(&clock, decoded_until, unpushed, speed, output_channels, processing_rate);
+                    if outgoing_pos < outgoing.len() {
+                        let wanted = (outgoing.len() - outgoing_pos)
+                            .min(capacity - (capacity as f32 * low_water_fraction) as usize);
+                        let waiting_from = Instant::now();
+                        producer.wait_for_space(wanted, Duration::from_millis(5));
+                        load.add_idle(waiting_from.elapsed());
+                        continue;
+                    }
+                    outgoing.clear();
+                    outgoing_pos = 0;
+                }
+
+                if decoded_pos < decoded.len() {
+                    // About a DSP block's worth once converted to the processing rate
+                    let frames = (convert_frames as u64 * decoder_rate as u64 / processing_rate.max(1) as u64).max(1);
+                    let end = (decoded_pos + frames as usize * decoder_channels).min(decoded.len());
+                    let chunk = &decoded[decoded_pos..end];
+                    let mut samples = match &mut resampler {
+                        Some(r) => r.process(chunk).unwrap_or_else(|_| chunk.to_vec()),
+                        None => chunk.to_vec(),
+                    };
+                    decoded_pos = end;
+                    if let Some(s) = &mut stretch {
+                        samples = s.process(&samples);
+                    }
+                    if !mapper.is_passthrough() {
+                        samples = mapper.process(&samples);
+                    }
+
+                    pending.extend_from_slice(&samples);
+                    let block_len = dsp_block_frames.map_or(usize::MAX, |frames| frames.max(1) * output_channels as usize);
+                    process_blocks(&mut dsp, &clock, &mut pending, &mut outgoing, block_len, dsp_block_frames.is_none());
+
+                    let converted_secs = (decoded_pos / decoder_channels) as f64 / decoder_rate as f64;
+                    decoded_until = decoded_start.map(|start| start + converted_secs);
+                    outgoing_pos += producer.push_slice(&outgoing[outgoing_pos..]);
+                    let unpushed = pending.len() + outgoing.len() - outgoing_pos;
+                    publish_source_time(&clock, decoded_until, unpushed, speed, output_channels, processing_rate);
+                    continue;
+                }
+                if finished {
+                    if decoder.has_failed() {
+                        clock.record_fatal_error();
+                    }
+                    clock.set_decode_load(0.0);
                     clock.set_eos(true);
                     is_decoding.store(false, Ordering::SeqCst);
-                    return; // Song finished
+                    break; // Song finished
+                }
+
+                // Fill up to the high mark, then stay idle until the output drains to the low mark.
+                // The capacity follows the device's channel count, so it's re-read each time
+                let high_water = (capacity as f32 * high_water_fraction) as usize;
+                let low_water = (capacity as f32 * low_water_fraction) as usize;
+                let occupied = producer.occupied_len();
+                if refilling && occupied >= high_water {
+                    refilling = false;
+                } else if !refilling && occupied <= low_water {
+                    refilling = true;
+                }
+                if !refilling && !draining {
+                    // Sleeps for as long as the output takes to play down to the low mark, so
+                    // an idle thread wakes about once per refill. A command cuts it short and
+                    // is handled first thing on the next pass
+                    let samples_per_sec = processing_rate.max(1) as f64 * output_channels.max(1) as f64;
+                    let drain = Duration::from_secs_f64(occupied.saturating_sub(low_water) as f64 / samples_per_sec)
+                        .max(MIN_DECODE_IDLE);
+                    let waiting_from = Instant::now();
+                    match rx.recv_timeout(drain) {
+                        Ok(cmd) => woken_by = Some(cmd),
+                        Err(RecvTimeoutError::Timeout) => {}
+                        // Nothing can send a command any more, so only the space is worth waking for
+                        Err(RecvTimeoutError::Disconnected) => {
+                            producer.wait_for_space(capacity - low_water, drain);
+                        }
+                    }
+                    load.add_idle(waiting_from.elapsed());
+                    continue;
+                }
+
+                let packet = if draining { None } else { decoder.decode_next() };
+                clock.record_decode_errors(decoder.take_recovered_errors());
+                if let Some(mut samples) = packet {
+                    if let Some(metadata) = decoder.take_metadata_update() {
+                        if let Ok(mut current) = current_metadata.lock() {
+                            *current = Some(metadata.clone());
+                        }
+                        events.emit(EngineEvent::MetadataChanged(metadata));
+                    }
+                    // Some packets legitimately decode to nothing, e.g. codec priming
+                    if samples.is_empty() {
+                        continue;
+                    }
+                    // Some formats and adaptive streams change their channel layout or sample
+                    // rate mid-stream. A zero means the decoder can't tell, so keep the old one
+                    let channels = match decoder.channels() as usize {
+                        0 => decoder_channels,
+                        channels => channels,
+                    };
+                    let rate = match decoder.sample_rate() {
+                        0 => decoder_rate,
+                        rate => rate,
+                    };
+                    if channels != decoder_channels || rate != decoder_rate {
+                        // The old format's last chunk is still in the converters
+                        pending.extend_from_slice(&flush_conversion(&mut resampler, &mut stretch, &mapper, false));
+                        let block_len = dsp_block_frames.map_or(usize::MAX, |frames| frames.max(1) * output_channels as usize);
+                        process_blocks(&mut dsp, &clock, &mut pending, &mut outgoing, block_len, dsp_block_frames.is_none());
+                        decoder_channels = channels;
+                        decoder_rate = rate;
+                        resampler = match build_input_resampler(
+                            decoder_rate,
+                            processing_rate,
+                            decoder_channels,
+                            speed,
+                            speed_affects_pitch,
+                            resampler_chunk,
+                        ) {
+                            Ok(resampler) => resampler,
+                            Err(_) => {
+                                end_failed_stream(&clock, &is_decoding);
+                                break 'decode;
+                            }
+                        };
+                        stretch = build_time_stretch(
+                            processing_rate,
+                            decoder_channels,
+                            speed,
+                            speed_affects_pitch,
+                        );
+                        clock.set_latency_samples(resampler_latency(&resampler, output_channels));
+                        mapper = ChannelMapper::new(
+                            decoder_channels,
+                            output_channels as usize,
+                            channel_mode,
+                            downmix,
+                        );
+                    }
+                    // A partial frame would shift every channel after it, so drop it
+                    let partial = samples.len() % decoder_channels;
+                    if partial != 0 {
+                        samples.truncate(samples.len() - partial);
+                    }
+                    if samples.is_empty() {
+                        continue;
+                    }
+                    decoded_start = decoder.current_position_secs();
+                    decoded = samples;
+                    decoded_pos = 0;
+                } else {
+                    pending.extend_from_slice(&flush_conversion(&mut resampler, &mut stretch, &mapper, true));
+                    // The last block is likely short, but it still has to be heard
+                    let block_len = dsp_block_frames.map_or(usize::MAX, |frames| frames.max(1) * output_channels as usize);
+                    process_blocks(&mut dsp, &clock, &mut pending, &mut outgoing, block_len, true);
+                    // Reported once the tail is all in the buffer
+                    finished = true;
                 }
             }
 
@@ -198,6 +1137,19 @@ impl AudioEngine {
         if self.is_playing() {
             return Ok(());
         }
+        if self.is_loading() {
+            return Err("A track is still loading, call complete_load first".into());
+        }
+
+        // The playback thread only exits once stopped, so a paused engine still has it
+        // running and only the output needs restarting
+        if self.clock.get_state() == PlaybackState::Paused && self.playback_thread.is_some() {
+            self.clock.set_state(PlaybackState::Playing);
+            if let Ok(mut out) = self.output.lock() {
+                out.start()?;
+            }
+            return Ok(());
+        }
 
         if let Some(h) = self.playback_thread.take() {
             let _ = h.join();
@@ -211,28 +1163,198 @@ impl AudioEngine {
 
         let output_arc = self.output.clone();
         let clock = self.clock.clone();
+        let events = self.events.clone();
+        let low_water = self.config.buffering_low_water;
+        let recovered = self.config.buffering_recovered;
+        let resync_threshold = self.config.drift_resync_threshold_secs;
+        let auto_pause_after = self.config.underrun_auto_pause_secs;
+        let finished = self.playback_finished.clone();
+        finished.set_running(true);
 
         let handle = thread::spawn(move || {
+            let mut drifting = 0;
+            let mut starved_since: Option<Instant> = None;
             while clock.get_state() != PlaybackState::Stopped {
                 if let Ok(mut out) = output_arc.lock() {
                     out.tick();
                 }
+
+                // A buffer draining at the end of the file isn't starving
+                if clock.get_state() == PlaybackState::Playing && !clock.is_eos() {
+                    let fill = clock.buffer_fill();
+                    if !clock.is_buffering() && fill < low_water {
+                        clock.set_buffering(true);
+                        events.emit(EngineEvent::Buffering);
+                    } else if clock.is_buffering() && fill >= recovered {
+                        clock.set_buffering(false);
+                        clock.set_auto_paused(false);
+                        events.emit(EngineEvent::Ready);
+                    }
+
+                    match auto_pause_after {
+                        Some(limit) if clock.is_starved() && !clock.is_auto_paused() => {
+                            let since = *starved_since.get_or_insert_with(Instant::now);
+                            if since.elapsed().as_secs_f64() >= limit {
+                                clock.set_auto_paused(true);
+                                if !clock.is_buffering() {
+                                    clock.set_buffering(true);
+                                    events.emit(EngineEvent::Buffering);
+                                }
+                            }
+                        }
+                        _ => starved_since = None,
+                    }
+                } else if clock.is_auto_paused() && clock.is_eos() {
+                    // The rest of the stream is in, so there's nothing left to wait for
+                    clock.set_auto_paused(false);
+                    clock.set_buffering(false);
+                    events.emit(EngineEvent::Ready);
+                }
+
+                // Check the clock against the decoder's position while playback is steady
+                let steady = clock.get_state() == PlaybackState::Playing
+                    && !clock.is_eos()
+                    && !clock.is_buffering()
+                    && !clock.should_clear_buffer();
+                match clock.measure_drift().filter(|_| steady) {
+                    Some(drift) => {
+                        clock.set_drift_secs(drift);
+                        let over = resync_threshold.is_some_and(|t| drift.abs() > t);
+                        drifting = if over { drifting + 1 } else { 0 };
+                        if drifting >= DRIFT_CONFIRMATIONS {
+                            let frames = ((clock.get_time_secs() - drift) * clock.get_sample_rate() as f64).max(0.0) as u64;
+                            let pos = frames * clock.get_channels() as u64 + clock.get_latency_samples();
+                            clock.set_sample_pos(pos);
+                            drifting = 0;
+                        }
+                    }
+                    None => drifting = 0,
+                }
                 thread::sleep(Duration::from_millis(100));
             }
+            finished.set_running(false);
         });
 
         self.playback_thread = Some(handle);
         Ok(())
     }
 
+    /// Blocks until the playing track has ended and its buffered audio has been heard,
+    /// for tools that play a file and exit. The end is noticed within about 100 ms.
+    /// `timeout` bounds the wait; `None` waits as long as it takes.
+    pub fn wait_until_finished(&self, timeout: Option<Duration>) -> Result<(), WaitError> {
+        if self.clock.get_state() != PlaybackState::Playing {
+            return Err(WaitError::NotPlaying);
+        }
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let signal = &self.playback_finished;
+        let mut running = signal.running.lock().map_err(|_| WaitError::Stopped)?;
+        while *running {
+            running = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Err(WaitError::TimedOut);
+                    }
+                    signal.cvar.wait_timeout(running, left).map_err(|_| WaitError::Stopped)?.0
+                }
+                None => signal.cvar.wait(running).map_err(|_| WaitError::Stopped)?,
+            };
+        }
+
+        // The playback thread only exits once the output has stopped
+        if self.clock.get_fatal_errors() > 0 {
+            Err(WaitError::DecodeFailed)
+        } else if !self.clock.is_eos() {
+            Err(WaitError::Stopped)
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn pause(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.clock.set_state(PlaybackState::Paused);
-        if let Ok(mut out) = self.output.lock() {
-            out.pause()?;
+        // Holding the last frame needs the callback to keep running
+        if self.clock.get_pause_behavior() == PauseBehavior::Silence {
+            if let Ok(mut out) = self.output.lock() {
+                out.pause()?;
+            }
         }
         Ok(())
     }
 
+    /// Sets the output buffer length, see `EngineConfig::buffer_duration_ms`. The buffer
+    /// is rebuilt right away if nothing is playing, otherwise on the next load.
+    pub fn set_buffer_duration_ms(&mut self, ms: u32) {
+        self.config.buffer_duration_ms = ms.clamp(MIN_BUFFER_DURATION_MS, MAX_BUFFER_DURATION_MS);
+        self.resize_buffer();
+    }
+
+    pub fn buffer_duration_ms(&self) -> u32 {
+        self.config.buffer_duration_ms
+    }
+
+    /// Applies a `LatencyMode` preset. The ring buffer is rebuilt right away if nothing is
+    /// playing, otherwise on the next load. The watermarks apply from the next `play` or
+    /// load, and the device buffer size the next time the output opens the device.
+    pub fn set_latency_mode(&mut self, mode: LatencyMode) {
+        mode.apply(&mut self.config);
+        self.clock.set_device_buffer_frames(self.config.device_buffer_frames);
+        self.resize_buffer();
+    }
+
+    // Rebuilds the ring buffer if the configured duration at the device's current rate
+    // calls for a different size, or the output's channel count changed since it was made.
+    // Only possible while the engine holds the producer.
+    fn resize_buffer(&mut self) {
+        let Some(producer) = &self.producer else {
+            return;
+        };
+        if producer.frames() != self.buffer_frames() || !producer.fits_channels() {
+            self.rebuild_buffer();
+        }
+    }
+
+    fn buffer_frames(&self) -> usize {
+        buffer_frames(self.config.buffer_duration_ms, self.clock.get_sample_rate())
+    }
+
+    fn rebuild_buffer(&mut self) {
+        let frames = self.buffer_frames();
+        let (producer, consumer) = create_audio_buffer(frames, self.clock.get_channels() as usize);
+        let capacity = consumer.capacity();
+        let Ok(mut out) = self.output.lock() else {
+            return;
+        };
+        if out.replace_consumer(consumer).is_ok() {
+            self.producer = Some(producer);
+            self.clock.set_buffer_capacity(capacity as u64);
+        }
+    }
+
+    /// See `EngineConfig::decode_thread_priority`. Takes effect from the next load.
+    pub fn set_decode_thread_priority(&mut self, priority: Option<u8>) {
+        self.config.decode_thread_priority = priority;
+    }
+
+    pub fn set_paused_output_behavior(&self, behavior: PauseBehavior) {
+        self.clock.set_pause_behavior(behavior);
+    }
+
+    pub fn paused_output_behavior(&self) -> PauseBehavior {
+        self.clock.get_pause_behavior()
+    }
+
+    /// `Flush` (the default) drops buffered audio on a seek; `Smooth` crossfades from it
+    /// into the new position.
+    pub fn set_seek_behavior(&self, behavior: SeekBehavior) {
+        self.clock.set_seek_behavior(behavior);
+    }
+
+    pub fn seek_behavior(&self) -> SeekBehavior {
+        self.clock.get_seek_behavior()
+    }
+
     pub fn stop(&mut self) {
         self.clock.set_state(PlaybackState::Stopped);
 
@@ -260,53 +1382,3014 @@ impl AudioEngine {
                 self.producer = Some(p);
             }
         }
+        if let Some(monitor) = self.monitor.take() {
+            monitor.running.store(false, Ordering::SeqCst);
+            if let Ok(p) = monitor.worker.join() {
+                self.producer = Some(p);
+            }
+        }
 
-        self.clock.set_sample_pos(0);
-        self.clock.set_eos(false);
+        self.clock.reset();
     }
 
-    pub fn set_bass_boost(&self, enabled: bool) {
-        self.bass_boost_enabled.store(enabled, Ordering::SeqCst);
-        if let Some(tx) = &self.command_tx {
-            let _ = tx.send(DecoderCommand::SetBassBoost(enabled));
-        }
-    }
+    /// Plays an input device live through the DSP chain, e.g. for karaoke or monitoring.
+    /// `input_device` is a device name, `None` takes the default input. Any playback is
+    /// stopped first; `stop` ends monitoring. The input is resampled to the output rate
+    /// when they differ. Changes to the DSP settings apply while monitoring; the bass
+    /// settings and custom nodes are taken as they are when monitoring starts.
+    ///
+    /// Latency is the input and output device periods, plus a fixed 20 ms cushion against
+    /// callback jitter, plus the resampler's delay when the rates differ.
+    pub fn start_monitor(&mut self, input_device: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        self.stop();
+        self.cancel_pending_load();
 
-    pub fn set_bass_intensity(&self, intensity: f32) {
-        if let Ok(mut v) = self.bass_boost_intensity.lock() {
-            *v = intensity.clamp(0.0, 100.0);
-        }
-        if let Some(tx) = &self.command_tx {
-            let _ = tx.send(DecoderCommand::SetBassIntensity(intensity));
-        }
+        let (capture, input) = InputCapture::open(input_device)?;
+        let (sample_rate, channels) = (capture.sample_rate(), capture.channels());
+        self.monitor_input(Some(capture), input, sample_rate, channels)
     }
 
-    pub fn seek(&mut self, time: f64) {
-        let pos =
-            (time * self.clock.get_sample_rate() as f64 * self.clock.get_channels() as f64) as u64;
-        self.clock.set_sample_pos(pos);
-        self.clock.signal_clear_buffer();
-        self.clock.set_eos(false);
-        if let Some(tx) = &self.command_tx {
-            let _ = tx.send(DecoderCommand::Seek(time));
+    // Plays `input`, filled with interleaved audio at `sample_rate` by `capture` or by
+    // whatever stands in for a device
+    fn monitor_input(
+        &mut self,
+        capture: Option<InputCapture>,
+        mut input: AudioBufferConsumer,
+        sample_rate: u32,
+        channels: u32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let channels = channels.max(1) as usize;
+        let mut stream = self.build_streaming_input(sample_rate, channels)?;
+        self.configure_stream_dsp(stream.dsp_mut());
+        let mut producer = self.producer.take().ok_or("Producer missing")?;
+        // Live input is never sped up
+        producer.mark_speed(1.0, 0);
+
+        let cushion = self.clock.get_sample_rate() as f32 * MONITOR_CUSHION_SECS;
+        let cushion = cushion as usize * self.clock.get_channels() as usize;
+        producer.push_slice(&vec![0.0; cushion]);
+
+        let running = Arc::new(AtomicBool::new(true));
+        let worker_running = running.clone();
+        let (dsp_tx, dsp_rx) = mpsc::channel::<DspSettings>();
+        let worker = thread::spawn(move || {
+            let mut block = vec![0.0; 4096 * channels];
+            let mut pending: Vec<f32> = Vec::new();
+            while worker_running.load(Ordering::SeqCst) {
+                if let Some(settings) = dsp_rx.try_iter().last() {
+                    stream.dsp_mut().apply_settings(&settings);
+                }
+                let read = input.pop_slice(&mut block);
+                if read == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                    continue;
+                }
+                pending.extend_from_slice(&block[..read]);
+                // Live audio can't wait, so whatever doesn't fit is dropped. Only a
+                // trailing partial frame is kept for the next round.
+                stream.push(&mut producer, &pending[..pending.len() - pending.len() % channels]);
+                pending.drain(..pending.len() - pending.len() % channels);
+            }
+            producer
+        });
+
+        self.monitor = Some(Monitor {
+            _capture: capture,
+            running,
+            worker,
+            dsp_tx,
+        });
+        self.clock.set_state(PlaybackState::Playing);
+        let started = match self.output.lock() {
+            Ok(mut out) => out.start(),
+            Err(_) => Ok(()),
+        };
+        if let Err(e) = started {
+            // Back to stopped, with the producer returned and the cushion cleared
+            self.stop();
+            return Err(e);
         }
+        Ok(())
     }
 
-    pub fn get_time_secs(&self) -> f64 {
-        self.clock.get_time_secs()
+    pub fn is_monitoring(&self) -> bool {
+        self.monitor.is_some()
     }
 
-    pub fn is_playing(&self) -> bool {
-        self.clock.get_state() == PlaybackState::Playing
+    /// Stops decoding but lets the output play what's already buffered before stopping,
+    /// for a clean ending. Blocks until the buffer has drained; the position keeps
+    /// advancing meanwhile and is only reset once playback has ended. When not playing
+    /// this is the same as `stop`.
+    pub fn stop_gracefully(&mut self) {
+        if self.clock.get_state() == PlaybackState::Playing {
+            // Let the decode thread push what it has decoded, including the partial DSP
+            // block and the resampler's tail, then hand the producer back
+            if let Some(tx) = self.command_tx.take() {
+                let _ = tx.send(DecoderCommand::Finish);
+            }
+            if let Some(h) = self.decode_thread.take() {
+                let _ = h.join();
+            }
+            if let Some(rx) = self.producer_return_rx.take() {
+                if let Ok(p) = rx.recv() {
+                    self.producer = Some(p);
+                }
+            }
+
+            // The output stops itself once it runs dry at end of stream
+            self.clock.set_eos(true);
+            let rate = self.clock.get_sample_rate().max(1) as u64 * self.clock.get_channels().max(1) as u64;
+            let buffered_ms = self.clock.get_buffered_samples() * 1000 / rate;
+            let deadline = Instant::now() + Duration::from_millis(buffered_ms + 1000);
+            while self.clock.get_state() == PlaybackState::Playing && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+
+        self.stop();
+    }
+
+    /// Fades over the time set with `set_bass_toggle_ramp_ms`.
+    pub fn set_bass_boost(&self, enabled: bool) {
+        self.dsp_params.bass_boost_enabled.store(enabled, Ordering::SeqCst);
+        if let Some(tx) = &self.command_tx {
+            let _ = tx.send(DecoderCommand::SetBassBoost(enabled));
+        }
+    }
+
+    /// Lowers the level ahead of the bass boost by as much as the boost currently adds,
+    /// so the limiter after it has less to catch and pumps less.
+    pub fn set_bass_auto_headroom(&self, enabled: bool) {
+        self.dsp_params.bass_auto_headroom.store(enabled, Ordering::SeqCst);
+        if let Some(tx) = &self.command_tx {
+            let _ = tx.send(DecoderCommand::SetBassAutoHeadroom(enabled));
+        }
+    }
+
+    /// Steepness of the bass processor's rumble high-pass as a Butterworth order: 2
+    /// (12 dB/oct, the default), 4, 6 or 8 (48 dB/oct). Odd orders round up.
+    pub fn set_bass_rumble_order(&self, order: usize) {
+        let order = order.clamp(2, 8);
+        self.dsp_params.bass_rumble_order.store(order, Ordering::SeqCst);
+        if let Some(tx) = &self.command_tx {
+            let _ = tx.send(DecoderCommand::SetBassRumbleOrder(order));
+        }
+    }
+
+    /// Strength of the bass boost, `0.0` to `100.0`. Defaults to `50.0`. NaN is ignored.
+    pub fn set_bass_intensity(&self, intensity: f32) {
+        if intensity.is_nan() {
+            return;
+        }
+        let intensity = intensity.clamp(0.0, 100.0);
+        self.dsp_params.bass_boost_intensity.store(intensity);
+        if let Some(tx) = &self.command_tx {
+            let _ = tx.send(DecoderCommand::SetBassIntensity(intensity));
+        }
+    }
+
+    /// How strongly the adaptive bass boost backs off while the limiter keeps clamping,
+    /// `0.0` (ignore the limiter) to `1.0`. Defaults to `0.5`. See
+    /// `BassProcessor::set_limiter_coupling`.
+    pub fn set_bass_limiter_coupling(&self, coupling: f32) {
+        let coupling = coupling.clamp(0.0, 1.0);
+        self.dsp_params.bass_limiter_coupling.store(coupling);
+        if let Some(tx) = &self.command_tx {
+            let _ = tx.send(DecoderCommand::SetBassLimiterCoupling(coupling));
+        }
+    }
+
+    /// Time `set_bass_boost` takes to fade the boost fully in or out, in milliseconds.
+    /// Defaults to 100. Zero switches it at once.
+    pub fn set_bass_toggle_ramp_ms(&self, ms: f32) {
+        let ms = ms.max(0.0);
+        self.dsp_params.bass_toggle_ramp_ms.store(ms);
+        if let Some(tx) = &self.command_tx {
+            let _ = tx.send(DecoderCommand::SetBassToggleRamp(ms));
+        }
+    }
+
+    /// Removes any DC offset from decoded audio. On by default.
+    pub fn set_dc_blocker(&mut self, enabled: bool) {
+        self.dsp_settings.dc_blocker.enabled = enabled;
+        self.send_dsp_settings();
+    }
+
+    /// Has the output limiter catch inter-sample peaks, which can still clip the DAC when
+    /// every sample is under the ceiling. Off by default; it costs some CPU and 6 frames
+    /// of latency. See `LimiterSettings::true_peak`.
+    pub fn set_limiter_true_peak(&mut self, enabled: bool) {
+        self.dsp_settings.limiter.true_peak = enabled;
+        self.send_dsp_settings();
+    }
+
+    /// Routes channels to arbitrary device channels, e.g. a stereo file to outputs 3 and 4
+    /// with `ChannelRouting::new().route(0, 2, 1.0).route(1, 3, 1.0)`. Routing applies after
+    /// the channel mode has mapped the source to the device's channel count, so source
+    /// channels beyond the file's own are silent. `ChannelRouting::default()` restores
+    /// the identity.
+    pub fn set_output_channel_layout(&mut self, routing: ChannelRouting) {
+        self.dsp_settings.routing = routing;
+        self.send_dsp_settings();
+    }
+
+    /// Trims output channel `ch` by `db`; `f32::NEG_INFINITY` mutes it. Persists across
+    /// tracks, and channels the device doesn't have are kept for when it does. Channels
+    /// from 64 on are ignored.
+    pub fn set_channel_gain(&mut self, ch: usize, db: f32) {
+        if ch >= MAX_OUTPUT_CHANNELS {
+            return;
+        }
+        let gains = &mut self.dsp_settings.channel_gains.gains_db;
+        if gains.len() <= ch {
+            gains.resize(ch + 1, 0.0);
+        }
+        gains[ch] = db;
+        self.send_dsp_settings();
+    }
+
+    /// Sets the gain of every output channel in dB at once, indexed by channel. Channels
+    /// past the end of `gains_db` go back to 0 dB.
+    pub fn set_channel_gains(&mut self, gains_db: &[f32]) {
+        self.dsp_settings.channel_gains.gains_db = gains_db.to_vec();
+        self.send_dsp_settings();
+    }
+
+    pub fn channel_gains(&self) -> &[f32] {
+        &self.dsp_settings.channel_gains.gains_db
+    }
+
+    /// Shifts level toward one ear, `-1.0` (left only) to `1.0` (right only). Only the
+    /// far side is attenuated; see `ChannelGainsSettings::balance`.
+    pub fn set_balance(&mut self, balance: f32) {
+        self.dsp_settings.channel_gains.balance = balance.clamp(-1.0, 1.0);
+        self.send_dsp_settings();
+    }
+
+    pub fn balance(&self) -> f32 {
+        self.dsp_settings.channel_gains.balance
+    }
+
+    /// Inverts the polarity of output channel `ch`. Channels from 64 on are ignored, like
+    /// in `set_channel_gain`.
+    pub fn set_channel_polarity(&mut self, ch: usize, inverted: bool) {
+        if ch >= MAX_OUTPUT_CHANNELS {
+            return;
+        }
+        let polarity = &mut self.dsp_settings.phase_correction.inverted;
+        if polarity.len() <= ch {
+            polarity.resize(ch + 1, false);
+        }
+        polarity[ch] = inverted;
+        self.send_dsp_settings();
+    }
+
+    /// Flips the right channel automatically while the front pair measures as
+    /// polarity-inverted. Off by default.
+    pub fn set_phase_correction(&mut self, enabled: bool) {
+        self.dsp_settings.phase_correction.auto = enabled;
+        self.send_dsp_settings();
+    }
+
+    /// Switches the parametric EQ to one of the built-in curves, gliding from the current
+    /// one. `Flat` turns it off. See `EqPreset` for each curve.
+    pub fn set_eq_preset(&mut self, preset: EqPreset) {
+        self.dsp_settings.eq = preset.settings();
+        self.send_dsp_settings();
+    }
+
+    pub fn set_eq(&mut self, enabled: bool) {
+        self.dsp_settings.eq.enabled = enabled;
+        self.send_dsp_settings();
+    }
+
+    /// Replaces the EQ's bands with a custom curve. Keep the number of bands the same as
+    /// the current curve's for the gains to glide instead of switching at once.
+    pub fn set_eq_bands(&mut self, bands: &[EqBand]) {
+        self.dsp_settings.eq.bands = bands.to_vec();
+        self.send_dsp_settings();
+    }
+
+    pub fn eq_bands(&self) -> &[EqBand] {
+        &self.dsp_settings.eq.bands
+    }
+
+    pub fn set_crossfeed(&mut self, enabled: bool) {
+        self.dsp_settings.crossfeed.enabled = enabled;
+        self.send_dsp_settings();
+    }
+
+    pub fn set_crossfeed_amount(&mut self, amount: f32) {
+        self.dsp_settings.crossfeed.amount = amount.clamp(0.0, 1.0);
+        self.send_dsp_settings();
+    }
+
+    /// Low-pass cutoff of the crossfed signal, clamped to 100 Hz up to 0.45 of the
+    /// processing rate.
+    pub fn set_crossfeed_cutoff(&mut self, cutoff_hz: f32) {
+        let rate = self.clock.get_sample_rate() as f32;
+        self.dsp_settings.crossfeed.cutoff = crossfeed::clamp_cutoff(cutoff_hz, rate);
+        self.send_dsp_settings();
+    }
+
+    /// Splits the front pair into low and high bands on separate output channels for
+    /// bi-amping. Needs a device with enough channels for both pairs (e.g. 4).
+    pub fn set_crossover(&mut self, enabled: bool) {
+        self.dsp_settings.crossover.enabled = enabled;
+        self.send_dsp_settings();
+    }
+
+    pub fn set_crossover_params(
+        &mut self,
+        frequency: f32,
+        low_channels: [usize; 2],
+        high_channels: [usize; 2],
+    ) {
+        let crossover = &mut self.dsp_settings.crossover;
+        crossover.frequency = frequency;
+        crossover.low_channels = low_channels;
+        crossover.high_channels = high_channels;
+        self.send_dsp_settings();
+    }
+
+    pub fn set_de_esser(&mut self, enabled: bool) {
+        self.dsp_settings.de_esser.enabled = enabled;
+        self.send_dsp_settings();
+    }
+
+    pub fn set_de_esser_params(&mut self, frequency: f32, threshold_db: f32, ratio: f32) {
+        let de_esser = &mut self.dsp_settings.de_esser;
+        de_esser.frequency = frequency;
+        de_esser.threshold_db = threshold_db;
+        de_esser.ratio = ratio;
+        self.send_dsp_settings();
+    }
+
+    pub fn set_phaser(&mut self, enabled: bool) {
+        self.dsp_settings.phaser.enabled = enabled;
+        self.send_dsp_settings();
+    }
+
+    pub fn set_phaser_params(
+        &mut self,
+        rate_hz: f32,
+        depth: f32,
+        feedback: f32,
+        stages: usize,
+        mix: f32,
+    ) {
+        let phaser = &mut self.dsp_settings.phaser;
+        phaser.rate_hz = rate_hz;
+        phaser.depth = depth;
+        phaser.feedback = feedback;
+        phaser.stages = stages;
+        phaser.mix = mix;
+        self.send_dsp_settings();
+    }
+
+    pub fn set_lfo_mod(&mut self, enabled: bool) {
+        self.dsp_settings.lfo_mod.enabled = enabled;
+        self.send_dsp_settings();
+    }
+
+    /// Configures the tremolo (`LfoTarget::Gain`) or auto-pan (`LfoTarget::Pan`) effect.
+    pub fn set_lfo_mod_params(
+        &mut self,
+        target: LfoTarget,
+        waveform: LfoWaveform,
+        rate_hz: f32,
+        depth: f32,
+        stereo_phase_offset: f32,
+    ) {
+        let lfo_mod = &mut self.dsp_settings.lfo_mod;
+        lfo_mod.target = target;
+        lfo_mod.waveform = waveform;
+        lfo_mod.rate_hz = rate_hz;
+        lfo_mod.depth = depth;
+        lfo_mod.stereo_phase_offset = stereo_phase_offset;
+        self.send_dsp_settings();
+    }
+
+    /// Adds a custom effect to the DSP chain, just ahead of the limiter, and returns its id
+    /// for `set_dsp_order` and `remove_dsp_node`. It stays in place across tracks and
+    /// output changes. See `DspNode` for what `process` may and may not do.
+    ///
+    /// `make` builds an instance for each chain that runs it: one per track for playback,
+    /// kept across output changes, and separate ones for `process_samples` and the
+    /// monitor, so their state never mixes.
+    pub fn add_dsp_node<F>(&mut self, make: F) -> NodeId
+    where
+        F: Fn() -> Box<dyn DspNode + Send> + Send + Sync + 'static,
+    {
+        let id = self.next_dsp_node;
+        self.next_dsp_node += 1;
+        self.dsp_nodes.push((id, Arc::new(make)));
+
+        if let Some(order) = &mut self.dsp_settings.order {
+            let limiter = order.iter().position(|n| *n == NodeId::Limiter).unwrap_or(order.len());
+            order.insert(limiter, NodeId::Custom(id));
+        }
+        self.send_dsp_nodes();
+        self.send_dsp_settings();
+        NodeId::Custom(id)
+    }
+
+    /// Removes a node added with `add_dsp_node`. Returns `false` for unknown and built-in
+    /// ids; built-in stages can only be bypassed with `set_dsp_order`.
+    pub fn remove_dsp_node(&mut self, id: NodeId) -> bool {
+        let NodeId::Custom(n) = id else {
+            return false;
+        };
+        let before = self.dsp_nodes.len();
+        self.dsp_nodes.retain(|(node, _)| *node != n);
+        if self.dsp_nodes.len() == before {
+            return false;
+        }
+
+        if let Some(order) = &mut self.dsp_settings.order {
+            order.retain(|node| *node != id);
+        }
+        self.dsp_settings.disabled.retain(|node| *node != id);
+        self.send_dsp_nodes();
+        self.send_dsp_settings();
+        true
+    }
+
+    fn send_dsp_nodes(&self) {
+        if let Some(tx) = &self.command_tx {
+            let _ = tx.send(DecoderCommand::SetDspNodes(self.dsp_nodes.clone()));
+        }
+    }
+
+    /// Sets the order the DSP stages run in, e.g. to put the HF EQ ahead of the bass or
+    /// drop the limiter. Stages left out are bypassed. Fails if a stage is listed twice
+    /// or a custom node isn't registered.
+    pub fn set_dsp_order(&mut self, order: &[NodeId]) -> Result<(), Box<dyn std::error::Error>> {
+        for (i, id) in order.iter().enumerate() {
+            if let NodeId::Custom(n) = id {
+                if !self.dsp_nodes.iter().any(|(node, _)| node == n) {
+                    return Err(format!("No DSP node {:?} was added", id).into());
+                }
+            }
+            if order[..i].contains(id) {
+                return Err(format!("DSP node {:?} is listed twice", id).into());
+            }
+        }
+        self.dsp_settings.order = Some(order.to_vec());
+        self.send_dsp_settings();
+        Ok(())
+    }
+
+    pub fn dsp_order(&self) -> Vec<NodeId> {
+        self.dsp_settings
+            .order
+            .clone()
+            .unwrap_or_else(|| default_order(self.dsp_nodes.iter().map(|(id, _)| *id)))
+    }
+
+    /// Switches a DSP stage off or back on while leaving it where it is in the order,
+    /// e.g. to compare with and without the HF EQ. Off passes audio through untouched but
+    /// keeps the stage's state, so it picks up smoothly when switched back on. For the
+    /// bass this is `set_bass_boost`. Fails for a custom node that isn't registered.
+    pub fn set_node_enabled(&mut self, id: NodeId, enabled: bool) -> Result<(), Box<dyn std::error::Error>> {
+        match id {
+            NodeId::Bass => {
+                self.set_bass_boost(enabled);
+                return Ok(());
+            }
+            NodeId::Custom(n) if !self.dsp_nodes.iter().any(|(node, _)| *node == n) => {
+                return Err(format!("No DSP node {:?} was added", id).into());
+            }
+            _ => {}
+        }
+        self.dsp_settings.disabled.retain(|node| *node != id);
+        if !enabled {
+            self.dsp_settings.disabled.push(id);
+        }
+        self.send_dsp_settings();
+        Ok(())
+    }
+
+    pub fn node_enabled(&self, id: NodeId) -> bool {
+        match id {
+            NodeId::Bass => self.dsp_params.bass_boost_enabled.load(Ordering::SeqCst),
+            _ => !self.dsp_settings.disabled.contains(&id),
+        }
+    }
+
+    pub fn export_preset(&self) -> DspPreset {
+        DspPreset {
+            bass_boost: self.dsp_params.bass_boost_enabled.load(Ordering::SeqCst),
+            bass_intensity: self.dsp_params.bass_boost_intensity.load(),
+            settings: self.dsp_settings.clone(),
+        }
+    }
+
+    /// Applies every parameter in `preset` to the running chain. Nodes update in place
+    /// rather than being rebuilt, so playback continues without a gap.
+    pub fn apply_preset(&mut self, preset: &DspPreset) {
+        self.set_bass_boost(preset.bass_boost);
+        self.set_bass_intensity(preset.bass_intensity);
+        self.dsp_settings = preset.settings.clone();
+        self.send_dsp_settings();
+    }
+
+    pub fn save_session(&self) -> Session {
+        Session {
+            file: self.current_file(),
+            position_secs: self.get_time_secs(),
+            volume: self.volume.load(),
+            volume_taper: self.volume_taper,
+            normalization: self.normalization,
+            playback_speed: self.playback_speed(),
+            preset: self.export_preset(),
+        }
+    }
+
+    /// Applies every setting in `session`, then loads its file and seeks to where it was,
+    /// leaving it paused there. Before an output has set the format the seek waits for
+    /// it. A session without a file unloads the current one.
+    pub fn restore_session(&mut self, session: &Session) -> Result<(), SessionError> {
+        self.set_volume_taper(session.volume_taper);
+        self.set_volume(session.volume);
+        self.set_normalization(session.normalization);
+        self.set_playback_speed(session.playback_speed);
+        self.apply_preset(&session.preset);
+
+        let Some(path) = &session.file else {
+            self.unload();
+            return Ok(());
+        };
+        let deferred = session.position_secs > 0.0 && !self.clock.is_configured();
+        if deferred {
+            self.start_secs = Some(session.position_secs);
+        }
+        let loaded = self.load(path).map_err(|e| SessionError::Load {
+            path: path.clone(),
+            error: e.to_string(),
+        });
+        // Only a decoder that was started has taken it
+        self.start_secs = None;
+        loaded?;
+        if deferred && !self.seekable {
+            return Err(SessionError::Seek(SeekError::NotSeekable));
+        } else if session.position_secs > 0.0 && !deferred {
+            self.seek(session.position_secs).map_err(SessionError::Seek)?;
+        }
+        self.clock.set_state(PlaybackState::Paused);
+        Ok(())
+    }
+
+    fn send_dsp_settings(&self) {
+        if let Some(tx) = &self.command_tx {
+            let _ = tx.send(DecoderCommand::UpdateDsp(Box::new(self.dsp_settings.clone())));
+        }
+        if let Some(monitor) = &self.monitor {
+            let _ = monitor.dsp_tx.send(self.dsp_settings.clone());
+        }
+    }
+
+    /// Forces the number of channels the engine renders. The result is always mapped to
+    /// the device's channel count in software, so it works on any device.
+    pub fn set_output_channel_mode(&mut self, mode: ChannelMode) {
+        self.channel_mode = mode;
+        if let Some(tx) = &self.command_tx {
+            let _ = tx.send(DecoderCommand::SetChannelMode(mode));
+        }
+    }
+
+    pub fn output_channel_mode(&self) -> ChannelMode {
+        self.channel_mode
+    }
+
+    /// How a source with more than two channels is folded down when it plays on a stereo
+    /// or mono device, or with `ChannelMode::ForceStereo` or `ForceMono`. `Downmix::Itu`
+    /// unless changed. A `push_samples` stream keeps the downmix it was created with.
+    pub fn set_downmix(&mut self, downmix: Downmix) {
+        self.downmix = downmix;
+        if let Some(tx) = &self.command_tx {
+            let _ = tx.send(DecoderCommand::SetDownmix(downmix));
+        }
+    }
+
+    pub fn downmix(&self) -> Downmix {
+        self.downmix
+    }
+
+    /// Whether playback moves to the new default device when the system's default changes.
+    /// On by default. Turned off, the engine stays on the device it opened and only
+    /// reconnects after a stream error.
+    pub fn set_follow_default_device(&self, follow: bool) {
+        if let Ok(mut out) = self.output.lock() {
+            out.set_follow_default_device(follow);
+        }
+    }
+
+    /// The format the output device was opened with, `None` while no device is connected.
+    pub fn output_format(&self) -> Option<OutputFormat> {
+        self.output.lock().ok()?.format()
+    }
+
+    /// Whether a real device is playing the audio. `false` while the engine falls back to
+    /// a `NullBackend`, which keeps decoding and the clock running without sound.
+    pub fn is_output_available(&self) -> bool {
+        self.output.lock().map(|out| out.has_device()).unwrap_or(false)
+    }
+
+    pub fn output_channels(&self) -> u32 {
+        self.clock.get_channels()
+    }
+
+    /// Whether the loaded source supports `seek`. Pipes and live streams usually don't.
+    pub fn seekable(&self) -> bool {
+        self.seekable
+    }
+
+    pub fn seek(&mut self, time: f64) -> Result<(), SeekError> {
+        self.send_seek(time, SeekTarget::Secs(time))
+    }
+
+    /// Seeks to a frame of the source, counted at its own sample rate, e.g. for exact
+    /// loop points. The decoder lands on that frame exactly where the format allows it,
+    /// instead of going through seconds and rounding.
+    pub fn seek_to_sample(&mut self, frame: u64) -> Result<(), SeekError> {
+        let time = frame as f64 / self.source_sample_rate.max(1) as f64;
+        self.send_seek(time, SeekTarget::Frame(frame))
+    }
+
+    /// Length of the loaded source in frames at its own sample rate, if known.
+    pub fn total_frames(&self) -> Option<u64> {
+        self.total_frames
+    }
+
+    fn send_seek(&mut self, time: f64, target: SeekTarget) -> Result<(), SeekError> {
+        if !self.seekable {
+            return Err(SeekError::NotSeekable);
+        }
+        if !self.clock.is_configured() {
+            return Err(SeekError::NoOutputFormat);
+        }
+        self.clock.set_time_secs(time);
+        self.clock.clear_source_time();
+        self.clock.signal_clear_buffer();
+        self.clock.set_eos(false);
+        if let Some(tx) = &self.command_tx {
+            let _ = tx.send(DecoderCommand::Seek(target));
+        }
+        Ok(())
+    }
+
+    pub fn get_time_secs(&self) -> f64 {
+        self.clock.get_time_secs()
+    }
+
+    /// How far the output clock was ahead of the decoder's position at the last check, in
+    /// seconds (negative if behind). See `EngineConfig::drift_resync_threshold_secs`.
+    pub fn clock_drift_secs(&self) -> f64 {
+        self.clock.get_drift_secs()
+    }
+
+    /// Playback position for a seekbar or time display, interpolated between output
+    /// callbacks. See `Clock::get_time_secs_smoothed`; `get_time_secs` stays authoritative.
+    pub fn get_time_secs_smoothed(&self) -> f64 {
+        self.clock.get_time_secs_smoothed()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.clock.get_state() == PlaybackState::Playing
+    }
+
+    /// Like the clock's state, but reports `Buffering` while playing from a starved buffer.
+    pub fn get_state(&self) -> PlaybackState {
+        match self.clock.get_state() {
+            PlaybackState::Playing if self.clock.is_buffering() => PlaybackState::Buffering,
+            state => state,
+        }
+    }
+
+    /// Output buffer occupancy as a fraction of its capacity, as last seen by the output.
+    pub fn buffer_fill(&self) -> f32 {
+        self.clock.buffer_fill()
+    }
+
+    /// Positions in the track, in seconds, where the output most recently ran dry, oldest
+    /// first. Keeps the last 64 since the track was loaded, one for each underrun counted
+    /// in `EngineStatus::underruns`, for matching glitches to parts of the track.
+    pub fn recent_dropouts(&self) -> Vec<f64> {
+        self.clock.dropouts().recent()
+    }
+
+    /// Decode errors recovered from since the track was loaded, e.g. corrupt packets
+    /// skipped in tolerant mode. A count that keeps climbing points to a flaky source.
+    pub fn decode_error_count(&self) -> u64 {
+        self.clock.get_decode_errors()
+    }
+
+    /// Errors that ended decoding early since the track was loaded. Playback stops as if
+    /// the track had finished, with whatever was decoded before still heard.
+    pub fn fatal_error_count(&self) -> u64 {
+        self.clock.get_fatal_errors()
+    }
+
+    /// Zeroes both error counts, e.g. to judge a source by how it's doing lately.
+    pub fn flush_decoder_errors(&mut self) {
+        self.clock.reset_decode_errors();
+    }
+
+    pub fn status(&self) -> EngineStatus {
+        EngineStatus {
+            position_secs: self.clock.get_time_secs(),
+            duration_secs: self.get_metadata().and_then(|m| m.duration_secs),
+            state: self.get_state(),
+            volume: self.volume.load(),
+            buffer_fill: self.clock.buffer_fill(),
+            underruns: self.clock.get_underruns(),
+            clipped: self.clock.is_clipped(),
+        }
+    }
+
+    /// Output volume, `0.0..=1.0`, mapped to a gain by the volume taper. Applied in the
+    /// output callback so it takes effect immediately instead of after the buffered audio.
+    /// Non-finite values are ignored.
+    pub fn set_volume(&self, volume: f32) {
+        if !volume.is_finite() {
+            return;
+        }
+        self.volume.store(volume.clamp(0.0, 1.0));
+        self.apply_volume();
+    }
+
+    /// The control value last passed to `set_volume`, before the taper.
+    pub fn volume(&self) -> f32 {
+        self.volume.load()
+    }
+
+    /// `Linear` by default, so `set_volume` is a plain gain unless this is changed.
+    pub fn set_volume_taper(&mut self, taper: VolumeTaper) {
+        self.volume_taper = taper;
+        self.apply_volume();
+    }
+
+    pub fn volume_taper(&self) -> VolumeTaper {
+        self.volume_taper
+    }
+
+    /// Applies the loaded track's gain tag, see `AudioMetadata::normalization_gain_db`.
+    /// Tracks without one play unchanged. The gain goes in ahead of the DSP chain, so the
+    /// limiter catches peaks a positive one pushes over full scale, and like other DSP
+    /// changes it's heard once the buffered audio has played.
+    pub fn set_normalization(&mut self, enabled: bool) {
+        self.normalization = enabled;
+        self.apply_normalization();
+    }
+
+    pub fn normalization(&self) -> bool {
+        self.normalization
+    }
+
+    fn apply_volume(&self) {
+        let gain = self.volume_taper.gain(self.volume.load()) * 10.0f32.powf(self.track_gain_db / 20.0);
+        self.clock.set_volume(gain);
+    }
+
+    fn apply_normalization(&self) {
+        let db = match self.get_metadata() {
+            Some(metadata) if self.normalization => metadata.normalization_gain_db().unwrap_or(0.0),
+            _ => 0.0,
+        };
+        self.dsp_params.normalization_gain_db.store(db);
+        if let Some(tx) = &self.command_tx {
+            let _ = tx.send(DecoderCommand::SetNormalizationGain(db));
+        }
+    }
+
+    /// Adds `path` to the end of the play queue. See `play_next`.
+    pub fn enqueue<P: AsRef<Path>>(&mut self, path: P) {
+        self.enqueue_with_gain(path, 0.0);
+    }
+
+    /// Like `enqueue`, with a manual trim in dB applied while that track plays, on top
+    /// of the volume and any normalization. Clamped to -60..=+12 dB like the output gain.
+    pub fn enqueue_with_gain<P: AsRef<Path>>(&mut self, path: P, gain_db: f32) {
+        let gain_db = gain_db.clamp(MIN_OUTPUT_GAIN_DB, MAX_OUTPUT_GAIN_DB);
+        self.queue.push_back((path.as_ref().to_path_buf(), gain_db));
+    }
+
+    /// Loads and plays the next queued track with its gain offset. Returns `false` if
+    /// the queue was empty. The engine doesn't advance on its own; call this when a
+    /// track finishes, e.g. after `wait_until_finished`. A track that fails to load is
+    /// still taken off the queue.
+    pub fn play_next(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        let Some((path, gain_db)) = self.queue.pop_front() else {
+            return Ok(false);
+        };
+        self.load(&path)?;
+        self.track_gain_db = gain_db;
+        self.apply_volume();
+        self.play()?;
+        Ok(true)
+    }
+
+    pub fn queue_len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn clear_queue(&mut self) {
+        self.queue.clear();
+    }
+
+    /// Gain offset of the queued track playing now, `0.0` for one loaded directly.
+    pub fn track_gain_db(&self) -> f32 {
+        self.track_gain_db
+    }
+
+    /// Final trim applied in the output callback after all processing, limiting and
+    /// volume, e.g. to match the level of other apps. Clamped to -60..=+12 dB and ramped
+    /// over a callback so changes don't click. Positive values can push the limited
+    /// signal past full scale. Non-finite values are ignored.
+    pub fn set_output_gain_db(&self, db: f32) {
+        if !db.is_finite() {
+            return;
+        }
+        self.clock
+            .set_output_gain_db(db.clamp(MIN_OUTPUT_GAIN_DB, MAX_OUTPUT_GAIN_DB));
+    }
+
+    pub fn output_gain_db(&self) -> f32 {
+        self.clock.get_output_gain_db()
+    }
+
+    /// Plays faster or slower, `1.0` being normal speed, clamped to `0.25..=4.0`. Whether
+    /// pitch follows is set with `set_speed_affects_pitch`. Applies to audio decoded from
+    /// now on, so it's heard once the buffer ahead of it has played, and carries over to
+    /// the next track. The position keeps counting in the track's own time. Non-finite
+    /// speeds are ignored.
+    pub fn set_playback_speed(&mut self, speed: f32) {
+        if !speed.is_finite() {
+            return;
+        }
+        self.clock
+            .set_playback_speed(speed.clamp(MIN_PLAYBACK_SPEED, MAX_PLAYBACK_SPEED));
+    }
+
+    pub fn playback_speed(&self) -> f32 {
+        self.clock.get_playback_speed()
+    }
+
+    /// How `set_playback_speed` changes speed. `true`, the default, resamples like tape
+    /// played faster or slower: pitch moves with speed, ramped so the change doesn't
+    /// click. `false` time-stretches instead, keeping the pitch at the cost of some
+    /// smearing on transients and about 50 ms more latency. Switching rebuilds the stage
+    /// involved, which can drop a few milliseconds of audio.
+    pub fn set_speed_affects_pitch(&mut self, affects_pitch: bool) {
+        self.clock.set_speed_affects_pitch(affects_pitch);
+    }
+
+    pub fn speed_affects_pitch(&self) -> bool {
+        self.clock.speed_affects_pitch()
+    }
+
+    /// Current limiter gain reduction in dB across all channels, `0.0` when idle. It's
+    /// measured as audio is decoded, so it leads what's heard by the buffered amount.
+    pub fn limiter_reduction_db(&self) -> f32 {
+        self.clock.get_limiter_reduction_db()
+    }
+
+    /// Share of the time the decode thread spent decoding, resampling and processing
+    /// rather than waiting for buffer space, `0.0..=1.0`, averaged over the last half
+    /// second. Near `1.0` it barely keeps up with playback (an expensive resampler, a slow
+    /// disk or a loaded CPU) and underruns are likely. `0.0` while nothing is decoding.
+    pub fn decode_load(&self) -> f32 {
+        self.clock.get_decode_load()
+    }
+
+    /// Whether the front pair currently measures as one channel being a polarity-inverted
+    /// copy of the other. Like the limiter meter, it leads what's heard.
+    pub fn phase_inversion_detected(&self) -> bool {
+        self.clock.is_phase_inverted()
+    }
+
+    /// Copies the samples most recently written to the device into `out`, interleaved and
+    /// oldest first, and returns how many were copied (whole frames only). Unlike anything
+    /// taken from the decoder, this is exactly what's being heard, after DSP and volume.
+    pub fn latest_output_block(&self, out: &mut [f32]) -> usize {
+        let channels = self.clock.get_channels().max(1) as usize;
+        self.clock.output_tap().read_latest(out, channels)
+    }
+
+    /// Spectrum of what's currently being heard, taken from the output tap, grouped into
+    /// `band_count` logarithmically spaced bands over the range set with
+    /// `set_spectrum_range` (20 Hz to 20 kHz by default). Each value is the band's average
+    /// energy in dB relative to a full-scale sine, floored at -120.
+    pub fn get_spectrum_bands(&mut self, band_count: usize) -> Vec<f32> {
+        let channels = self.clock.get_channels().max(1) as usize;
+        self.spectrum_input.resize(self.spectrum.fft_size() * channels, 0.0);
+        let read = self.clock.output_tap().read_latest(&mut self.spectrum_input, channels);
+
+        if self.clock.is_configured() {
+            self.spectrum.set_sample_rate(self.clock.get_device_sample_rate() as f32);
+        }
+        self.spectrum.analyze(&self.spectrum_input[..read], channels);
+        let mut bands = self.spectrum.bands(band_count);
+        self.spectrum_ballistics.apply(&mut bands);
+        bands
+    }
+
+    /// Peak level of each output channel over the last 20 ms of what's being heard, in
+    /// dB, floored at -120. With `MeterChannelMode::Summed` it's a single level for their
+    /// mono sum instead.
+    pub fn get_output_levels_db(&mut self) -> Vec<f32> {
+        let channels = self.clock.get_channels().max(1) as usize;
+        let frames = (self.clock.get_device_sample_rate() as f32 * LEVEL_WINDOW_SECS) as usize;
+        let mut block = vec![0.0; frames.max(1) * channels];
+        let read = self.clock.output_tap().read_latest(&mut block, channels);
+
+        let mut levels = match self.meter_channel_mode {
+            MeterChannelMode::Summed => vec![summed_peak_level_db(&block[..read], channels)],
+            MeterChannelMode::PerChannel => peak_levels_db(&block[..read], channels),
+        };
+        self.level_ballistics.apply(&mut levels);
+        levels
+    }
+
+    /// Whether `get_output_levels_db` reports each channel or their mono sum. The sum is
+    /// only measured; the output keeps all its channels. The spectrum is always taken
+    /// from the mono sum. `MeterChannelMode::PerChannel` by default.
+    pub fn set_meter_channel_mode(&mut self, mode: MeterChannelMode) {
+        self.meter_channel_mode = mode;
+    }
+
+    pub fn meter_channel_mode(&self) -> MeterChannelMode {
+        self.meter_channel_mode
+    }
+
+    /// How well what's currently playing survives a mono downmix, measured on the front
+    /// pair of the output over the last 100 ms or so. See `MonoCompatibility`.
+    pub fn mono_compatibility_check(&self) -> MonoCompatibility {
+        let channels = self.clock.get_channels().max(1) as usize;
+        let frames = (self.clock.get_device_sample_rate() as f32 * CORRELATION_WINDOW_SECS) as usize;
+        let mut block = vec![0.0; frames.max(1) * channels];
+        let read = self.clock.output_tap().read_latest(&mut block, channels);
+        mono_compatibility(&block[..read], channels)
+    }
+
+    /// Correlation between the left and right output, `-1.0..=1.0`. Negative values
+    /// mean the channels partly cancel when summed to mono.
+    pub fn stereo_correlation(&self) -> f32 {
+        self.mono_compatibility_check().correlation
+    }
+
+    /// Smooths `get_spectrum_bands` and `get_output_levels_db` so visualizers don't
+    /// flicker: readings rise with an `attack_ms` time constant and fall with a
+    /// `release_ms` one. `0.0` for both (the default) returns raw values; an attack of
+    /// `0.0` with a few hundred ms of release gives a classic peak meter. The smoothing
+    /// runs when the values are polled, never on the audio thread.
+    pub fn set_meter_ballistics(&mut self, attack_ms: f32, release_ms: f32) {
+        self.spectrum_ballistics.set_times(attack_ms, release_ms);
+        self.level_ballistics.set_times(attack_ms, release_ms);
+    }
+
+    pub fn set_spectrum_range(&mut self, min_hz: f32, max_hz: f32) {
+        self.spectrum.set_frequency_range(min_hz, max_hz);
+    }
+
+    pub fn reset_clip_indicator(&self) {
+        self.clock.reset_clipped();
+    }
+
+    /// Metadata of the loaded source, kept up to date with what the stream carries
+    /// in-band, see `EngineEvent::MetadataChanged`.
+    pub fn get_metadata(&self) -> Option<AudioMetadata> {
+        self.current_metadata.lock().ok()?.clone()
+    }
+
+    fn set_current_metadata(&self, metadata: Option<AudioMetadata>) {
+        if let Ok(mut current) = self.current_metadata.lock() {
+            *current = metadata;
+        }
+    }
+
+    /// Container, codec, bit depth and bitrate of the loaded source, as far as its decoder
+    /// knows them.
+    pub fn format_info(&self) -> Option<&CodecInfo> {
+        self.format_info.as_ref()
+    }
+
+    /// Where the loaded audio comes from. Set by every load and kept through `stop`,
+    /// until `unload`.
+    pub fn source(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    /// Path of the loaded file, `None` when nothing is loaded or the audio isn't from a file.
+    pub fn current_file(&self) -> Option<PathBuf> {
+        match &self.source {
+            Some(Source::File(path)) => Some(path.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Stops playback and forgets the loaded source along with its metadata and chapters.
+    pub fn unload(&mut self) {
+        self.stop();
+        self.cancel_pending_load();
+        self.source = None;
+        self.streaming = None;
+        self.set_current_metadata(None);
+        self.format_info = None;
+        self.chapters.clear();
+        self.seekable = true;
+        self.total_frames = None;
+        self.track_gain_db = 0.0;
+        self.apply_volume();
+    }
+
+    pub fn subscribe(&self) -> Receiver<EngineEvent> {
+        self.events.subscribe()
+    }
+
+    /// Starts computing a mono min/max overview of the whole loaded file with `buckets`
+    /// points, from the track picked with `load_track` if any. It runs on its own thread
+    /// with its own decoder, so playback is unaffected; progress is reported through
+    /// `EngineEvent::WaveformProgress`.
+    pub fn generate_waveform(&self, buckets: usize) -> Result<WaveformJob, Box<dyn std::error::Error>> {
+        let path = self.current_file().ok_or("No file loaded")?;
+        let decoder = match self.file_track {
+            Some(track_id) => SymphoniaDecoder::new_with_track(path, track_id)?,
+            None => SymphoniaDecoder::new(path)?,
+        };
+        Ok(WaveformJob::spawn(decoder, buckets, self.events.clone()))
+    }
+
+    /// Measures the integrated loudness, loudness range and peaks of the file at `path`
+    /// on its own thread and decoder, without playing it or touching playback. Progress
+    /// is reported through `EngineEvent::LoudnessProgress`; `LoudnessJob::wait` returns
+    /// the report.
+    pub fn analyze_loudness<P: AsRef<Path>>(&self, path: P) -> Result<LoudnessJob, Box<dyn std::error::Error>> {
+        let decoder = SymphoniaDecoder::new(path)?;
+        Ok(LoudnessJob::spawn(decoder, self.events.clone()))
+    }
+
+    /// Chapters of the loaded file, sorted by start time. Empty if it has none.
+    pub fn chapters(&self) -> &[Chapter] {
+        &self.chapters
+    }
+
+    /// Seeks to the start of the chapter after the current position, returning its index.
+    pub fn next_chapter(&mut self) -> Option<usize> {
+        let index = next_chapter_index(&self.chapters, self.get_time_secs())?;
+        self.seek(self.chapters[index].start_secs).ok()?;
+        Some(index)
+    }
+
+    /// Seeks to the start of the current chapter, or to the previous chapter when the
+    /// position is already close to the current one's start.
+    pub fn previous_chapter(&mut self) -> Option<usize> {
+        let index = previous_chapter_index(&self.chapters, self.get_time_secs())?;
+        self.seek(self.chapters[index].start_secs).ok()?;
+        Some(index)
+    }
+}
+
+fn next_chapter_index(chapters: &[Chapter], position: f64) -> Option<usize> {
+    // A small tolerance so sitting exactly on a boundary doesn't count as before it
+    chapters.iter().position(|c| c.start_secs > position + 0.001)
+}
+
+fn previous_chapter_index(chapters: &[Chapter], position: f64) -> Option<usize> {
+    let current = chapters.iter().rposition(|c| c.start_secs <= position + 0.001)?;
+    if position - chapters[current].start_secs < CHAPTER_RESTART_WINDOW_SECS && current > 0 {
+        Some(current - 1)
+    } else {
+        Some(current)
+    }
+}
+
+// Waits until audio buffered before a seek has been dropped, so nothing decoded after
+// the seek is thrown away with it. A playing output drops it on its next callback; a
+// paused (or stalled) stream doesn't call back, so the buffer is cleared from here instead.
+fn drop_stale_audio(clock: &Clock, output: &Mutex<Box<dyn AudioOutput + Send>>) {
+    let deadline = Instant::now() + Duration::from_millis(200);
+    while clock.should_clear_buffer() {
+        if clock.get_state() != PlaybackState::Playing || Instant::now() >= deadline {
+            if let Ok(mut out) = output.lock() {
+                out.clear_buffer();
+            }
+            clock.reset_clear_buffer();
+            clock.suppress_underrun();
+            return;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+// Measures how much of its time the decode thread spends working, as opposed to waiting
+// for the output to make room
+struct LoadMeter {
+    window_start: Instant,
+    idle: Duration,
+}
+
+impl LoadMeter {
+    fn new() -> Self {
+        Self {
+            window_start: Instant::now(),
+            idle: Duration::ZERO,
+        }
+    }
+
+    fn add_idle(&mut self, idle: Duration) {
+        self.idle += idle;
+    }
+
+    // Publishes the load of the window so far once it spans `DECODE_LOAD_WINDOW`
+    fn publish(&mut self, clock: &Clock) {
+        let elapsed = self.window_start.elapsed();
+        if elapsed < DECODE_LOAD_WINDOW {
+            return;
+        }
+        let busy = elapsed.saturating_sub(self.idle);
+        clock.set_decode_load((busy.as_secs_f64() / elapsed.as_secs_f64()) as f32);
+        *self = Self::new();
+    }
+}
+
+// Best effort: an unprivileged process is often not allowed to raise its priority
+fn raise_thread_priority(priority: u8) {
+    if let Ok(value) = ThreadPriorityValue::try_from(priority.min(99)) {
+        let _ = set_current_thread_priority(ThreadPriority::Crossplatform(value));
+    }
+}
+
+// Group delay of the resampler, in interleaved output samples. The output's own rate
+// conversion drops its delay, so it doesn't count
+fn resampler_latency(resampler: &Option<Resampler>, output_channels: u32) -> u64 {
+    resampler.as_ref().map_or(0, |r| r.output_delay()) as u64 * output_channels as u64
+}
+
+fn build_resampler(
+    decoder_rate: u32,
+    output_rate: u32,
+    channels: usize,
+    chunk_size: usize,
+) -> Result<Option<Resampler>, Box<dyn std::error::Error>> {
+    if decoder_rate != output_rate {
+        Ok(Some(Resampler::new(decoder_rate, output_rate, channels, chunk_size)?))
+    } else {
+        Ok(None)
+    }
+}
+
+// First resampling stage, from the decoder to the processing rate. Varispeed needs the
+// sinc resampler even when the rates match, since only it can change its ratio
+fn build_input_resampler(
+    decoder_rate: u32,
+    processing_rate: u32,
+    channels: usize,
+    speed: f32,
+    speed_affects_pitch: bool,
+    chunk_size: usize,
+) -> Result<Option<Resampler>, Box<dyn std::error::Error>> {
+    if !speed_affects_pitch || speed == 1.0 {
+        return build_resampler(decoder_rate, processing_rate, channels, chunk_size);
+    }
+    let mut resampler =
+        Resampler::with_kind(decoder_rate, processing_rate, channels, chunk_size, ResamplerKind::Sinc)?;
+    let _ = resampler.set_ratio(1.0 / speed);
+    Ok(Some(resampler))
+}
+
+// A resampler the decode thread can't rebuild for a new format or speed leaves nothing
+// it could play correctly, so the stream ends there, counted as a fatal error
+fn end_failed_stream(clock: &Clock, is_decoding: &AtomicBool) {
+    clock.record_fatal_error();
+    clock.set_decode_load(0.0);
+    clock.set_eos(true);
+    is_decoding.store(false, Ordering::SeqCst);
+}
+
+// Whatever the resampler and the time stretch still hold, mapped to the output's channels.
+// At the end of a stream the resampler's last chunk keeps the silence it's padded out
+// with, which carries what the output's own converter holds out after it. Mid-stream
+// that silence would be a gap
+fn flush_conversion(
+    resampler: &mut Option<Resampler>,
+    stretch: &mut Option<TimeStretch>,
+    mapper: &ChannelMapper,
+    end_of_stream: bool,
+) -> Vec<f32> {
+    let mut tail = match resampler {
+        Some(r) if end_of_stream => r.flush().unwrap_or_default(),
+        Some(r) => r.flush_partial().unwrap_or_default(),
+        None => Vec::new(),
+    };
+    if let Some(s) = stretch {
+        tail = s.process(&tail);
+        tail.extend(s.flush());
+    }
+    if tail.is_empty() {
+        return tail;
+    }
+    mapper.process(&tail)
+}
+
+fn build_time_stretch(
+    processing_rate: u32,
+    channels: usize,
+    speed: f32,
+    speed_affects_pitch: bool,
+) -> Option<TimeStretch> {
+    (!speed_affects_pitch && speed != 1.0).then(|| TimeStretch::new(processing_rate, channels, speed))
+}
+
+fn buffer_frames(duration_ms: u32, sample_rate: u32) -> usize {
+    let ms = duration_ms.clamp(MIN_BUFFER_DURATION_MS, MAX_BUFFER_DURATION_MS);
+    (ms as u64 * sample_rate.max(1) as u64 / 1000) as usize
+}
+
+// Playback's bass and normalization parameters. The decode thread holds a clone, so the
+// chains it builds start from whatever the setters last stored
+#[derive(Clone)]
+struct DspParams {
+    bass_boost_enabled: Arc<AtomicBool>,
+    bass_auto_headroom: Arc<AtomicBool>,
+    bass_rumble_order: Arc<AtomicUsize>,
+    bass_boost_intensity: Arc<AtomicF32>,
+    bass_limiter_coupling: Arc<AtomicF32>,
+    bass_toggle_ramp_ms: Arc<AtomicF32>,
+    // Track normalization in dB, applied ahead of the DSP chain
+    normalization_gain_db: Arc<AtomicF32>,
+}
+
+// Sets a chain up the way playback's is: bass, normalization when `normalize` is set,
+// custom nodes (reusing the `carried` instances where they match) and the settings
+fn configure_dsp(
+    dsp: &mut DspChain,
+    params: &DspParams,
+    normalize: bool,
+    settings: &DspSettings,
+    nodes: &[(u32, NodeFactory)],
+    carried: Vec<(u32, Box<dyn DspNode + Send>)>,
+) {
+    dsp.bass
+        .set_enabled(params.bass_boost_enabled.load(Ordering::SeqCst));
+    dsp.bass
+        .set_auto_headroom(params.bass_auto_headroom.load(Ordering::SeqCst));
+    dsp.bass
+        .set_rumble_order(params.bass_rumble_order.load(Ordering::SeqCst));
+    dsp.bass.set_intensity(params.bass_boost_intensity.load());
+    dsp.bass.set_limiter_coupling(params.bass_limiter_coupling.load());
+    dsp.bass.set_toggle_ramp_ms(params.bass_toggle_ramp_ms.load());
+    if normalize {
+        dsp.set_input_gain_db(params.normalization_gain_db.load());
+    }
+    dsp.sync_custom_nodes(nodes, carried);
+    dsp.apply_settings(settings);
+}
+
+// An f32 shared with the decode thread. Unlike a mutex it can't be poisoned by a
+// panicking thread, so updates keep landing after one.
+struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    fn new(value: f32) -> Self {
+        Self(AtomicU32::new(value.to_bits()))
+    }
+
+    fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::SeqCst))
+    }
+
+    fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::SeqCst);
+    }
+}
+
+// Runs the DSP over `pending` in blocks of `block_len` samples, appending them to `outgoing`
+// and updating the meters after each. Whatever is short of a block stays in `pending`
+// unless `flush` is set
+fn process_blocks(
+    dsp: &mut DspChain,
+    clock: &Clock,
+    pending: &mut Vec<f32>,
+    outgoing: &mut Vec<f32>,
+    block_len: usize,
+    flush: bool,
+) {
+    let mut start = 0;
+    while pending.len() - start >= block_len || (flush && start < pending.len()) {
+        let end = pending.len().min(start.saturating_add(block_len));
+        let from = outgoing.len();
+        outgoing.extend_from_slice(&pending[start..end]);
+        dsp.process(&mut outgoing[from..]);
+        clock.set_limiter_reduction_db(dsp.limiter_reduction_db());
+        clock.set_phase_inverted(dsp.phase_inversion_detected());
+        start = end;
+    }
+    pending.drain(..start);
+}
+
+// Sets the source time to where decoding has got to, less the `unpushed` samples still
+// waiting for a DSP block or for room in the buffer
+fn publish_source_time(
+    clock: &Clock,
+    decoded_until: Option<f64>,
+    unpushed: usize,
+    speed: f32,
+    channels: u32,
+    sample_rate: u32,
+) {
+    if let Some(secs) = decoded_until {
+        let unpushed_secs = unpushed as f64 * speed as f64 / (channels.max(1) as f64 * sample_rate.max(1) as f64);
+        clock.set_source_time(secs - unpushed_secs);
+    }
+}
+
+impl Drop for AudioEngine {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::output::null_backend::NullBackend;
+    use crate::test_util::mock_output::{MockOutput, PlayedSamples};
+    use crate::test_util::signal_generator::{Signal, SignalGenerator};
+
+    const TONE: Signal = Signal::Sine { frequency: 1000.0, amplitude: 0.5 };
+
+    // Half a second of tone at 44.1 kHz followed by half a second at 48 kHz, like a stream
+    // whose format changes partway
+    struct RateSwitch {
+        first: SignalGenerator,
+        second: SignalGenerator,
+        switched: bool,
+    }
+
+    impl RateSwitch {
+        fn new() -> Self {
+            Self {
+                first: SignalGenerator::new(TONE, 44100, 2, 0.5),
+                second: SignalGenerator::new(TONE, 48000, 2, 0.5),
+                switched: false,
+            }
+        }
+    }
+
+    impl AudioDecoder for RateSwitch {
+        fn decode_next(&mut self) -> Option<Vec<f32>> {
+            if !self.switched {
+                if let Some(block) = self.first.decode_next() {
+                    return Some(block);
+                }
+                self.switched = true;
+            }
+            self.second.decode_next()
+        }
+
+        fn sample_rate(&self) -> u32 {
+            if self.switched { 48000 } else { 44100 }
+        }
+
+        fn channels(&self) -> u32 {
+            2
+        }
+
+        fn seek(&mut self, _time_secs: f64) {}
+
+        fn duration(&self) -> Option<f64> {
+            Some(1.0)
+        }
+
+        fn metadata(&self) -> Option<AudioMetadata> {
+            None
+        }
+    }
+
+    // An engine playing into a `MockOutput` at `sample_rate` with `channels` channels
+    fn mock_engine(sample_rate: u32, channels: u32) -> (AudioEngine, PlayedSamples) {
+        mock_engine_with_config(EngineConfig::default(), sample_rate, channels)
+    }
+
+    fn mock_engine_with_config(
+        config: EngineConfig,
+        sample_rate: u32,
+        channels: u32,
+    ) -> (AudioEngine, PlayedSamples) {
+        let mut played = None;
+        let engine = AudioEngine::with_config_and_output(config, |consumer, clock| {
+            let output = MockOutput::new(consumer, clock, sample_rate, channels);
+            played = Some(output.played());
+            Box::new(output)
+        })
+        .unwrap();
+        (engine, played.unwrap())
+    }
+
+    // Half a second of stereo audio in packets of a fixed size, like a codec's frames
+    struct Packets {
+        frames: usize,
+        remaining: usize,
+    }
+
+    impl AudioDecoder for Packets {
+        fn decode_next(&mut self) -> Option<Vec<f32>> {
+            let frames = self.frames.min(self.remaining);
+            self.remaining -= frames;
+            (frames > 0).then(|| vec![0.1; frames * 2])
+        }
+
+        fn sample_rate(&self) -> u32 {
+            44100
+        }
+
+        fn channels(&self) -> u32 {
+            2
+        }
+
+        fn seek(&mut self, _time_secs: f64) {}
+
+        fn duration(&self) -> Option<f64> {
+            Some(0.5)
+        }
+
+        fn metadata(&self) -> Option<AudioMetadata> {
+            None
+        }
+    }
+
+    // Notes the length of every block the DSP chain hands it
+    struct BlockSizes(Arc<Mutex<Vec<usize>>>);
+
+    impl DspNode for BlockSizes {
+        fn process(&mut self, samples: &mut [f32], _channels: usize, _sample_rate: f32) {
+            self.0.lock().unwrap().push(samples.len());
+        }
+    }
+
+    // Multiplies every sample by its gain
+    struct Gain(f32);
+
+    impl DspNode for Gain {
+        fn process(&mut self, samples: &mut [f32], _channels: usize, _sample_rate: f32) {
+            samples.iter_mut().for_each(|s| *s *= self.0);
+        }
+    }
+
+    // A long tone that, while `starved` is set, only trickles out a few milliseconds of
+    // audio every 20 ms, like a network stream that can't keep up
+    struct Trickle {
+        generator: SignalGenerator,
+        starved: Arc<AtomicBool>,
+    }
+
+    impl AudioDecoder for Trickle {
+        fn decode_next(&mut self) -> Option<Vec<f32>> {
+            if self.starved.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(20));
+                return Some(vec![0.0; 128]);
+            }
+            self.generator.decode_next()
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.generator.sample_rate()
+        }
+
+        fn channels(&self) -> u32 {
+            self.generator.channels()
+        }
+
+        fn seek(&mut self, time_secs: f64) {
+            self.generator.seek(time_secs);
+        }
+
+        fn duration(&self) -> Option<f64> {
+            self.generator.duration()
+        }
+
+        fn metadata(&self) -> Option<AudioMetadata> {
+            None
+        }
+    }
+
+    // Stereo blocks with a stray sample on the end, from a decoder that reports no channel
+    // count on every other block. The right channel is half the left
+    struct Ragged {
+        blocks: usize,
+        frame: usize,
+    }
+
+    impl AudioDecoder for Ragged {
+        fn decode_next(&mut self) -> Option<Vec<f32>> {
+            self.blocks = self.blocks.checked_sub(1)?;
+            let mut samples = Vec::new();
+            for _ in 0..1000 {
+                let left = 0.5 * (self.frame as f32 * 0.05).sin();
+                samples.extend([left, 0.5 * left]);
+                self.frame += 1;
+            }
+            samples.push(0.9);
+            Some(samples)
+        }
+
+        fn sample_rate(&self) -> u32 {
+            44100
+        }
+
+        fn channels(&self) -> u32 {
+            if self.blocks.is_multiple_of(2) { 2 } else { 0 }
+        }
+
+        fn seek(&mut self, _time_secs: f64) {}
+
+        fn duration(&self) -> Option<f64> {
+            None
+        }
+
+        fn metadata(&self) -> Option<AudioMetadata> {
+            None
+        }
+    }
+
+    // A tone with an empty block, like a codec's priming packet, before each real one
+    struct Gappy {
+        generator: SignalGenerator,
+        blocks: usize,
+    }
+
+    impl AudioDecoder for Gappy {
+        fn decode_next(&mut self) -> Option<Vec<f32>> {
+            self.blocks += 1;
+            if !self.blocks.is_multiple_of(2) {
+                return Some(Vec::new());
+            }
+            self.generator.decode_next()
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.generator.sample_rate()
+        }
+
+        fn channels(&self) -> u32 {
+            self.generator.channels()
+        }
+
+        fn seek(&mut self, time_secs: f64) {
+            self.generator.seek(time_secs);
+        }
+
+        fn duration(&self) -> Option<f64> {
+            self.generator.duration()
+        }
+
+        fn metadata(&self) -> Option<AudioMetadata> {
+            None
+        }
+    }
+
+    // A decoder whose first block panics, taking the decode thread down with it
+    struct Panics;
+
+    impl AudioDecoder for Panics {
+        fn decode_next(&mut self) -> Option<Vec<f32>> {
+            panic!("decoder panicked");
+        }
+
+        fn sample_rate(&self) -> u32 {
+            44100
+        }
+
+        fn channels(&self) -> u32 {
+            2
+        }
+
+        fn seek(&mut self, _time_secs: f64) {}
+
+        fn duration(&self) -> Option<f64> {
+            None
+        }
+
+        fn metadata(&self) -> Option<AudioMetadata> {
+            None
+        }
+    }
+
+    // A radio-like stream that announces a new title on its 5th and 15th blocks
+    struct Retitled {
+        generator: SignalGenerator,
+        blocks: usize,
+        update: Option<AudioMetadata>,
+    }
+
+    impl AudioDecoder for Retitled {
+        fn decode_next(&mut self) -> Option<Vec<f32>> {
+            self.blocks += 1;
+            let title = match self.blocks {
+                5 => "First",
+                15 => "Second",
+                _ => return self.generator.decode_next(),
+            };
+            self.update = Some(AudioMetadata { title: Some(title.to_string()), ..Default::default() });
+            self.generator.decode_next()
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.generator.sample_rate()
+        }
+
+        fn channels(&self) -> u32 {
+            self.generator.channels()
+        }
+
+        fn seek(&mut self, _time_secs: f64) {}
+
+        fn duration(&self) -> Option<f64> {
+            None
+        }
+
+        fn metadata(&self) -> Option<AudioMetadata> {
+            Some(AudioMetadata::default())
+        }
+
+        fn take_metadata_update(&mut self) -> Option<AudioMetadata> {
+            self.update.take()
+        }
+    }
+
+    // A long tone that notes when each block was asked for and how many samples it had
+    struct CallTimes {
+        generator: SignalGenerator,
+        calls: Arc<Mutex<Vec<(Instant, usize)>>>,
+    }
+
+    impl AudioDecoder for CallTimes {
+        fn decode_next(&mut self) -> Option<Vec<f32>> {
+            let block = self.generator.decode_next();
+            let len = block.as_ref().map_or(0, Vec::len);
+            self.calls.lock().unwrap().push((Instant::now(), len));
+            block
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.generator.sample_rate()
+        }
+
+        fn channels(&self) -> u32 {
+            self.generator.channels()
+        }
+
+        fn seek(&mut self, time_secs: f64) {
+            self.generator.seek(time_secs);
+        }
+
+        fn duration(&self) -> Option<f64> {
+            self.generator.duration()
+        }
+
+        fn metadata(&self) -> Option<AudioMetadata> {
+            None
+        }
+    }
+
+    // A tone that takes `delay` to decode each block, like a CPU-bound codec
+    struct Slow {
+        generator: SignalGenerator,
+        delay: Duration,
+    }
+
+    impl AudioDecoder for Slow {
+        fn decode_next(&mut self) -> Option<Vec<f32>> {
+            thread::sleep(self.delay);
+            self.generator.decode_next()
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.generator.sample_rate()
+        }
+
+        fn channels(&self) -> u32 {
+            self.generator.channels()
+        }
+
+        fn seek(&mut self, time_secs: f64) {
+            self.generator.seek(time_secs);
+        }
+
+        fn duration(&self) -> Option<f64> {
+            self.generator.duration()
+        }
+
+        fn metadata(&self) -> Option<AudioMetadata> {
+            None
+        }
+    }
+
+    // Whether `expected` arrives within `timeout`, skipping any other events
+    fn wait_for_event(events: &Receiver<EngineEvent>, expected: EngineEvent, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            match events.recv_timeout(left) {
+                Ok(event) if event == expected => return true,
+                Ok(_) => {}
+                Err(_) => return false,
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn chapter_navigation_at_boundaries() {
+        let chapters: Vec<Chapter> = [0.0, 60.0, 120.0]
+            .into_iter()
+            .map(|start_secs| Chapter { title: None, start_secs })
+            .collect();
+
+        // Just before a boundary "next" lands on it, and sitting on it moves past it
+        assert_eq!(next_chapter_index(&chapters, 59.99), Some(1));
+        assert_eq!(next_chapter_index(&chapters, 60.0), Some(2));
+        assert_eq!(next_chapter_index(&chapters, 120.0), None);
+
+        // "Previous" restarts the chapter unless it only just started
+        assert_eq!(previous_chapter_index(&chapters, 90.0), Some(1));
+        assert_eq!(previous_chapter_index(&chapters, 61.0), Some(0));
+        assert_eq!(previous_chapter_index(&chapters, 1.0), Some(0));
+    }
+
+    #[test]
+    fn log_taper_spans_silence_to_unity() {
+        let taper = VolumeTaper::Logarithmic;
+        assert_eq!(taper.gain(0.0), 0.0);
+        assert_eq!(taper.gain(1.0), 1.0);
+        let half_db = 20.0 * taper.gain(0.5).log10();
+        assert!((half_db + 30.0).abs() < 0.01, "{half_db}");
+
+        assert_eq!(VolumeTaper::Linear.gain(0.5), 0.5);
+    }
+
+    #[test]
+    fn dsp_blocks_keep_their_size_whatever_the_packet_size() {
+        // Packet sizes of MP3 and of a typical FLAC stream
+        for packet_frames in [1152, 4096] {
+            let config = EngineConfig { dsp_block_frames: Some(512), ..EngineConfig::default() };
+            let (mut engine, _played) = mock_engine_with_config(config, 44100, 2);
+            let sizes = Arc::new(Mutex::new(Vec::new()));
+            let node_sizes = sizes.clone();
+            engine.add_dsp_node(move || Box::new(BlockSizes(node_sizes.clone())));
+            engine.load_decoder(Packets { frames: packet_frames, remaining: 22050 }).unwrap();
+            engine.play().unwrap();
+            engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+
+            // All but the tail at the end are whole blocks
+            let sizes = sizes.lock().unwrap();
+            let (tail, blocks) = sizes.split_last().unwrap();
+            assert!(blocks.iter().all(|&len| len == 1024), "{packet_frames}: {sizes:?}");
+            assert_eq!(blocks.len() * 1024 + tail, 44100);
+        }
+    }
+
+    #[test]
+    fn a_track_shorter_than_a_dsp_block_still_meters_the_limiter() {
+        // The whole track is the short last block
+        let config = EngineConfig { dsp_block_frames: Some(44100), ..EngineConfig::default() };
+        let (mut engine, _played) = mock_engine_with_config(config, 44100, 2);
+        // Well over full scale into the limiter
+        engine.add_dsp_node(|| Box::new(Gain(10.0)));
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 0.5)).unwrap();
+        engine.play().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while engine.limiter_reduction_db() > -1.0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(engine.limiter_reduction_db() < -1.0, "{}", engine.limiter_reduction_db());
+    }
+
+    #[test]
+    fn each_metadata_revision_fires_one_event() {
+        let (mut engine, _played) = mock_engine(44100, 2);
+        // Decoding starts on load, so listen from before it
+        let events = engine.subscribe();
+        let generator = SignalGenerator::new(TONE, 44100, 2, 1.0);
+        engine.load_decoder(Retitled { generator, blocks: 0, update: None }).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+
+        let titles: Vec<_> = events
+            .try_iter()
+            .filter_map(|event| match event {
+                EngineEvent::MetadataChanged(metadata) => Some(metadata.title),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(titles, [Some("First".to_string()), Some("Second".to_string())]);
+        assert_eq!(engine.get_metadata().unwrap().title.as_deref(), Some("Second"));
+    }
+
+    #[test]
+    fn starved_buffer_reports_buffering_until_it_recovers() {
+        let config = EngineConfig { buffer_duration_ms: 300, ..EngineConfig::default() };
+        let (mut engine, _played) = mock_engine_with_config(config, 44100, 2);
+        let starved = Arc::new(AtomicBool::new(false));
+        let generator = SignalGenerator::new(TONE, 44100, 2, 30.0);
+        engine.load_decoder(Trickle { generator, starved: starved.clone() }).unwrap();
+        let events = engine.subscribe();
+        engine.play().unwrap();
+
+        // Let the first fill settle before starving it
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while engine.buffer_fill() < 0.5 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        thread::sleep(Duration::from_millis(300));
+        while events.try_recv().is_ok() {}
+
+        starved.store(true, Ordering::SeqCst);
+        assert!(wait_for_event(&events, EngineEvent::Buffering, Duration::from_secs(2)));
+        assert_eq!(engine.get_state(), PlaybackState::Buffering);
+        assert!(engine.buffer_fill() < 0.1, "fill {}", engine.buffer_fill());
+
+        // Only back up to the recovery mark ends it, not the low-water mark
+        starved.store(false, Ordering::SeqCst);
+        assert!(wait_for_event(&events, EngineEvent::Ready, Duration::from_secs(2)));
+        assert_eq!(engine.get_state(), PlaybackState::Playing);
+        assert!(engine.buffer_fill() >= 0.5, "fill {}", engine.buffer_fill());
     }
 
-    pub fn get_metadata(&self) -> Option<&AudioMetadata> {
-        self.current_metadata.as_ref()
+    // The decode load once playback of `decoder` has settled past the first fill
+    fn settled_decode_load(decoder: impl AudioDecoder + Send + 'static) -> f32 {
+        let (mut engine, _played) = mock_engine(44100, 2);
+        engine.load_decoder(decoder).unwrap();
+        engine.play().unwrap();
+        thread::sleep(Duration::from_millis(2000));
+        engine.decode_load()
     }
-}
 
-impl Drop for AudioEngine {
-    fn drop(&mut self) {
-        self.stop();
+    #[test]
+    fn decode_load_tracks_how_hard_decoding_works() {
+        let fast = SignalGenerator::new(TONE, 44100, 2, 30.0);
+        let load = settled_decode_load(fast);
+        assert!(load < 0.3, "fast source at {load}");
+
+        // 20 ms per 1024 frame block is most of the 23 ms each block plays for
+        let generator = SignalGenerator::new(TONE, 44100, 2, 30.0);
+        let load = settled_decode_load(Slow { generator, delay: Duration::from_millis(20) });
+        assert!(load > 0.7, "slow source at {load}");
+    }
+
+    #[test]
+    fn sustained_underrun_pauses_until_the_buffer_refills() {
+        let config = EngineConfig {
+            buffer_duration_ms: 300,
+            underrun_auto_pause_secs: Some(0.2),
+            ..EngineConfig::default()
+        };
+        let (mut engine, _played) = mock_engine_with_config(config, 44100, 2);
+        let starved = Arc::new(AtomicBool::new(false));
+        let generator = SignalGenerator::new(TONE, 44100, 2, 30.0);
+        engine.load_decoder(Trickle { generator, starved: starved.clone() }).unwrap();
+        let events = engine.subscribe();
+        engine.play().unwrap();
+        thread::sleep(Duration::from_millis(500));
+        while events.try_recv().is_ok() {}
+
+        // The stream stalls: buffering is reported, then playback holds its position
+        starved.store(true, Ordering::SeqCst);
+        assert!(wait_for_event(&events, EngineEvent::Buffering, Duration::from_secs(2)));
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !engine.clock.is_auto_paused() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(engine.clock.is_auto_paused());
+        let held = engine.get_time_secs();
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(engine.get_time_secs(), held);
+        assert_eq!(engine.get_state(), PlaybackState::Buffering);
+
+        // Once it's back and the buffer has refilled, playback carries on from there
+        starved.store(false, Ordering::SeqCst);
+        assert!(wait_for_event(&events, EngineEvent::Ready, Duration::from_secs(2)));
+        assert!(!engine.clock.is_auto_paused());
+        thread::sleep(Duration::from_millis(300));
+        assert!(engine.get_time_secs() > held + 0.1, "{} after {held}", engine.get_time_secs());
+    }
+
+    #[test]
+    fn corrupt_packets_are_counted_while_playback_continues() {
+        use crate::test_util::ogg_flac::{flac_frame, flac_header, ogg_page};
+
+        // Four good 4096-frame packets with three corrupt ones among them
+        let packets = [false, true, false, true, true, false, false];
+        let mut file = ogg_page(1, 0, 0x02, 0, &flac_header(44100, 2));
+        for (i, &corrupt) in packets.iter().enumerate() {
+            let flags = if i == packets.len() - 1 { 0x04 } else { 0 };
+            let granule = (i as u64 + 1) * 4096;
+            file.extend(ogg_page(1, i as u32 + 1, flags, granule, &flac_frame(i as u8, 8192, corrupt)));
+        }
+        let path = std::env::temp_dir().join(format!("mewo-errors-{}.ogg", std::process::id()));
+        std::fs::write(&path, file).unwrap();
+
+        let config = EngineConfig { tolerant_decoding: true, ..EngineConfig::default() };
+        let (mut engine, played) = mock_engine_with_config(config, 44100, 2);
+        let loaded = engine.load(&path);
+        let _ = std::fs::remove_file(&path);
+        loaded.unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+
+        assert_eq!(engine.decode_error_count(), 3);
+        assert_eq!(engine.fatal_error_count(), 0);
+        assert_eq!(played.lock().unwrap().len(), 4 * 4096 * 2);
+    }
+
+    #[test]
+    fn decoding_refills_in_bursts_between_the_water_marks() {
+        let (mut engine, _played) = mock_engine(44100, 2);
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let generator = SignalGenerator::new(TONE, 44100, 2, 30.0);
+        engine.load_decoder(CallTimes { generator, calls: calls.clone() }).unwrap();
+        engine.play().unwrap();
+        thread::sleep(Duration::from_millis(500));
+
+        // Over two seconds the buffer stays in the band between the marks, with a margin
+        // for a loaded machine...
+        let from = Instant::now();
+        while from.elapsed() < Duration::from_secs(2) {
+            let fill = engine.buffer_fill();
+            assert!(fill > 0.4, "fill {fill}");
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        // ...while decoding pauses about every 300 ms, the time it takes to drain 90% to
+        // 60%, rather than topping up every 10 ms callback. Only gaps far longer than a
+        // stalled thread would leave count as pauses
+        let times: Vec<Instant> = calls.lock().unwrap().iter().map(|&(at, _)| at).collect();
+        let window: Vec<_> = times.into_iter().filter(|&at| at >= from).collect();
+        let pauses = window.windows(2).filter(|pair| pair[1] - pair[0] > Duration::from_millis(100)).count();
+        assert!((2..=12).contains(&pauses), "{pauses} pauses");
+    }
+
+    #[test]
+    fn preset_round_trip_keeps_every_parameter() {
+        use crate::engine::dsp::channel_gains::ChannelGainsSettings;
+        use crate::engine::dsp::crossfeed::CrossfeedSettings;
+        use crate::engine::dsp::de_esser::DeEsserSettings;
+        use crate::engine::dsp::limiter::LimiterSettings;
+        use crate::engine::dsp::lfo_mod::LfoModSettings;
+        use crate::engine::dsp::parametric_eq::EqBandShape;
+        use crate::engine::dsp::phaser::PhaserSettings;
+
+        // Every node moved off its defaults
+        let mut settings = DspSettings::default();
+        settings.dc_blocker.enabled = false;
+        settings.phase_correction.auto = true;
+        settings.phase_correction.inverted = vec![false, true];
+        settings.eq = EqPreset::Vocal.settings();
+        settings.eq.bands.push(EqBand { shape: EqBandShape::HighShelf, frequency: 9000.0, gain_db: -4.5, q: 0.8 });
+        settings.de_esser = DeEsserSettings { enabled: true, frequency: 7000.0, threshold_db: -24.0, ratio: 6.0 };
+        settings.crossfeed = CrossfeedSettings { enabled: true, amount: 0.45, cutoff: 650.0 };
+        settings.phaser = PhaserSettings { enabled: true, rate_hz: 1.5, depth: 0.6, feedback: -0.4, stages: 6, mix: 0.3 };
+        settings.lfo_mod = LfoModSettings {
+            enabled: true,
+            target: LfoTarget::Pan,
+            waveform: LfoWaveform::Triangle,
+            rate_hz: 2.5,
+            depth: 0.7,
+            stereo_phase_offset: 0.25,
+        };
+        settings.channel_gains = ChannelGainsSettings { gains_db: vec![-1.5, 0.0], balance: 0.2 };
+        settings.routing = ChannelRouting::new().route(1, 0, 1.0).route(0, 1, 1.0);
+        settings.limiter = LimiterSettings { true_peak: true };
+        settings.order = Some(vec![NodeId::Limiter, NodeId::Eq, NodeId::Bass]);
+        settings.disabled = vec![NodeId::Phaser];
+        let preset = DspPreset { bass_boost: true, bass_intensity: 72.5, settings };
+
+        // Applied to a playing engine and read back
+        let (mut engine, _played) = mock_engine(44100, 2);
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 0.5)).unwrap();
+        engine.play().unwrap();
+        engine.apply_preset(&preset);
+        let exported = engine.export_preset();
+        assert_eq!(exported, preset);
+
+        // ...and carried over to another engine
+        let (mut other, _played) = mock_engine(44100, 2);
+        other.apply_preset(&exported);
+        assert_eq!(other.export_preset(), preset);
+
+        #[cfg(feature = "serde")]
+        assert_eq!(DspPreset::from_json(&preset.to_json().unwrap()).unwrap(), preset);
+    }
+
+    #[test]
+    fn partial_frames_are_dropped_without_shifting_channels() {
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.load_decoder(Ragged { blocks: 20, frame: 0 }).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+
+        let played = played.lock().unwrap();
+        assert_eq!(played.len(), 20 * 1000 * 2);
+        for frame in played.chunks_exact(2) {
+            assert!((frame[1] - 0.5 * frame[0]).abs() < 1e-3, "{frame:?}");
+        }
+    }
+
+    #[test]
+    fn decoding_at_a_raised_priority_still_plays() {
+        let config = EngineConfig { decode_thread_priority: Some(90), ..EngineConfig::default() };
+        let (mut engine, played) = mock_engine_with_config(config, 44100, 2);
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 0.5)).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(played.lock().unwrap().len(), 44100);
+    }
+
+    #[test]
+    fn graceful_stop_plays_out_the_buffer() {
+        let (mut engine, played) = mock_engine(44100, 2);
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let generator = SignalGenerator::new(TONE, 44100, 2, 30.0);
+        engine.load_decoder(CallTimes { generator, calls: calls.clone() }).unwrap();
+        engine.play().unwrap();
+        thread::sleep(Duration::from_millis(300));
+        assert!(engine.buffer_fill() > 0.3);
+
+        // Everything decoded by the time decoding stops is heard, and only then does the
+        // position go back to the start
+        engine.stop_gracefully();
+        let decoded: usize = calls.lock().unwrap().iter().map(|&(_, len)| len).sum();
+        assert_eq!(played.lock().unwrap().len(), decoded);
+        assert_eq!(engine.get_time_secs(), 0.0);
+    }
+
+    #[test]
+    fn forced_processing_rate_keeps_the_duration() {
+        // 44.1 kHz source, processed at 48 kHz, played on a 44.1 kHz device
+        let config = EngineConfig { processing_sample_rate: Some(48000), ..EngineConfig::default() };
+        let (mut engine, played) = mock_engine_with_config(config, 44100, 2);
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 1.0)).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+
+        // The resamplers' delay plays as a lead-in the clock counts as latency, so it's the
+        // tone itself that has to last a second
+        let played = played.lock().unwrap();
+        let first = played.iter().position(|s| s.abs() > 0.01).unwrap() / 2;
+        let last = played.iter().rposition(|s| s.abs() > 0.01).unwrap() / 2;
+        assert!((last - first).abs_diff(44100) < 441, "{first}..{last}");
+        let frequency = tone_frequency(&played, 44100);
+        assert!((frequency - 1000.0).abs() < 10.0, "{frequency} Hz");
+    }
+
+    #[test]
+    fn rapid_pause_toggles_lose_nothing() {
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 1.0)).unwrap();
+        engine.play().unwrap();
+
+        // The position holds still while paused and never goes back
+        let mut last = 0.0;
+        for _ in 0..20 {
+            engine.pause().unwrap();
+            let paused_at = engine.get_time_secs();
+            assert!(paused_at >= last, "{paused_at} after {last}");
+            thread::sleep(Duration::from_millis(5));
+            assert_eq!(engine.get_time_secs(), paused_at);
+            last = paused_at;
+            engine.play().unwrap();
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // Every sample plays exactly once
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(played.lock().unwrap().len(), 88200);
+    }
+
+    #[test]
+    fn odd_resampler_chunk_is_rounded_and_keeps_the_duration() {
+        for (requested, effective) in [(1000, 1024), (5000, 8192), (10, 64), (512, 512)] {
+            let config = EngineConfig { resampler_chunk_frames: requested, ..EngineConfig::default() };
+            assert_eq!(config.effective_resampler_chunk_frames(), effective);
+        }
+
+        // A 48 kHz source on a 44.1 kHz device, through resamplers built from a 1000 frame request
+        let config = EngineConfig { resampler_chunk_frames: 1000, ..EngineConfig::default() };
+        let (mut engine, played) = mock_engine_with_config(config, 44100, 2);
+        assert_eq!(engine.resampler_chunk_frames(), 1024);
+        engine.load_decoder(SignalGenerator::new(TONE, 48000, 2, 1.0)).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+
+        let played = played.lock().unwrap();
+        let first = played.iter().position(|s| s.abs() > 0.01).unwrap() / 2;
+        let last = played.iter().rposition(|s| s.abs() > 0.01).unwrap() / 2;
+        assert!((last - first).abs_diff(44100) < 441, "{first}..{last}");
+        let frequency = tone_frequency(&played, 44100);
+        assert!((frequency - 1000.0).abs() < 10.0, "{frequency} Hz");
+    }
+
+    #[test]
+    fn latency_is_the_resamplers_delay() {
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.load_decoder(SignalGenerator::new(Signal::Impulse, 48000, 2, 0.5)).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+
+        let resampler = Resampler::new(48000, 44100, 2, EngineConfig::default().resampler_chunk_frames).unwrap();
+        let latency_frames = engine.clock.get_latency_samples() / 2;
+        assert!(latency_frames > 0);
+        assert_eq!(latency_frames, resampler.output_delay() as u64);
+
+        // ...and it's where the impulse is heard
+        let played = played.lock().unwrap();
+        let peak = (0..played.len() / 2).max_by(|&a, &b| played[a * 2].abs().total_cmp(&played[b * 2].abs())).unwrap();
+        assert!((peak as u64).abs_diff(latency_frames) <= 1, "peak at {peak}, latency {latency_frames}");
+    }
+
+    #[test]
+    fn pushed_samples_play_back_through_the_output() {
+        // What `new_streaming` sets up, on a mock output
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.streaming = Some(engine.build_streaming_input(44100, 2).unwrap());
+        engine.seekable = false;
+        engine.source = Some(Source::Streaming);
+
+        let mut generator = SignalGenerator::new(TONE, 44100, 2, 3.0);
+        let mut tone = Vec::new();
+        while let Some(block) = generator.decode_next() {
+            tone.extend(block);
+        }
+
+        // Three seconds don't fit in a one second buffer, so only whole frames up to
+        // its size are taken until it drains
+        let mut pushed = engine.push_samples(&tone);
+        assert!(pushed > 0 && pushed < tone.len(), "{pushed}");
+        assert_eq!(pushed % 2, 0);
+
+        engine.play().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(6);
+        while pushed < tone.len() && Instant::now() < deadline {
+            pushed += engine.push_samples(&tone[pushed..]);
+            thread::sleep(Duration::from_millis(10));
+        }
+        while played.lock().unwrap().len() < tone.len() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // All of it, still the same tone at the same level
+        let played = played.lock().unwrap();
+        assert_eq!(played.len(), tone.len());
+        let frequency = tone_frequency(&played, 44100);
+        assert!((frequency - 1000.0).abs() < 5.0, "{frequency} Hz");
+        let rms = (played.iter().map(|s| s * s).sum::<f32>() / played.len() as f32).sqrt();
+        assert!((rms - 0.5 / 2.0f32.sqrt()).abs() < 0.005, "{rms}");
+    }
+
+    #[test]
+    fn monitored_input_plays_through_the_output() {
+        // A mono input at 48 kHz, looped back from a buffer instead of a device
+        let (mut engine, played) = mock_engine(44100, 2);
+        let (mut input, capture) = create_audio_buffer(24000, 1);
+        engine.monitor_input(None, capture, 48000, 1).unwrap();
+        assert!(engine.is_monitoring());
+
+        let mut generator = SignalGenerator::new(TONE, 48000, 1, 1.0);
+        let mut tone = Vec::new();
+        while let Some(block) = generator.decode_next() {
+            tone.extend(block);
+        }
+        let mut pushed = 0;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pushed < tone.len() && Instant::now() < deadline {
+            pushed += input.push_slice(&tone[pushed..]);
+            thread::sleep(Duration::from_millis(10));
+        }
+        // Most of the second, as the resampler keeps hold of a partial chunk at the end
+        while played.lock().unwrap().len() < 44100 * 3 / 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        engine.stop();
+        assert!(!engine.is_monitoring());
+
+        // Resampled to the output rate and on both sides
+        let played = played.lock().unwrap();
+        let played = &played[..44100 * 3 / 2];
+        let frequency = tone_frequency(played, 44100);
+        assert!((frequency - 1000.0).abs() < 5.0, "{frequency} Hz");
+        let settled = &played[44100 / 2..];
+        assert!(settled.chunks_exact(2).all(|frame| frame[0] == frame[1]));
+        let rms = (settled.iter().map(|s| s * s).sum::<f32>() / settled.len() as f32).sqrt();
+        assert!((rms - 0.5 / 2.0f32.sqrt()).abs() < 0.01, "{rms}");
+    }
+
+    #[test]
+    fn plays_on_without_a_device() {
+        // What the output manager falls back to when no device can be opened
+        let mut played = None;
+        let mut engine = AudioEngine::with_output(|consumer, clock| {
+            let mut output = NullBackend::new(consumer, clock);
+            played = Some(output.record());
+            Box::new(output)
+        })
+        .unwrap();
+        let played = played.unwrap();
+        assert!(!engine.is_output_available());
+
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 2.0)).unwrap();
+        engine.play().unwrap();
+        thread::sleep(Duration::from_millis(500));
+        let position = engine.get_time_secs();
+        assert!(position > 0.3 && position < 0.7, "{position}");
+        thread::sleep(Duration::from_millis(200));
+        assert!(engine.get_time_secs() > position);
+
+        let played = played.lock().unwrap();
+        let frequency = tone_frequency(&played, 44100);
+        assert!((frequency - 1000.0).abs() < 10.0, "{frequency} Hz");
+    }
+
+    #[test]
+    fn bass_intensity_reaches_the_dsp_clamped() {
+        let (mut engine, _played) = mock_engine(44100, 2);
+        // Stands in for the decode thread, keeping what its bass processor would be set to
+        let (tx, rx) = mpsc::channel();
+        engine.command_tx = Some(tx);
+        let mut dsp_side = engine.export_preset().bass_intensity;
+
+        let cases = [
+            (150.0, 100.0),
+            (-5.0, 0.0),
+            (f32::INFINITY, 100.0),
+            (f32::NEG_INFINITY, 0.0),
+            (40.0, 40.0),
+            (f32::NAN, 40.0),
+        ];
+        for (asked, expected) in cases {
+            engine.set_bass_intensity(asked);
+            for cmd in rx.try_iter() {
+                if let DecoderCommand::SetBassIntensity(v) = cmd {
+                    dsp_side = v;
+                }
+            }
+            assert_eq!(dsp_side, expected, "{asked}");
+            assert_eq!(engine.export_preset().bass_intensity, expected, "{asked}");
+        }
+    }
+
+    #[test]
+    fn bass_intensity_survives_a_decode_thread_panic() {
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.set_bass_boost(true);
+        engine.set_bass_intensity(30.0);
+        engine.load_decoder(Panics).unwrap();
+        engine.play().unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        engine.set_bass_intensity(80.0);
+        assert_eq!(engine.export_preset().bass_intensity, 80.0);
+
+        // The next track still plays, boost and all
+        engine.stop();
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 0.5)).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(played.lock().unwrap().len(), 44100);
+        assert_eq!(engine.export_preset().bass_intensity, 80.0);
+    }
+
+    #[test]
+    fn crossfeed_cutoff_is_stored_clamped() {
+        let (mut engine, _played) = mock_engine(44100, 2);
+        engine.set_crossfeed_cutoff(50.0);
+        assert_eq!(engine.export_preset().settings.crossfeed.cutoff, 100.0);
+        engine.set_crossfeed_cutoff(30000.0);
+        assert_eq!(engine.export_preset().settings.crossfeed.cutoff, 44100.0 * 0.45);
+    }
+
+    #[test]
+    fn out_of_range_channel_gain_is_ignored() {
+        let (mut engine, _played) = mock_engine(44100, 2);
+        engine.set_channel_gain(1, -6.0);
+        engine.set_channel_gain(MAX_OUTPUT_CHANNELS, -3.0);
+        engine.set_channel_gain(usize::MAX, -3.0);
+        assert_eq!(engine.channel_gains(), &[0.0, -6.0]);
+    }
+
+    #[test]
+    fn out_of_range_channel_polarity_is_ignored() {
+        let (mut engine, _played) = mock_engine(44100, 2);
+        engine.set_channel_polarity(1, true);
+        engine.set_channel_polarity(usize::MAX, true);
+        assert_eq!(engine.dsp_settings.phase_correction.inverted, [false, true]);
+    }
+
+    #[test]
+    fn non_finite_output_gain_is_ignored() {
+        let (engine, _played) = mock_engine(44100, 2);
+        engine.set_output_gain_db(-6.0);
+        engine.set_output_gain_db(f32::NAN);
+        engine.set_output_gain_db(f32::INFINITY);
+        assert_eq!(engine.output_gain_db(), -6.0);
+    }
+
+    #[test]
+    fn non_finite_volume_is_ignored() {
+        let (engine, _played) = mock_engine(44100, 2);
+        engine.set_volume(0.5);
+        engine.set_volume(f32::NAN);
+        engine.set_volume(f32::NEG_INFINITY);
+        assert_eq!(engine.volume(), 0.5);
+    }
+
+    #[test]
+    fn non_finite_playback_speed_is_ignored() {
+        let (mut engine, _played) = mock_engine(44100, 2);
+        engine.set_playback_speed(2.0);
+        engine.set_playback_speed(f32::NAN);
+        engine.set_playback_speed(f32::INFINITY);
+        assert_eq!(engine.playback_speed(), 2.0);
+    }
+
+    #[test]
+    fn empty_blocks_change_nothing() {
+        // Bass boost on, so a skipped block that still counted would shift its adaptation
+        fn play<D: AudioDecoder + Send + 'static>(decoder: D) -> Vec<f32> {
+            let (mut engine, played) = mock_engine(44100, 2);
+            engine.set_bass_boost(true);
+            engine.load_decoder(decoder).unwrap();
+            engine.play().unwrap();
+            engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+            let played = played.lock().unwrap().clone();
+            played
+        }
+        let bass_tone = Signal::Sine { frequency: 60.0, amplitude: 0.5 };
+        let plain = play(SignalGenerator::new(bass_tone, 44100, 2, 1.0));
+        let generator = SignalGenerator::new(bass_tone, 44100, 2, 1.0);
+        let gappy = play(Gappy { generator, blocks: 0 });
+        assert_eq!(plain.len(), 88200);
+        assert_eq!(gappy, plain);
+    }
+
+    #[test]
+    fn plays_every_sample_of_a_tone() {
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 0.5)).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(played.lock().unwrap().len(), 44100);
+    }
+
+    #[test]
+    fn waiting_returns_once_the_track_has_been_heard() {
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 0.5)).unwrap();
+        assert_eq!(engine.wait_until_finished(None), Err(WaitError::NotPlaying));
+
+        let started = Instant::now();
+        engine.play().unwrap();
+        assert_eq!(engine.wait_until_finished(Some(Duration::from_millis(100))), Err(WaitError::TimedOut));
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+        let waited = started.elapsed();
+        assert_eq!(played.lock().unwrap().len(), 44100);
+        assert!(waited >= Duration::from_millis(450), "{waited:?}");
+        assert!(waited < Duration::from_millis(1200), "{waited:?}");
+    }
+
+    #[test]
+    fn processing_samples_matches_playback() {
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.set_eq_preset(EqPreset::BassBoost);
+        engine.set_crossfeed(true);
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 0.5)).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+
+        let mut samples = Vec::new();
+        let mut generator = SignalGenerator::new(TONE, 44100, 2, 0.5);
+        while let Some(block) = generator.decode_next() {
+            samples.extend(block);
+        }
+        engine.process_samples(&mut samples);
+
+        let played = played.lock().unwrap();
+        assert_eq!(played.len(), samples.len());
+        let worst = played.iter().zip(&samples).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+        assert!(worst < 1e-5, "{worst}");
+    }
+
+    #[test]
+    fn forced_mono_plays_the_same_on_both_sides() {
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.set_output_channel_mode(ChannelMode::ForceMono);
+        // Independent noise on each channel, so nothing matches unless it's folded
+        let noise = Signal::WhiteNoise { amplitude: 0.5 };
+        engine.load_decoder(SignalGenerator::new(noise, 44100, 2, 0.5)).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+
+        let played = played.lock().unwrap();
+        assert_eq!(played.len(), 44100);
+        assert!(played.chunks_exact(2).all(|frame| frame[0] == frame[1]));
+        assert!(played.iter().any(|&s| s != 0.0));
+    }
+
+    #[test]
+    fn per_channel_meter_reads_a_hard_panned_tone_on_its_own_side() {
+        let (mut engine, _played) = mock_engine(44100, 2);
+        // 40 ms of tone on the left only, straight into what the output has written
+        let mut generator = SignalGenerator::new(TONE, 44100, 1, 0.04);
+        let mut left = Vec::new();
+        while let Some(block) = generator.decode_next() {
+            left.extend(block);
+        }
+        engine.clock.output_tap().write(left.iter().flat_map(|&s| [s, 0.0]));
+
+        let levels = engine.get_output_levels_db();
+        assert_eq!(levels.len(), 2);
+        assert!((levels[0] + 6.02).abs() < 0.1, "{levels:?}");
+        assert_eq!(levels[1], -120.0);
+
+        // The mono sum halves the left side
+        engine.set_meter_channel_mode(MeterChannelMode::Summed);
+        let levels = engine.get_output_levels_db();
+        assert_eq!(levels.len(), 1);
+        assert!((levels[0] + 12.04).abs() < 0.1, "{levels:?}");
+    }
+
+    #[test]
+    fn load_play_seek_stop() {
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 5.0)).unwrap();
+        engine.play().unwrap();
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(engine.get_state(), PlaybackState::Playing);
+
+        engine.seek(3.0).unwrap();
+        thread::sleep(Duration::from_millis(300));
+        let position = engine.get_time_secs();
+        assert!((3.0..3.6).contains(&position), "position {position}");
+
+        engine.stop();
+        assert_eq!(engine.get_state(), PlaybackState::Stopped);
+        let stopped_at = played.lock().unwrap().len();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(played.lock().unwrap().len(), stopped_at);
+    }
+
+    // Frequency of a tone over the second half of interleaved stereo `samples`, short of
+    // the very end, from its rising zero crossings
+    fn tone_frequency(samples: &[f32], sample_rate: u32) -> f64 {
+        let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+        let settled = &left[left.len() / 2..left.len() * 9 / 10];
+        let crossings = settled.windows(2).filter(|w| w[0] < 0.0 && w[1] >= 0.0).count();
+        crossings as f64 * sample_rate as f64 / settled.len() as f64
+    }
+
+    #[test]
+    fn seek_while_paused_resumes_at_the_target() {
+        // A 100 Hz to 10 kHz sweep over ten seconds: 1 kHz marks the five second point,
+        // and the audio buffered before the seek is around 100 Hz
+        let sweep = Signal::LogSweep { start_hz: 100.0, end_hz: 10000.0, amplitude: 0.5 };
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.load_decoder(SignalGenerator::new(sweep, 44100, 2, 10.0)).unwrap();
+        engine.play().unwrap();
+        thread::sleep(Duration::from_millis(300));
+        engine.pause().unwrap();
+        engine.seek(5.0).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert!((engine.get_time_secs() - 5.0).abs() < 0.01, "{}", engine.get_time_secs());
+
+        let resumed_at = played.lock().unwrap().len();
+        engine.play().unwrap();
+        thread::sleep(Duration::from_millis(300));
+        engine.stop();
+
+        let played = played.lock().unwrap();
+        let first = &played[resumed_at..resumed_at + 4410];
+        let frequency = tone_frequency(first, 44100);
+        assert!((frequency - 1000.0).abs() < 50.0, "{frequency} Hz");
+    }
+
+    // Plays two seconds of the tone at double speed and returns the frequency heard once
+    // varispeed has ramped up
+    fn frequency_at_double_speed(affects_pitch: bool) -> f64 {
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.set_playback_speed(2.0);
+        engine.set_speed_affects_pitch(affects_pitch);
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 2.0)).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+        let played = played.lock().unwrap();
+        tone_frequency(&played, 44100)
+    }
+
+    #[test]
+    fn speed_shifts_pitch_only_in_resample_mode() {
+        let shifted = frequency_at_double_speed(true);
+        assert!((shifted - 2000.0).abs() < 20.0, "{shifted} Hz");
+        let held = frequency_at_double_speed(false);
+        assert!((held - 1000.0).abs() < 20.0, "{held} Hz");
+    }
+
+    #[test]
+    fn rate_change_midstream_keeps_the_duration() {
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.load_decoder(RateSwitch::new()).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(engine.clock.get_device_sample_rate(), 44100);
+        // A second at the device rate, give or take the resampler's edges
+        let frames = played.lock().unwrap().len() / 2;
+        assert!(frames.abs_diff(44100) < 441, "{frames} frames");
+    }
+
+    #[test]
+    fn rate_change_midstream_plays_what_the_old_resampler_held() {
+        // The 44.1 kHz half is resampled, the 48 kHz half isn't
+        let (mut engine, played) = mock_engine(48000, 2);
+        engine.load_decoder(RateSwitch::new()).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+        // Neither the chunk short of a full one nor what the filter's delay held is
+        // lost, and the silence the flush pads with isn't played
+        let frames = played.lock().unwrap().len() / 2;
+        assert!(frames.abs_diff(48000) < 100, "{frames} frames");
+    }
+
+    // A generated signal that notes every seek the engine asks of it, and can claim to be
+    // a source that can't seek
+    struct SeekLog {
+        generator: SignalGenerator,
+        seeks: Arc<Mutex<Vec<f64>>>,
+        seekable: bool,
+    }
+
+    impl AudioDecoder for SeekLog {
+        fn decode_next(&mut self) -> Option<Vec<f32>> {
+            self.generator.decode_next()
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.generator.sample_rate()
+        }
+
+        fn channels(&self) -> u32 {
+            self.generator.channels()
+        }
+
+        fn seek(&mut self, time_secs: f64) {
+            self.seeks.lock().unwrap().push(time_secs);
+            self.generator.seek(time_secs);
+        }
+
+        fn is_seekable(&self) -> bool {
+            self.seekable
+        }
+
+        fn duration(&self) -> Option<f64> {
+            self.generator.duration()
+        }
+
+        fn metadata(&self) -> Option<AudioMetadata> {
+            None
+        }
+    }
+
+    // A tone in blocks of about a second, noting when each seek reaches it
+    struct Bulky {
+        generator: SignalGenerator,
+        seeks: Arc<Mutex<Vec<Instant>>>,
+    }
+
+    impl AudioDecoder for Bulky {
+        fn decode_next(&mut self) -> Option<Vec<f32>> {
+            let mut block = Vec::new();
+            for _ in 0..40 {
+                block.extend(self.generator.decode_next()?);
+            }
+            Some(block)
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.generator.sample_rate()
+        }
+
+        fn channels(&self) -> u32 {
+            self.generator.channels()
+        }
+
+        fn seek(&mut self, time_secs: f64) {
+            self.seeks.lock().unwrap().push(Instant::now());
+            self.generator.seek(time_secs);
+        }
+
+        fn duration(&self) -> Option<f64> {
+            self.generator.duration()
+        }
+
+        fn metadata(&self) -> Option<AudioMetadata> {
+            None
+        }
+    }
+
+    #[test]
+    fn upsampled_blocks_larger_than_the_buffer_keep_seek_and_stop_responsive() {
+        // Each block upsamples to over two seconds of 96 kHz audio for a 200 ms buffer
+        let config = EngineConfig { buffer_duration_ms: 200, ..EngineConfig::default() };
+        let (mut engine, _played) = mock_engine_with_config(config, 96000, 2);
+        let seeks = Arc::new(Mutex::new(Vec::new()));
+        let generator = SignalGenerator::new(TONE, 44100, 2, 30.0);
+        engine.load_decoder(Bulky { generator, seeks: seeks.clone() }).unwrap();
+        engine.play().unwrap();
+        thread::sleep(Duration::from_millis(300));
+
+        let asked = Instant::now();
+        engine.seek(20.0).unwrap();
+        let deadline = asked + Duration::from_secs(2);
+        while seeks.lock().unwrap().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let reached = seeks.lock().unwrap().first().map(|at| at.duration_since(asked));
+        assert!(reached.is_some_and(|waited| waited < Duration::from_millis(150)), "{reached:?}");
+
+        thread::sleep(Duration::from_millis(100));
+        let asked = Instant::now();
+        engine.stop();
+        assert!(asked.elapsed() < Duration::from_millis(150), "{:?}", asked.elapsed());
+    }
+
+    #[test]
+    fn non_seekable_source_rejects_seeks() {
+        let (mut engine, _played) = mock_engine(44100, 2);
+        let seeks = Arc::new(Mutex::new(Vec::new()));
+        let generator = SignalGenerator::new(TONE, 44100, 2, 5.0);
+        engine.load_decoder(SeekLog { generator, seeks: seeks.clone(), seekable: false }).unwrap();
+        assert!(!engine.seekable());
+        engine.play().unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        // Playback carries on from where it was, and the decoder is never asked to seek
+        let before = engine.get_time_secs();
+        assert_eq!(engine.seek(3.0), Err(SeekError::NotSeekable));
+        thread::sleep(Duration::from_millis(200));
+        let after = engine.get_time_secs();
+        assert!(after > before && after < 1.0, "{before} then {after}");
+        assert!(seeks.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn injected_drift_is_detected_and_corrected() {
+        let config = EngineConfig { drift_resync_threshold_secs: Some(0.05), ..Default::default() };
+        let (mut engine, _played) = mock_engine_with_config(config, 44100, 2);
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 10.0)).unwrap();
+        engine.play().unwrap();
+        // A reading taken while a push is in flight can be off by a packet, so wait for
+        // one that isn't
+        thread::sleep(Duration::from_millis(500));
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while engine.clock_drift_secs().abs() > 0.05 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(engine.clock_drift_secs().abs() < 0.05, "{}", engine.clock_drift_secs());
+
+        // Half a second the output never played
+        let started = Instant::now();
+        let before = engine.get_time_secs();
+        engine.clock.increment_samples(44100 * 2 / 2);
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while engine.clock_drift_secs() < 0.4 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(engine.clock_drift_secs() > 0.4, "{}", engine.clock_drift_secs());
+
+        // Put right once it has lasted, back to where the audio really is
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while engine.clock_drift_secs().abs() > 0.05 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(engine.clock_drift_secs().abs() < 0.05, "{}", engine.clock_drift_secs());
+        let expected = before + started.elapsed().as_secs_f64();
+        assert!((engine.get_time_secs() - expected).abs() < 0.1, "{} vs {expected}", engine.get_time_secs());
+    }
+
+    #[test]
+    fn rapid_seeks_coalesce_to_the_last() {
+        let (mut engine, _played) = mock_engine(44100, 2);
+        let seeks = Arc::new(Mutex::new(Vec::new()));
+        let generator = SignalGenerator::new(TONE, 44100, 2, 5.0);
+        engine.load_decoder(SeekLog { generator, seeks: seeks.clone(), seekable: true }).unwrap();
+        engine.play().unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        let targets: Vec<f64> = (0..50).map(|i| 1.0 + i as f64 * 0.05).collect();
+        for &target in &targets {
+            engine.seek(target).unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+        thread::sleep(Duration::from_millis(300));
+        let seeks = seeks.lock().unwrap();
+        assert!(seeks.len() < 10, "{} seeks", seeks.len());
+        assert_eq!(seeks.last(), targets.last());
+    }
+
+    // Writes half a second of the tone as a 16-bit stereo WAV at 44.1 kHz
+    fn write_tone_wav(path: &Path) {
+        let mut generator = SignalGenerator::new(TONE, 44100, 2, 0.5);
+        let mut samples = Vec::new();
+        while let Some(block) = generator.decode_next() {
+            samples.extend(block);
+        }
+        let data_len = samples.len() as u32 * 2;
+        let mut bytes = Vec::with_capacity(44 + data_len as usize);
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        // PCM, 2 channels, 44.1 kHz, 4-byte frames of 16 bits
+        for field in [1u16, 2] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(&44100u32.to_le_bytes());
+        bytes.extend_from_slice(&(44100u32 * 4).to_le_bytes());
+        for field in [4u16, 16] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        for sample in samples {
+            bytes.extend_from_slice(&((sample * i16::MAX as f32) as i16).to_le_bytes());
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    // A FIFO stands in for a slow network file: opening it blocks until a writer shows up
+    #[test]
+    fn current_file_follows_the_last_load_until_unload() {
+        let path = std::env::temp_dir().join(format!("mewo-current-{}.wav", std::process::id()));
+        write_tone_wav(&path);
+        let (mut engine, _played) = mock_engine(44100, 2);
+        assert_eq!(engine.source(), None);
+
+        let loaded = engine.load(&path);
+        let _ = std::fs::remove_file(&path);
+        loaded.unwrap();
+        assert_eq!(engine.current_file(), Some(path.clone()));
+        assert_eq!(engine.source(), Some(&Source::File(path)));
+        // Kept through a stop
+        engine.stop();
+        assert!(engine.current_file().is_some());
+
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 0.5)).unwrap();
+        assert_eq!(engine.current_file(), None);
+        assert_eq!(engine.source(), Some(&Source::Decoder));
+
+        engine.unload();
+        assert_eq!(engine.source(), None);
+        assert!(!engine.is_loaded());
+    }
+
+    #[test]
+    fn queued_tracks_play_at_their_own_gain() {
+        let path = std::env::temp_dir().join(format!("mewo-queue-{}.wav", std::process::id()));
+        write_tone_wav(&path);
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.enqueue_with_gain(&path, 0.0);
+        engine.enqueue_with_gain(&path, -12.0);
+
+        let mut peaks = Vec::new();
+        let mut heard = 0;
+        while engine.play_next().unwrap() {
+            engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+            let played = played.lock().unwrap();
+            peaks.push(played[heard..].iter().fold(0.0f32, |peak, s| peak.max(s.abs())));
+            heard = played.len();
+        }
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(peaks.len(), 2);
+        assert!(peaks[0] > 0.4, "{peaks:?}");
+        let ratio_db = 20.0 * (peaks[1] / peaks[0]).log10();
+        assert!((ratio_db + 12.0).abs() < 0.2, "{ratio_db} dB");
+    }
+
+    #[test]
+    fn session_round_trip_restores_position_and_settings() {
+        let path = std::env::temp_dir().join(format!("mewo-session-{}.wav", std::process::id()));
+        write_tone_wav(&path);
+        let (mut engine, _played) = mock_engine(44100, 2);
+        engine.load(&path).unwrap();
+        engine.set_volume_taper(VolumeTaper::Logarithmic);
+        engine.set_volume(0.7);
+        engine.set_normalization(true);
+        engine.set_playback_speed(1.25);
+        engine.set_eq_preset(EqPreset::Rock);
+        engine.set_bass_boost(true);
+        engine.seek(0.3).unwrap();
+        let saved = engine.save_session();
+        #[cfg(feature = "serde")]
+        let saved = Session::from_json(&saved.to_json().unwrap()).unwrap();
+
+        let (mut restored, _played) = mock_engine(44100, 2);
+        let result = restored.restore_session(&saved);
+        let _ = std::fs::remove_file(&path);
+        result.unwrap();
+        assert_eq!(restored.save_session(), saved);
+        assert_eq!(restored.current_file(), Some(path.clone()));
+        assert!((restored.get_time_secs() - 0.3).abs() < 1e-3, "{}", restored.get_time_secs());
+
+        // A file that's gone since is reported, with the settings applied all the same
+        let (mut moved, _played) = mock_engine(44100, 2);
+        match moved.restore_session(&saved) {
+            Err(SessionError::Load { path: failed, .. }) => assert_eq!(failed, path),
+            other => panic!("{other:?}"),
+        }
+        assert_eq!(moved.save_session().preset, saved.preset);
+        assert_eq!(moved.save_session().volume, 0.7);
+    }
+
+    // An output whose device hasn't reported its format yet
+    struct Unopened(Option<AudioBufferConsumer>);
+
+    impl AudioOutput for Unopened {
+        fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+            Ok(())
+        }
+
+        fn pause(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+            Ok(())
+        }
+
+        fn is_healthy(&self) -> bool {
+            true
+        }
+
+        fn shutdown(&mut self) -> Option<AudioBufferConsumer> {
+            self.0.take()
+        }
+
+        fn tick(&mut self) {}
+
+        fn clear_buffer(&mut self) {}
+    }
+
+    #[test]
+    fn session_restored_before_the_output_has_a_format_seeks_once_it_does() {
+        let path = std::env::temp_dir().join(format!("mewo-early-session-{}.wav", std::process::id()));
+        write_tone_wav(&path);
+        let session = Session { file: Some(path.clone()), position_secs: 0.3, ..Session::default() };
+        let mut engine =
+            AudioEngine::with_config_and_output(EngineConfig::default(), |consumer, _clock| {
+                Box::new(Unopened(Some(consumer)))
+            })
+            .unwrap();
+
+        let result = engine.restore_session(&session);
+        let _ = std::fs::remove_file(&path);
+        result.unwrap();
+        assert_eq!(engine.get_state(), PlaybackState::Paused);
+
+        engine.clock.set_device_sample_rate(44100);
+        engine.clock.set_channels(2);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while (engine.get_time_secs() - 0.3).abs() > 1e-3 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!((engine.get_time_secs() - 0.3).abs() < 1e-3, "{}", engine.get_time_secs());
+        assert_eq!(engine.get_state(), PlaybackState::Paused);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn load_async_returns_at_once_and_a_newer_load_wins() {
+        let dir = std::env::temp_dir();
+        let slow = dir.join(format!("mewo-slow-{}.wav", std::process::id()));
+        let valid = dir.join(format!("mewo-async-{}.wav", std::process::id()));
+        assert!(std::process::Command::new("mkfifo").arg(&slow).status().unwrap().success());
+        write_tone_wav(&valid);
+
+        let (mut engine, _played) = mock_engine(44100, 2);
+        let events = engine.subscribe();
+        let started = Instant::now();
+        let stale = engine.load_async(&slow);
+        assert!(started.elapsed() < Duration::from_millis(100));
+        assert!(engine.is_loading());
+        assert!(engine.play().is_err());
+
+        // The second load goes ahead while the first is still stuck
+        let pending = engine.load_async(&valid);
+        assert!(wait_for_event(&events, EngineEvent::Loaded, Duration::from_secs(2)));
+        engine.complete_load(pending).unwrap();
+        assert!(!engine.is_loading());
+        engine.play().unwrap();
+        assert!(engine.complete_load(stale).is_err());
+
+        // Unblock the first; it fails, but nobody hears about it
+        std::fs::write(&slow, b"not audio").unwrap();
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            if let Ok(event) = events.recv_timeout(left) {
+                assert!(!matches!(event, EngineEvent::LoadFailed(_)), "{event:?}");
+            }
+        }
+
+        std::fs::remove_file(&slow).unwrap();
+        std::fs::remove_file(&valid).unwrap();
+    }
+
+    #[test]
+    fn failed_load_leaves_the_engine_usable() {
+        let dir = std::env::temp_dir();
+        let valid = dir.join(format!("mewo-load-{}.wav", std::process::id()));
+        write_tone_wav(&valid);
+
+        let (mut engine, played) = mock_engine(44100, 2);
+        assert!(engine.load(dir.join("mewo-missing.wav")).is_err());
+        engine.load(&valid).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+        let _ = std::fs::remove_file(&valid);
+        assert_eq!(played.lock().unwrap().len(), 44100);
+    }
+
+    #[test]
+    fn device_rate_change_keeps_the_buffer() {
+        let (mut engine, played) = mock_engine(44100, 2);
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 5.0)).unwrap();
+        engine.play().unwrap();
+        thread::sleep(Duration::from_millis(600));
+        let fill = engine.buffer_fill();
+        let position = engine.get_time_secs();
+        assert!(fill > 0.5, "fill {fill}");
+
+        engine.clock.set_device_sample_rate(48000);
+        let switched = Instant::now();
+        let mut lowest = fill;
+        while switched.elapsed() < Duration::from_millis(500) {
+            lowest = lowest.min(engine.buffer_fill());
+            thread::sleep(Duration::from_millis(5));
+        }
+        // The output converts from then on; the buffer and its rate stay as they were
+        assert!(lowest > fill / 2.0, "fill fell from {fill} to {lowest}");
+        assert_eq!(engine.clock.get_sample_rate(), 44100);
+        let advanced = engine.get_time_secs() - position;
+        assert!((advanced - switched.elapsed().as_secs_f64()).abs() < 0.1, "advanced {advanced}s");
+        assert!(played.lock().unwrap().len() > 44100);
+    }
+
+    #[test]
+    fn buffer_capacity_matches_the_requested_duration() {
+        for (rate, channels) in [(44100, 2), (48000, 6), (96000, 1)] {
+            let (mut engine, _played) = mock_engine(rate, channels);
+            for (ms, expected_ms) in [(250, 250), (2000, 2000), (10, 50), (60_000, 5000)] {
+                engine.set_buffer_duration_ms(ms);
+                assert_eq!(engine.buffer_duration_ms(), expected_ms);
+                let frames = rate as usize * expected_ms as usize / 1000;
+                assert_eq!(engine.producer.as_ref().unwrap().frames(), frames, "{rate} Hz, {ms} ms");
+                assert_eq!(engine.clock.get_buffer_capacity(), (frames * channels as usize) as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn buffer_is_rebuilt_for_the_outputs_channel_count() {
+        // The buffer is made for stereo before the output says it has six channels
+        let (engine, _played) = mock_engine(48000, 6);
+        let producer = engine.producer.as_ref().unwrap();
+        assert!(producer.fits_channels());
+        assert_eq!(producer.capacity(), producer.frames() * 6);
+        assert_eq!(engine.clock.get_buffer_capacity(), producer.capacity() as u64);
+    }
+
+    #[test]
+    fn latency_mode_rebuilds_the_buffer() {
+        let (mut engine, played) = mock_engine(44100, 2);
+        let modes = [
+            (LatencyMode::LowLatency, 100, Some(256)),
+            (LatencyMode::HighStability, 3000, Some(4096)),
+            (LatencyMode::Balanced, 1000, None),
+            (LatencyMode::LowLatency, 100, Some(256)),
+        ];
+        for (mode, ms, device_frames) in modes {
+            engine.set_latency_mode(mode);
+            let frames = 44100 * ms / 1000;
+            assert_eq!(engine.producer.as_ref().unwrap().frames(), frames, "{mode:?}");
+            assert_eq!(engine.clock.get_buffer_capacity(), frames as u64 * 2, "{mode:?}");
+            assert_eq!(engine.clock.get_device_buffer_frames(), device_frames, "{mode:?}");
+        }
+
+        // The output reads from the new buffer
+        engine.load_decoder(SignalGenerator::new(TONE, 44100, 2, 0.5)).unwrap();
+        engine.play().unwrap();
+        engine.wait_until_finished(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(played.lock().unwrap().len(), 44100);
     }
-}
\ No newline at end of file
+}
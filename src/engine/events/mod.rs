@@ -0,0 +1,50 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use crate::engine::decoder::AudioMetadata;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineEvent {
+    /// Fraction of the file scanned by a running waveform overview, `0.0..=1.0`.
+    WaveformProgress(f32),
+    /// Fraction of the file scanned by a running loudness analysis, `0.0..=1.0`.
+    LoudnessProgress(f32),
+    /// The output buffer ran low during playback.
+    Buffering,
+    /// The output buffer recovered after `Buffering`.
+    Ready,
+    /// A file started with `load_async` opened successfully.
+    Loaded,
+    /// A file started with `load_async` couldn't be opened.
+    LoadFailed(String),
+    /// The playing stream carried new metadata in-band, e.g. the next song's title on an
+    /// internet radio station. Identical repeats are filtered out.
+    MetadataChanged(AudioMetadata),
+}
+
+/// Fans engine events out to every subscriber. Emitting never blocks on a slow reader,
+/// but it does take a lock, so it must not be called from the audio callback.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Sender<EngineEvent>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self) -> Receiver<EngineEvent> {
+        let (tx, rx) = mpsc::channel();
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(tx);
+        }
+        rx
+    }
+
+    pub fn emit(&self, event: EngineEvent) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            // Dropped receivers are pruned here rather than on unsubscribe
+            subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use crate::engine::buffer::{create_audio_buffer, AudioBufferConsumer, AudioBufferProducer};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+
+// Room for this much captured audio; anything the engine doesn't pick up in time is dropped
+const CAPTURE_BUFFER_SECS: f32 = 0.5;
+
+/// Captures an input device into a ring buffer of interleaved `f32` samples.
+pub struct InputCapture {
+    _stream: Stream,
+    sample_rate: u32,
+    channels: u32,
+}
+
+impl InputCapture {
+    /// Opens the input device called `device_name`, or the default input with `None`, and
+    /// starts capturing right away. Returns the capture and the buffer it fills.
+    pub fn open(
+        device_name: Option<&str>,
+    ) -> Result<(Self, AudioBufferConsumer), Box<dyn std::error::Error>> {
+        let host = cpal::default_host();
+        let device = match device_name {
+            Some(name) => host
+                .input_devices()?
+                .find(|d| d.description().map(|desc| desc.name() == name).unwrap_or(false))
+                .ok_or_else(|| format!("No input device named {}", name))?,
+            None => host.default_input_device().ok_or("No input device available")?,
+        };
+
+        let supported = device.default_input_config()?;
+        let sample_format = supported.sample_format();
+        let config: StreamConfig = supported.into();
+        let frames = (config.sample_rate as f32 * CAPTURE_BUFFER_SECS) as usize;
+        let (producer, consumer) = create_audio_buffer(frames, config.channels as usize);
+
+        let stream = match sample_format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, producer)?,
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, producer)?,
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, producer)?,
+            _ => return Err("Unsupported input sample format".into()),
+        };
+        stream.play()?;
+
+        Ok((
+            Self {
+                _stream: stream,
+                sample_rate: config.sample_rate,
+                channels: config.channels as u32,
+            },
+            consumer,
+        ))
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u32 {
+        self.channels
+    }
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mut producer: AudioBufferProducer,
+) -> Result<Stream, Box<dyn std::error::Error>>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _| {
+            for sample in data {
+                // Full means the engine has fallen behind; newer audio matters more, but
+                // dropping here keeps the callback simple
+                if producer.push(sample.to_sample::<f32>()).is_err() {
+                    break;
+                }
+            }
+        },
+        |_| {},
+        None,
+    )?;
+    Ok(stream)
+}
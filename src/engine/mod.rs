@@ -1,6 +1,12 @@
+pub mod analysis;
 pub mod buffer;
 pub mod decoder;
 pub mod dsp;
 pub mod output;
 pub mod clock;
-pub mod engine;
\ No newline at end of file
+pub mod config;
+pub mod engine;
+pub mod events;
+pub mod session;
+pub mod input;
+pub mod streaming;
@@ -1,16 +1,22 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Stream, StreamConfig, SampleFormat, FromSample, Sample};
+use cpal::{BufferSize, HostId, Stream, StreamConfig, SampleFormat, SupportedBufferSize, FromSample, Sample};
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use crate::engine::buffer::AudioBufferConsumer;
-use crate::engine::clock::{Clock, PlaybackState};
-use crate::engine::output::AudioOutput;
+use crate::engine::clock::{Clock, PauseBehavior, PlaybackState, SeekBehavior};
+use crate::engine::output::rate_converter::{ConverterSlot, RateConverter, HISTORY_FRAMES};
+use crate::engine::output::{swap_consumer, AudioOutput, OutputFormat};
 
 pub struct CpalBackend {
     _stream: Stream,
+    clock: Arc<Clock>,
+    converters: Arc<ConverterSlot>,
+    host_id: HostId,
     device_id: String,
+    follow_default: bool,
     is_healthy: Arc<AtomicBool>,
     consumer: Arc<Mutex<Option<AudioBufferConsumer>>>,
+    format: OutputFormat,
 }
 
 impl CpalBackend {
@@ -18,7 +24,22 @@ impl CpalBackend {
         consumer: AudioBufferConsumer,
         clock: Arc<Clock>,
     ) -> Result<Self, (AudioBufferConsumer, Box<dyn std::error::Error>)> {
-        let host = cpal::default_host();
+        Self::with_host(consumer, clock, cpal::default_host().id())
+    }
+
+    /// Opens the default output device of a specific cpal host, e.g. `HostId::Asio`.
+    pub fn with_host(
+        consumer: AudioBufferConsumer,
+        clock: Arc<Clock>,
+        host_id: HostId,
+    ) -> Result<Self, (AudioBufferConsumer, Box<dyn std::error::Error>)> {
+        let host = match cpal::host_from_id(host_id) {
+            Ok(h) => h,
+            Err(e) => {
+                let message = format!("The {} audio host isn't available: {}", host_id.name(), e);
+                return Err((consumer, message.into()));
+            }
+        };
         let device = match host.default_output_device() {
             Some(d) => d,
             None => return Err((consumer, "No output device available".into())),
@@ -32,10 +53,25 @@ impl CpalBackend {
         };
 
         let sample_format = config_inner.sample_format();
-        let config: StreamConfig = config_inner.into();
+        let supported_buffer = *config_inner.buffer_size();
+        let mut config: StreamConfig = config_inner.into();
+        if let Some(frames) = clock.get_device_buffer_frames() {
+            config.buffer_size = match supported_buffer {
+                SupportedBufferSize::Range { min, max } => BufferSize::Fixed(frames.clamp(min, max)),
+                SupportedBufferSize::Unknown => BufferSize::Fixed(frames),
+            };
+        }
+        let format = OutputFormat {
+            sample_rate: config.sample_rate,
+            channels: config.channels as u32,
+            sample_format: sample_format.to_string(),
+            bits_per_sample: sample_format.bits_per_sample(),
+        };
 
-        clock.set_sample_rate(config.sample_rate);
+        clock.set_device_sample_rate(config.sample_rate);
         clock.set_channels(config.channels as u32);
+        consumer.set_channels(config.channels as usize);
+        clock.set_buffer_capacity(consumer.capacity() as u64);
 
         let is_healthy = Arc::new(AtomicBool::new(true));
         let is_healthy_err = is_healthy.clone();
@@ -47,6 +83,9 @@ impl CpalBackend {
         let shared_consumer = Arc::new(Mutex::new(Some(consumer)));
         let consumer_for_callback = shared_consumer.clone();
         let clock_for_callback = clock.clone();
+        let converters = Arc::new(ConverterSlot::default());
+        converters.prepare(&clock);
+        let mut held = HeldFrame::new(converters.clone(), config.channels as usize, config.sample_rate);
 
         let stream_res = match sample_format {
             SampleFormat::F32 => device.build_output_stream(
@@ -54,7 +93,7 @@ impl CpalBackend {
                 move |data: &mut [f32], _| {
                     if let Ok(mut guard) = consumer_for_callback.lock() {
                         if let Some(c) = guard.as_mut() {
-                            process_audio(data, c, &clock_for_callback);
+                            process_audio(data, c, &clock_for_callback, &mut held);
                         }
                     }
                 },
@@ -66,7 +105,7 @@ impl CpalBackend {
                 move |data: &mut [i16], _| {
                     if let Ok(mut guard) = consumer_for_callback.lock() {
                         if let Some(c) = guard.as_mut() {
-                            process_audio(data, c, &clock_for_callback);
+                            process_audio(data, c, &clock_for_callback, &mut held);
                         }
                     }
                 },
@@ -78,7 +117,7 @@ impl CpalBackend {
                 move |data: &mut [u16], _| {
                     if let Ok(mut guard) = consumer_for_callback.lock() {
                         if let Some(c) = guard.as_mut() {
-                            process_audio(data, c, &clock_for_callback);
+                            process_audio(data, c, &clock_for_callback, &mut held);
                         }
                     }
                 },
@@ -94,9 +133,14 @@ impl CpalBackend {
         match stream_res {
             Ok(stream) => Ok(Self {
                 _stream: stream,
+                clock,
+                converters,
+                host_id,
                 device_id,
+                follow_default: true,
                 is_healthy,
                 consumer: shared_consumer,
+                format,
             }),
             Err(e) => {
                 let consumer = shared_consumer.lock().unwrap().take().unwrap();
@@ -108,6 +152,8 @@ impl CpalBackend {
 
 impl AudioOutput for CpalBackend {
     fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        // The buffer may have changed rate for a new track since the last start
+        self.converters.prepare(&self.clock);
         self._stream.play()?;
         Ok(())
     }
@@ -123,18 +169,11 @@ impl AudioOutput for CpalBackend {
     }
 
     fn is_healthy(&self) -> bool {
-        if !self.is_healthy.load(Ordering::SeqCst) {
-            return false;
-        }
-        let host = cpal::default_host();
-        if let Some(device) = host.default_output_device() {
-            if let Ok(name) = device.name() {
-                if name != self.device_id {
-                    return false;
-                }
-            }
-        }
-        true
+        self.is_healthy.load(Ordering::SeqCst)
+            && keeps_device(self.follow_default, &self.device_id, || {
+                let host = cpal::host_from_id(self.host_id).ok()?;
+                Some(host.default_output_device().and_then(|device| device.name().ok()))
+            })
     }
 
     fn shutdown(&mut self) -> Option<AudioBufferConsumer> {
@@ -142,7 +181,17 @@ impl AudioOutput for CpalBackend {
         self.consumer.lock().ok()?.take()
     }
 
-    fn tick(&mut self) {}
+    fn tick(&mut self) {
+        self.converters.prepare(&self.clock);
+    }
+
+    fn format(&self) -> Option<OutputFormat> {
+        Some(self.format.clone())
+    }
+
+    fn set_follow_default_device(&mut self, follow: bool) {
+        self.follow_default = follow;
+    }
 
     fn clear_buffer(&mut self) {
         if let Ok(mut guard) = self.consumer.lock() {
@@ -151,26 +200,280 @@ impl AudioOutput for CpalBackend {
             }
         }
     }
+
+    fn replace_consumer(
+        &mut self,
+        consumer: AudioBufferConsumer,
+    ) -> Result<AudioBufferConsumer, AudioBufferConsumer> {
+        swap_consumer(&self.consumer, consumer)
+    }
 }
 
-fn process_audio<T: Sample + FromSample<f32>>(
+// State carried between callbacks: the last frame written while playing, replayed with a
+// decaying gain under `HoldLast`, the old audio a smooth seek fades out, and the
+// conversion to the device's rate
+#[derive(Default)]
+pub(crate) struct HeldFrame {
+    frame: [f32; MAX_CHANNELS],
+    // Channels in `frame`, 0 until a frame has played
+    frame_channels: usize,
+    gain: f32,
+    // Never longer than `SEEK_TAIL_SECS` at the rate `new` was given, so it stays
+    // within what `new` reserves
+    tail: Vec<f32>,
+    tail_pos: usize,
+    fade_pos: usize,
+    fading: bool,
+    // Output trim the last callback ended on, ramped from to the new one
+    output_gain: Option<f32>,
+    // Fraction of a frame the clock still owes from playing at a speed other than 1.0,
+    // or at a device rate other than the buffer's
+    position_remainder: f64,
+    converter: Option<RateConverter>,
+    // Where converters are built and dropped, off the audio thread
+    converters: Arc<ConverterSlot>,
+    // Audio read for this callback at the device's rate, before gain. Reserved by `new`
+    // for `MAX_READ_FRAMES` and never grown, so larger callbacks are read in pieces
+    read: Vec<f32>,
+    // The last audio read while no conversion was needed, to prime a converter with.
+    // Never longer than `HISTORY_FRAMES`, so it stays within what `new` reserves
+    history: Vec<f32>,
+    // Position the clock was left at. If it's anywhere else next time, something moved it
+    // (a seek, a stop, a new track) and what the converter holds is stale
+    last_pos: u64,
+}
+
+// Most channels `HeldFrame` holds a frame of, enough for 7.1
+const MAX_CHANNELS: usize = 8;
+// Frames the callback reads from the buffer at a time
+const MAX_READ_FRAMES: usize = 8192;
+// Time for a held frame to fade to -60 dB
+const HOLD_FADE_SECS: f32 = 0.5;
+// Old audio kept by a smooth seek to bridge the wait for the new position
+const SEEK_TAIL_SECS: f32 = 0.05;
+// Length of the crossfade from the old audio into the new position
+const SEEK_FADE_SECS: f32 = 0.01;
+
+impl HeldFrame {
+    /// State for an output playing `channels` channels at `sample_rate`, taking its rate
+    /// converters from `converters`. Call `ConverterSlot::prepare` off the audio thread
+    /// whenever the buffer's or the device's rate may have changed.
+    pub(crate) fn new(converters: Arc<ConverterSlot>, channels: usize, sample_rate: u32) -> Self {
+        let channels = channels.max(1);
+        Self {
+            converters,
+            tail: Vec::with_capacity((SEEK_TAIL_SECS * sample_rate as f32) as usize * channels),
+            history: Vec::with_capacity(HISTORY_FRAMES * channels),
+            read: Vec::with_capacity(MAX_READ_FRAMES * channels),
+            ..Default::default()
+        }
+    }
+
+    // Keeps a converter between the buffer's rate and the device's only while they
+    // differ. Returns `false` while one is needed but hasn't been handed over yet
+    fn update_converter(&mut self, from: u32, to: u32, channels: usize, pos: u64) -> bool {
+        if from == to {
+            if let Some(previous) = self.converter.take() {
+                self.converters.retire(previous);
+                self.converters.clear_active();
+            }
+            return true;
+        }
+        if let Some(converter) = &mut self.converter {
+            if converter.converts(from, to, channels) {
+                if pos != self.last_pos {
+                    converter.reset();
+                }
+                return true;
+            }
+        }
+
+        let Some(mut converter) = self.converters.take(from, to, channels) else {
+            return false;
+        };
+        match self.converter.take() {
+            Some(previous) => {
+                converter.prime(previous.last_input());
+                self.converters.retire(previous);
+            }
+            None => converter.prime(&self.history),
+        }
+        self.converter = Some(converter);
+        true
+    }
+
+    fn remember(&mut self, samples: &[f32], channels: usize) {
+        let keep = HISTORY_FRAMES * channels;
+        let samples = &samples[samples.len().saturating_sub(keep)..];
+        let excess = (self.history.len() + samples.len()).saturating_sub(keep);
+        self.history.drain(..excess);
+        self.history.extend_from_slice(samples);
+    }
+
+    fn capture_tail(&mut self, consumer: &mut AudioBufferConsumer, channels: usize, sample_rate: u32) {
+        let len = ((SEEK_TAIL_SECS * sample_rate as f32) as usize * channels).min(self.tail.capacity());
+        self.tail.clear();
+        self.tail.resize(len / channels * channels, 0.0);
+        let read = match &mut self.converter {
+            Some(converter) => {
+                let read = converter.pull(consumer, &mut self.tail, false);
+                converter.reset();
+                read
+            }
+            None => consumer.pop_slice(&mut self.tail),
+        };
+        self.tail.truncate(read / channels * channels);
+        self.tail_pos = 0;
+        self.fade_pos = 0;
+        self.fading = false;
+    }
+
+    // Mixes the seek tail into `data`, whose first `read` samples are new audio and the
+    // rest silence. The tail plays on its own until new audio shows up or it's about to
+    // run out, then crossfades. Returns whether any of it played.
+    fn mix_tail<T: Sample + FromSample<f32>>(
+        &mut self,
+        data: &mut [T],
+        read: usize,
+        channels: usize,
+        gain: f32,
+        fade_frames: usize,
+    ) -> bool
+    where
+        f32: FromSample<T>,
+    {
+        if self.tail_pos >= self.tail.len() {
+            return false;
+        }
+        for (i, frame) in data.chunks_exact_mut(channels).enumerate() {
+            let remaining = (self.tail.len() - self.tail_pos) / channels;
+            if (i + 1) * channels <= read || remaining <= fade_frames {
+                self.fading = true;
+            }
+            let new_gain = if self.fading {
+                self.fade_pos as f32 / fade_frames as f32
+            } else {
+                0.0
+            };
+            for (out, old) in frame.iter_mut().zip(&self.tail[self.tail_pos..]) {
+                let new = out.to_sample::<f32>();
+                *out = T::from_sample(new * new_gain + old * gain * (1.0 - new_gain));
+            }
+            self.tail_pos += channels;
+            if self.fading {
+                self.fade_pos += 1;
+            }
+            if self.fade_pos >= fade_frames || self.tail_pos >= self.tail.len() {
+                self.tail.clear();
+                self.tail_pos = 0;
+                break;
+            }
+        }
+        true
+    }
+}
+
+/// Fills one device callback's worth of `data` from the buffer, honoring the clock's
+/// state, and returns how many samples came from the buffer (the rest are silence).
+///
+/// `data` is at the clock's device rate. When that differs from the buffer's rate the
+/// audio is converted here, with a converter `HeldFrame`'s `ConverterSlot` built off the
+/// audio thread. Until it's handed over the callback plays silence and leaves the buffer
+/// as it is.
+pub(crate) fn process_audio<T: Sample + FromSample<f32>>(
     data: &mut [T],
     consumer: &mut AudioBufferConsumer,
     clock: &Arc<Clock>,
-) {
+    held: &mut HeldFrame,
+) -> usize
+where
+    f32: FromSample<T>,
+{
+    let channels = (clock.get_channels() as usize).max(1);
+    // Read once, so the whole callback acts on the same state even if it changes meanwhile
+    let state = clock.get_state();
+    let buffer_rate = clock.get_sample_rate();
+    let device_rate = clock.get_device_sample_rate();
+    let converting = held.update_converter(buffer_rate, device_rate, channels, clock.get_sample_pos());
+
     if clock.should_clear_buffer() {
+        if state == PlaybackState::Playing && clock.get_seek_behavior() == SeekBehavior::Smooth {
+            held.capture_tail(consumer, channels, device_rate);
+        }
         consumer.clear();
+        if let Some(converter) = &mut held.converter {
+            converter.reset();
+        }
         clock.reset_clear_buffer();
+        clock.suppress_underrun();
     }
 
-    if clock.get_state() != PlaybackState::Playing {
-        for sample in data.iter_mut() {
-            *sample = T::from_sample(0.0);
+    // Auto-paused output stays `Playing` but plays silence, leaving the buffer to refill
+    if state != PlaybackState::Playing || clock.is_auto_paused() || !converting {
+        if state == PlaybackState::Paused
+            && clock.get_pause_behavior() == PauseBehavior::HoldLast
+            && held.frame_channels == channels
+        {
+            let decay = 0.001f32.powf(1.0 / (HOLD_FADE_SECS * device_rate.max(1) as f32));
+            for frame in data.chunks_mut(channels) {
+                for (out, sample) in frame.iter_mut().zip(&held.frame[..channels]) {
+                    *out = T::from_sample(sample * held.gain);
+                }
+                held.gain *= decay;
+            }
+        } else {
+            for sample in data.iter_mut() {
+                *sample = T::from_sample(0.0);
+            }
         }
-        return;
+        clock.set_buffered_samples(consumer.occupied_len() as u64);
+        clock.suppress_underrun();
+        clock.output_tap().write(data.iter().map(|s| s.to_sample::<f32>()));
+        held.last_pos = clock.get_sample_pos();
+        return 0;
     }
 
-    let samples_read = consumer.pop_slice_f32(data);
+    let volume = clock.get_volume();
+    let output_gain = 10.0f32.powf(clock.get_output_gain_db() / 20.0);
+    let ramp_from = held.output_gain.replace(output_gain).unwrap_or(output_gain);
+    let mut read = std::mem::take(&mut held.read);
+    let reserved = read.capacity();
+    // Whole frames at a time, each piece taking its share of the gain ramp
+    let piece = reserved - reserved % channels;
+    let ramp_step = (output_gain - ramp_from) / (data.len() / channels).max(1) as f32;
+    let (mut samples_read, mut peak) = (0, 0.0f32);
+    while piece > 0 && samples_read < data.len() {
+        let len = piece.min(data.len() - samples_read);
+        read.resize(len, 0.0);
+        let count = match &mut held.converter {
+            Some(converter) => converter.pull(consumer, &mut read, clock.is_eos()),
+            None => {
+                let count = consumer.pop_slice(&mut read);
+                held.remember(&read[..count], channels);
+                count
+            }
+        };
+        let frame = (samples_read / channels) as f32;
+        let (written, piece_peak) = write_with_gain(
+            &mut data[samples_read..samples_read + len],
+            read[..count].iter().copied(),
+            volume * (ramp_from + ramp_step * frame),
+            volume * (ramp_from + ramp_step * (frame + (len / channels) as f32)),
+            channels,
+        );
+        samples_read += written;
+        peak = peak.max(piece_peak);
+        if count < len {
+            break;
+        }
+    }
+    debug_assert_eq!(read.capacity(), reserved, "the callback's read buffer grew");
+    held.read = read;
+    clock.set_buffered_samples(consumer.occupied_len() as u64);
+
+    if peak > 1.0 {
+        clock.set_clipped();
+    }
 
     if samples_read < data.len() {
         for sample in &mut data[samples_read..] {
@@ -178,28 +481,257 @@ fn process_audio<T: Sample + FromSample<f32>>(
         }
     }
 
-    clock.increment_samples(samples_read as u64);
+    let fade_frames = ((SEEK_FADE_SECS * device_rate as f32) as usize).max(1);
+    let bridged = held.mix_tail(data, samples_read, channels, volume * output_gain, fade_frames);
+
+    if samples_read >= channels && channels <= MAX_CHANNELS {
+        let start = samples_read - samples_read % channels - channels;
+        for (held, sample) in held.frame.iter_mut().zip(&data[start..start + channels]) {
+            *held = sample.to_sample::<f32>();
+        }
+        held.frame_channels = channels;
+        held.gain = 1.0;
+    }
+
+    clock.output_tap().write(data.iter().map(|s| s.to_sample::<f32>()));
+    // The clock runs in source time at the buffer's rate, so off normal speed or at
+    // another device rate it advances more or less than played. The speed is the one the
+    // audio just read was rendered at, not the setting, which is ahead of the buffer
+    let speed = consumer.speed();
+    if speed == 1.0 && device_rate == buffer_rate {
+        clock.increment_samples(samples_read as u64);
+    } else {
+        let rate_ratio = buffer_rate as f64 / device_rate.max(1) as f64;
+        let frames = (samples_read / channels) as f64 * speed as f64 * rate_ratio + held.position_remainder;
+        held.position_remainder = frames.fract();
+        clock.increment_samples(frames as u64 * channels as u64);
+    }
+    held.last_pos = clock.get_sample_pos();
+    clock.record_output(samples_read < data.len() && !clock.is_eos() && !bridged);
 
     if samples_read == 0 && clock.is_eos() {
         clock.set_state(PlaybackState::Stopped);
     }
+    samples_read
 }
 
-trait ConsumerExt {
-    fn pop_slice_f32<T: Sample + FromSample<f32>>(&mut self, data: &mut [T]) -> usize;
+// Writes `samples` into `data` with a gain ramped per frame from `gain_from` to `gain_to`
+// across all of `data`, so a change doesn't click. Returns how many were written and
+// their peak after gain
+fn write_with_gain<T: Sample + FromSample<f32>>(
+    data: &mut [T],
+    samples: impl Iterator<Item = f32>,
+    gain_from: f32,
+    gain_to: f32,
+    channels: usize,
+) -> (usize, f32) {
+    let mut count = 0;
+    let mut peak = 0.0f32;
+    let step = (gain_to - gain_from) / (data.len() / channels).max(1) as f32;
+    // `data` goes first, so no sample is taken that there's no room for
+    for (i, (out, sample)) in data.iter_mut().zip(samples).enumerate() {
+        let sample = sample * (gain_from + step * (i / channels + 1) as f32);
+        peak = peak.max(sample.abs());
+        *out = T::from_sample(sample);
+        count += 1;
+    }
+    (count, peak)
 }
 
-impl ConsumerExt for AudioBufferConsumer {
-    fn pop_slice_f32<T: Sample + FromSample<f32>>(&mut self, data: &mut [T]) -> usize {
-        let mut count = 0;
-        for out in data.iter_mut() {
-            if let Some(sample) = self.pop() {
-                *out = T::from_sample(sample);
-                count += 1;
-            } else {
-                break;
+// Whether the open device is still the one to play on: a pinned device always is, a
+// followed default only until the default moves. `default_name` is the current default's
+// name if there is one, or `None` when the host itself has gone
+fn keeps_device(
+    follow_default: bool,
+    device_id: &str,
+    default_name: impl FnOnce() -> Option<Option<String>>,
+) -> bool {
+    if !follow_default {
+        return true;
+    }
+    match default_name() {
+        Some(Some(name)) => name == device_id,
+        Some(None) => true,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::buffer::create_audio_buffer;
+
+    // One 10 ms callback of a steady stereo frame, then half a second of callbacks
+    // paused under `behavior`. Returns what the paused callbacks emitted
+    fn paused_output(behavior: PauseBehavior) -> Vec<f32> {
+        let clock = Arc::new(Clock::new(48000));
+        clock.set_device_sample_rate(48000);
+        clock.set_pause_behavior(behavior);
+        let (mut producer, mut consumer) = create_audio_buffer(48000, 2);
+        consumer.set_channels(2);
+        for _ in 0..4800 {
+            producer.push_slice(&[0.5, -0.25]);
+        }
+        let mut held = HeldFrame::new(Arc::new(ConverterSlot::default()), 2, 48000);
+
+        let mut data = vec![0.0f32; 960];
+        clock.set_state(PlaybackState::Playing);
+        process_audio(&mut data, &mut consumer, &clock, &mut held);
+
+        clock.set_state(PlaybackState::Paused);
+        let mut paused = Vec::new();
+        for _ in 0..50 {
+            assert_eq!(process_audio(&mut data, &mut consumer, &clock, &mut held), 0);
+            paused.extend_from_slice(&data);
+        }
+        paused
+    }
+
+    #[test]
+    fn a_pinned_device_ignores_default_changes() {
+        let moved = || Some(Some("USB Headset".to_string()));
+        assert!(!keeps_device(true, "Speakers", moved));
+        assert!(keeps_device(true, "Speakers", || Some(Some("Speakers".to_string()))));
+
+        // Pinned, the default isn't even looked up
+        assert!(keeps_device(false, "Speakers", moved));
+        assert!(keeps_device(false, "Speakers", || unreachable!()));
+    }
+
+    // Plays 10 ms of a steady level, seeks under `behavior` and plays 40 ms more, with
+    // the new position's audio (a lower level) arriving one callback after the seek.
+    // Returns the left channel from the seek on
+    fn seek_output(behavior: SeekBehavior) -> Vec<f32> {
+        let clock = Arc::new(Clock::new(48000));
+        clock.set_device_sample_rate(48000);
+        clock.set_channels(2);
+        clock.set_seek_behavior(behavior);
+        clock.set_state(PlaybackState::Playing);
+        let (mut producer, mut consumer) = create_audio_buffer(48000, 2);
+        consumer.set_channels(2);
+        producer.push_slice(&[0.5; 4800 * 2]);
+        let mut held = HeldFrame::new(Arc::new(ConverterSlot::default()), 2, 48000);
+        let mut data = vec![0.0f32; 480 * 2];
+        process_audio(&mut data, &mut consumer, &clock, &mut held);
+
+        clock.signal_clear_buffer();
+        let mut left = Vec::new();
+        for callback in 0..4 {
+            if callback == 1 {
+                producer.push_slice(&[0.25; 4800 * 2]);
             }
+            process_audio(&mut data, &mut consumer, &clock, &mut held);
+            left.extend(data.iter().step_by(2));
+        }
+        left
+    }
+
+    #[test]
+    fn smooth_seek_crossfades_instead_of_going_silent() {
+        // A flush leaves a gap until the new audio arrives
+        let flushed = seek_output(SeekBehavior::Flush);
+        assert!(flushed[..480].iter().all(|&s| s == 0.0));
+
+        // The old audio bridges the wait, then fades into the new without a dip
+        let smooth = seek_output(SeekBehavior::Smooth);
+        assert!(smooth[..480].iter().all(|&s| s == 0.5));
+        assert!(smooth.windows(2).all(|w| w[1] <= w[0] && w[1] >= 0.25), "{smooth:?}");
+        assert!(smooth[480..960].iter().any(|&s| s > 0.25 && s < 0.5));
+        assert_eq!(smooth[smooth.len() - 1], 0.25);
+    }
+
+    // Last sample of a 10 ms callback playing a steady 0.8, the level the limiter lets
+    // through, at `volume` and `output_gain_db`
+    fn played_level(volume: f32, output_gain_db: f32) -> f32 {
+        let clock = Arc::new(Clock::new(48000));
+        clock.set_device_sample_rate(48000);
+        clock.set_channels(2);
+        clock.set_state(PlaybackState::Playing);
+        clock.set_volume(volume);
+        clock.set_output_gain_db(output_gain_db);
+        let (mut producer, mut consumer) = create_audio_buffer(48000, 2);
+        consumer.set_channels(2);
+        producer.push_slice(&[0.8; 480 * 2]);
+        let mut held = HeldFrame::new(Arc::new(ConverterSlot::default()), 2, 48000);
+        let mut data = vec![0.0f32; 480 * 2];
+        process_audio(&mut data, &mut consumer, &clock, &mut held);
+        data[data.len() - 1]
+    }
+
+    #[test]
+    fn output_gain_trims_after_the_limiter_whatever_the_volume() {
+        // +6 dB takes the limited signal past full scale; it's the last stage
+        let boost = 10.0f32.powf(6.0 / 20.0);
+        assert!((played_level(1.0, 6.0) - 0.8 * boost).abs() < 1e-5);
+        assert!(played_level(1.0, 6.0) > 1.0);
+        for volume in [1.0, 0.5, 0.25] {
+            let ratio = played_level(volume, -12.0) / played_level(volume, 0.0);
+            assert!((ratio - 10.0f32.powf(-12.0 / 20.0)).abs() < 1e-5, "{volume}: {ratio}");
+        }
+    }
+
+    #[test]
+    fn a_callback_past_the_read_reserve_plays_in_pieces() {
+        let clock = Arc::new(Clock::new(48000));
+        clock.set_device_sample_rate(48000);
+        clock.set_channels(2);
+        clock.set_state(PlaybackState::Playing);
+        let (mut producer, mut consumer) = create_audio_buffer(48000, 2);
+        consumer.set_channels(2);
+        let frames = MAX_READ_FRAMES * 2 + 100;
+        let samples: Vec<f32> = (0..frames * 2).map(|i| (i % 1000) as f32 / 1000.0).collect();
+        producer.push_slice(&samples);
+        let mut held = HeldFrame::new(Arc::new(ConverterSlot::default()), 2, 48000);
+        let reserved = held.read.capacity();
+
+        let mut data = vec![0.0f32; frames * 2];
+        assert_eq!(process_audio(&mut data, &mut consumer, &clock, &mut held), data.len());
+        assert_eq!(data, samples);
+        assert_eq!(held.read.capacity(), reserved);
+    }
+
+    #[test]
+    fn running_dry_records_where_in_the_track() {
+        let clock = Arc::new(Clock::new(48000));
+        clock.set_device_sample_rate(48000);
+        clock.set_channels(2);
+        clock.set_state(PlaybackState::Playing);
+        clock.set_time_secs(12.5);
+        let (mut producer, mut consumer) = create_audio_buffer(48000, 2);
+        consumer.set_channels(2);
+        producer.push_slice(&[0.5; 480 * 2]);
+        let mut held = HeldFrame::new(Arc::new(ConverterSlot::default()), 2, 48000);
+        let mut data = vec![0.0f32; 480 * 2];
+
+        // 10 ms play out, then the buffer is empty; staying dry is the same dropout
+        process_audio(&mut data, &mut consumer, &clock, &mut held);
+        assert!(clock.dropouts().recent().is_empty());
+        for _ in 0..3 {
+            process_audio(&mut data, &mut consumer, &clock, &mut held);
         }
-        count
+        let dropouts = clock.dropouts().recent();
+        assert_eq!(dropouts.len(), 1);
+        assert!((dropouts[0] - 12.51).abs() < 1e-6, "{dropouts:?}");
+        assert_eq!(clock.get_underruns(), 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn silence_while_paused() {
+        assert!(paused_output(PauseBehavior::Silence).iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn hold_last_repeats_the_frame_and_fades_it_out() {
+        let paused = paused_output(PauseBehavior::HoldLast);
+        assert_eq!(&paused[..2], &[0.5, -0.25]);
+
+        // The same frame all along, fading to -60 dB by the end of the half second
+        for frame in paused.chunks_exact(2) {
+            assert!((frame[1] + 0.5 * frame[0]).abs() < 1e-6, "{frame:?}");
+        }
+        let quarter = paused.len() / 4 / 2 * 2;
+        assert!(paused[quarter] < 0.5 && paused[quarter] > 0.05, "{}", paused[quarter]);
+        let last = paused[paused.len() - 2];
+        assert!(last < 0.5 * 0.0011, "{last}");
+    }
+}
@@ -0,0 +1,42 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+// Dropouts remembered before the oldest is overwritten
+const LOG_CAPACITY: usize = 64;
+
+/// Media positions, in seconds, of the most recent underruns. Only the output writes to
+/// it, with plain atomic stores, so recording one never waits on a reader.
+pub struct DropoutLog {
+    positions: Vec<AtomicU64>,
+    // Total dropouts ever recorded; the newest sits just before this
+    written: AtomicUsize,
+}
+
+impl Default for DropoutLog {
+    fn default() -> Self {
+        Self {
+            positions: (0..LOG_CAPACITY).map(|_| AtomicU64::new(0)).collect(),
+            written: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl DropoutLog {
+    pub fn record(&self, secs: f64) {
+        let pos = self.written.load(Ordering::Relaxed);
+        self.positions[pos % LOG_CAPACITY].store(secs.to_bits(), Ordering::Relaxed);
+        self.written.store(pos.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Up to the last 64 positions, oldest first.
+    pub fn recent(&self) -> Vec<f64> {
+        let end = self.written.load(Ordering::Acquire);
+        let start = end.saturating_sub(LOG_CAPACITY);
+        (start..end)
+            .map(|i| f64::from_bits(self.positions[i % LOG_CAPACITY].load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.written.store(0, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::engine::buffer::AudioBufferConsumer;
+use crate::engine::clock::Clock;
+use crate::engine::output::cpal_backend::{process_audio, HeldFrame};
+use crate::engine::output::rate_converter::ConverterSlot;
+use crate::engine::output::{swap_consumer, AudioOutput, OutputFormat};
+use jack::{AsyncClient, AudioOut, Client, ClientOptions, Control, Frames, Port, PortFlags, ProcessScope};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+const CLIENT_NAME: &str = "mewo";
+
+/// Plays through a JACK server as a stereo client. JACK dictates the sample rate, so the
+/// engine resamples to it like to any device rate. The client stays active for its whole
+/// life; pausing just plays silence, as the clock's state already decides that.
+pub struct JackBackend {
+    client: Option<AsyncClient<Notifications, Process>>,
+    clock: Arc<Clock>,
+    converters: Arc<ConverterSlot>,
+    is_healthy: Arc<AtomicBool>,
+    consumer: Arc<Mutex<Option<AudioBufferConsumer>>>,
+    format: OutputFormat,
+}
+
+impl JackBackend {
+    pub fn new(
+        consumer: AudioBufferConsumer,
+        clock: Arc<Clock>,
+    ) -> Result<Self, (AudioBufferConsumer, Box<dyn std::error::Error>)> {
+        let (client, _) = match Client::new(CLIENT_NAME, ClientOptions::NO_START_SERVER) {
+            Ok(c) => c,
+            Err(e) => return Err((consumer, e.into())),
+        };
+        let ports = ["out_l", "out_r"].map(|name| client.register_port(name, AudioOut));
+        let [Ok(left), Ok(right)] = ports else {
+            return Err((consumer, "Failed to register JACK output ports".into()));
+        };
+
+        let sample_rate = client.sample_rate() as u32;
+        let format = OutputFormat {
+            sample_rate,
+            channels: 2,
+            sample_format: "f32".to_string(),
+            bits_per_sample: 32,
+        };
+        clock.set_device_sample_rate(sample_rate);
+        clock.set_channels(2);
+        consumer.set_channels(2);
+        clock.set_buffer_capacity(consumer.capacity() as u64);
+
+        let is_healthy = Arc::new(AtomicBool::new(true));
+        let shared_consumer = Arc::new(Mutex::new(Some(consumer)));
+        let converters = Arc::new(ConverterSlot::default());
+        converters.prepare(&clock);
+        let process = Process {
+            ports: [left, right],
+            consumer: shared_consumer.clone(),
+            clock: clock.clone(),
+            held: HeldFrame::new(converters.clone(), 2, sample_rate),
+            interleaved: vec![0.0; client.buffer_size() as usize * 2],
+        };
+        let notifications = Notifications {
+            clock: clock.clone(),
+            is_healthy: is_healthy.clone(),
+            sample_rate: sample_rate as Frames,
+        };
+
+        let client = match client.activate_async(notifications, process) {
+            Ok(c) => c,
+            Err(e) => {
+                let consumer = shared_consumer.lock().unwrap().take().unwrap();
+                return Err((consumer, e.into()));
+            }
+        };
+        connect_to_playback(client.as_client());
+
+        Ok(Self {
+            client: Some(client),
+            clock,
+            converters,
+            is_healthy,
+            consumer: shared_consumer,
+            format,
+        })
+    }
+}
+
+// Wire our ports to the first two physical playback ports, as most JACK players do
+fn connect_to_playback(client: &Client) {
+    let playback = client.ports(None, Some("32 bit float mono audio"), PortFlags::IS_INPUT | PortFlags::IS_PHYSICAL);
+    for (ours, theirs) in ["out_l", "out_r"].iter().zip(&playback) {
+        let ours = format!("{}:{}", client.name(), ours);
+        let _ = client.connect_ports_by_name(&ours, theirs);
+    }
+}
+
+impl AudioOutput for JackBackend {
+    fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.client.is_none() {
+            return Err("The JACK client has been shut down".into());
+        }
+        self.converters.prepare(&self.clock);
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.is_healthy.load(Ordering::SeqCst)
+    }
+
+    fn shutdown(&mut self) -> Option<AudioBufferConsumer> {
+        if let Some(client) = self.client.take() {
+            let _ = client.deactivate();
+        }
+        self.consumer.lock().ok()?.take()
+    }
+
+    fn tick(&mut self) {
+        self.converters.prepare(&self.clock);
+    }
+
+    fn clear_buffer(&mut self) {
+        if let Ok(mut guard) = self.consumer.lock() {
+            if let Some(c) = guard.as_mut() {
+                c.clear();
+            }
+        }
+    }
+
+    fn format(&self) -> Option<OutputFormat> {
+        Some(self.format.clone())
+    }
+
+    fn replace_consumer(
+        &mut self,
+        consumer: AudioBufferConsumer,
+    ) -> Result<AudioBufferConsumer, AudioBufferConsumer> {
+        swap_consumer(&self.consumer, consumer)
+    }
+}
+
+impl Drop for JackBackend {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            let _ = client.deactivate();
+        }
+    }
+}
+
+pub struct Process {
+    ports: [Port<AudioOut>; 2],
+    consumer: Arc<Mutex<Option<AudioBufferConsumer>>>,
+    clock: Arc<Clock>,
+    held: HeldFrame,
+    // JACK hands out one buffer per port, the engine's buffer is interleaved
+    interleaved: Vec<f32>,
+}
+
+impl jack::ProcessHandler for Process {
+    fn process(&mut self, _: &Client, ps: &ProcessScope) -> Control {
+        let frames = ps.n_frames() as usize;
+        // Only grows if the server's period size changes
+        self.interleaved.resize(frames * 2, 0.0);
+
+        if let Ok(mut guard) = self.consumer.lock() {
+            if let Some(c) = guard.as_mut() {
+                process_audio(&mut self.interleaved, c, &self.clock, &mut self.held);
+            }
+        }
+
+        let [left, right] = &mut self.ports;
+        let (left, right) = (left.as_mut_slice(ps), right.as_mut_slice(ps));
+        for (i, frame) in self.interleaved.chunks_exact(2).enumerate() {
+            left[i] = frame[0];
+            right[i] = frame[1];
+        }
+        Control::Continue
+    }
+}
+
+pub struct Notifications {
+    clock: Arc<Clock>,
+    is_healthy: Arc<AtomicBool>,
+    sample_rate: Frames,
+}
+
+impl jack::NotificationHandler for Notifications {
+    fn shutdown(&mut self, _: jack::ClientStatus, _: &str) {
+        self.is_healthy.store(false, Ordering::SeqCst);
+    }
+
+    // A rate change mid-stream needs a new resampler, so reconnect as if the device changed
+    fn sample_rate(&mut self, _: &Client, srate: Frames) -> Control {
+        if srate != self.sample_rate {
+            self.is_healthy.store(false, Ordering::SeqCst);
+        }
+        Control::Continue
+    }
+
+    fn xrun(&mut self, _: &Client) -> Control {
+        self.clock.record_underrun();
+        Control::Continue
+    }
+}
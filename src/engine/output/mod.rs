@@ -1,16 +1,101 @@
 pub mod cpal_backend;
+pub mod dropouts;
+#[cfg(feature = "jack")]
+pub mod jack_backend;
+pub mod null_backend;
 pub mod output_manager;
+pub(crate) mod rate_converter;
+pub mod tap;
 
 use crate::engine::buffer::AudioBufferConsumer;
 use crate::engine::clock::Clock;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
+/// Which audio system `OutputManager` opens the output on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputBackend {
+    /// The platform's default host through cpal.
+    #[default]
+    Cpal,
+    /// A JACK server, as a client named `mewo` wired to the physical playback ports.
+    #[cfg(feature = "jack")]
+    Jack,
+    /// cpal's ASIO host, for low-latency playback on Windows. Needs the `asio` feature and
+    /// the ASIO SDK at build time; opening fails with an error if no ASIO driver is installed.
+    #[cfg(all(target_os = "windows", feature = "asio"))]
+    Asio,
+    /// No device at all: the buffer is drained in real time and discarded, e.g. to run
+    /// analysis or the DSP chain on a machine without audio hardware.
+    Null,
+}
+
+/// The format a device stream was actually opened with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputFormat {
+    pub sample_rate: u32,
+    pub channels: u32,
+    /// Name of the device's sample format, e.g. `"f32"` or `"i16"`.
+    pub sample_format: String,
+    pub bits_per_sample: u32,
+}
+
+/// A sink the engine plays into. It's built from the engine's buffer consumer and clock
+/// (see `AudioEngine::with_output`) and is expected to:
+///
+/// - Drain the consumer in real time while started, as interleaved `f32` at the clock's
+///   channel count. Set the device's rate with `Clock::set_device_sample_rate` and the
+///   channel count before the first `start`. The buffer holds audio at
+///   `Clock::get_sample_rate`, which stays put when a later device has another rate;
+///   `process_audio` converts between the two.
+/// - Only take audio while the clock is `Playing`, play silence otherwise, and apply the
+///   clock's volume.
+/// - Drop everything buffered when `Clock::should_clear_buffer` is set, then reset it.
+/// - Advance the clock by the samples it consumed, and set it to `Stopped` once the
+///   buffer runs dry with EOS set.
+///
+/// `cpal_backend::process_audio` does all of this for one callback's worth of samples.
+/// Never block the consumer side on anything the decode thread may hold.
 pub trait AudioOutput: Send {
     fn start(&mut self) -> Result<(), Box<dyn std::error::Error>>;
     fn pause(&mut self) -> Result<(), Box<dyn std::error::Error>>;
     fn stop(&mut self) -> Result<(), Box<dyn std::error::Error>>;
     fn is_healthy(&self) -> bool;
+    /// Stops for good and hands the consumer back.
     fn shutdown(&mut self) -> Option<AudioBufferConsumer>;
+    /// Called periodically from the engine's playback thread, e.g. to reconnect.
     fn tick(&mut self);
+    /// Drops everything buffered right away, for when no callback is running to honor
+    /// `should_clear_buffer`.
     fn clear_buffer(&mut self);
+    /// `None` while no device stream is open.
+    fn format(&self) -> Option<OutputFormat> {
+        None
+    }
+    /// With `follow` on, a change of the system's default device counts as unhealthy so
+    /// the output moves to the new one. Off pins it to the device it opened, and only
+    /// stream errors count. Backends without a default device ignore it.
+    fn set_follow_default_device(&mut self, _follow: bool) {}
+    /// `false` for outputs that only pace playback by the wall clock, like `NullBackend`.
+    fn has_device(&self) -> bool {
+        true
+    }
+    /// Swaps in a new buffer to drain and hands the old consumer back. Outputs that can't
+    /// (or are shut down) return the new one as the error.
+    fn replace_consumer(
+        &mut self,
+        consumer: AudioBufferConsumer,
+    ) -> Result<AudioBufferConsumer, AudioBufferConsumer> {
+        Err(consumer)
+    }
+}
+
+// `replace_consumer` for outputs sharing their consumer with a callback through a slot
+pub(crate) fn swap_consumer(
+    slot: &Mutex<Option<AudioBufferConsumer>>,
+    consumer: AudioBufferConsumer,
+) -> Result<AudioBufferConsumer, AudioBufferConsumer> {
+    match slot.lock() {
+        Ok(mut guard) if guard.is_some() => Ok(guard.replace(consumer).unwrap()),
+        _ => Err(consumer),
+    }
 }
\ No newline at end of file
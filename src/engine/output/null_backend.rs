@@ -0,0 +1,187 @@
+use crate::engine::buffer::AudioBufferConsumer;
+use crate::engine::clock::Clock;
+use crate::engine::output::cpal_backend::{process_audio, HeldFrame};
+use crate::engine::output::rate_converter::ConverterSlot;
+use crate::engine::output::{swap_consumer, AudioOutput, OutputFormat};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// How often the virtual device asks for audio
+const CALLBACK_PERIOD: Duration = Duration::from_millis(10);
+// Format used when no device has set one on the clock yet
+const FALLBACK_SAMPLE_RATE: u32 = 44100;
+const FALLBACK_CHANNELS: u32 = 2;
+
+/// Interleaved samples a `NullBackend` has taken from the buffer, after volume.
+pub type PlayedSamples = Arc<Mutex<Vec<f32>>>;
+
+/// An output without a device. While started, a timer thread drains the buffer in real
+/// time, at the clock's device rate, through the cpal callback logic and discards it, so
+/// decoding, DSP, the output tap and the clock all keep running as if something were
+/// playing.
+pub struct NullBackend {
+    consumer: Arc<Mutex<Option<AudioBufferConsumer>>>,
+    clock: Arc<Clock>,
+    format: OutputFormat,
+    played: Option<PlayedSamples>,
+    running: Arc<AtomicBool>,
+    device_thread: Option<JoinHandle<()>>,
+}
+
+impl NullBackend {
+    /// Keeps the format a previous device left on the clock, if any.
+    pub fn new(consumer: AudioBufferConsumer, clock: Arc<Clock>) -> Self {
+        let (sample_rate, channels) = if clock.is_configured() {
+            (clock.get_device_sample_rate(), clock.get_channels())
+        } else {
+            (FALLBACK_SAMPLE_RATE, FALLBACK_CHANNELS)
+        };
+        Self::with_format(consumer, clock, sample_rate, channels)
+    }
+
+    /// Plays at the given format, setting it on the clock.
+    pub fn with_format(
+        consumer: AudioBufferConsumer,
+        clock: Arc<Clock>,
+        sample_rate: u32,
+        channels: u32,
+    ) -> Self {
+        clock.set_device_sample_rate(sample_rate);
+        clock.set_channels(channels);
+        consumer.set_channels(channels as usize);
+        clock.set_buffer_capacity(consumer.capacity() as u64);
+
+        Self {
+            consumer: Arc::new(Mutex::new(Some(consumer))),
+            clock,
+            format: OutputFormat {
+                sample_rate,
+                channels,
+                sample_format: "f32".to_string(),
+                bits_per_sample: 32,
+            },
+            played: None,
+            running: Arc::new(AtomicBool::new(false)),
+            device_thread: None,
+        }
+    }
+
+    /// Keeps what gets played instead of discarding it, and returns a handle to it.
+    pub fn record(&mut self) -> PlayedSamples {
+        self.played.get_or_insert_with(Default::default).clone()
+    }
+
+    fn halt(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(handle) = self.device_thread.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl AudioOutput for NullBackend {
+    fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.device_thread.is_some() {
+            return Ok(());
+        }
+        self.running.store(true, Ordering::SeqCst);
+
+        let consumer = self.consumer.clone();
+        let clock = self.clock.clone();
+        let played = self.played.clone();
+        let running = self.running.clone();
+        let channels = self.format.channels.max(1) as usize;
+        let sample_rate = self.format.sample_rate;
+
+        self.device_thread = Some(thread::spawn(move || {
+            // Nothing here is real-time, so converters are built on this thread too
+            let converters = Arc::new(ConverterSlot::default());
+            let mut held = HeldFrame::new(converters.clone(), channels, sample_rate);
+            let mut data = Vec::new();
+            let mut last = Instant::now();
+            // Frames of real time not asked for yet
+            let mut owed = 0.0f64;
+
+            while running.load(Ordering::SeqCst) {
+                thread::sleep(CALLBACK_PERIOD);
+
+                // Ask for however much real time has passed, in whole frames, at whatever
+                // rate the clock says the device runs at
+                let now = Instant::now();
+                owed += (now - last).as_secs_f64() * clock.get_device_sample_rate() as f64;
+                last = now;
+                let frames = owed as usize;
+                owed -= frames as f64;
+                data.resize(frames * channels, 0.0f32);
+                converters.prepare(&clock);
+
+                if let Ok(mut guard) = consumer.lock() {
+                    if let Some(c) = guard.as_mut() {
+                        let read = process_audio(&mut data, c, &clock, &mut held);
+                        if let Some(played) = &played {
+                            if let Ok(mut played) = played.lock() {
+                                played.extend_from_slice(&data[..read]);
+                            }
+                        }
+                    }
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.halt();
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.halt();
+        Ok(())
+    }
+
+    fn is_healthy(&self) -> bool {
+        true
+    }
+
+    fn shutdown(&mut self) -> Option<AudioBufferConsumer> {
+        self.halt();
+        self.consumer.lock().ok()?.take()
+    }
+
+    fn tick(&mut self) {}
+
+    fn clear_buffer(&mut self) {
+        if let Ok(mut guard) = self.consumer.lock() {
+            if let Some(c) = guard.as_mut() {
+                c.clear();
+            }
+        }
+    }
+
+    fn format(&self) -> Option<OutputFormat> {
+        Some(OutputFormat {
+            sample_rate: self.clock.get_device_sample_rate(),
+            ..self.format.clone()
+        })
+    }
+
+    fn has_device(&self) -> bool {
+        false
+    }
+
+    fn replace_consumer(
+        &mut self,
+        consumer: AudioBufferConsumer,
+    ) -> Result<AudioBufferConsumer, AudioBufferConsumer> {
+        swap_consumer(&self.consumer, consumer)
+    }
+}
+
+impl Drop for NullBackend {
+    fn drop(&mut self) {
+        self.halt();
+    }
+}
@@ -1,19 +1,48 @@
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use crate::engine::buffer::AudioBufferConsumer;
 use crate::engine::clock::{Clock, PlaybackState};
 use crate::engine::output::cpal_backend::CpalBackend;
-use crate::engine::output::AudioOutput;
+#[cfg(feature = "jack")]
+use crate::engine::output::jack_backend::JackBackend;
+use crate::engine::output::null_backend::NullBackend;
+use crate::engine::output::{AudioOutput, OutputBackend, OutputFormat};
+
+// How often a manager that fell back to the null output looks for a device again
+const DEVICE_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+type Connected = Result<Box<dyn AudioOutput + Send>, (AudioBufferConsumer, Box<dyn std::error::Error>)>;
 
 pub struct OutputManager {
-    backend: Option<CpalBackend>,
+    backend: Option<Box<dyn AudioOutput + Send>>,
+    kind: OutputBackend,
+    follow_default: bool,
+    fallback: bool,
+    // Set while playing into the null output for lack of a device
+    next_retry: Option<Instant>,
     consumer: Option<AudioBufferConsumer>,
     clock: Arc<Clock>,
 }
 
 impl OutputManager {
     pub fn new(consumer: AudioBufferConsumer, clock: Arc<Clock>) -> Self {
+        Self::with_backend(consumer, clock, OutputBackend::default(), false)
+    }
+
+    /// With `fallback` on, an output that can't be opened is replaced by a `NullBackend`
+    /// so playback still runs, and the device is retried every couple of seconds.
+    pub fn with_backend(
+        consumer: AudioBufferConsumer,
+        clock: Arc<Clock>,
+        kind: OutputBackend,
+        fallback: bool,
+    ) -> Self {
         let mut manager = Self {
             backend: None,
+            kind,
+            follow_default: true,
+            fallback,
+            next_retry: None,
             consumer: Some(consumer),
             clock,
         };
@@ -21,11 +50,35 @@ impl OutputManager {
         manager
     }
 
+    fn connect(&self, consumer: AudioBufferConsumer) -> Connected {
+        match self.kind {
+            OutputBackend::Cpal => CpalBackend::new(consumer, self.clock.clone())
+                .map(|b| Box::new(b) as Box<dyn AudioOutput + Send>),
+            OutputBackend::Null => Ok(Box::new(NullBackend::new(consumer, self.clock.clone()))),
+            #[cfg(feature = "jack")]
+            OutputBackend::Jack => JackBackend::new(consumer, self.clock.clone())
+                .map(|b| Box::new(b) as Box<dyn AudioOutput + Send>),
+            #[cfg(all(target_os = "windows", feature = "asio"))]
+            OutputBackend::Asio => CpalBackend::with_host(consumer, self.clock.clone(), cpal::HostId::Asio)
+                .map(|b| Box::new(b) as Box<dyn AudioOutput + Send>),
+        }
+    }
+
     pub fn try_reconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(consumer) = self.consumer.take() {
-            match CpalBackend::new(consumer, self.clock.clone()) {
-                Ok(backend) => {
+            match self.connect(consumer) {
+                Ok(mut backend) => {
+                    backend.set_follow_default_device(self.follow_default);
                     self.backend = Some(backend);
+                    self.next_retry = None;
+                    Ok(())
+                }
+                Err((recovered_consumer, e)) if self.fallback => {
+                    if self.next_retry.is_none() {
+                        eprintln!("Failed to reconnect audio, playing without a device: {}", e);
+                    }
+                    self.backend = Some(Box::new(NullBackend::new(recovered_consumer, self.clock.clone())));
+                    self.next_retry = Some(Instant::now() + DEVICE_RETRY_INTERVAL);
                     Ok(())
                 }
                 Err((recovered_consumer, e)) => {
@@ -40,8 +93,9 @@ impl OutputManager {
     }
 
     pub fn check_connection(&mut self) {
+        let retry_due = self.next_retry.is_some_and(|at| Instant::now() >= at);
         let needs_reconnect = match &self.backend {
-            Some(backend) => !backend.is_healthy(),
+            Some(backend) => !backend.is_healthy() || retry_due,
             None => true,
         };
 
@@ -104,6 +158,9 @@ impl AudioOutput for OutputManager {
 
     fn tick(&mut self) {
         self.check_connection();
+        if let Some(backend) = &mut self.backend {
+            backend.tick();
+        }
     }
 
     fn clear_buffer(&mut self) {
@@ -111,4 +168,30 @@ impl AudioOutput for OutputManager {
             backend.clear_buffer();
         }
     }
+
+    fn format(&self) -> Option<OutputFormat> {
+        self.backend.as_ref().and_then(|backend| backend.format())
+    }
+
+    fn set_follow_default_device(&mut self, follow: bool) {
+        self.follow_default = follow;
+        if let Some(backend) = &mut self.backend {
+            backend.set_follow_default_device(follow);
+        }
+    }
+
+    fn replace_consumer(
+        &mut self,
+        consumer: AudioBufferConsumer,
+    ) -> Result<AudioBufferConsumer, AudioBufferConsumer> {
+        match (&mut self.backend, &mut self.consumer) {
+            (Some(backend), _) => backend.replace_consumer(consumer),
+            (None, Some(stored)) => Ok(std::mem::replace(stored, consumer)),
+            (None, None) => Err(consumer),
+        }
+    }
+
+    fn has_device(&self) -> bool {
+        self.backend.as_ref().is_some_and(|backend| backend.has_device())
+    }
 }
\ No newline at end of file
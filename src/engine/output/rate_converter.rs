@@ -0,0 +1,213 @@
+use crate::engine::buffer::AudioBufferConsumer;
+use crate::engine::clock::Clock;
+use crate::engine::dsp::resampler::Resampler;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// Buffer frames converted at a time. Kept small, since a whole chunk has to be queued
+// before any of it can play
+const CHUNK_FRAMES: usize = 256;
+/// Frames of audio already played that a converter taking over mid-stream is primed
+/// with, so it carries on from them instead of starting from silence.
+pub(crate) const HISTORY_FRAMES: usize = CHUNK_FRAMES;
+
+/// Converts the buffer's audio to the device's rate right before it's played, for a
+/// device whose rate differs from the one the decode thread produces. Costs about a chunk
+/// (256 frames) plus the resampler's delay of latency, around 10 ms at 44.1 kHz. The
+/// delay is dropped from the output, so positions stay in step with what's heard.
+pub(crate) struct RateConverter {
+    resampler: Resampler,
+    from: u32,
+    to: u32,
+    channels: usize,
+    chunk: Vec<f32>,
+    // What the last chunk turned into, before the skipping
+    converted: Vec<f32>,
+    // Converted audio not played yet, from `ready_pos` on
+    ready: Vec<f32>,
+    ready_pos: usize,
+    // Output frames still to drop after a reset, while the resampler's delay comes out
+    skip: usize,
+    // Buffer frames taken and frames converted since the last reset
+    fed: u64,
+    emitted: u64,
+}
+
+impl RateConverter {
+    /// Allocates everything the converter will need, so it has to be built off the audio
+    /// thread. See `ConverterSlot`.
+    pub(crate) fn new(from: u32, to: u32, channels: usize) -> Option<Self> {
+        let channels = channels.max(1);
+        let resampler = Resampler::new(from, to, channels, CHUNK_FRAMES).ok()?;
+        let max_output = resampler.output_frames_max() * channels;
+        Some(Self {
+            skip: resampler.output_delay(),
+            resampler,
+            from,
+            to,
+            channels,
+            chunk: Vec::with_capacity(CHUNK_FRAMES * channels),
+            converted: Vec::with_capacity(max_output),
+            ready: Vec::with_capacity(max_output),
+            ready_pos: 0,
+            fed: 0,
+            emitted: 0,
+        })
+    }
+
+    /// Runs `history`, interleaved audio that was just played at the `from` rate, through
+    /// first and drops everything it turns into, so the first audio out continues it. Only
+    /// the last `HISTORY_FRAMES` are used.
+    pub(crate) fn prime(&mut self, history: &[f32]) {
+        let len = (history.len() / self.channels).min(CHUNK_FRAMES) * self.channels;
+        self.chunk.clear();
+        self.chunk.resize(CHUNK_FRAMES * self.channels - len, 0.0);
+        self.chunk.extend_from_slice(&history[history.len() - len..]);
+        self.skip += (CHUNK_FRAMES as f64 * self.to as f64 / self.from as f64).round() as usize;
+        self.convert_chunk(false);
+    }
+
+    /// The last interleaved audio taken from the buffer, for priming a converter that
+    /// replaces this one.
+    pub(crate) fn last_input(&self) -> &[f32] {
+        &self.chunk
+    }
+
+    pub(crate) fn converts(&self, from: u32, to: u32, channels: usize) -> bool {
+        self.from == from && self.to == to && self.channels == channels
+    }
+
+    /// Fills `out` with converted audio taken from `consumer` and returns how many samples
+    /// it wrote, short when not enough is queued for another chunk. With `flush` on, a
+    /// last partial chunk is converted too, as if silence followed it.
+    pub(crate) fn pull(&mut self, consumer: &mut AudioBufferConsumer, out: &mut [f32], flush: bool) -> usize {
+        let chunk_len = CHUNK_FRAMES * self.channels;
+        let mut written = 0;
+        loop {
+            let take = (self.ready.len() - self.ready_pos).min(out.len() - written);
+            out[written..written + take].copy_from_slice(&self.ready[self.ready_pos..self.ready_pos + take]);
+            written += take;
+            self.ready_pos += take;
+            if written == out.len() {
+                break;
+            }
+
+            let queued = consumer.occupied_len() / self.channels * self.channels;
+            // Everything there is has been converted, all but the silence padding it out
+            let expected = self.fed * self.to as u64 / self.from as u64;
+            if queued < chunk_len && !(flush && (queued > 0 || self.emitted < expected)) {
+                break;
+            }
+            self.chunk.clear();
+            self.chunk.extend((0..queued.min(chunk_len)).map_while(|_| consumer.pop()));
+            self.fed += (self.chunk.len() / self.channels) as u64;
+            self.chunk.resize(chunk_len, 0.0);
+            if !self.convert_chunk(flush) {
+                break;
+            }
+        }
+        consumer.notify_space();
+        written
+    }
+
+    // Converts `chunk` and queues the result, less what's still to be skipped and, when
+    // flushing, less what only the padding turned into
+    fn convert_chunk(&mut self, flush: bool) -> bool {
+        self.converted.clear();
+        if self.resampler.process_into(&self.chunk, &mut self.converted).is_err() {
+            return false;
+        }
+        let dropped = (self.skip * self.channels).min(self.converted.len());
+        self.skip -= dropped / self.channels;
+        let mut converted = &self.converted[dropped..];
+        if flush {
+            let expected = self.fed * self.to as u64 / self.from as u64;
+            let room = expected.saturating_sub(self.emitted) as usize * self.channels;
+            converted = &converted[..converted.len().min(room)];
+        }
+        self.emitted += (converted.len() / self.channels) as u64;
+        // Only converted once what's ready has all been played, so this stays within the
+        // capacity it was built with
+        self.ready.drain(..self.ready_pos);
+        self.ready_pos = 0;
+        self.ready.extend_from_slice(converted);
+        true
+    }
+
+    /// Drops the audio held from before a seek or a new track.
+    pub(crate) fn reset(&mut self) {
+        self.resampler.reset();
+        self.skip = self.resampler.output_delay();
+        self.ready.clear();
+        self.ready_pos = 0;
+        self.fed = 0;
+        self.emitted = 0;
+    }
+}
+
+/// Passes `RateConverter`s between the audio callback, which mustn't allocate or free
+/// memory, and a thread that may: the output's `start` and `tick`, or its device thread
+/// when it has no real-time one. That side builds converters with `prepare` and drops the
+/// ones the callback is done with; the callback only swaps them in and out, and never
+/// waits for the locks.
+#[derive(Default)]
+pub(crate) struct ConverterSlot {
+    // Built for the clock's format, waiting for the callback to take it
+    built: Mutex<Option<RateConverter>>,
+    // One the callback is done with
+    retired: Mutex<Option<RateConverter>>,
+    // Format of the converter the callback has, see `pack_format`, 0 for none
+    active: AtomicU64,
+}
+
+impl ConverterSlot {
+    /// Drops a retired converter, and builds one for the clock's current rates if the
+    /// output needs one and neither has nor is being handed one.
+    pub(crate) fn prepare(&self, clock: &Clock) {
+        if let Ok(mut retired) = self.retired.lock() {
+            retired.take();
+        }
+        let from = clock.get_sample_rate();
+        let to = clock.get_device_sample_rate();
+        let channels = (clock.get_channels() as usize).max(1);
+        if from == to || self.active.load(Ordering::Acquire) == pack_format(from, to, channels) {
+            return;
+        }
+        if let Ok(mut built) = self.built.lock() {
+            if !built.as_ref().is_some_and(|c| c.converts(from, to, channels)) {
+                *built = RateConverter::new(from, to, channels);
+            }
+        }
+    }
+
+    /// The converter built for this format, if it's ready. Called from the callback.
+    pub(crate) fn take(&self, from: u32, to: u32, channels: usize) -> Option<RateConverter> {
+        let mut built = self.built.try_lock().ok()?;
+        if !built.as_ref()?.converts(from, to, channels) {
+            return None;
+        }
+        self.active.store(pack_format(from, to, channels), Ordering::Release);
+        built.take()
+    }
+
+    /// Hands back a converter the callback no longer uses, to be dropped by `prepare`.
+    /// Only if the last one hasn't been collected yet (two format changes between calls
+    /// to `prepare`) or `prepare` is running is this one dropped where it is.
+    pub(crate) fn retire(&self, converter: RateConverter) {
+        if let Ok(mut retired) = self.retired.try_lock() {
+            if retired.is_none() {
+                *retired = Some(converter);
+            }
+        }
+    }
+
+    /// Notes that the callback has gone back to playing without a converter.
+    pub(crate) fn clear_active(&self) {
+        self.active.store(0, Ordering::Release);
+    }
+}
+
+// Packs a conversion into one atomic word: 28 bits for each rate, 8 for the channels
+fn pack_format(from: u32, to: u32, channels: usize) -> u64 {
+    (from as u64 & 0xFFF_FFFF) << 36 | (to as u64 & 0xFFF_FFFF) << 8 | (channels as u64 & 0xFF)
+}
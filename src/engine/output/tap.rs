@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+// About 85 ms of 48 kHz stereo, plenty for a scope or a spectrum frame
+const TAP_CAPACITY: usize = 8192;
+
+/// The most recent samples the output wrote to the device, for visualizers. The output
+/// writes with plain atomic stores, so it never waits on a reader. A reader that asks
+/// for nearly the whole capacity while the output is writing may see a few samples of
+/// the next block mixed in, which a visualizer won't notice.
+pub struct OutputTap {
+    samples: Vec<AtomicU32>,
+    // Total samples ever written; the newest sample sits just before this
+    written: AtomicUsize,
+}
+
+impl Default for OutputTap {
+    fn default() -> Self {
+        Self {
+            samples: (0..TAP_CAPACITY).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl OutputTap {
+    pub fn write(&self, block: impl Iterator<Item = f32>) {
+        let mut pos = self.written.load(Ordering::Relaxed);
+        for sample in block {
+            self.samples[pos % TAP_CAPACITY].store(sample.to_bits(), Ordering::Relaxed);
+            pos = pos.wrapping_add(1);
+        }
+        self.written.store(pos, Ordering::Release);
+    }
+
+    /// Copies the newest samples into the start of `out`, oldest first, and returns how many
+    /// were copied, at most `out.len()` and the tap's capacity, rounded down to whole
+    /// frames of `channels`.
+    pub fn read_latest(&self, out: &mut [f32], channels: usize) -> usize {
+        let channels = channels.max(1);
+        let end = self.written.load(Ordering::Acquire);
+        let count = out.len().min(TAP_CAPACITY).min(end);
+        let count = count - count % channels;
+        let start = end - count;
+        for (i, sample) in out[..count].iter_mut().enumerate() {
+            *sample = f32::from_bits(self.samples[(start + i) % TAP_CAPACITY].load(Ordering::Relaxed));
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_latest_returns_whole_frames() {
+        let tap = OutputTap::default();
+        // Three channels don't divide the capacity, so a full read has to round down
+        tap.write((0..3 * 3000).map(|i| (i % 3) as f32));
+
+        let mut out = vec![-1.0; 3 * 3000];
+        let read = tap.read_latest(&mut out, 3);
+
+        assert_eq!(read % 3, 0);
+        assert!(read <= TAP_CAPACITY);
+        assert_eq!(out[0], 0.0);
+        assert_eq!(out[read - 1], 2.0);
+    }
+}
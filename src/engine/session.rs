@@ -0,0 +1,69 @@
+use crate::engine::dsp::preset::DspPreset;
+use crate::engine::engine::{SeekError, VolumeTaper};
+use std::path::PathBuf;
+
+/// Where playback was and how it was set up, from `AudioEngine::save_session`, for a
+/// player that picks up where it left off with `AudioEngine::restore_session`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Session {
+    /// File that was loaded. `None` if nothing was, or the source wasn't a file. A track
+    /// picked with `load_track` comes back as the file's default track.
+    pub file: Option<PathBuf>,
+    pub position_secs: f64,
+    pub volume: f32,
+    pub volume_taper: VolumeTaper,
+    pub normalization: bool,
+    pub playback_speed: f32,
+    pub preset: DspPreset,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            file: None,
+            position_secs: 0.0,
+            volume: 1.0,
+            volume_taper: VolumeTaper::Linear,
+            normalization: false,
+            playback_speed: 1.0,
+            preset: DspPreset::default(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Session {
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parses a session saved by `to_json`. Missing fields fall back to their defaults.
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+/// The part of `AudioEngine::restore_session` that failed. The settings are applied
+/// before either, so they're restored regardless.
+#[derive(Debug)]
+pub enum SessionError {
+    /// The file couldn't be opened, e.g. it was moved or deleted since the session was saved.
+    Load { path: PathBuf, error: String },
+    /// The file loaded, but playback couldn't be moved to the saved position.
+    Seek(SeekError),
+}
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionError::Load { path, error } => {
+                write!(f, "Couldn't load {}: {}", path.display(), error)
+            }
+            SessionError::Seek(e) => write!(f, "Couldn't restore the position: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
@@ -0,0 +1,109 @@
+use crate::engine::buffer::AudioBufferProducer;
+use crate::engine::dsp::channel_mapper::{ChannelMapper, ChannelMode, Downmix};
+use crate::engine::config::EngineConfig;
+use crate::engine::dsp::dsp_chain::DspChain;
+use crate::engine::dsp::resampler::Resampler;
+
+/// The pipeline behind `AudioEngine::push_samples`: the same resample, channel map and
+/// DSP stages the decode thread runs, driven from the caller's thread instead.
+pub(crate) struct StreamingInput {
+    sample_rate: u32,
+    channels: usize,
+    output_rate: u32,
+    output_channels: usize,
+    resampler: Option<Resampler>,
+    mapper: ChannelMapper,
+    dsp: DspChain,
+    // Processed audio that didn't fit in the buffer last time
+    overflow: Vec<f32>,
+}
+
+impl StreamingInput {
+    pub fn new(
+        sample_rate: u32,
+        channels: usize,
+        output_rate: u32,
+        output_channels: usize,
+        channel_mode: ChannelMode,
+        downmix: Downmix,
+        config: &EngineConfig,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let channels = channels.max(1);
+        let resampler = if sample_rate != output_rate {
+            Some(Resampler::new(
+                sample_rate,
+                output_rate,
+                channels,
+                config.effective_resampler_chunk_frames(),
+            )?)
+        } else {
+            None
+        };
+        let mut dsp = DspChain::new(output_rate as f32, output_channels);
+        dsp.set_layout(config.dsp_layout);
+        dsp.set_precision(config.processing_precision);
+
+        Ok(Self {
+            sample_rate,
+            channels,
+            output_rate,
+            output_channels,
+            resampler,
+            mapper: ChannelMapper::new(channels, output_channels, channel_mode, downmix),
+            dsp,
+            overflow: Vec::new(),
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn matches_output(&self, output_rate: u32, output_channels: usize) -> bool {
+        self.output_rate == output_rate && self.output_channels == output_channels
+    }
+
+    pub fn dsp_mut(&mut self) -> &mut DspChain {
+        &mut self.dsp
+    }
+
+    /// Processes as much of `samples` as the buffer has room for and returns how many
+    /// input samples were taken, always a whole number of frames.
+    pub fn push(
+        &mut self,
+        producer: &mut AudioBufferProducer,
+        samples: &[f32],
+    ) -> usize {
+        // Finish what's left over before taking anything new
+        let pushed = producer.push_slice(&self.overflow);
+        self.overflow.drain(..pushed);
+        if !self.overflow.is_empty() {
+            return 0;
+        }
+
+        // Input frames that fit in the free space once converted to the output format
+        let vacant_frames = producer.vacant_len() / self.output_channels.max(1);
+        let frames = (vacant_frames as u64 * self.sample_rate as u64 / self.output_rate.max(1) as u64) as usize;
+        let accepted = (samples.len() / self.channels).min(frames) * self.channels;
+        if accepted == 0 {
+            return 0;
+        }
+
+        let mut block = samples[..accepted].to_vec();
+        if let Some(r) = &mut self.resampler {
+            block = r.process(&block).unwrap_or_default();
+        }
+        if !self.mapper.is_passthrough() {
+            block = self.mapper.process(&block);
+        }
+        self.dsp.process(&mut block);
+
+        let pushed = producer.push_slice(&block);
+        self.overflow.extend_from_slice(&block[pushed..]);
+        accepted
+    }
+}
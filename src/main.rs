@@ -1,9 +1,7 @@
-mod engine;
-
 use std::{io, thread};
 use std::io::Write;
 use std::time::Duration;
-use crate::engine::engine::AudioEngine;
+use test_engine::engine::engine::{AudioEngine, WaitError};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("--- Audio Engine Example ---");
@@ -29,13 +27,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("Playback started...");
     engine.play()?;
-    let mut counter = 0;
-    loop {
-        if counter == 10 {
-            break;
-        }
-        thread::sleep(Duration::from_secs(1));
-        counter += 1;
+    // Play the first song to the end, or for ten seconds at most
+    match engine.wait_until_finished(Some(Duration::from_secs(10))) {
+        Ok(()) | Err(WaitError::TimedOut) => {}
+        Err(e) => return Err(e.into()),
     }
 
     engine.load_and_play(r"D:\Downloads\test 2.mp3")?;
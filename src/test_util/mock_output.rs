@@ -0,0 +1,73 @@
+use crate::engine::buffer::AudioBufferConsumer;
+use crate::engine::clock::Clock;
+use crate::engine::output::null_backend::NullBackend;
+use crate::engine::output::{AudioOutput, OutputFormat};
+use std::sync::Arc;
+
+pub use crate::engine::output::null_backend::PlayedSamples;
+
+/// An `AudioOutput` with no hardware behind it: a recording `NullBackend` that reports
+/// having a device. Setting another device rate on the clock simulates switching to a
+/// device at that rate.
+pub struct MockOutput {
+    inner: NullBackend,
+    played: PlayedSamples,
+}
+
+impl MockOutput {
+    pub fn new(
+        consumer: AudioBufferConsumer,
+        clock: Arc<Clock>,
+        sample_rate: u32,
+        channels: u32,
+    ) -> Self {
+        let mut inner = NullBackend::with_format(consumer, clock, sample_rate, channels);
+        let played = inner.record();
+        Self { inner, played }
+    }
+
+    /// A handle to the recorded samples that stays valid after the output is handed to
+    /// the engine.
+    pub fn played(&self) -> PlayedSamples {
+        self.played.clone()
+    }
+}
+
+impl AudioOutput for MockOutput {
+    fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.inner.start()
+    }
+
+    fn pause(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.inner.pause()
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.inner.stop()
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.inner.is_healthy()
+    }
+
+    fn shutdown(&mut self) -> Option<AudioBufferConsumer> {
+        self.inner.shutdown()
+    }
+
+    fn tick(&mut self) {}
+
+    fn clear_buffer(&mut self) {
+        self.inner.clear_buffer();
+    }
+
+    fn format(&self) -> Option<OutputFormat> {
+        self.inner.format()
+    }
+
+    fn replace_consumer(
+        &mut self,
+        consumer: AudioBufferConsumer,
+    ) -> Result<AudioBufferConsumer, AudioBufferConsumer> {
+        self.inner.replace_consumer(consumer)
+    }
+}
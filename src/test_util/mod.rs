@@ -0,0 +1,6 @@
+pub mod mock_output;
+#[cfg(test)]
+pub mod ogg_flac;
+pub mod signal_generator;
+
+pub use signal_generator::{Signal, SignalGenerator};
@@ -0,0 +1,74 @@
+// Ogg's CRC-32: polynomial 0x04c11db7, no reflection, zero start
+fn ogg_crc(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04c1_1db7 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// An Ogg page holding a single packet shorter than 255 bytes.
+pub fn ogg_page(serial: u32, sequence: u32, flags: u8, granule: u64, packet: &[u8]) -> Vec<u8> {
+    let mut page = b"OggS".to_vec();
+    page.extend([0, flags]);
+    page.extend(granule.to_le_bytes());
+    page.extend(serial.to_le_bytes());
+    page.extend(sequence.to_le_bytes());
+    page.extend([0; 4]);
+    page.extend([1, packet.len() as u8]);
+    page.extend(packet);
+    let crc = ogg_crc(&page);
+    page[22..26].copy_from_slice(&crc.to_le_bytes());
+    page
+}
+
+/// The identification packet of FLAC in Ogg: the mapping header and a STREAMINFO block
+/// for 16-bit audio a second long.
+pub fn flac_header(sample_rate: u32, channels: u8) -> Vec<u8> {
+    let mut packet = vec![0x7f];
+    packet.extend(b"FLAC");
+    packet.extend([1, 0, 0, 0]);
+    packet.extend(b"fLaC");
+    packet.extend([0x80, 0, 0, 34]);
+    packet.extend(4096u16.to_be_bytes());
+    packet.extend(4096u16.to_be_bytes());
+    packet.extend([0; 6]);
+    let format = (sample_rate as u64) << 44
+        | ((channels - 1) as u64) << 41
+        | 15 << 36
+        | sample_rate as u64;
+    packet.extend(format.to_be_bytes());
+    packet.extend([0; 16]);
+    packet
+}
+
+/// A FLAC frame of 4096 stereo 16-bit frames holding one constant value, numbered
+/// `index`, or with a reserved subframe type when `corrupt`.
+pub fn flac_frame(index: u8, value: i16, corrupt: bool) -> Vec<u8> {
+    let mut frame = vec![0xff, 0xf8, 0xc9, 0x18, index];
+    let mut crc8 = 0u8;
+    for &byte in &frame {
+        crc8 ^= byte;
+        for _ in 0..8 {
+            crc8 = if crc8 & 0x80 != 0 { (crc8 << 1) ^ 0x07 } else { crc8 << 1 };
+        }
+    }
+    frame.push(crc8);
+    let subframe_type = if corrupt { 0x04 } else { 0x00 };
+    for _ in 0..2 {
+        frame.push(subframe_type);
+        frame.extend(value.to_be_bytes());
+    }
+    let mut crc16 = 0u16;
+    for &byte in &frame {
+        crc16 ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc16 = if crc16 & 0x8000 != 0 { (crc16 << 1) ^ 0x8005 } else { crc16 << 1 };
+        }
+    }
+    frame.extend(crc16.to_be_bytes());
+    frame
+}
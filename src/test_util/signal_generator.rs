@@ -0,0 +1,131 @@
+use crate::engine::decoder::{AudioDecoder, AudioMetadata};
+use std::f64::consts::PI;
+
+const BLOCK_FRAMES: u64 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signal {
+    Sine { frequency: f64, amplitude: f32 },
+    /// Uniform white noise. It's derived from the frame index, so it comes out the same
+    /// on every run and after every seek.
+    WhiteNoise { amplitude: f32 },
+    /// A single full-scale sample at the very start, silence afterwards.
+    Impulse,
+    /// Exponential sine sweep from `start_hz` to `end_hz` over the whole duration.
+    LogSweep { start_hz: f64, end_hz: f64, amplitude: f32 },
+}
+
+/// An `AudioDecoder` that synthesizes a test signal instead of reading a file, so DSP and
+/// timing can be checked deterministically. Every channel carries the same signal,
+/// except white noise which is independent per channel.
+pub struct SignalGenerator {
+    signal: Signal,
+    sample_rate: u32,
+    channels: u32,
+    total_frames: u64,
+    position: u64,
+}
+
+impl SignalGenerator {
+    pub fn new(signal: Signal, sample_rate: u32, channels: u32, duration_secs: f64) -> Self {
+        Self {
+            signal,
+            sample_rate: sample_rate.max(1),
+            channels: channels.max(1),
+            total_frames: (duration_secs.max(0.0) * sample_rate as f64) as u64,
+            position: 0,
+        }
+    }
+
+    fn sample(&self, frame: u64, ch: u32) -> f32 {
+        let t = frame as f64 / self.sample_rate as f64;
+        match self.signal {
+            Signal::Sine { frequency, amplitude } => {
+                amplitude * (2.0 * PI * frequency * t).sin() as f32
+            }
+            Signal::WhiteNoise { amplitude } => {
+                let bits = splitmix64(frame * self.channels as u64 + ch as u64);
+                // Top 24 bits as a uniform value in [-1, 1)
+                amplitude * ((bits >> 40) as f32 / (1u64 << 23) as f32 - 1.0)
+            }
+            Signal::Impulse => {
+                if frame == 0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Signal::LogSweep { start_hz, end_hz, amplitude } => {
+                let duration = self.total_frames as f64 / self.sample_rate as f64;
+                let k = (end_hz / start_hz).ln();
+                if duration <= 0.0 || k == 0.0 {
+                    return amplitude * (2.0 * PI * start_hz * t).sin() as f32;
+                }
+                let phase = 2.0 * PI * start_hz * duration / k * ((t * k / duration).exp() - 1.0);
+                amplitude * phase.sin() as f32
+            }
+        }
+    }
+}
+
+impl AudioDecoder for SignalGenerator {
+    fn decode_next(&mut self) -> Option<Vec<f32>> {
+        if self.position >= self.total_frames {
+            return None;
+        }
+
+        let end = (self.position + BLOCK_FRAMES).min(self.total_frames);
+        let mut samples = Vec::with_capacity(((end - self.position) * self.channels as u64) as usize);
+        for frame in self.position..end {
+            for ch in 0..self.channels {
+                samples.push(self.sample(frame, ch));
+            }
+        }
+        self.position = end;
+        Some(samples)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u32 {
+        self.channels
+    }
+
+    fn seek(&mut self, time_secs: f64) {
+        let frame = (time_secs.max(0.0) * self.sample_rate as f64) as u64;
+        self.position = frame.min(self.total_frames);
+    }
+
+    fn duration(&self) -> Option<f64> {
+        Some(self.total_frames as f64 / self.sample_rate as f64)
+    }
+
+    fn total_frames(&self) -> Option<u64> {
+        Some(self.total_frames)
+    }
+
+    fn seek_to_frame(&mut self, frame: u64) {
+        self.position = frame.min(self.total_frames);
+    }
+
+    fn current_position_secs(&self) -> Option<f64> {
+        Some(self.position as f64 / self.sample_rate as f64)
+    }
+
+    fn metadata(&self) -> Option<AudioMetadata> {
+        Some(AudioMetadata {
+            duration_secs: self.duration(),
+            title: Some(format!("{:?}", self.signal)),
+            ..Default::default()
+        })
+    }
+}
+
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}